@@ -50,12 +50,15 @@ fn produce_message<'a, 'b>(
 
     // ~ we leave the partition "unspecified" - this is a negative
     // partition - which causes the producer to find out one on its
-    // own using its underlying partitioner.
+    // own using its underlying partitioner.  without an explicit
+    // timestamp the message is assigned the time of its sending.
     try!(producer.send(&Record {
         topic: topic,
         partition: -1,
         key: (),
         value: data,
+        timestamp: None,
+        headers: Vec::new(),
     }));
 
     // ~ we can achieve exactly the same as above in a shorter way with
@@ -0,0 +1,12 @@
+#![no_main]
+
+use kafka::client::fetch::decode_fetch_response;
+use libfuzzer_sys::fuzz_target;
+
+// ~ the first byte selects the version of the fetch api the rest of
+// the input is decoded as
+fuzz_target!(|data: &[u8]| {
+    if let Some((&version, response)) = data.split_first() {
+        let _ = decode_fetch_response(response, (version % 5) as i16);
+    }
+});
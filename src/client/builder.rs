@@ -0,0 +1,727 @@
+//! Builders easing the construction of a configured `KafkaClient`
+//! and of the request structures it accepts, i.e. `FetchPartition`s
+//! and `ProduceMessage`s, validating their input along the way.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use error::{ErrorKind, Result};
+
+use super::{FetchPartition, ProduceMessage, KafkaClient, Compression, GroupOffsetStorage,
+            IsolationLevel, SaslConfig};
+use super::network::AddressResolver;
+#[cfg(feature = "security")]
+use super::SecurityConfig;
+use super::{DEFAULT_CLIENT_ID, DEFAULT_COMPRESSION, DEFAULT_COMPRESSION_LEVEL,
+            DEFAULT_COMPRESSION_THRESHOLD, DEFAULT_MESSAGE_VERSION,
+            DEFAULT_FETCH_MAX_WAIT_TIME_MILLIS, DEFAULT_FETCH_MIN_BYTES,
+            DEFAULT_FETCH_MAX_BYTES_PER_PARTITION, DEFAULT_FETCH_CRC_VALIDATION,
+            DEFAULT_MAX_MESSAGE_SET_DEPTH, DEFAULT_ISOLATION_LEVEL, DEFAULT_GROUP_OFFSET_STORAGE,
+            DEFAULT_RETRY_BACKOFF_TIME_MILLIS, DEFAULT_RETRY_MAX_ATTEMPTS,
+            DEFAULT_MAX_LEADER_RETRIES, DEFAULT_HONOR_THROTTLE, DEFAULT_IDEMPOTENT,
+            DEFAULT_METADATA_MAX_AGE_MILLIS, DEFAULT_CONNECTION_IDLE_TIMEOUT_MILLIS,
+            DEFAULT_NODELAY, DEFAULT_MAX_RESPONSE_SIZE, default_conn_rw_timeout};
+
+/// Collects the configuration of a `KafkaClient` and creates the
+/// client configured accordingly.  This is the preferred way of
+/// setting up a client: all options are specified in one place
+/// before the client's first use and are validated against each
+/// other.  Each option corresponds to the `KafkaClient` setter of
+/// the same name (e.g. `ClientBuilder::client_id` to
+/// `KafkaClient::set_client_id`) and defaults to the same value.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use kafka::client::{ClientBuilder, Compression};
+///
+/// let mut client = ClientBuilder::new(vec!["localhost:9092".to_owned()])
+///     .client_id("my-app".to_owned())
+///     .compression(Compression::GZIP)
+///     .socket_timeout(Some(Duration::from_secs(30)))
+///     .build()
+///     .unwrap();
+/// client.load_metadata_all().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ClientBuilder {
+    hosts: Vec<String>,
+    client_id: String,
+    compression: Compression,
+    compression_level: u32,
+    compression_threshold: usize,
+    message_version: i8,
+    fetch_max_wait_time: Duration,
+    fetch_min_bytes: i32,
+    fetch_max_bytes_per_partition: i32,
+    fetch_crc_validation: bool,
+    max_message_set_depth: u32,
+    isolation_level: IsolationLevel,
+    group_offset_storage: GroupOffsetStorage,
+    retry_backoff_time: Duration,
+    retry_max_attempts: u32,
+    max_leader_retries: u32,
+    honor_throttle: bool,
+    idempotent: bool,
+    operation_deadline: Option<Duration>,
+    metadata_max_age: Duration,
+    connection_idle_timeout: Duration,
+    socket_timeout: Option<Duration>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    address_resolver: Option<AddressResolver>,
+    max_response_size: usize,
+    wire_debug: bool,
+    sasl_config: Option<SaslConfig>,
+    #[cfg(feature = "security")]
+    security_config: Option<SecurityConfig>,
+}
+
+impl ClientBuilder {
+    /// Creates a new builder for a client bootstrapping from the
+    /// given hosts (see `KafkaClient::new`) with all options at their
+    /// defaults.
+    pub fn new(hosts: Vec<String>) -> ClientBuilder {
+        ClientBuilder {
+            hosts: hosts,
+            client_id: DEFAULT_CLIENT_ID.to_owned(),
+            compression: DEFAULT_COMPRESSION,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            message_version: DEFAULT_MESSAGE_VERSION,
+            fetch_max_wait_time: Duration::from_millis(DEFAULT_FETCH_MAX_WAIT_TIME_MILLIS),
+            fetch_min_bytes: DEFAULT_FETCH_MIN_BYTES,
+            fetch_max_bytes_per_partition: DEFAULT_FETCH_MAX_BYTES_PER_PARTITION,
+            fetch_crc_validation: DEFAULT_FETCH_CRC_VALIDATION,
+            max_message_set_depth: DEFAULT_MAX_MESSAGE_SET_DEPTH,
+            isolation_level: DEFAULT_ISOLATION_LEVEL,
+            group_offset_storage: DEFAULT_GROUP_OFFSET_STORAGE,
+            retry_backoff_time: Duration::from_millis(DEFAULT_RETRY_BACKOFF_TIME_MILLIS),
+            retry_max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+            max_leader_retries: DEFAULT_MAX_LEADER_RETRIES,
+            honor_throttle: DEFAULT_HONOR_THROTTLE,
+            idempotent: DEFAULT_IDEMPOTENT,
+            operation_deadline: None,
+            metadata_max_age: Duration::from_millis(DEFAULT_METADATA_MAX_AGE_MILLIS),
+            connection_idle_timeout: Duration::from_millis(
+                DEFAULT_CONNECTION_IDLE_TIMEOUT_MILLIS,
+            ),
+            socket_timeout: default_conn_rw_timeout(),
+            nodelay: DEFAULT_NODELAY,
+            keepalive: None,
+            address_resolver: None,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            wire_debug: false,
+            sasl_config: None,
+            #[cfg(feature = "security")]
+            security_config: None,
+        }
+    }
+
+    /// See `KafkaClient::set_client_id`.
+    pub fn client_id(mut self, client_id: String) -> ClientBuilder {
+        self.client_id = client_id;
+        self
+    }
+
+    /// See `KafkaClient::set_compression`.
+    pub fn compression(mut self, compression: Compression) -> ClientBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// See `KafkaClient::set_compression_level`.
+    pub fn compression_level(mut self, level: u32) -> ClientBuilder {
+        self.compression_level = level;
+        self
+    }
+
+    /// See `KafkaClient::set_compression_threshold`.
+    pub fn compression_threshold(mut self, bytes: usize) -> ClientBuilder {
+        self.compression_threshold = bytes;
+        self
+    }
+
+    /// See `KafkaClient::set_message_version`.
+    pub fn message_version(mut self, version: i8) -> ClientBuilder {
+        self.message_version = version;
+        self
+    }
+
+    /// See `KafkaClient::set_fetch_max_wait_time`.
+    pub fn fetch_max_wait_time(mut self, max_wait_time: Duration) -> ClientBuilder {
+        self.fetch_max_wait_time = max_wait_time;
+        self
+    }
+
+    /// See `KafkaClient::set_fetch_min_bytes`.
+    pub fn fetch_min_bytes(mut self, min_bytes: i32) -> ClientBuilder {
+        self.fetch_min_bytes = min_bytes;
+        self
+    }
+
+    /// See `KafkaClient::set_fetch_max_bytes_per_partition`.
+    pub fn fetch_max_bytes_per_partition(mut self, max_bytes: i32) -> ClientBuilder {
+        self.fetch_max_bytes_per_partition = max_bytes;
+        self
+    }
+
+    /// See `KafkaClient::set_fetch_crc_validation`.
+    pub fn fetch_crc_validation(mut self, validate_crc: bool) -> ClientBuilder {
+        self.fetch_crc_validation = validate_crc;
+        self
+    }
+
+    /// See `KafkaClient::set_max_message_set_depth`.
+    pub fn max_message_set_depth(mut self, depth: u32) -> ClientBuilder {
+        self.max_message_set_depth = depth;
+        self
+    }
+
+    /// See `KafkaClient::set_isolation_level`.
+    pub fn isolation_level(mut self, isolation_level: IsolationLevel) -> ClientBuilder {
+        self.isolation_level = isolation_level;
+        self
+    }
+
+    /// See `KafkaClient::set_group_offset_storage`.
+    pub fn group_offset_storage(mut self, storage: GroupOffsetStorage) -> ClientBuilder {
+        self.group_offset_storage = storage;
+        self
+    }
+
+    /// See `KafkaClient::set_retry_backoff_time`.
+    pub fn retry_backoff_time(mut self, time: Duration) -> ClientBuilder {
+        self.retry_backoff_time = time;
+        self
+    }
+
+    /// See `KafkaClient::set_retry_max_attempts`.
+    pub fn retry_max_attempts(mut self, attempts: u32) -> ClientBuilder {
+        self.retry_max_attempts = attempts;
+        self
+    }
+
+    /// See `KafkaClient::set_max_leader_retries`.
+    pub fn max_leader_retries(mut self, retries: u32) -> ClientBuilder {
+        self.max_leader_retries = retries;
+        self
+    }
+
+    /// See `KafkaClient::set_honor_throttle`.
+    pub fn honor_throttle(mut self, honor_throttle: bool) -> ClientBuilder {
+        self.honor_throttle = honor_throttle;
+        self
+    }
+
+    /// See `KafkaClient::set_idempotent`.
+    pub fn idempotent(mut self, idempotent: bool) -> ClientBuilder {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// See `KafkaClient::set_operation_deadline`.
+    pub fn operation_deadline(mut self, deadline: Option<Duration>) -> ClientBuilder {
+        self.operation_deadline = deadline;
+        self
+    }
+
+    /// See `KafkaClient::set_metadata_max_age`.
+    pub fn metadata_max_age(mut self, max_age: Duration) -> ClientBuilder {
+        self.metadata_max_age = max_age;
+        self
+    }
+
+    /// See `KafkaClient::set_connection_idle_timeout`.
+    pub fn connection_idle_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.connection_idle_timeout = timeout;
+        self
+    }
+
+    /// See `KafkaClient::set_socket_timeout`.
+    pub fn socket_timeout(mut self, timeout: Option<Duration>) -> ClientBuilder {
+        self.socket_timeout = timeout;
+        self
+    }
+
+    /// See `KafkaClient::set_nodelay`.
+    pub fn nodelay(mut self, nodelay: bool) -> ClientBuilder {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// See `KafkaClient::set_keepalive`.
+    pub fn keepalive(mut self, keepalive: Option<Duration>) -> ClientBuilder {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// See `KafkaClient::set_address_resolver`.
+    pub fn address_resolver(
+        mut self,
+        resolver: Box<dyn Fn(&str, i32) -> SocketAddr + Send>,
+    ) -> ClientBuilder {
+        self.address_resolver = Some(AddressResolver::new(resolver));
+        self
+    }
+
+    /// See `KafkaClient::set_max_response_size`.
+    pub fn max_response_size(mut self, bytes: usize) -> ClientBuilder {
+        self.max_response_size = bytes;
+        self
+    }
+
+    /// See `KafkaClient::set_wire_debug`.
+    pub fn wire_debug(mut self, wire_debug: bool) -> ClientBuilder {
+        self.wire_debug = wire_debug;
+        self
+    }
+
+    /// See `KafkaClient::set_sasl_config`.
+    pub fn sasl_config(mut self, sasl_config: SaslConfig) -> ClientBuilder {
+        self.sasl_config = Some(sasl_config);
+        self
+    }
+
+    /// Secures the connections to the brokers through TLS.  See
+    /// `KafkaClient::new_secure`.
+    #[cfg(feature = "security")]
+    pub fn security_config(mut self, security: SecurityConfig) -> ClientBuilder {
+        self.security_config = Some(security);
+        self
+    }
+
+    /// Validates the options and creates the client.  The client
+    /// still needs to load metadata before its first use.
+    ///
+    /// Fails with `ErrorKind::InvalidClientConfig` if no hosts have
+    /// been specified, an option is out of range, or options conflict
+    /// with each other: idempotence and the read committed isolation
+    /// level require the message format v2, the max. response size
+    /// must accommodate the max. bytes fetched per partition, and SASL
+    /// authentication requires a username.
+    ///
+    /// The TLS setup cannot be validated here: a `SecurityConfig`
+    /// wraps an already built `SslConnector`, which does not expose
+    /// its trusted certificate authorities.  A connector lacking the
+    /// CA of the brokers' certificates surfaces only upon connecting
+    /// to a broker, as `ErrorKind::Ssl` failing the TLS handshake.
+    pub fn build(self) -> Result<KafkaClient> {
+        try!(self.validate());
+        #[cfg(not(feature = "security"))]
+        let mut client = KafkaClient::new(self.hosts);
+        #[cfg(feature = "security")]
+        let mut client = match self.security_config {
+            None => KafkaClient::new(self.hosts),
+            Some(security) => KafkaClient::new_secure(self.hosts, security),
+        };
+        client.set_client_id(self.client_id);
+        client.set_compression(self.compression);
+        try!(client.set_compression_level(self.compression_level));
+        client.set_compression_threshold(self.compression_threshold);
+        try!(client.set_message_version(self.message_version));
+        // ~ the socket timeout first; it may get extended by the
+        // fetch max wait time
+        client.set_socket_timeout(self.socket_timeout);
+        try!(client.set_fetch_max_wait_time(self.fetch_max_wait_time));
+        client.set_fetch_min_bytes(self.fetch_min_bytes);
+        client.set_fetch_max_bytes_per_partition(self.fetch_max_bytes_per_partition);
+        client.set_fetch_crc_validation(self.fetch_crc_validation);
+        client.set_max_message_set_depth(self.max_message_set_depth);
+        client.set_isolation_level(self.isolation_level);
+        client.set_group_offset_storage(self.group_offset_storage);
+        client.set_retry_backoff_time(self.retry_backoff_time);
+        client.set_retry_max_attempts(self.retry_max_attempts);
+        client.set_max_leader_retries(self.max_leader_retries);
+        client.set_honor_throttle(self.honor_throttle);
+        client.set_idempotent(self.idempotent);
+        client.set_operation_deadline(self.operation_deadline);
+        client.set_metadata_max_age(self.metadata_max_age);
+        client.set_connection_idle_timeout(self.connection_idle_timeout);
+        client.set_nodelay(self.nodelay);
+        client.set_keepalive(self.keepalive);
+        client.conn_pool.set_address_resolver(self.address_resolver);
+        client.set_max_response_size(self.max_response_size);
+        client.set_wire_debug(self.wire_debug);
+        client.set_sasl_config(self.sasl_config);
+        Ok(client)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.hosts.is_empty() {
+            bail!(ErrorKind::InvalidClientConfig("no hosts specified"));
+        }
+        if self.compression_level > 9 {
+            bail!(ErrorKind::InvalidClientConfig("compression level out of range"));
+        }
+        if !(0..=2).contains(&self.message_version) {
+            bail!(ErrorKind::InvalidClientConfig("unsupported message version"));
+        }
+        if self.idempotent && self.message_version < 2 {
+            bail!(ErrorKind::InvalidClientConfig(
+                "idempotence requires the message format v2",
+            ));
+        }
+        if self.isolation_level == IsolationLevel::ReadCommitted && self.message_version < 2 {
+            bail!(ErrorKind::InvalidClientConfig(
+                "read committed isolation requires the message format v2",
+            ));
+        }
+        if self.fetch_max_bytes_per_partition > 0 &&
+            self.max_response_size < self.fetch_max_bytes_per_partition as usize
+        {
+            bail!(ErrorKind::InvalidClientConfig(
+                "max. response size below the fetch max. bytes per partition",
+            ));
+        }
+        if let Some(ref sasl) = self.sasl_config {
+            if sasl.username().is_empty() {
+                bail!(ErrorKind::InvalidClientConfig("SASL requires a username"));
+            }
+        }
+        Ok(())
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Assembles a list of `FetchPartition`s to be passed to
+/// `KafkaClient::fetch_messages`.
+///
+/// Each call to `partition` starts a new entry for the most recently
+/// specified `topic`; subsequent calls to `offset` and `max_bytes`
+/// apply to that entry.
+///
+/// # Example
+///
+/// ```no_run
+/// use kafka::client::{KafkaClient, FetchRequestBuilder};
+///
+/// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+/// client.load_metadata_all().unwrap();
+/// let reqs = FetchRequestBuilder::new()
+///     .topic("my-topic")
+///     .partition(0).offset(100).max_bytes(1 << 20)
+///     .partition(1).offset(42)
+///     .build()
+///     .unwrap();
+/// let resps = client.fetch_messages(reqs).unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct FetchRequestBuilder<'a> {
+    topic: Option<&'a str>,
+    partitions: Vec<FetchPartition<'a>>,
+    error: Option<&'static str>,
+}
+
+impl<'a> FetchRequestBuilder<'a> {
+    /// Creates a new, empty builder.
+    pub fn new() -> FetchRequestBuilder<'a> {
+        FetchRequestBuilder::default()
+    }
+
+    /// Sets the topic for the partitions specified subsequently.
+    pub fn topic(mut self, topic: &'a str) -> FetchRequestBuilder<'a> {
+        self.topic = Some(topic);
+        self
+    }
+
+    /// Starts a new entry fetching the given partition of the
+    /// current topic as of offset zero.
+    pub fn partition(mut self, partition: i32) -> FetchRequestBuilder<'a> {
+        match self.topic {
+            Some(topic) => self.partitions.push(FetchPartition::new(topic, partition, 0)),
+            None => self.error = self.error.or(Some("partition specified before any topic")),
+        }
+        self
+    }
+
+    /// Sets the offset as of which to fetch the current partition.
+    pub fn offset(mut self, offset: i64) -> FetchRequestBuilder<'a> {
+        match self.partitions.last_mut() {
+            Some(p) => p.offset = offset,
+            None => self.error = self.error.or(Some("offset specified before any partition")),
+        }
+        self
+    }
+
+    /// Sets the max. amount of data to fetch for the current
+    /// partition.  See `FetchPartition::max_bytes`.
+    pub fn max_bytes(mut self, max_bytes: i32) -> FetchRequestBuilder<'a> {
+        match self.partitions.last_mut() {
+            Some(p) => p.max_bytes = max_bytes,
+            None => self.error = self.error.or(Some("max_bytes specified before any partition")),
+        }
+        self
+    }
+
+    /// Validates the specified entries and returns them.
+    ///
+    /// Fails with `ErrorKind::InvalidRequest` if the builder has been
+    /// misused, no partitions have been specified, or any entry
+    /// refers to an empty topic name, a negative partition, or a
+    /// negative offset.
+    pub fn build(self) -> Result<Vec<FetchPartition<'a>>> {
+        if let Some(reason) = self.error {
+            bail!(ErrorKind::InvalidRequest(reason));
+        }
+        if self.partitions.is_empty() {
+            bail!(ErrorKind::InvalidRequest("no partitions specified"));
+        }
+        for p in &self.partitions {
+            try!(validate_topic_partition(p.topic, p.partition));
+            if p.offset < 0 {
+                bail!(ErrorKind::InvalidRequest("negative offset"));
+            }
+        }
+        Ok(self.partitions)
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Assembles a single `ProduceMessage` to be passed to
+/// `KafkaClient::produce_messages`.
+///
+/// # Example
+///
+/// ```
+/// use kafka::client::MessageBuilder;
+///
+/// let msg = MessageBuilder::new()
+///     .topic("my-topic")
+///     .partition(0)
+///     .key(b"key")
+///     .value(b"value")
+///     .build()
+///     .unwrap();
+/// assert_eq!("my-topic", msg.topic);
+/// ```
+#[derive(Debug, Default)]
+pub struct MessageBuilder<'a, 'b> {
+    topic: &'a str,
+    partition: i32,
+    key: Option<&'b [u8]>,
+    value: Option<&'b [u8]>,
+    timestamp: Option<i64>,
+    headers: &'b [(String, Vec<u8>)],
+}
+
+impl<'a, 'b> MessageBuilder<'a, 'b> {
+    /// Creates a new builder for a message without key and value
+    /// destined to partition zero.
+    pub fn new() -> MessageBuilder<'a, 'b> {
+        MessageBuilder::default()
+    }
+
+    /// Sets the topic to produce the message to.
+    pub fn topic(mut self, topic: &'a str) -> MessageBuilder<'a, 'b> {
+        self.topic = topic;
+        self
+    }
+
+    /// Sets the partition to produce the message to.
+    pub fn partition(mut self, partition: i32) -> MessageBuilder<'a, 'b> {
+        self.partition = partition;
+        self
+    }
+
+    /// Sets the key of the message.
+    pub fn key(mut self, key: &'b [u8]) -> MessageBuilder<'a, 'b> {
+        self.key = Some(key);
+        self
+    }
+
+    /// Sets the value of the message.
+    pub fn value(mut self, value: &'b [u8]) -> MessageBuilder<'a, 'b> {
+        self.value = Some(value);
+        self
+    }
+
+    /// Sets the (create) timestamp of the message in milliseconds
+    /// since the unix epoch.  See `ProduceMessage::timestamp`.
+    pub fn timestamp(mut self, timestamp: i64) -> MessageBuilder<'a, 'b> {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the headers of the message.  See
+    /// `ProduceMessage::headers`.
+    pub fn headers(mut self, headers: &'b [(String, Vec<u8>)]) -> MessageBuilder<'a, 'b> {
+        self.headers = headers;
+        self
+    }
+
+    /// Validates the message and returns it.
+    ///
+    /// Fails with `ErrorKind::InvalidRequest` if the topic name is
+    /// empty or the partition or timestamp is negative.
+    pub fn build(self) -> Result<ProduceMessage<'a, 'b>> {
+        try!(validate_topic_partition(self.topic, self.partition));
+        if self.timestamp.map_or(false, |t| t < 0) {
+            bail!(ErrorKind::InvalidRequest("negative timestamp"));
+        }
+        Ok(ProduceMessage {
+            key: self.key,
+            value: self.value,
+            topic: self.topic,
+            partition: self.partition,
+            timestamp: self.timestamp,
+            headers: self.headers,
+        })
+    }
+}
+
+fn validate_topic_partition(topic: &str, partition: i32) -> Result<()> {
+    if topic.is_empty() {
+        bail!(ErrorKind::InvalidRequest("empty topic name"));
+    }
+    if partition < 0 {
+        bail!(ErrorKind::InvalidRequest("negative partition"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use error::{Error, ErrorKind};
+    use client::{IsolationLevel, SaslConfig, DEFAULT_CLIENT_ID};
+    use super::{ClientBuilder, FetchRequestBuilder, MessageBuilder};
+
+    fn assert_invalid<T: ::std::fmt::Debug>(r: ::error::Result<T>, reason: &str) {
+        match r {
+            Err(Error(ErrorKind::InvalidRequest(r), _)) if r == reason => {}
+            r => panic!("Expected InvalidRequest({:?}), but got: {:?}", reason, r),
+        }
+    }
+
+    #[test]
+    fn test_fetch_request_builder() {
+        let reqs = FetchRequestBuilder::new()
+            .topic("a")
+            .partition(0)
+            .offset(100)
+            .max_bytes(1 << 20)
+            .partition(1)
+            .topic("b")
+            .partition(2)
+            .offset(7)
+            .build()
+            .unwrap();
+        let reqs: Vec<_> = reqs.iter()
+            .map(|p| (p.topic, p.partition, p.offset, p.max_bytes))
+            .collect();
+        assert_eq!(vec![("a", 0, 100, 1 << 20), ("a", 1, 0, -1), ("b", 2, 7, -1)], reqs);
+    }
+
+    #[test]
+    fn test_fetch_request_builder_invalid() {
+        assert_invalid(FetchRequestBuilder::new().build(), "no partitions specified");
+        assert_invalid(
+            FetchRequestBuilder::new().partition(0).topic("a").partition(1).build(),
+            "partition specified before any topic",
+        );
+        assert_invalid(
+            FetchRequestBuilder::new().topic("a").offset(1).partition(0).build(),
+            "offset specified before any partition",
+        );
+        assert_invalid(
+            FetchRequestBuilder::new().topic("").partition(0).build(),
+            "empty topic name",
+        );
+        assert_invalid(
+            FetchRequestBuilder::new().topic("a").partition(-1).build(),
+            "negative partition",
+        );
+        assert_invalid(
+            FetchRequestBuilder::new().topic("a").partition(0).offset(-2).build(),
+            "negative offset",
+        );
+    }
+
+    #[test]
+    fn test_message_builder() {
+        let headers = vec![("h".to_owned(), b"v".to_vec())];
+        let msg = MessageBuilder::new()
+            .topic("a")
+            .partition(3)
+            .key(b"k")
+            .value(b"v")
+            .timestamp(1000)
+            .headers(&headers)
+            .build()
+            .unwrap();
+        assert_eq!("a", msg.topic);
+        assert_eq!(3, msg.partition);
+        assert_eq!(Some(&b"k"[..]), msg.key);
+        assert_eq!(Some(&b"v"[..]), msg.value);
+        assert_eq!(Some(1000), msg.timestamp);
+        assert_eq!(1, msg.headers.len());
+
+        assert_invalid(MessageBuilder::new().build(), "empty topic name");
+        assert_invalid(
+            MessageBuilder::new().topic("a").partition(-1).build(),
+            "negative partition",
+        );
+        assert_invalid(
+            MessageBuilder::new().topic("a").timestamp(-1).build(),
+            "negative timestamp",
+        );
+    }
+
+    #[test]
+    fn test_client_builder() {
+        let client = ClientBuilder::new(vec!["localhost:9092".to_owned()])
+            .client_id("my-app".to_owned())
+            .compression_level(1)
+            .compression_threshold(1024)
+            .message_version(2)
+            .idempotent(true)
+            .socket_timeout(Some(Duration::from_secs(1)))
+            .fetch_max_wait_time(Duration::from_secs(2))
+            .nodelay(false)
+            .build()
+            .unwrap();
+        assert_eq!("my-app", client.client_id());
+        assert_eq!(1, client.compression_level());
+        assert_eq!(1024, client.compression_threshold());
+        assert_eq!(2, client.message_version());
+        assert!(client.idempotent());
+        assert!(!client.nodelay());
+        // ~ extended by the fetch max wait time
+        assert!(client.socket_timeout().unwrap() > Duration::from_secs(2));
+
+        let client = ClientBuilder::new(vec!["localhost:9092".to_owned()]).build().unwrap();
+        assert_eq!(DEFAULT_CLIENT_ID, client.client_id());
+    }
+
+    #[test]
+    fn test_client_builder_invalid() {
+        fn assert_invalid_config(b: ClientBuilder, reason: &str) {
+            match b.build() {
+                Err(Error(ErrorKind::InvalidClientConfig(r), _)) if r == reason => {}
+                r => panic!("Expected InvalidClientConfig({:?}), but got: {:?}", reason, r),
+            }
+        }
+        let b = || ClientBuilder::new(vec!["localhost:9092".to_owned()]);
+        assert_invalid_config(ClientBuilder::new(vec![]), "no hosts specified");
+        assert_invalid_config(b().compression_level(10), "compression level out of range");
+        assert_invalid_config(b().message_version(3), "unsupported message version");
+        assert_invalid_config(b().idempotent(true), "idempotence requires the message format v2");
+        assert_invalid_config(
+            b().isolation_level(IsolationLevel::ReadCommitted).message_version(1),
+            "read committed isolation requires the message format v2",
+        );
+        assert_invalid_config(
+            b().max_response_size(1024).fetch_max_bytes_per_partition(4096),
+            "max. response size below the fetch max. bytes per partition",
+        );
+        assert_invalid_config(
+            b().sasl_config(SaslConfig::plain("", "secret")),
+            "SASL requires a username",
+        );
+    }
+}
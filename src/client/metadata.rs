@@ -4,6 +4,8 @@
 use std::collections::hash_map;
 use std::fmt;
 
+use error::KafkaCode;
+
 use super::KafkaClient;
 use super::state::{ClientState, TopicPartitions, TopicPartitionIter, TopicPartition};
 
@@ -299,6 +301,57 @@ impl<'a> Partition<'a> {
     pub fn is_available(&self) -> bool {
         self.leader().is_some()
     }
+
+    /// Retrieves the node id of this partition's leader broker as
+    /// reported by the cluster; `-1` if the partition has no leader.
+    #[inline]
+    pub fn leader_id(&self) -> i32 {
+        self.partition.leader_id()
+    }
+
+    /// Retrieves the node ids of the brokers replicating this
+    /// partition.
+    #[inline]
+    pub fn replicas(&self) -> &'a [i32] {
+        self.partition.replicas()
+    }
+
+    /// Retrieves the node ids of the replicas currently in sync with
+    /// the partition's leader.
+    #[inline]
+    pub fn isr(&self) -> &'a [i32] {
+        self.partition.isr()
+    }
+
+    /// Retrieves the error reported for this partition along with its
+    /// metadata - if any, e.g. `KafkaCode::LeaderNotAvailable` or
+    /// `KafkaCode::ReplicaNotAvailable`.
+    #[inline]
+    pub fn error(&self) -> Option<KafkaCode> {
+        self.partition.error()
+    }
+
+    /// Retrieves the error code reported for this partition along
+    /// with its metadata; `0` if there is no error.  Unlike
+    /// `Partition::error`, this preserves codes unknown to this
+    /// library.
+    #[inline]
+    pub fn error_code(&self) -> i16 {
+        self.partition.error_code()
+    }
+
+    /// Takes a snapshot of this partition's metadata detached from
+    /// the client it was loaded by.
+    pub fn metadata(&self) -> PartitionMetadata {
+        PartitionMetadata {
+            id: self.id,
+            leader: self.leader_id(),
+            replicas: self.replicas().to_vec(),
+            isr: self.isr().to_vec(),
+            error: self.error(),
+            error_code: self.error_code(),
+        }
+    }
 }
 
 impl<'a> fmt::Debug for Partition<'a> {
@@ -306,3 +359,32 @@ impl<'a> fmt::Debug for Partition<'a> {
         write!(f, "Partition {{ id: {}, leader: {:?} }}", self.id(), self.leader())
     }
 }
+
+/// A snapshot of the metadata of a topic partition as last loaded by
+/// a `KafkaClient`.  See `KafkaClient::topic_metadata`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionMetadata {
+    /// The identifier of the partition.
+    pub id: i32,
+    /// The node id of the partition's leader broker; `-1` if the
+    /// partition has no leader.
+    pub leader: i32,
+    /// The node ids of the brokers replicating the partition.
+    pub replicas: Vec<i32>,
+    /// The node ids of the replicas in sync with the leader.
+    pub isr: Vec<i32>,
+    /// The error reported for the partition - if any.
+    pub error: Option<KafkaCode>,
+    /// The error code reported for the partition; `0` if there is no
+    /// error.  Preserves codes unknown to this library which `error`
+    /// reports as `KafkaCode::Unknown`.
+    pub error_code: i16,
+}
+
+impl PartitionMetadata {
+    /// Determines whether some of the partition's replicas are not
+    /// in sync with its leader.
+    pub fn is_under_replicated(&self) -> bool {
+        self.isr.len() < self.replicas.len()
+    }
+}
@@ -7,15 +7,19 @@
 use std;
 use std::collections::hash_map;
 use std::collections::hash_map::HashMap;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::iter::Iterator;
 use std::mem;
+use std::net::SocketAddr;
 use std::thread;
 use std::time::{Duration, Instant};
 
 // pub re-export
 pub use compression::Compression;
 pub use utils::PartitionOffset;
+pub use self::builder::{ClientBuilder, FetchRequestBuilder, MessageBuilder};
+pub use self::shared::SharedClient;
+pub use self::sasl::SaslConfig;
 
 #[cfg(feature = "security")]
 pub use self::network::SecurityConfig;
@@ -27,18 +31,24 @@ use protocol::{self, ResponseParser};
 use client_internals::KafkaClientInternals;
 
 pub mod metadata;
+mod builder;
 mod state;
 mod network;
+mod sasl;
+#[cfg(feature = "security")]
+mod scram;
+mod shared;
 
 // ~ re-export (only) certain types from the protocol::fetch module as
 // 'client::fetch'.
 pub mod fetch {
     //! A representation of fetched messages from Kafka.
 
-    pub use protocol::fetch::{Data, Message, Partition, Response, Topic};
+    pub use protocol::fetch::{Data, Message, Partition, Response, Topic, decode_fetch_response};
 }
 
-const DEFAULT_CONNECTION_RW_TIMEOUT_SECS: u64 = 120;
+/// The default value for `KafkaClient::set_socket_timeout(..)`
+pub const DEFAULT_CONNECTION_RW_TIMEOUT_SECS: u64 = 120;
 
 fn default_conn_rw_timeout() -> Option<Duration> {
     match DEFAULT_CONNECTION_RW_TIMEOUT_SECS {
@@ -47,12 +57,34 @@ fn default_conn_rw_timeout() -> Option<Duration> {
     }
 }
 
+/// The default value for `KafkaClient::set_nodelay(..)`
+pub const DEFAULT_NODELAY: bool = true;
+
+/// The default value for `KafkaClient::set_max_response_size(..)`
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 100 * 1024 * 1024;
+
+/// The default value for `KafkaClient::set_client_id(..)`
+pub const DEFAULT_CLIENT_ID: &'static str = "kafka-rust";
+
 /// The default value for `KafkaClient::set_compression(..)`
 pub const DEFAULT_COMPRESSION: Compression = Compression::NONE;
 
+/// The default value for `KafkaClient::set_compression_level(..)`
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// The default value for `KafkaClient::set_compression_threshold(..)`
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 0;
+
+/// The default value for `KafkaClient::set_message_version(..)`
+pub const DEFAULT_MESSAGE_VERSION: i8 = 0;
+
 /// The default value for `KafkaClient::set_fetch_max_wait_time(..)`
 pub const DEFAULT_FETCH_MAX_WAIT_TIME_MILLIS: u64 = 100;
 
+/// The time by which the socket timeout is made to exceed the
+/// fetch max wait time; see `KafkaClient::set_fetch_max_wait_time`.
+pub const FETCH_MAX_WAIT_TIME_MARGIN_MILLIS: u64 = 5_000;
+
 /// The default value for `KafkaClient::set_fetch_min_bytes(..)`
 pub const DEFAULT_FETCH_MIN_BYTES: i32 = 4096;
 
@@ -62,6 +94,12 @@ pub const DEFAULT_FETCH_MAX_BYTES_PER_PARTITION: i32 = 32 * 1024;
 /// The default value for `KafkaClient::set_fetch_crc_validation(..)`
 pub const DEFAULT_FETCH_CRC_VALIDATION: bool = true;
 
+/// The default value for `KafkaClient::set_max_message_set_depth(..)`
+pub const DEFAULT_MAX_MESSAGE_SET_DEPTH: u32 = protocol::fetch::DEFAULT_MAX_MESSAGE_SET_DEPTH;
+
+/// The default value for `KafkaClient::set_isolation_level(..)`
+pub const DEFAULT_ISOLATION_LEVEL: IsolationLevel = IsolationLevel::ReadUncommitted;
+
 /// The default value for `KafkaClient::set_group_offset_storage(..)`
 pub const DEFAULT_GROUP_OFFSET_STORAGE: GroupOffsetStorage = GroupOffsetStorage::Zookeeper;
 
@@ -73,14 +111,46 @@ pub const DEFAULT_RETRY_BACKOFF_TIME_MILLIS: u64 = 100;
 // approximetaly up to two minutes
 pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 120_000 / DEFAULT_RETRY_BACKOFF_TIME_MILLIS as u32;
 
+/// The default value for `KafkaClient::set_max_leader_retries(..)`
+pub const DEFAULT_MAX_LEADER_RETRIES: u32 = 1;
+
+/// The default value for `KafkaClient::set_honor_throttle(..)`
+pub const DEFAULT_HONOR_THROTTLE: bool = false;
+
+/// The default value for `KafkaClient::set_idempotent(..)`
+pub const DEFAULT_IDEMPOTENT: bool = false;
+
+/// The default value for `KafkaClient::set_metadata_max_age(..)`;
+/// zero, i.e. metadata is never refreshed automatically.
+pub const DEFAULT_METADATA_MAX_AGE_MILLIS: u64 = 0;
+
 /// The default value for `KafkaClient::set_connection_idle_timeout(..)`
 pub const DEFAULT_CONNECTION_IDLE_TIMEOUT_MILLIS: u64 = 540_000;
 
+/// The time `KafkaClient::create_topics` and
+/// `KafkaClient::create_partitions` allow the controller to complete
+/// the creation of the requested topics or partitions.
+pub const CREATE_TOPICS_TIMEOUT_MILLIS: u64 = 30_000;
+
+/// The partition assignment strategy advertised by
+/// `KafkaClient::join_group`.  The leader of a group is expected to
+/// distribute the subscribed topic partitions accordingly among the
+/// group's members, i.e. in a round robin fashion over the members
+/// and partitions each sorted by name and id.
+pub const GROUP_ASSIGNMENT_STRATEGY: &'static str = "roundrobin";
+
+// the protocol type consumer groups are registered under with kafka
+const GROUP_PROTOCOL_TYPE: &'static str = "consumer";
+
 /// Client struct keeping track of brokers and topic metadata.
 ///
 /// Implements methods described by the [Kafka Protocol](http://kafka.apache.org/protocol.html).
 ///
 /// You will have to load metadata before making any other request.
+///
+/// `ClientBuilder` is the preferred way of creating a configured
+/// client; the `set_*` methods remain available to adjust a client
+/// after its creation.
 #[derive(Debug)]
 pub struct KafkaClient {
     // ~ this kafka client configuration
@@ -99,12 +169,22 @@ struct ClientConfig {
     hosts: Vec<String>,
     // ~ compression to use when sending messages
     compression: Compression,
+    compression_level: u32,
+    // ~ the size a message set must exceed to get compressed
+    compression_threshold: usize,
+    // ~ the message format (magic byte) of produced and fetched
+    // messages
+    message_version: i8,
     // ~ these are the defaults when fetching messages for details
     // refer to the kafka wire protocol
     fetch_max_wait_time: i32,
     fetch_min_bytes: i32,
     fetch_max_bytes_per_partition: i32,
     fetch_crc_validation: bool,
+    // ~ how deep compressed message sets may wrap each other
+    max_message_set_depth: u32,
+    // ~ which messages of transactional producers to fetch
+    isolation_level: IsolationLevel,
     // ~ the version of the API to use for the corresponding kafka
     // calls; note that this might have an effect on the storage type
     // kafka will then use (zookeeper or __consumer_offsets).  it is
@@ -119,12 +199,26 @@ struct ClientConfig {
     // ~ the number of repeated retry attempts; prevents endless
     // repetition of a retry attempt
     retry_max_attempts: u32,
+    // ~ the number of times to refresh metadata and re-send messages
+    // which failed due to a moved partition leader
+    max_leader_retries: u32,
+    // ~ whether to back off for the throttle time reported by the
+    // brokers in produce and fetch responses
+    honor_throttle: bool,
+    // ~ whether to produce messages as an idempotent producer
+    idempotent: bool,
+    // ~ the max. time to spend producing or fetching messages
+    // including all retries; `None` for no limit
+    operation_deadline: Option<Duration>,
+    // ~ the age after which loaded metadata is refreshed before
+    // producing or fetching messages; zero to never refresh it
+    metadata_max_age: Duration,
 }
 
 // --------------------------------------------------------------------
 
 /// Possible values when querying a topic's offset.
-/// See `KafkaClient::fetch_offsets`.
+/// See `KafkaClient::fetch_offsets` and `Consumer::seek`.
 #[derive(Debug, Copy, Clone)]
 pub enum FetchOffset {
     /// Receive the earliest available offset.
@@ -135,6 +229,10 @@ pub enum FetchOffset {
     /// timestamp in milliseconds.
     /// See https://cwiki.apache.org/confluence/display/KAFKA/Writing+a+Driver+for+Kafka#WritingaDriverforKafka-Offsets
     ByTime(i64),
+    /// A particular, already known offset.  This does not require
+    /// asking Kafka; `KafkaClient::fetch_offsets` reports it as is for
+    /// each available partition.
+    Exact(i64),
 }
 
 impl FetchOffset {
@@ -142,7 +240,8 @@ impl FetchOffset {
         match *self {
             FetchOffset::Earliest => -2,
             FetchOffset::Latest => -1,
-            FetchOffset::ByTime(n) => n,
+            FetchOffset::ByTime(n) |
+            FetchOffset::Exact(n) => n,
         }
     }
 }
@@ -179,6 +278,20 @@ impl GroupOffsetStorage {
     }
 }
 
+/// Determines which messages written by transactional producers are
+/// fetched.  See `KafkaClient::set_isolation_level`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Fetch all messages including those of pending and aborted
+    /// transactions.
+    ReadUncommitted = 0,
+    /// Fetch only the messages of committed transactions (and those
+    /// written outside of transactions); messages are delivered only
+    /// up to the partitions' last stable offset, i.e. up to the
+    /// first pending transaction.  Available as of Kafka 0.11.
+    ReadCommitted = 1,
+}
+
 /// Data point identifying a topic partition to fetch a group's offset
 /// for.  See `KafkaClient::fetch_group_offsets`.
 #[derive(Debug)]
@@ -238,10 +351,245 @@ impl<'a> AsRef<CommitOffset<'a>> for CommitOffset<'a> {
 
 // --------------------------------------------------------------------
 
+/// The outcome of joining a consumer group.
+/// See `KafkaClient::join_group`.
+#[derive(Debug)]
+pub struct GroupMembership {
+    /// The generation of the group this membership is valid for.
+    pub generation_id: i32,
+    /// The id under which the group coordinator knows this member.
+    pub member_id: String,
+    /// The id of the member elected to compute the group's partition
+    /// assignment.
+    pub leader_id: String,
+    /// The partition assignment strategy selected by the group
+    /// coordinator.
+    pub protocol: String,
+    /// The members of the group along with their subscriptions.
+    /// This is available to the group leader only and is empty for
+    /// all other members.
+    pub members: Vec<GroupMember>,
+}
+
+impl GroupMembership {
+    /// Tells whether this member was elected the leader of the group
+    /// and is hence responsible for computing the group's partition
+    /// assignment.
+    pub fn is_leader(&self) -> bool {
+        self.member_id == self.leader_id
+    }
+}
+
+/// A member of a consumer group as reported to the group's leader.
+/// See `GroupMembership::members`.
+#[derive(Debug)]
+pub struct GroupMember {
+    /// The id of the member.
+    pub member_id: String,
+    /// The topics the member subscribes to.
+    pub topics: Vec<String>,
+}
+
+/// The topic partitions a group leader assigns to a member of its
+/// group.  See `KafkaClient::sync_group`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GroupAssignment {
+    /// The id of the member to receive the assignment.
+    pub member_id: String,
+    /// The assigned `(topic, partition)` pairs.
+    pub partitions: Vec<(String, i32)>,
+}
+
+/// The state of a consumer group as reported by its coordinator.
+/// See `KafkaClient::describe_groups`.
+#[derive(Debug)]
+pub struct GroupDescription {
+    /// The name of the group.
+    pub group: String,
+    /// The state of the group, e.g. "Stable", "PreparingRebalance",
+    /// "Empty", or "Dead" for a group which does not exist.
+    pub state: String,
+    /// The protocol type of the group, e.g. "consumer"; empty if the
+    /// group has no members.
+    pub protocol_type: String,
+    /// The partition assignment strategy selected for the group;
+    /// empty unless the group is stable.
+    pub protocol: String,
+    /// The current members of the group.
+    pub members: Vec<GroupMemberDescription>,
+}
+
+/// A member of a consumer group as reported by the group's
+/// coordinator.  See `GroupDescription::members`.
+#[derive(Debug)]
+pub struct GroupMemberDescription {
+    /// The id of the member.
+    pub member_id: String,
+    /// The client id the member joined the group with.
+    pub client_id: String,
+    /// The host the member joined the group from.
+    pub client_host: String,
+    /// The topics the member subscribes to.  Available only for
+    /// groups of the "consumer" protocol type.
+    pub topics: Vec<String>,
+    /// The `(topic, partition)` pairs assigned to the member.
+    /// Available only for groups of the "consumer" protocol type
+    /// which completed their rebalance.
+    pub assignment: Vec<(String, i32)>,
+}
+
+// --------------------------------------------------------------------
+
+/// Describes a topic to be created.
+/// See `KafkaClient::create_topics`.
+#[derive(Debug)]
+pub struct TopicSpec<'a> {
+    /// The name of the topic to create.
+    pub name: &'a str,
+    /// The number of partitions of the new topic.
+    pub partitions: i32,
+    /// The number of replicas of each partition of the new topic.
+    pub replication_factor: i16,
+    /// Topic level configuration entries as `(name, value)` pairs,
+    /// e.g. `("cleanup.policy", "compact")`.
+    pub configs: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> TopicSpec<'a> {
+    /// Describes a new topic without any explicit configuration.
+    pub fn new(name: &'a str, partitions: i32, replication_factor: i16) -> Self {
+        TopicSpec {
+            name: name,
+            partitions: partitions,
+            replication_factor: replication_factor,
+            configs: &[],
+        }
+    }
+
+    /// Sets the topic level configuration entries of the new topic.
+    pub fn with_configs(mut self, configs: &'a [(&'a str, &'a str)]) -> Self {
+        self.configs = configs;
+        self
+    }
+}
+
+/// The outcome of an administrative operation on a single topic.
+/// See `KafkaClient::create_topics` and `KafkaClient::delete_topics`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TopicResult {
+    /// The name of the topic.
+    pub topic: String,
+    /// `Ok` if the operation succeeded for the topic, otherwise the
+    /// error reported by Kafka, e.g. `KafkaCode::TopicAlreadyExists`
+    /// or `KafkaCode::InvalidTopic`.
+    pub result: std::result::Result<(), KafkaCode>,
+    /// The error code as reported by Kafka; `0` if the operation
+    /// succeeded.  Preserves codes unknown to this library which
+    /// `result` reports as `KafkaCode::Unknown`.
+    pub error_code: i16,
+}
+
+/// The kinds of resources whose configuration can be described and
+/// altered.  See `KafkaClient::describe_configs`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigResourceType {
+    /// A topic identified by its name.
+    Topic = 2,
+    /// A broker identified by its id, e.g. `"1"`.
+    Broker = 4,
+}
+
+/// The configuration of a single resource as reported by Kafka.
+/// See `KafkaClient::describe_configs`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigResource {
+    /// The type of the resource.
+    pub resource_type: ConfigResourceType,
+    /// The name of the resource.
+    pub name: String,
+    /// `Ok` if the configuration could be described, otherwise the
+    /// error reported by Kafka, e.g.
+    /// `KafkaCode::TopicAuthorizationFailed` or
+    /// `KafkaCode::ClusterAuthorizationFailed`.
+    pub result: std::result::Result<(), KafkaCode>,
+    /// The error code as reported by Kafka; `0` if the configuration
+    /// could be described.  Preserves codes unknown to this library
+    /// which `result` reports as `KafkaCode::Unknown`.
+    pub error_code: i16,
+    /// The configuration entries of the resource; empty if `result`
+    /// is an error.
+    pub entries: Vec<ConfigEntry>,
+}
+
+/// A single configuration entry of a resource.
+/// See `ConfigResource::entries`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfigEntry {
+    /// The name of the entry, e.g. `"retention.ms"`.
+    pub name: String,
+    /// The value of the entry; empty if the entry is sensitive.
+    pub value: String,
+    /// Whether the entry can not be altered.
+    pub read_only: bool,
+    /// Whether the entry has its default value.
+    pub is_default: bool,
+    /// Whether the entry holds sensitive data, e.g. a password.
+    pub sensitive: bool,
+}
+
+/// Describes the configuration to set for a single resource.
+/// See `KafkaClient::alter_configs`.
+#[derive(Debug)]
+pub struct ConfigChange<'a> {
+    /// The type of the resource to alter.
+    pub resource_type: ConfigResourceType,
+    /// The name of the resource to alter.
+    pub name: &'a str,
+    /// The configuration entries as `(name, value)` pairs.
+    pub configs: &'a [(&'a str, &'a str)],
+}
+
+impl<'a> ConfigChange<'a> {
+    /// Describes the configuration entries to set for the named
+    /// resource.
+    pub fn new(
+        resource_type: ConfigResourceType,
+        name: &'a str,
+        configs: &'a [(&'a str, &'a str)],
+    ) -> Self {
+        ConfigChange {
+            resource_type: resource_type,
+            name: name,
+            configs: configs,
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Counters describing the traffic of a `KafkaClient` since its
+/// creation or the last call to `KafkaClient::reset_stats`.  See
+/// `KafkaClient::stats`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ClientStats {
+    /// The number of bytes sent to brokers.
+    pub bytes_sent: u64,
+    /// The number of bytes received from brokers.
+    pub bytes_received: u64,
+    /// The number of sent produce requests.
+    pub produce_requests: u64,
+    /// The number of sent fetch requests.
+    pub fetch_requests: u64,
+    /// The number of messages delivered by fetch requests.
+    pub messages_fetched: u64,
+}
+
+// --------------------------------------------------------------------
+
 /// Possible choices on acknowledgement requirements when
 /// producing/sending messages to Kafka. See
 /// `KafkaClient::produce_messages`.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RequiredAcks {
     /// Indicates to the receiving Kafka broker not to acknowlegde
     /// messages sent to it at all. Sending messages with this
@@ -275,6 +623,17 @@ pub struct ProduceMessage<'a, 'b> {
     /// The partition (of the corresponding topic) to produce this
     /// message to.
     pub partition: i32,
+
+    /// The (create) timestamp of this message in milliseconds since
+    /// the unix epoch.  If `None`, the message is assigned the time
+    /// of its sending.  Transmitted only with the message formats v1
+    /// and v2; see `KafkaClient::set_message_version`.
+    pub timestamp: Option<i64>,
+
+    /// The headers - key/value pairs - of this message.  Transmitted
+    /// only with the message format v2; see
+    /// `KafkaClient::set_message_version`.
+    pub headers: &'b [(String, Vec<u8>)],
 }
 
 impl<'a, 'b> AsRef<ProduceMessage<'a, 'b>> for ProduceMessage<'a, 'b> {
@@ -297,8 +656,23 @@ impl<'a, 'b> ProduceMessage<'a, 'b> {
             value: value,
             topic: topic,
             partition: partition,
+            timestamp: None,
+            headers: &[],
         }
     }
+
+    /// Sets the (create) timestamp of this message in milliseconds
+    /// since the unix epoch.
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the headers of this message.
+    pub fn with_headers(mut self, headers: &'b [(String, Vec<u8>)]) -> Self {
+        self.headers = headers;
+        self
+    }
 }
 
 // --------------------------------------------------------------------
@@ -351,6 +725,48 @@ impl<'a> AsRef<FetchPartition<'a>> for FetchPartition<'a> {
     }
 }
 
+/// The messages fetched from a single topic partition.  See
+/// `KafkaClient::fetch_messages_multi`.  Unlike `fetch::Partition`
+/// this owns its data and is hence not bound to the lifetime of the
+/// fetch response it was delivered in.
+#[derive(Debug)]
+pub struct TopicMessages {
+    /// The topic the messages were fetched from.
+    pub topic: String,
+
+    /// The partition the messages were fetched from.
+    pub partition: i32,
+
+    /// The "high water mark offset" of the partition; see
+    /// `fetch::Data::highwatermark_offset`.  `-1` if fetching failed.
+    pub highwatermark_offset: i64,
+
+    /// Whether the fetched data ended with an incomplete message; see
+    /// `fetch::Data::is_truncated`.
+    pub truncated: bool,
+
+    /// The fetched messages, or the error reported for the partition.
+    /// Partitions without a currently known leader report
+    /// `KafkaCode::LeaderNotAvailable`, unknown ones
+    /// `KafkaCode::UnknownTopicOrPartition`.
+    pub messages: std::result::Result<Vec<FetchedMessage>, KafkaCode>,
+}
+
+/// A message delivered as part of `TopicMessages`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedMessage {
+    /// The offset at which the message resides in its partition.
+    pub offset: i64,
+    /// The "key" data of the message; empty if there is none.
+    pub key: Vec<u8>,
+    /// The value data of the message; empty if there is none.
+    pub value: Vec<u8>,
+    /// The timestamp of the message; see `fetch::Message::timestamp`.
+    pub timestamp: i64,
+    /// The headers of the message; see `fetch::Message::headers`.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
 /// A confirmation of messages sent back by the Kafka broker
 /// to confirm delivery of producer messages.
 #[derive(Debug)]
@@ -370,8 +786,20 @@ pub struct ProducePartitionConfirm {
     /// to this partition, or an error if one occurred.
     pub offset: std::result::Result<i64, KafkaCode>,
 
+    /// The error code as reported by Kafka; `0` if there was no
+    /// error.  Unlike `offset`, this preserves codes unknown to this
+    /// library which `offset` reports as `KafkaCode::Unknown`.
+    pub error_code: i16,
+
     /// The partition to which the message(s) were appended.
     pub partition: i32,
+
+    /// The time (in milliseconds since the unix epoch) at which the
+    /// broker appended the message(s) to the partition.  Available
+    /// only for topics configured with `LogAppendTime` as their
+    /// message timestamp type and if produced with the message
+    /// format v1 or newer (see `KafkaClient::set_message_version`.)
+    pub log_append_time: Option<i64>,
 }
 
 
@@ -381,6 +809,13 @@ impl KafkaClient {
     /// Creates a new instance of KafkaClient. Before being able to
     /// successfully use the new client, you'll have to load metadata.
     ///
+    /// The `hosts` are given as "host:port", with ipv6 literals
+    /// enclosed in brackets, e.g. "[::1]:9092".  A host resolving to
+    /// multiple addresses is connected to through the first reachable
+    /// of them.  If none of the hosts can be reached, loading metadata
+    /// fails with `ErrorKind::NoHostReachable` listing the tried hosts
+    /// and chaining the error of the last connection attempt.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -390,19 +825,29 @@ impl KafkaClient {
     pub fn new(hosts: Vec<String>) -> KafkaClient {
         KafkaClient {
             config: ClientConfig {
-                client_id: String::new(),
+                client_id: DEFAULT_CLIENT_ID.to_owned(),
                 hosts: hosts,
                 compression: DEFAULT_COMPRESSION,
+                compression_level: DEFAULT_COMPRESSION_LEVEL,
+                compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+                message_version: DEFAULT_MESSAGE_VERSION,
                 fetch_max_wait_time: protocol::to_millis_i32(
                     Duration::from_millis(DEFAULT_FETCH_MAX_WAIT_TIME_MILLIS),
                 ).expect("invalid default-fetch-max-time-millis"),
                 fetch_min_bytes: DEFAULT_FETCH_MIN_BYTES,
                 fetch_max_bytes_per_partition: DEFAULT_FETCH_MAX_BYTES_PER_PARTITION,
                 fetch_crc_validation: DEFAULT_FETCH_CRC_VALIDATION,
+                max_message_set_depth: DEFAULT_MAX_MESSAGE_SET_DEPTH,
+                isolation_level: DEFAULT_ISOLATION_LEVEL,
                 offset_fetch_version: DEFAULT_GROUP_OFFSET_STORAGE.offset_fetch_version(),
                 offset_commit_version: DEFAULT_GROUP_OFFSET_STORAGE.offset_commit_version(),
                 retry_backoff_time: Duration::from_millis(DEFAULT_RETRY_BACKOFF_TIME_MILLIS),
                 retry_max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+                max_leader_retries: DEFAULT_MAX_LEADER_RETRIES,
+                honor_throttle: DEFAULT_HONOR_THROTTLE,
+                idempotent: DEFAULT_IDEMPOTENT,
+                operation_deadline: None,
+                metadata_max_age: Duration::from_millis(DEFAULT_METADATA_MAX_AGE_MILLIS),
             },
             conn_pool: network::Connections::new(
                 default_conn_rw_timeout(),
@@ -457,19 +902,29 @@ impl KafkaClient {
     pub fn new_secure(hosts: Vec<String>, security: SecurityConfig) -> KafkaClient {
         KafkaClient {
             config: ClientConfig {
-                client_id: String::new(),
+                client_id: DEFAULT_CLIENT_ID.to_owned(),
                 hosts: hosts,
                 compression: DEFAULT_COMPRESSION,
+                compression_level: DEFAULT_COMPRESSION_LEVEL,
+                compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+                message_version: DEFAULT_MESSAGE_VERSION,
                 fetch_max_wait_time: protocol::to_millis_i32(
                     Duration::from_millis(DEFAULT_FETCH_MAX_WAIT_TIME_MILLIS),
                 ).expect("invalid default-fetch-max-time-millis"),
                 fetch_min_bytes: DEFAULT_FETCH_MIN_BYTES,
                 fetch_max_bytes_per_partition: DEFAULT_FETCH_MAX_BYTES_PER_PARTITION,
                 fetch_crc_validation: DEFAULT_FETCH_CRC_VALIDATION,
+                max_message_set_depth: DEFAULT_MAX_MESSAGE_SET_DEPTH,
+                isolation_level: DEFAULT_ISOLATION_LEVEL,
                 offset_fetch_version: DEFAULT_GROUP_OFFSET_STORAGE.offset_fetch_version(),
                 offset_commit_version: DEFAULT_GROUP_OFFSET_STORAGE.offset_commit_version(),
                 retry_backoff_time: Duration::from_millis(DEFAULT_RETRY_BACKOFF_TIME_MILLIS),
                 retry_max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+                max_leader_retries: DEFAULT_MAX_LEADER_RETRIES,
+                honor_throttle: DEFAULT_HONOR_THROTTLE,
+                idempotent: DEFAULT_IDEMPOTENT,
+                operation_deadline: None,
+                metadata_max_age: Duration::from_millis(DEFAULT_METADATA_MAX_AGE_MILLIS),
             },
             conn_pool: network::Connections::new_with_security(
                 default_conn_rw_timeout(),
@@ -489,11 +944,23 @@ impl KafkaClient {
     }
 
     /// Sets the client_id to be sent along every request to the
-    /// remote Kafka brokers.  By default, this value is the empty
-    /// string.
+    /// remote Kafka brokers.  By default, this value is
+    /// `DEFAULT_CLIENT_ID`.
     ///
     /// Kafka brokers write out this client id to their
-    /// request/response trace log - if configured appropriately.
+    /// request/response trace log - if configured appropriately - and
+    /// use it to attribute traffic to quotas.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kafka::client::{KafkaClient, DEFAULT_CLIENT_ID};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// assert_eq!(DEFAULT_CLIENT_ID, client.client_id());
+    /// client.set_client_id("my-app".to_owned());
+    /// assert_eq!("my-app", client.client_id());
+    /// ```
     pub fn set_client_id(&mut self, client_id: String) {
         self.config.client_id = client_id;
     }
@@ -525,14 +992,130 @@ impl KafkaClient {
         self.config.compression
     }
 
+    /// Sets the level at which to compress messages sent out with
+    /// `Compression::GZIP`; ranging from `0` (no compression) to `9`
+    /// (best compression.)  Lower levels trade compression ratio for
+    /// less CPU time spent.  Levels out of this range are rejected
+    /// with `ErrorKind::CodecError`.
+    ///
+    /// Note: this affects only sending messages; decompressing
+    /// fetched messages is independent of the level they were
+    /// compressed with.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kafka::client::{Compression, KafkaClient};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_compression(Compression::GZIP);
+    /// client.set_compression_level(1).unwrap();
+    /// ```
+    pub fn set_compression_level(&mut self, level: u32) -> Result<()> {
+        if level > 9 {
+            bail!(ErrorKind::CodecError);
+        }
+        self.config.compression_level = level;
+        Ok(())
+    }
+
+    /// Retrieves the current `KafkaClient::set_compression_level`
+    /// setting.
+    #[inline]
+    pub fn compression_level(&self) -> u32 {
+        self.config.compression_level
+    }
+
+    /// Sets the size in bytes the uncompressed messages sent to a
+    /// single partition must exceed to get compressed with the
+    /// configured compression method.  Smaller message sets are sent
+    /// uncompressed, sparing the overhead of compressing data which
+    /// hardly benefits from it.  The default,
+    /// `DEFAULT_COMPRESSION_THRESHOLD`, compresses all message sets.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kafka::client::{Compression, KafkaClient};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_compression(Compression::GZIP);
+    /// client.set_compression_threshold(1024);
+    /// ```
+    pub fn set_compression_threshold(&mut self, bytes: usize) {
+        self.config.compression_threshold = bytes;
+    }
+
+    /// Retrieves the current `KafkaClient::set_compression_threshold`
+    /// setting.
+    #[inline]
+    pub fn compression_threshold(&self) -> usize {
+        self.config.compression_threshold
+    }
+
+    /// Sets the format version of produced and fetched messages;
+    /// either `0` (the default), `1`, or `2`.  The version `1`
+    /// requires Kafka 0.10 or newer and adds timestamps to messages
+    /// (see `ProduceMessage::timestamp` and
+    /// `fetch::Message::timestamp`.)  The version `2` - record
+    /// batches - requires Kafka 0.11 or newer and additionally adds
+    /// headers to messages (see `ProduceMessage::headers` and
+    /// `fetch::Message::headers`.)  Fails with
+    /// `ErrorKind::UnsupportedProtocol` for any other version.
+    ///
+    /// Brokers convert messages stored in a newer format down to the
+    /// configured one when delivering them, dropping the information
+    /// the older format cannot carry.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_message_version(2).unwrap();
+    /// assert_eq!(2, client.message_version());
+    /// ```
+    pub fn set_message_version(&mut self, version: i8) -> Result<()> {
+        if !(0..=2).contains(&version) {
+            bail!(ErrorKind::UnsupportedProtocol);
+        }
+        self.config.message_version = version;
+        Ok(())
+    }
+
+    /// Retrieves the current `KafkaClient::set_message_version`
+    /// setting.
+    #[inline]
+    pub fn message_version(&self) -> i8 {
+        self.config.message_version
+    }
+
     /// Sets the maximum time in milliseconds to wait for insufficient
     /// data to become available when fetching messages.
     ///
+    /// Brokers hold back a fetch response for up to this time.  If
+    /// the configured socket timeout (see
+    /// `KafkaClient::set_socket_timeout`) does not exceed it, the
+    /// socket timeout is extended to `max_wait_time` plus
+    /// `FETCH_MAX_WAIT_TIME_MARGIN_MILLIS` such that such long polls
+    /// do not fail with a timed out read.
+    ///
     /// See also `KafkaClient::set_fetch_min_bytes(..)` and
     /// `KafkaClient::set_fetch_max_bytes_per_partition(..)`.
     #[inline]
     pub fn set_fetch_max_wait_time(&mut self, max_wait_time: Duration) -> Result<()> {
         self.config.fetch_max_wait_time = try!(protocol::to_millis_i32(max_wait_time));
+        let rw_timeout = self.conn_pool.rw_timeout();
+        let long_poll_timeout = __long_poll_rw_timeout(rw_timeout, max_wait_time);
+        if long_poll_timeout != rw_timeout {
+            debug!(
+                "Extending socket timeout to {:?} for a fetch max wait time of {:?}",
+                long_poll_timeout,
+                max_wait_time
+            );
+            self.conn_pool.set_rw_timeout(long_poll_timeout);
+        }
         Ok(())
     }
 
@@ -618,7 +1201,9 @@ impl KafkaClient {
     ///
     /// This ensures detection of on-the-wire or on-disk corruption to
     /// fetched messages.  This check adds some overhead, so it may be
-    /// disabled in cases seeking extreme performance.
+    /// disabled in cases seeking extreme performance.  A corrupted
+    /// message fails the fetch with `ErrorKind::CrcMismatch`
+    /// identifying the message's offset.
     #[inline]
     pub fn set_fetch_crc_validation(&mut self, validate_crc: bool) {
         self.config.fetch_crc_validation = validate_crc;
@@ -631,6 +1216,46 @@ impl KafkaClient {
         self.config.fetch_crc_validation
     }
 
+    /// Specifies how many levels of compressed message sets may be
+    /// nested within each other in a fetch response.
+    ///
+    /// A compressed message wraps a message set of its own; producers
+    /// normally nest at most one level, but a malicious or broken
+    /// broker could send arbitrarily deep nestings.  A fetch response
+    /// exceeding this limit fails to decode with
+    /// `ErrorKind::CodecError`.  A value of `0` rejects compressed
+    /// messages altogether.
+    #[inline]
+    pub fn set_max_message_set_depth(&mut self, depth: u32) {
+        self.config.max_message_set_depth = depth;
+    }
+
+    /// Retrieves the current `KafkaClient::set_max_message_set_depth`
+    /// setting.
+    #[inline]
+    pub fn max_message_set_depth(&self) -> u32 {
+        self.config.max_message_set_depth
+    }
+
+    /// Specifies which messages written by transactional producers
+    /// to fetch; see `IsolationLevel`.
+    ///
+    /// Note: brokers respect the isolation level only if messages are
+    /// fetched in the message format v2 (see
+    /// `KafkaClient::set_message_version`); older formats always
+    /// read uncommitted.
+    #[inline]
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.config.isolation_level = isolation_level;
+    }
+
+    /// Retrieves the current `KafkaClient::set_isolation_level`
+    /// setting.
+    #[inline]
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.config.isolation_level
+    }
+
     /// Specifies the group offset storage to address when fetching or
     /// committing group offsets.
     ///
@@ -693,6 +1318,143 @@ impl KafkaClient {
         self.config.retry_max_attempts
     }
 
+    /// Specifies how many times messages which Kafka refused to
+    /// accept due to the client's outdated knowledge of partition
+    /// leaders (i.e. `NotLeaderForPartition`, `LeaderNotAvailable`,
+    /// or `UnknownTopicOrPartition`) will be re-sent after
+    /// transparently refreshing the metadata of the affected topics.
+    /// Zero disables this behavior.
+    ///
+    /// The same applies to topic partitions which fail to be fetched
+    /// through `KafkaClient::fetch_messages` or
+    /// `KafkaClient::fetch_messages_multi` (and hence through
+    /// `Consumer`) with `NotLeaderForPartition` or
+    /// `LeaderNotAvailable`: these are re-fetched from their new
+    /// leaders.
+    #[inline]
+    pub fn set_max_leader_retries(&mut self, retries: u32) {
+        self.config.max_leader_retries = retries;
+    }
+
+    /// Retrieves the current `KafkaClient::set_max_leader_retries`
+    /// setting.
+    #[inline]
+    pub fn max_leader_retries(&self) -> u32 {
+        self.config.max_leader_retries
+    }
+
+    /// Specifies whether `KafkaClient::fetch_messages` and
+    /// `KafkaClient::produce_messages` shall honor the throttle time
+    /// reported by brokers enforcing client quotas.  If enabled, these
+    /// methods sleep for the longest throttle time reported in their
+    /// responses before returning, thereby backing off instead of
+    /// getting throttled even harder.
+    ///
+    /// Note: brokers report a throttle time only to clients speaking
+    /// version 1 or newer of the produce and fetch apis, i.e. when
+    /// using a message format newer than v0 (see
+    /// `KafkaClient::set_message_version`.)
+    #[inline]
+    pub fn set_honor_throttle(&mut self, honor_throttle: bool) {
+        self.config.honor_throttle = honor_throttle;
+    }
+
+    /// Retrieves the current `KafkaClient::set_honor_throttle`
+    /// setting.
+    #[inline]
+    pub fn honor_throttle(&self) -> bool {
+        self.config.honor_throttle
+    }
+
+    /// Specifies whether `KafkaClient::produce_messages` (and hence
+    /// `Producer`) shall act as an idempotent producer.  If enabled,
+    /// the client obtains a producer id from the brokers and numbers
+    /// the messages sent to each partition such that the brokers
+    /// discard duplicates of messages re-sent after a failure, e.g.
+    /// after a moved partition leader.  Supported by brokers as of
+    /// Kafka 0.11.
+    ///
+    /// Idempotence requires the message format v2 (see
+    /// `KafkaClient::set_message_version`) and `RequiredAcks::All`;
+    /// producing otherwise fails with `ErrorKind::InvalidRequest`.
+    /// Messages the brokers recognize as duplicates are confirmed
+    /// with an offset of `-1`.  Upon any other failure the client
+    /// obtains a new producer id before producing further messages.
+    ///
+    /// Note: this does not prevent duplicates caused by the
+    /// application re-sending messages itself.
+    #[inline]
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.config.idempotent = idempotent;
+        self.state.set_producer_id(None);
+    }
+
+    /// Retrieves the current `KafkaClient::set_idempotent` setting.
+    #[inline]
+    pub fn idempotent(&self) -> bool {
+        self.config.idempotent
+    }
+
+    /// Bounds the total time `KafkaClient::produce_messages` and
+    /// `KafkaClient::fetch_messages` (and hence `Producer` and
+    /// `Consumer`) may take, including establishing connections,
+    /// refreshing metadata, and re-sending messages to moved
+    /// partition leaders.  Once the deadline passes, these methods
+    /// fail with `ErrorKind::DeadlineExceeded` (chaining the error
+    /// of the interrupted network operation, if any.)  The socket
+    /// timeout (see `KafkaClient::set_socket_timeout`) still applies
+    /// to the individual reads and writes.  `None` - the default -
+    /// imposes no such bound.
+    ///
+    /// Note: an interrupted produce request may still get processed
+    /// by the brokers.
+    #[inline]
+    pub fn set_operation_deadline(&mut self, deadline: Option<Duration>) {
+        self.config.operation_deadline = deadline;
+    }
+
+    /// Retrieves the current `KafkaClient::set_operation_deadline`
+    /// setting.
+    #[inline]
+    pub fn operation_deadline(&self) -> Option<Duration> {
+        self.config.operation_deadline
+    }
+
+    /// Specifies the max. age of the loaded metadata.  Once exceeded,
+    /// `KafkaClient::produce_messages` and
+    /// `KafkaClient::fetch_messages` (and hence `Producer` and
+    /// `Consumer`) first reload the metadata of all the topics known
+    /// to the client, reducing the time requests get routed to
+    /// partition leaders which moved in the meantime.  A failure to
+    /// reload the metadata fails the produce or fetch call.
+    ///
+    /// A value of zero - the default - disables the automatic
+    /// refresh; metadata is then reloaded only upon explicit calls
+    /// to `KafkaClient::load_metadata` (or when producing to moved
+    /// partition leaders; see `KafkaClient::set_max_leader_retries`.)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_metadata_max_age(Duration::from_secs(300));
+    /// assert_eq!(Duration::from_secs(300), client.metadata_max_age());
+    /// ```
+    #[inline]
+    pub fn set_metadata_max_age(&mut self, max_age: Duration) {
+        self.config.metadata_max_age = max_age;
+    }
+
+    /// Retrieves the current `KafkaClient::set_metadata_max_age`
+    /// setting.
+    #[inline]
+    pub fn metadata_max_age(&self) -> Duration {
+        self.config.metadata_max_age
+    }
+
     /// Specifies the timeout after which idle connections will
     /// transparently be closed/re-established by `KafkaClient`.
     ///
@@ -711,31 +1473,347 @@ impl KafkaClient {
         self.conn_pool.idle_timeout()
     }
 
-    /// Provides a view onto the currently loaded metadata of known .
+    /// Sets the read and write timeout of the sockets connected to the
+    /// Kafka brokers.  A request to a broker which does not respond
+    /// within this time fails with an `Io` error of kind
+    /// `TimedOut` (see also `Error::is_retriable`.)  `None` disables
+    /// the timeout such that requests may block indefinitely.
+    ///
+    /// By default, this is `DEFAULT_CONNECTION_RW_TIMEOUT_SECS`
+    /// seconds.  The setting applies to already established as well
+    /// as to future connections.
     ///
     /// # Examples
-    /// ```no_run
+    ///
+    /// ```
+    /// use std::time::Duration;
     /// use kafka::client::KafkaClient;
-    /// use kafka::client::metadata::Broker;
     ///
-    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
-    /// client.load_metadata_all().unwrap();
-    /// for topic in client.topics() {
-    ///   for partition in topic.partitions() {
-    ///     println!("{} #{} => {}", topic.name(), partition.id(),
-    ///              partition.leader()
-    ///                       .map(Broker::host)
-    ///                       .unwrap_or("no-leader!"));
-    ///   }
-    /// }
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_socket_timeout(Some(Duration::from_secs(10)));
+    /// assert_eq!(Some(Duration::from_secs(10)), client.socket_timeout());
     /// ```
     #[inline]
-    pub fn topics(&self) -> metadata::Topics {
-        metadata::Topics::new(self)
+    pub fn set_socket_timeout(&mut self, timeout: Option<Duration>) {
+        self.conn_pool.set_rw_timeout(timeout);
     }
 
-    /// Resets and loads metadata for all topics from the underlying
-    /// brokers.
+    /// Retrieves the current `KafkaClient::set_socket_timeout` setting.
+    #[inline]
+    pub fn socket_timeout(&self) -> Option<Duration> {
+        self.conn_pool.rw_timeout()
+    }
+
+    /// Specifies whether broker connections are established with
+    /// `TCP_NODELAY`, i.e. with Nagle's algorithm disabled.
+    ///
+    /// By default, this is `DEFAULT_NODELAY` such that (small)
+    /// requests are sent without delay, favoring latency.  Disabling
+    /// it lets the operating system coalesce small writes into fewer
+    /// packets, which may improve the throughput of workloads sending
+    /// many small requests at the cost of their latency.  The setting
+    /// applies only to connections established afterwards.
+    #[inline]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.conn_pool.set_nodelay(nodelay);
+    }
+
+    /// Retrieves the current `KafkaClient::set_nodelay` setting.
+    #[inline]
+    pub fn nodelay(&self) -> bool {
+        self.conn_pool.nodelay()
+    }
+
+    /// Enables TCP keep-alive (`SO_KEEPALIVE`) on broker connections
+    /// such that dead peers get detected even on otherwise idle
+    /// connections.  The given duration specifies the time a
+    /// connection must be idle before the first keep-alive probe is
+    /// sent.  `None`, the default, disables keep-alive.  The setting
+    /// applies only to connections established afterwards.
+    ///
+    /// Note: Keep-alive is supported on unix platforms only; the idle
+    /// time is honored on Linux, Android, FreeBSD, DragonFly, macOS,
+    /// and iOS (in whole seconds) while other unix platforms apply the
+    /// system's default.  Elsewhere, connecting to brokers fails with
+    /// keep-alive enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_keepalive(Some(Duration::from_secs(60)));
+    /// assert_eq!(Some(Duration::from_secs(60)), client.keepalive());
+    /// ```
+    #[inline]
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) {
+        self.conn_pool.set_keepalive(keepalive);
+    }
+
+    /// Retrieves the current `KafkaClient::set_keepalive` setting.
+    #[inline]
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.conn_pool.keepalive()
+    }
+
+    /// Sets the function mapping the host name and port of a broker
+    /// to the address to connect to.  It is consulted whenever the
+    /// client is about to connect to a broker - be it one of the
+    /// bootstrap hosts or a broker advertised through the cluster's
+    /// metadata - and allows reaching brokers whose advertised
+    /// listeners aren't reachable as they are, e.g. through ssh
+    /// tunnels or service meshes.  The advertised host name is still
+    /// used to verify the broker's certificate (see
+    /// `SecurityConfig::with_hostname_verification`.)  By default,
+    /// hosts are resolved through the system's resolver.  The setting
+    /// applies only to connections established afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::net::SocketAddr;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// // ~ reach all brokers through local port forwardings
+    /// client.set_address_resolver(Box::new(|_host: &str, port: i32| {
+    ///     SocketAddr::from(([127, 0, 0, 1], port as u16 + 10000))
+    /// }));
+    /// ```
+    #[inline]
+    pub fn set_address_resolver(&mut self, resolver: Box<dyn Fn(&str, i32) -> SocketAddr + Send>) {
+        self.conn_pool.set_address_resolver(Some(network::AddressResolver::new(resolver)));
+    }
+
+    /// Sets the max. size in bytes of a response the client is willing
+    /// to receive from a broker.  Responses declaring a larger size -
+    /// typically the result of a corrupted connection or a
+    /// misbehaving peer - are rejected with
+    /// `ErrorKind::ResponseTooLarge` rather than making the client
+    /// attempt to allocate the declared amount of memory.  The
+    /// connection the response was to be read from is closed; the
+    /// next request to the broker establishes a new one.
+    ///
+    /// By default, this is `DEFAULT_MAX_RESPONSE_SIZE` bytes.  Make
+    /// sure the limit accommodates the largest expected fetch
+    /// response, i.e. `fetch_max_bytes_per_partition` times the
+    /// number of partitions fetched in one go.  The setting applies
+    /// to already established as well as to future connections.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_max_response_size(16 * 1024 * 1024);
+    /// assert_eq!(16 * 1024 * 1024, client.max_response_size());
+    /// ```
+    #[inline]
+    pub fn set_max_response_size(&mut self, bytes: usize) {
+        self.conn_pool.set_max_response_size(bytes);
+    }
+
+    /// Retrieves the current `KafkaClient::set_max_response_size`
+    /// setting.
+    #[inline]
+    pub fn max_response_size(&self) -> usize {
+        self.conn_pool.max_response_size()
+    }
+
+    /// Enables logging a hex dump of every request sent to and every
+    /// response received from the brokers - along with the api key,
+    /// the api version, and the correlation id of the request - at
+    /// the trace level of the `log` crate.  This is meant to help
+    /// diagnosing protocol issues, e.g. `ErrorKind::CodecError`s; the
+    /// dumps include all message data.  The exchanges authenticating
+    /// a connection (see `KafkaClient::set_sasl_config`) are never
+    /// dumped.  Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_wire_debug(true);
+    /// assert!(client.wire_debug());
+    /// ```
+    #[inline]
+    pub fn set_wire_debug(&mut self, wire_debug: bool) {
+        self.conn_pool.set_wire_debug(wire_debug);
+    }
+
+    /// Retrieves the current `KafkaClient::set_wire_debug` setting.
+    #[inline]
+    pub fn wire_debug(&self) -> bool {
+        self.conn_pool.wire_debug()
+    }
+
+    /// Sets the SASL mechanism and credentials with which to
+    /// authenticate the connections to the brokers; `None` disables
+    /// authentication (the default.)  Besides `PLAIN`, the
+    /// `SCRAM-SHA-256` and `SCRAM-SHA-512` mechanisms are supported
+    /// with the `security` feature enabled.
+    ///
+    /// The setting applies to connections established from now on;
+    /// already established connections are closed.  Establishing a
+    /// connection fails with `ErrorKind::AuthenticationFailed` if the
+    /// broker rejects the credentials.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, SaslConfig};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.set_sasl_config(Some(SaslConfig::plain("alice", "alice-secret")));
+    /// client.load_metadata_all().unwrap();
+    /// ```
+    pub fn set_sasl_config(&mut self, sasl_config: Option<SaslConfig>) {
+        self.conn_pool.set_sasl_config(sasl_config);
+    }
+
+    /// Retrieves the current `KafkaClient::set_sasl_config` setting.
+    #[inline]
+    pub fn sasl_config(&self) -> Option<&SaslConfig> {
+        self.conn_pool.sasl_config()
+    }
+
+    /// Provides a view onto the currently loaded metadata of known .
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    /// use kafka::client::metadata::Broker;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// for topic in client.topics() {
+    ///   for partition in topic.partitions() {
+    ///     println!("{} #{} => {}", topic.name(), partition.id(),
+    ///              partition.leader()
+    ///                       .map(Broker::host)
+    ///                       .unwrap_or("no-leader!"));
+    ///   }
+    /// }
+    /// ```
+    #[inline]
+    pub fn topics(&self) -> metadata::Topics {
+        metadata::Topics::new(self)
+    }
+
+    /// Retrieves a snapshot of this client's traffic counters.  The
+    /// counters are maintained at virtually no cost; they can be
+    /// polled periodically and e.g. exported to a monitoring system.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// let stats = client.stats();
+    /// println!("sent: {} bytes, received: {} bytes", stats.bytes_sent, stats.bytes_received);
+    /// ```
+    #[inline]
+    pub fn stats(&self) -> ClientStats {
+        *self.conn_pool.stats()
+    }
+
+    /// Resets all counters of `KafkaClient::stats` to zero.
+    pub fn reset_stats(&mut self) {
+        *self.conn_pool.stats_mut() = ClientStats::default();
+    }
+
+    /// Closes all connections to the brokers and consumes the client.
+    ///
+    /// Unlike merely dropping the client, this reports failures to
+    /// shut down the connections.  All connections are closed even if
+    /// some of them fail to; the first failure is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// // ~ ... use the client ...
+    /// client.close().unwrap();
+    /// ```
+    pub fn close(mut self) -> Result<()> {
+        self.conn_pool.close_all()
+    }
+
+    /// Retrieves the leader, replicas, and in-sync replicas of each
+    /// partition of the specified topic - ordered by partition id -
+    /// as of the last loaded metadata.  Returns `None` if no metadata
+    /// for the topic has been loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// for p in client.topic_metadata("my-topic").unwrap_or_default() {
+    ///   if p.is_under_replicated() {
+    ///     println!("#{} is under-replicated: isr {:?} of {:?}", p.id, p.isr, p.replicas);
+    ///   }
+    /// }
+    /// ```
+    pub fn topic_metadata(&self, topic: &str) -> Option<Vec<metadata::PartitionMetadata>> {
+        self.topics().partitions(topic).map(|ps| ps.iter().map(|p| p.metadata()).collect())
+    }
+
+    /// Provides the brokers of the Kafka cluster as discovered
+    /// through the so far loaded metadata.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// for broker in client.brokers() {
+    ///   println!("broker #{} => {}", broker.id(), broker.host());
+    /// }
+    /// ```
+    #[inline]
+    pub fn brokers(&self) -> &[metadata::Broker] {
+        self.state.brokers()
+    }
+
+    /// Provides the controller of the Kafka cluster as last
+    /// discovered through `KafkaClient::load_controller` or any of
+    /// the administrative operations routed to it (e.g.
+    /// `KafkaClient::create_topics`.)  `None` if not yet discovered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_controller().unwrap();
+    /// if let Some(broker) = client.controller() {
+    ///   println!("controller: #{} => {}", broker.id(), broker.host());
+    /// }
+    /// ```
+    #[inline]
+    pub fn controller(&self) -> Option<&metadata::Broker> {
+        self.state.controller()
+    }
+
+    /// Determines the current controller of the Kafka cluster by
+    /// requesting metadata (v1) from the first reachable of the
+    /// underlying brokers; see `KafkaClient::controller`.  Requires
+    /// Kafka 0.10 or newer.
+    ///
+    /// Fails with `ErrorKind::Kafka(KafkaCode::NotController)` - a
+    /// retriable error - if no controller is currently elected.
+    pub fn load_controller(&mut self) -> Result<()> {
+        self.find_controller().map(|_| ())
+    }
+
+    /// Resets and loads metadata for all topics from the underlying
+    /// brokers.
     ///
     /// # Examples
     ///
@@ -791,38 +1869,210 @@ impl KafkaClient {
         self.state.clear_metadata();
     }
 
+    /// Fetches the names of all topics available on the underlying
+    /// brokers.  The names are delivered sorted and free of
+    /// duplicates.
+    ///
+    /// Note: unlike `load_metadata_all` this does not alter the
+    /// metadata stored in the client.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// let topics = client.list_topics().unwrap();
+    /// println!("my-topic exists: {}", topics.iter().any(|t| t == "my-topic"));
+    /// ```
+    pub fn list_topics(&mut self) -> Result<Vec<String>> {
+        let resp = try!(self.fetch_metadata::<&str>(&[]));
+        let mut names: Vec<String> = resp.topics.into_iter().map(|t| t.topic).collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Determines the number of partitions of the specified topic;
+    /// `None` if the topic does not exist.  Consults the loaded
+    /// metadata first and refreshes the topic's metadata only if it
+    /// is not known to this client yet.
+    ///
+    /// Note: unlike `load_metadata` this never causes the brokers to
+    /// automatically create the topic.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// match client.topic_partition_count("my-topic").unwrap() {
+    ///   Some(n) if n >= 3 => println!("my-topic is ready"),
+    ///   Some(n) => println!("my-topic has only {} partitions", n),
+    ///   None => println!("my-topic does not exist"),
+    /// }
+    /// ```
+    pub fn topic_partition_count(&mut self, topic: &str) -> Result<Option<i32>> {
+        if let Some(n) = self.known_partition_count(topic) {
+            return Ok(Some(n));
+        }
+        // ~ request the metadata of all topics; explicitly requesting
+        // the topic's metadata might have the brokers auto-create it
+        let mut resp = try!(self.fetch_metadata::<&str>(&[]));
+        resp.topics.retain(|t| t.topic == topic);
+        try!(self.state.merge_metadata(resp));
+        Ok(self.known_partition_count(topic))
+    }
+
+    // ~ the number of partitions of the given topic as of the loaded
+    // metadata; a topic loaded without partitions (e.g. reported as
+    // unknown by the brokers) is considered not known
+    fn known_partition_count(&self, topic: &str) -> Option<i32> {
+        match self.state.partitions_for(topic) {
+            Some(tps) if !tps.is_empty() => Some(tps.len() as i32),
+            _ => None,
+        }
+    }
+
     /// Fetches metadata about the specified topics from all of the
-    /// underlying brokers (`self.hosts`).
+    /// underlying brokers (`self.hosts`).  Upon the first call, the
+    /// api versions supported by the brokers are determined first
+    /// (see `KafkaClient::api_versions`.)
     fn fetch_metadata<T: AsRef<str>>(
         &mut self,
         topics: &[T],
     ) -> Result<protocol::MetadataResponse> {
+        self.negotiate_api_versions();
+        let api_version = self.state
+            .api_versions()
+            .and_then(|versions| {
+                let ours = (0, protocol::metadata::MAX_API_VERSION);
+                protocol::api_versions::select_version(versions, protocol::API_KEY_METADATA, ours)
+            })
+            .unwrap_or(0);
+        let parser = protocol::metadata::ResponseParser { api_version: api_version };
         let correlation = self.state.next_correlation_id();
         let now = Instant::now();
+        let mut failed = Vec::new();
 
         for host in &self.config.hosts {
-            debug!("fetch_metadata: requesting metadata from {}", host);
+            debug!("fetch_metadata: requesting metadata (v{}) from {}", api_version, host);
             match self.conn_pool.get_conn(host, now) {
-                Ok(conn) => {
+                Ok(mut conn) => {
                     let req =
-                        protocol::MetadataRequest::new(correlation, &self.config.client_id, topics);
-                    match __send_request(conn, req) {
-                        Ok(_) => return __get_response::<protocol::MetadataResponse>(conn),
+                        protocol::MetadataRequest::new(correlation, &self.config.client_id, topics)
+                            .with_api_version(api_version);
+                    match __send_request(&mut conn, req) {
+                        Ok(cid) => {
+                            let r = __z_get_response(&mut conn, cid, &parser);
+                            return __close_desynced(&mut self.conn_pool, host, r);
+                        }
                         Err(e) => {
                             debug!(
                                 "fetch_metadata: failed to request metadata from {}: {}",
                                 host,
                                 e
-                            )
+                            );
+                            failed.push((host.clone(), e));
                         }
                     }
                 }
                 Err(e) => {
                     debug!("fetch_metadata: failed to connect to {}: {}", host, e);
+                    failed.push((host.clone(), e));
+                }
+            }
+        }
+        Err(__no_host_reachable(failed))
+    }
+
+    /// Determines the versions of the Kafka APIs supported by the
+    /// underlying brokers.  Returns a mapping of api key to the
+    /// inclusive range `(min, max)` of the versions supported for
+    /// that api.  Requires Kafka 0.10 or newer.
+    ///
+    /// The result is remembered by this client and from then on
+    /// used to choose the versions of the metadata, produce, and
+    /// fetch requests: each is sent in the newest version supported
+    /// by both, the brokers and this library; for produce and fetch
+    /// requests, the configured message format (see
+    /// `KafkaClient::set_message_version`) is downgraded as necessary
+    /// to the newest one supported by the brokers.
+    ///
+    /// Unless called explicitly, the api versions are determined
+    /// implicitly upon loading metadata for the first time.  If that
+    /// fails - brokers older than Kafka 0.10 do not support the
+    /// request - the client falls back to the oldest versions of the
+    /// metadata api and to the configured message format without
+    /// trying again.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// let versions = client.api_versions().unwrap();
+    /// // ~ the version range of the produce api
+    /// println!("produce: {:?}", versions.get(&0));
+    /// ```
+    pub fn api_versions(&mut self) -> Result<HashMap<i16, (i16, i16)>> {
+        if let Some(versions) = self.state.api_versions() {
+            return Ok(versions.clone());
+        }
+        let versions = try!(try!(self.fetch_api_versions()).into_versions());
+        self.state.set_api_versions(versions.clone());
+        Ok(versions)
+    }
+
+    /// Determines the supported api versions unless known or already
+    /// attempted to determine upon loading metadata; failures are
+    /// only logged.
+    fn negotiate_api_versions(&mut self) {
+        if self.state.api_versions().is_some() || self.state.api_versions_requested() {
+            return;
+        }
+        self.state.set_api_versions_requested();
+        if let Err(e) = self.api_versions() {
+            debug!("negotiate_api_versions: using the default api versions: {}", e);
+        }
+    }
+
+    /// Fetches the supported api versions from the first reachable
+    /// of the underlying brokers (`self.hosts`).
+    fn fetch_api_versions(&mut self) -> Result<protocol::ApiVersionsResponse> {
+        let correlation = self.state.next_correlation_id();
+        let now = Instant::now();
+        let mut failed = Vec::new();
+
+        for host in &self.config.hosts {
+            debug!("fetch_api_versions: requesting api versions from {}", host);
+            match self.conn_pool.get_conn(host, now) {
+                Ok(mut conn) => {
+                    let req =
+                        protocol::ApiVersionsRequest::new(correlation, &self.config.client_id);
+                    match __send_request(&mut conn, req) {
+                        Ok(cid) => {
+                            let r = __get_response::<protocol::ApiVersionsResponse>(&mut conn, cid);
+                            // ~ brokers not supporting the request close
+                            // the connection
+                            if r.is_err() {
+                                self.conn_pool.close_conn(host);
+                            }
+                            return r;
+                        }
+                        Err(e) => {
+                            debug!(
+                                "fetch_api_versions: failed to request api versions from {}: {}",
+                                host,
+                                e
+                            );
+                            failed.push((host.clone(), e));
+                        }
+                    }
+                }
+                Err(e) => {
+                    debug!("fetch_api_versions: failed to connect to {}: {}", host, e);
+                    failed.push((host.clone(), e));
                 }
             }
         }
-        bail!(ErrorKind::NoHostReachable)
+        Err(__no_host_reachable(failed))
     }
 
     /// Fetch offsets for a list of topics
@@ -848,6 +2098,11 @@ impl KafkaClient {
         let time = offset.to_kafka_value();
         let n_topics = topics.len();
 
+        if let FetchOffset::Exact(offset) = offset {
+            // ~ nothing to ask kafka for
+            return Ok(__exact_offsets(&self.state, topics, offset));
+        }
+
         let state = &mut self.state;
         let correlation = state.next_correlation_id();
 
@@ -900,17 +2155,17 @@ impl KafkaClient {
                     for p in tp.partitions {
                         let partition_offset = match p.into_offset() {
                             Ok(po) => po,
-                            Err(code) => {
-                                err = Some((p.partition, code));
+                            Err(e) => {
+                                err = Some((p.partition, e));
                                 break;
                             }
                         };
                         resp_offsets.push(partition_offset);
                     }
                 }
-                if let Some((partition, code)) = err {
+                if let Some((partition, e)) = err {
                     let topic = KafkaClient::get_key_from_entry(entry);
-                    bail!(ErrorKind::TopicPartitionError(topic, partition, code));
+                    return Err(__partition_error(topic, partition, e));
                 }
                 if let hash_map::Entry::Vacant(e) = entry {
                     // unwrap is ok because if it is Vacant, it would have
@@ -923,49 +2178,211 @@ impl KafkaClient {
         Ok(res)
     }
 
-    /// Takes ownership back from the given HashMap Entry.
-    fn get_key_from_entry<'a, K: 'a, V: 'a>(entry: hash_map::Entry<'a, K, V>) -> K {
-        match entry {
-            hash_map::Entry::Occupied(e) => e.remove_entry().0,
-            hash_map::Entry::Vacant(e) => e.into_key(),
-        }
-    }
-
-    /// Fetch offset for a single topic.
+    /// Fetches the offsets of the given topic partitions as of the
+    /// specified point in time.  For each partition the offset of the
+    /// first message with a timestamp equal to or greater than
+    /// `timestamp` (unix timestamp in milliseconds) is determined; if
+    /// there is no such message, the offset is reported as `-1`.
+    /// The special values `-2` and `-1` ask for the earliest and the
+    /// latest offset respectively (see `FetchOffset`.)
+    ///
+    /// Unlike `KafkaClient::fetch_offsets` this uses the version `1`
+    /// of Kafka's list offsets API and requires Kafka 0.10.1 or newer
+    /// with messages stored in the message format v1 or newer.
+    ///
+    /// Fails with `ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)`
+    /// if the leader of any of the partitions is not known to this
+    /// client, and with `ErrorKind::TopicPartitionError` if Kafka
+    /// reports an error for any of them.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use kafka::client::{KafkaClient, FetchOffset};
+    /// use kafka::client::KafkaClient;
     ///
     /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
     /// client.load_metadata_all().unwrap();
-    /// let offsets = client.fetch_topic_offsets("my-topic", FetchOffset::Latest).unwrap();
+    /// let tps = [("my-topic".to_owned(), 0), ("my-topic".to_owned(), 1)];
+    /// let offsets = client.fetch_offsets_by_time(&tps, 1_500_000_000_000).unwrap();
     /// ```
     ///
-    /// Returns a vector of the offset data for each available partition.
-    /// See also `KafkaClient::fetch_offsets`.
-    pub fn fetch_topic_offsets<T: AsRef<str>>(
+    /// Returns the offsets in the order of the given topic partitions.
+    pub fn fetch_offsets_by_time(
         &mut self,
-        topic: T,
-        offset: FetchOffset,
+        tps: &[(String, i32)],
+        timestamp: i64,
     ) -> Result<Vec<PartitionOffset>> {
-        let topic = topic.as_ref();
+        let state = &mut self.state;
+        let correlation = state.next_correlation_id();
 
-        let mut m = try!(self.fetch_offsets(&[topic], offset));
-        let offs = m.remove(topic).unwrap_or(vec![]);
-        if offs.is_empty() {
-            bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition))
-        } else {
-            Ok(offs)
+        // ~ map topic and partition to the corresponding broker
+        let config = &self.config;
+        let mut reqs: HashMap<&str, protocol::OffsetRequest> = HashMap::new();
+        for &(ref topic, partition) in tps {
+            match state.find_broker(topic, partition) {
+                None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+                Some(host) => {
+                    reqs.entry(host)
+                        .or_insert_with(|| {
+                            protocol::OffsetRequest::new_by_time(correlation, &config.client_id)
+                        })
+                        .add(topic, partition, timestamp)
+                }
+            }
         }
-    }
 
-    /// Fetch messages from Kafka (multiple topic, partitions).
-    ///
-    /// It takes a vector specifying the topic partitions and their
-    /// offsets as of which to fetch messages.  Additionally, the
-    /// default "max fetch size per partition" can be explicitely
+        let now = Instant::now();
+        let mut offsets: HashMap<(String, i32), i64> = HashMap::with_capacity(tps.len());
+        for (host, req) in reqs {
+            let resp = try!(__send_receive::<_, protocol::TimeOffsetResponse>(
+                &mut self.conn_pool,
+                host,
+                now,
+                req,
+            ));
+            for tp in resp.topic_partitions {
+                for p in tp.partitions {
+                    match p.into_offset() {
+                        Ok(po) => {
+                            offsets.insert((tp.topic.clone(), po.partition), po.offset);
+                        }
+                        Err(e) => return Err(__partition_error(tp.topic, p.partition, e)),
+                    }
+                }
+            }
+        }
+        Ok(
+            tps.iter()
+                .map(|tp| {
+                    PartitionOffset {
+                        partition: tp.1,
+                        offset: offsets.get(tp).cloned().unwrap_or(-1),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Fetches the high watermarks - i.e. the log end offsets - of
+    /// the given topic partitions through a single list offsets
+    /// request per partition leader.  Unlike `fetch_offsets` this
+    /// does not load any metadata; the leaders of the partitions must
+    /// already be known to this client.
+    ///
+    /// The lag of a consumer group in a partition is the difference
+    /// between the partition's high watermark and the group's
+    /// committed offset (see `KafkaClient::fetch_group_offsets`.)
+    ///
+    /// Fails with `ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)`
+    /// if the leader of any of the partitions is not known to this
+    /// client, and with `ErrorKind::TopicPartitionError` if Kafka
+    /// reports an error for any of them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let tps = [("my-topic".to_owned(), 0), ("my-topic".to_owned(), 1)];
+    /// for (partition, hw) in client.high_watermarks(&tps).unwrap() {
+    ///     println!("my-topic:{} high watermark: {}", partition, hw);
+    /// }
+    /// ```
+    ///
+    /// Returns the pairs of partition id and high watermark in the
+    /// order of the given topic partitions.
+    pub fn high_watermarks(&mut self, tps: &[(String, i32)]) -> Result<Vec<(i32, i64)>> {
+        let state = &mut self.state;
+        let correlation = state.next_correlation_id();
+
+        // ~ map topic and partition to the corresponding broker
+        let config = &self.config;
+        let mut reqs: HashMap<&str, protocol::OffsetRequest> = HashMap::new();
+        for &(ref topic, partition) in tps {
+            match state.find_broker(topic, partition) {
+                None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+                Some(host) => {
+                    reqs.entry(host)
+                        .or_insert_with(|| {
+                            protocol::OffsetRequest::new(correlation, &config.client_id)
+                        })
+                        .add(topic, partition, FetchOffset::Latest.to_kafka_value())
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut offsets: HashMap<(String, i32), i64> = HashMap::with_capacity(tps.len());
+        for (host, req) in reqs {
+            let resp = try!(__send_receive::<_, protocol::OffsetResponse>(
+                &mut self.conn_pool,
+                host,
+                now,
+                req,
+            ));
+            for tp in resp.topic_partitions {
+                for p in tp.partitions {
+                    match p.into_offset() {
+                        Ok(po) => {
+                            offsets.insert((tp.topic.clone(), po.partition), po.offset);
+                        }
+                        Err(e) => return Err(__partition_error(tp.topic, p.partition, e)),
+                    }
+                }
+            }
+        }
+        Ok(
+            tps.iter()
+                .map(|tp| (tp.1, offsets.get(tp).cloned().unwrap_or(-1)))
+                .collect(),
+        )
+    }
+
+    /// Takes ownership back from the given HashMap Entry.
+    fn get_key_from_entry<'a, K: 'a, V: 'a>(entry: hash_map::Entry<'a, K, V>) -> K {
+        match entry {
+            hash_map::Entry::Occupied(e) => e.remove_entry().0,
+            hash_map::Entry::Vacant(e) => e.into_key(),
+        }
+    }
+
+    /// Fetch offset for a single topic.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, FetchOffset};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let offsets = client.fetch_topic_offsets("my-topic", FetchOffset::Latest).unwrap();
+    /// ```
+    ///
+    /// Returns a vector of the offset data for each available partition.
+    /// See also `KafkaClient::fetch_offsets`.
+    pub fn fetch_topic_offsets<T: AsRef<str>>(
+        &mut self,
+        topic: T,
+        offset: FetchOffset,
+    ) -> Result<Vec<PartitionOffset>> {
+        let topic = topic.as_ref();
+
+        let mut m = try!(self.fetch_offsets(&[topic], offset));
+        let offs = m.remove(topic).unwrap_or(vec![]);
+        if offs.is_empty() {
+            bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition))
+        } else {
+            Ok(offs)
+        }
+    }
+
+    /// Fetch messages from Kafka (multiple topic, partitions).
+    ///
+    /// It takes a vector specifying the topic partitions and their
+    /// offsets as of which to fetch messages.  Additionally, the
+    /// default "max fetch size per partition" can be explicitely
     /// overriden if it is "defined" - this is, if `max_bytes` is
     /// greater than zero.
     ///
@@ -984,6 +2401,14 @@ impl KafkaClient {
     /// than requested (while Kafka might for efficiency reasons sent
     /// messages with a lower offset.)
     ///
+    /// * This method groups the requested topic partitions by their
+    /// leader and issues exactly one fetch request per involved
+    /// broker.  The result contains one response per such broker -
+    /// in no particular order.  Topic partitions without a currently
+    /// known leader are silently skipped.  See
+    /// `KafkaClient::fetch_messages_multi` to get the fetched data in
+    /// the order requested instead.
+    ///
     /// Note: before using this method consider using
     /// `kafka::consumer::Consumer` instead which provides an easier
     /// to use API for the regular use-case of fetching messesage from
@@ -1029,6 +2454,62 @@ impl KafkaClient {
     /// See also `kafka::consumer`.
     /// See also `KafkaClient::set_fetch_max_bytes_per_partition`.
     pub fn fetch_messages<'a, I, J>(&mut self, input: I) -> Result<Vec<fetch::Response>>
+    where
+        J: AsRef<FetchPartition<'a>>,
+        I: IntoIterator<Item = J>,
+    {
+        let input: Vec<J> = input.into_iter().collect();
+        self.with_operation_deadline(|client| {
+            try!(client.refresh_stale_metadata());
+            client.fetch_messages_retrying(&input)
+        })
+    }
+
+    // ~ fetches the given partitions re-fetching those which failed
+    // due to a moved partition leader (see `set_max_leader_retries`)
+    fn fetch_messages_retrying<'a, J>(&mut self, input: &[J]) -> Result<Vec<fetch::Response>>
+    where
+        J: AsRef<FetchPartition<'a>>,
+    {
+        let mut resps = try!(self.do_fetch_messages(input));
+        let mut attempt = 0;
+        while attempt < self.config.max_leader_retries {
+            let failed = __fetch_leader_errors(&resps);
+            if failed.is_empty() {
+                break;
+            }
+            attempt += 1;
+            debug!(
+                "fetch_messages: refreshing metadata due to leader errors (attempt: {}): {:?}",
+                attempt,
+                failed
+            );
+            try!(__retry_sleep_until(&self.config, self.conn_pool.deadline()));
+            {
+                let mut topics: Vec<&str> = failed.iter().map(|&(ref t, _)| t.as_str()).collect();
+                topics.sort();
+                topics.dedup();
+                try!(self.load_metadata(&topics));
+            }
+            let retried = try!(self.do_fetch_messages(input.iter().filter(|inp| {
+                let inp = inp.as_ref();
+                failed.iter().any(|&(ref t, p)| t == inp.topic && p == inp.partition)
+            })));
+            // ~ replace the failed partitions with the re-fetched ones;
+            // partitions still without a known leader keep their error
+            let refetched: Vec<(String, i32)> = failed
+                .into_iter()
+                .filter(|&(ref t, p)| __fetched(&retried, t, p))
+                .collect();
+            for resp in &mut resps {
+                protocol::fetch::remove_partitions(resp, &refetched);
+            }
+            resps.extend(retried);
+        }
+        Ok(resps)
+    }
+
+    fn do_fetch_messages<'a, I, J>(&mut self, input: I) -> Result<Vec<fetch::Response>>
     where
         J: AsRef<FetchPartition<'a>>,
         I: IntoIterator<Item = J>,
@@ -1037,6 +2518,7 @@ impl KafkaClient {
         let config = &self.config;
 
         let correlation = state.next_correlation_id();
+        let message_version = __negotiated_message_version(state, config, protocol::API_KEY_FETCH);
 
         // Map topic and partition to the corresponding broker
         let mut reqs: HashMap<&str, protocol::FetchRequest> = HashMap::new();
@@ -1050,7 +2532,8 @@ impl KafkaClient {
                             &config.client_id,
                             config.fetch_max_wait_time,
                             config.fetch_min_bytes,
-                        )
+                            message_version,
+                        ).with_isolation_level(config.isolation_level as i8)
                     })
                     .add(
                         inp.topic,
@@ -1068,6 +2551,92 @@ impl KafkaClient {
         __fetch_messages(&mut self.conn_pool, config, reqs)
     }
 
+    /// Fetches messages from the specified broker - typically a
+    /// follower replica of the given partitions - instead of from the
+    /// partitions' leaders.  This allows e.g. rack aware consumers to
+    /// fetch from a replica nearby.  The broker is identified by its
+    /// node id (see `metadata::Broker::id` and
+    /// `metadata::Partition::replicas`.)
+    ///
+    /// Note: Brokers serve a consumer's fetch for partitions they do
+    /// not lead only as of version 11 of the fetch api (KIP-392,
+    /// Kafka 2.4); this method therefore determines the supported api
+    /// versions (see `KafkaClient::api_versions`) and always sends
+    /// fetch requests in that version.  The request still identifies
+    /// this client as a consumer, i.e. as not being a replica itself.
+    /// Brokers not hosting a replica of a requested partition report
+    /// `KafkaCode::NotLeaderForPartition` for it.  Even when fetching
+    /// from a follower, the returned high-watermark offsets are those
+    /// of the partitions' leaders as propagated to the follower;
+    /// messages are visible on a follower only once they are
+    /// committed.
+    ///
+    /// Fails with `KafkaCode::BrokerNotAvailable` if no broker with
+    /// the given id is known from the loaded metadata and with
+    /// `ErrorKind::UnsupportedProtocol` if the brokers do not support
+    /// version 11 of the fetch api.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, FetchPartition};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// let follower = {
+    ///   let topics = client.topics();
+    ///   let p = topics.partitions("my-topic").unwrap().partition(0).unwrap();
+    ///   p.replicas().iter().cloned().find(|&id| id != p.leader_id()).unwrap()
+    /// };
+    /// let resps = client.fetch_from_replica(follower, &[FetchPartition::new("my-topic", 0, 0)])
+    ///   .unwrap();
+    /// ```
+    pub fn fetch_from_replica(
+        &mut self,
+        broker_id: i32,
+        reqs: &[FetchPartition],
+    ) -> Result<Vec<fetch::Response>> {
+        let host = match self.state.brokers().iter().find(|b| b.id() == broker_id) {
+            Some(b) => b.host().to_owned(),
+            None => bail!(ErrorKind::Kafka(KafkaCode::BrokerNotAvailable)),
+        };
+        {
+            let versions = try!(self.api_versions());
+            let v = protocol::fetch::API_VERSION_FOLLOWER_FETCH;
+            let key = protocol::API_KEY_FETCH;
+            if protocol::api_versions::select_version(&versions, key, (v, v)).is_none() {
+                bail!(ErrorKind::UnsupportedProtocol);
+            }
+        }
+        let state = &mut self.state;
+        let config = &self.config;
+
+        let correlation = state.next_correlation_id();
+        let mut req = protocol::FetchRequest::new(
+            correlation,
+            &config.client_id,
+            config.fetch_max_wait_time,
+            config.fetch_min_bytes,
+            config.message_version,
+        ).with_isolation_level(config.isolation_level as i8);
+        req.header.api_version = protocol::fetch::API_VERSION_FOLLOWER_FETCH;
+        for inp in reqs {
+            req.add(
+                inp.topic,
+                inp.partition,
+                inp.offset,
+                if inp.max_bytes > 0 {
+                    inp.max_bytes
+                } else {
+                    config.fetch_max_bytes_per_partition
+                },
+            );
+        }
+        let mut fetches = HashMap::with_capacity(1);
+        fetches.insert(&host[..], req);
+        __fetch_messages(&mut self.conn_pool, config, fetches)
+    }
+
     /// Fetch messages from a single kafka partition.
     ///
     /// See `KafkaClient::fetch_messages`.
@@ -1078,6 +2647,41 @@ impl KafkaClient {
         self.fetch_messages(&[req])
     }
 
+    /// Fetches messages from multiple topic partitions at once and
+    /// delivers them per topic partition in the order requested.
+    ///
+    /// Like `KafkaClient::fetch_messages`, this groups the requested
+    /// topic partitions by their leader and issues exactly one fetch
+    /// request per involved broker; consuming many partitions thereby
+    /// costs a single round trip per broker rather than one per
+    /// partition.  The responses are then reassembled such that the
+    /// n-th element of the result corresponds to the n-th element of
+    /// `reqs`.  Topic partitions without a currently known leader are
+    /// reported as failed rather than skipped.  The fetched messages
+    /// are copied out of the responses; use `fetch_messages` to
+    /// process them without copying.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, FetchPartition};
+    ///
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata_all().unwrap();
+    /// let reqs = &[FetchPartition::new("my-topic", 0, 0),
+    ///              FetchPartition::new("my-topic", 1, 0)];
+    /// for tm in client.fetch_messages_multi(reqs).unwrap() {
+    ///   match tm.messages {
+    ///     Err(code) => println!("{}:{}: {:?}", tm.topic, tm.partition, code),
+    ///     Ok(msgs) => println!("{}:{}: {} messages", tm.topic, tm.partition, msgs.len()),
+    ///   }
+    /// }
+    /// ```
+    pub fn fetch_messages_multi(&mut self, reqs: &[FetchPartition]) -> Result<Vec<TopicMessages>> {
+        let resps = try!(self.fetch_messages(reqs));
+        Ok(reqs.iter().map(|req| __topic_messages(&self.state, &resps, req)).collect())
+    }
+
     /// Send a message to Kafka
     ///
     /// `required_acks` - indicates how many acknowledgements the
@@ -1134,159 +2738,1081 @@ impl KafkaClient {
         )
     }
 
-    /// Commit offset for a topic partitions on behalf of a consumer group.
+    /// Creates the described topics.  The request is sent to the
+    /// controller of the cluster as discovered through metadata.
+    ///
+    /// The outcome is reported individually for each topic: a topic
+    /// which already exists, for example, results in
+    /// `KafkaCode::TopicAlreadyExists`, an invalid topic name in
+    /// `KafkaCode::InvalidTopic`; the other topics of the request are
+    /// not affected by such errors.  The method itself fails only if
+    /// the request could not be carried out at all.  Requires Kafka
+    /// 0.10.1 or newer.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use kafka::client::{KafkaClient, CommitOffset};
+    /// use kafka::client::{KafkaClient, TopicSpec};
     ///
-    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
-    /// client.load_metadata_all().unwrap();
-    /// client.commit_offsets("my-group",
-    ///     &[CommitOffset::new("my-topic", 0, 100),
-    ///       CommitOffset::new("my-topic", 1, 99)])
-    ///    .unwrap();
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// let configs = [("cleanup.policy", "compact")];
+    /// let specs = [TopicSpec::new("my-topic", 4, 1),
+    ///              TopicSpec::new("my-compacted-topic", 1, 1).with_configs(&configs)];
+    /// for r in client.create_topics(&specs).unwrap() {
+    ///   println!("{}: {:?}", r.topic, r.result);
+    /// }
     /// ```
-    ///
-    /// In this example, we commit the offset 100 for the topic
-    /// partition "my-topic:0" and 99 for the topic partition
-    /// "my-topic:1".  Once successfully committed, these can then be
-    /// retrieved using `fetch_group_offsets` even from another
-    /// process or at much later point in time to resume comusing the
-    /// topic partitions as of these offsets.
-    pub fn commit_offsets<'a, J, I>(&mut self, group: &str, offsets: I) -> Result<()>
-    where
-        J: AsRef<CommitOffset<'a>>,
-        I: IntoIterator<Item = J>,
-    {
-        let mut req = protocol::OffsetCommitRequest::new(
-            group,
-            self.config.offset_commit_version,
+    pub fn create_topics(&mut self, specs: &[TopicSpec]) -> Result<Vec<TopicResult>> {
+        let controller = try!(self.find_controller());
+        let timeout = try!(protocol::to_millis_i32(
+            Duration::from_millis(CREATE_TOPICS_TIMEOUT_MILLIS),
+        ));
+        let mut req = protocol::CreateTopicsRequest::new(
             self.state.next_correlation_id(),
             &self.config.client_id,
+            timeout,
         );
-        for o in offsets {
-            let o = o.as_ref();
-            if self.state.contains_topic_partition(o.topic, o.partition) {
-                req.add(o.topic, o.partition, o.offset, "");
-            } else {
-                bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition));
-            }
-        }
-        if req.topic_partitions.is_empty() {
-            debug!("commit_offsets: no offsets provided");
-            Ok(())
-        } else {
-            __commit_offsets(req, &mut self.state, &mut self.conn_pool, &self.config)
+        for spec in specs {
+            req.add(spec.name, spec.partitions, spec.replication_factor, spec.configs);
         }
+        let resp = try!(__send_receive::<_, protocol::CreateTopicsResponse>(
+            &mut self.conn_pool,
+            &controller,
+            Instant::now(),
+            req,
+        ));
+        Ok(resp.topics.iter().map(__to_topic_result).collect())
     }
 
-    /// Commit offset of a particular topic partition on behalf of a
-    /// consumer group.
+    /// Deletes the named topics.  The request is sent to the
+    /// controller of the cluster as discovered through metadata.
+    ///
+    /// `timeout` - the time the controller is given to complete the
+    /// deletion before responding
+    ///
+    /// The outcome is reported individually for each topic, e.g.
+    /// `KafkaCode::UnknownTopicOrPartition` for a topic which does
+    /// not exist, or `KafkaCode::TopicAuthorizationFailed` for a
+    /// topic this client may not delete.  The method itself fails
+    /// only if the request could not be carried out at all.  Requires
+    /// Kafka 0.10.1 or newer with topic deletion enabled
+    /// (`delete.topic.enable`.)
+    ///
+    /// Note: deletion happens asynchronously on the brokers.  A
+    /// successful result only means the controller accepted the
+    /// deletion of the topic; the topic may still be visible in
+    /// metadata for a while.  Reloading metadata is necessary for
+    /// this client to forget about the deleted topics.
     ///
     /// # Examples
     ///
     /// ```no_run
+    /// use std::time::Duration;
     /// use kafka::client::KafkaClient;
     ///
-    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
-    /// client.load_metadata_all().unwrap();
-    /// client.commit_offset("my-group", "my-topic", 0, 100).unwrap();
+    /// let mut client = KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// let rs = client.delete_topics(&["my-topic"], Duration::from_secs(10)).unwrap();
+    /// for r in rs {
+    ///   println!("{}: {:?}", r.topic, r.result);
+    /// }
     /// ```
+    pub fn delete_topics(&mut self, names: &[&str], timeout: Duration) -> Result<Vec<TopicResult>> {
+        let controller = try!(self.find_controller());
+        let req = protocol::DeleteTopicsRequest::new(
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+            names,
+            try!(protocol::to_millis_i32(timeout)),
+        );
+        let resp = try!(__send_receive::<_, protocol::DeleteTopicsResponse>(
+            &mut self.conn_pool,
+            &controller,
+            Instant::now(),
+            req,
+        ));
+        Ok(resp.topics.iter().map(__to_topic_result).collect())
+    }
+
+    /// Increases the number of partitions of the given topic to
+    /// `new_total`.  The request is sent to the controller of the
+    /// cluster as discovered through metadata.
     ///
-    /// See also `KafkaClient::commit_offsets`.
-    pub fn commit_offset(
-        &mut self,
-        group: &str,
-        topic: &str,
-        partition: i32,
-        offset: i64,
-    ) -> Result<()> {
-        self.commit_offsets(group, &[CommitOffset::new(topic, partition, offset)])
+    /// The topic's current partition count is taken from the
+    /// metadata loaded by this client; `new_total` must exceed it,
+    /// otherwise the method fails with
+    /// `ErrorKind::InvalidPartitionCount` without contacting the
+    /// controller.  Kafka does not support decreasing the number of
+    /// partitions of a topic.  Errors reported by the controller for
+    /// the topic are returned as `ErrorKind::Kafka`.  Requires Kafka
+    /// 1.0 or newer.
+    ///
+    /// Note: keyed messages are assigned to partitions based on the
+    /// number of the topic's partitions (see
+    /// `producer::DefaultPartitioner`.)  After the expansion, new
+    /// messages with a particular key are likely to end up in a
+    /// different partition than earlier messages with the same key,
+    /// breaking any per-key ordering relied upon by consumers.
+    ///
+    /// Metadata needs to be reloaded for this client to learn about
+    /// the new partitions.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// let mut client = kafka::client::KafkaClient::new(vec!("localhost:9092".to_owned()));
+    /// client.load_metadata(&["my-topic"]).unwrap();
+    /// client.create_partitions("my-topic", 8).unwrap();
+    /// client.load_metadata(&["my-topic"]).unwrap();
+    /// ```
+    pub fn create_partitions(&mut self, topic: &str, new_total: i32) -> Result<()> {
+        let current = match self.state.partitions_for(topic) {
+            None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+            Some(tp) => tp.len() as i32,
+        };
+        if new_total <= current {
+            bail!(ErrorKind::InvalidPartitionCount(current, new_total));
+        }
+        let controller = try!(self.find_controller());
+        let timeout = try!(protocol::to_millis_i32(
+            Duration::from_millis(CREATE_TOPICS_TIMEOUT_MILLIS),
+        ));
+        let req = protocol::CreatePartitionsRequest::new(
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+            topic,
+            new_total,
+            timeout,
+        );
+        let resp = try!(__send_receive::<_, protocol::CreatePartitionsResponse>(
+            &mut self.conn_pool,
+            &controller,
+            Instant::now(),
+            req,
+        ));
+        for t in resp.topics {
+            if t.error != 0 {
+                debug!(
+                    "create_partitions: failed to create partitions for {}: {}",
+                    t.topic,
+                    t.message
+                );
+                bail!(protocol::admin::topic_error(t.error));
+            }
+        }
+        Ok(())
     }
 
-    /// Fetch offset for a specified list of topic partitions of a consumer group
+    /// Deletes the records of the given partitions before the
+    /// specified offsets.  Takes triples of topic, partition and
+    /// offset; the records with an offset lower than the given one
+    /// become unavailable to consumers.  Requires the brokers to run
+    /// Kafka 0.11 or newer.
+    ///
+    /// Note that brokers only delete whole log segments; the records
+    /// of a segment partially covered by the requested offset stay on
+    /// disk until the segment gets removed by the broker's retention.
+    /// The returned low watermark is the earliest offset still
+    /// available to consumers and may hence differ from the requested
+    /// offset.
+    ///
+    /// Fails with `ErrorKind::TopicPartitionError` and
+    /// `KafkaCode::OffsetOutOfRange` if an offset lies beyond the
+    /// partition's high watermark.  Use `-1` as the offset to delete
+    /// all records up to the current high watermark.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use kafka::client::{KafkaClient, FetchGroupOffset};
+    /// use kafka::client::KafkaClient;
     ///
     /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
     /// client.load_metadata_all().unwrap();
-    ///
-    /// let offsets =
-    ///      client.fetch_group_offsets("my-group",
-    ///             &[FetchGroupOffset::new("my-topic", 0),
-    ///               FetchGroupOffset::new("my-topic", 1)])
-    ///             .unwrap();
+    /// let targets = [("my-topic".to_owned(), 0, 1000)];
+    /// for (partition, lw) in client.delete_records(&targets).unwrap() {
+    ///     println!("my-topic:{} low watermark: {}", partition, lw);
+    /// }
     /// ```
     ///
-    /// See also `KafkaClient::fetch_group_topic_offsets`.
-    pub fn fetch_group_offsets<'a, J, I>(
-        &mut self,
+    /// Returns the pairs of partition id and new low watermark in the
+    /// order of the given targets.
+    pub fn delete_records(&mut self, targets: &[(String, i32, i64)]) -> Result<Vec<(i32, i64)>> {
+        let timeout = try!(protocol::to_millis_i32(
+            Duration::from_millis(CREATE_TOPICS_TIMEOUT_MILLIS),
+        ));
+        let state = &mut self.state;
+        let correlation = state.next_correlation_id();
+
+        // ~ map topic and partition to the corresponding broker
+        let config = &self.config;
+        let mut reqs: HashMap<&str, protocol::DeleteRecordsRequest> = HashMap::new();
+        for &(ref topic, partition, offset) in targets {
+            match state.find_broker(topic, partition) {
+                None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+                Some(host) => {
+                    reqs.entry(host)
+                        .or_insert_with(|| {
+                            protocol::DeleteRecordsRequest::new(
+                                correlation,
+                                &config.client_id,
+                                timeout,
+                            )
+                        })
+                        .add(topic, partition, offset)
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let mut lws: HashMap<(String, i32), i64> = HashMap::with_capacity(targets.len());
+        for (host, req) in reqs {
+            let resp = try!(__send_receive::<_, protocol::DeleteRecordsResponse>(
+                &mut self.conn_pool,
+                host,
+                now,
+                req,
+            ));
+            for t in resp.topics {
+                for p in t.partitions {
+                    if let Err(e) = p.to_result() {
+                        return Err(__partition_error(t.topic, p.partition, e));
+                    }
+                    lws.insert((t.topic.clone(), p.partition), p.low_watermark);
+                }
+            }
+        }
+        Ok(
+            targets
+                .iter()
+                .map(|&(ref topic, partition, _)| {
+                    let lw = lws.get(&(topic.clone(), partition)).cloned().unwrap_or(-1);
+                    (partition, lw)
+                })
+                .collect(),
+        )
+    }
+
+    /// Describes the configuration of the given resources, i.e.
+    /// topics and brokers.  Broker configs are requested from the
+    /// broker itself and hence require the client's metadata to know
+    /// about the broker; topic configs are requested from any broker.
+    /// Requires Kafka 0.11 or newer.
+    ///
+    /// The outcome is reported individually for each resource, e.g.
+    /// `KafkaCode::TopicAuthorizationFailed` for a topic or
+    /// `KafkaCode::ClusterAuthorizationFailed` for a broker whose
+    /// configuration this client may not describe.  The method
+    /// itself fails only if the requests could not be carried out at
+    /// all.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, ConfigResourceType};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let resources = [(ConfigResourceType::Topic, "my-topic")];
+    /// for r in client.describe_configs(&resources).unwrap() {
+    ///     for e in r.entries {
+    ///         println!("{}: {} = {}", r.name, e.name, e.value);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Returns the configurations in the order of the given
+    /// resources.
+    pub fn describe_configs(
+        &mut self,
+        resources: &[(ConfigResourceType, &str)],
+    ) -> Result<Vec<ConfigResource>> {
+        let mut hosts: HashMap<String, Vec<(ConfigResourceType, &str)>> = HashMap::new();
+        for &(resource_type, name) in resources {
+            let host = try!(self.config_resource_host(resource_type, name));
+            hosts.entry(host).or_insert_with(Vec::new).push((resource_type, name));
+        }
+
+        let now = Instant::now();
+        let mut described = HashMap::with_capacity(resources.len());
+        for (host, rs) in hosts {
+            let mut req = protocol::DescribeConfigsRequest::new(
+                self.state.next_correlation_id(),
+                &self.config.client_id,
+            );
+            for &(resource_type, name) in &rs {
+                req.add(resource_type as i8, name);
+            }
+            let resp = try!(__send_receive::<_, protocol::DescribeConfigsResponse>(
+                &mut self.conn_pool,
+                &host,
+                now,
+                req,
+            ));
+            for r in resp.resources {
+                if r.error != 0 {
+                    debug!(
+                        "describe_configs: failed to describe {}: {}",
+                        r.resource_name,
+                        r.error_message
+                    );
+                }
+                described.insert((r.resource_type, r.resource_name.clone()), r);
+            }
+        }
+        Ok(
+            resources
+                .iter()
+                .map(|&(resource_type, name)| {
+                    let key = (resource_type as i8, name.to_owned());
+                    let (result, error_code, entries) = match described.remove(&key) {
+                        // ~ the broker did not report on the resource
+                        None => (Err(KafkaCode::Unknown), KafkaCode::Unknown.to_i16(), vec![]),
+                        Some(r) => {
+                            let result = r.to_result().map_err(|e| protocol::kafka_code(&e));
+                            (result, r.error, r.entries)
+                        }
+                    };
+                    ConfigResource {
+                        resource_type: resource_type,
+                        name: name.to_owned(),
+                        result: result,
+                        error_code: error_code,
+                        entries: entries.into_iter().map(__to_config_entry).collect(),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Replaces the configuration of the given resources, i.e.
+    /// topics and brokers.  Requests are routed as described for
+    /// `KafkaClient::describe_configs`.  Requires Kafka 0.11 or
+    /// newer.
+    ///
+    /// Note: the given entries replace the resource's whole
+    /// configuration; entries not mentioned are reset to their
+    /// defaults.
+    ///
+    /// Fails with the error reported for the first resource which
+    /// could not be altered, e.g. `KafkaCode::TopicAuthorizationFailed`
+    /// or `KafkaCode::ClusterAuthorizationFailed` if this client may
+    /// not alter a topic or broker configuration respectively; the
+    /// errors of all failed resources are logged.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, ConfigChange, ConfigResourceType};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// let configs = [("retention.ms", "86400000")];
+    /// let changes = [ConfigChange::new(ConfigResourceType::Topic, "my-topic", &configs)];
+    /// client.alter_configs(&changes).unwrap();
+    /// ```
+    pub fn alter_configs(&mut self, changes: &[ConfigChange]) -> Result<()> {
+        let mut hosts: HashMap<String, Vec<&ConfigChange>> = HashMap::new();
+        for change in changes {
+            let host = try!(self.config_resource_host(change.resource_type, change.name));
+            hosts.entry(host).or_insert_with(Vec::new).push(change);
+        }
+
+        let now = Instant::now();
+        let mut failure = None;
+        for (host, cs) in hosts {
+            let mut req = protocol::AlterConfigsRequest::new(
+                self.state.next_correlation_id(),
+                &self.config.client_id,
+            );
+            for c in cs {
+                req.add(c.resource_type as i8, c.name, c.configs);
+            }
+            let resp = try!(__send_receive::<_, protocol::AlterConfigsResponse>(
+                &mut self.conn_pool,
+                &host,
+                now,
+                req,
+            ));
+            for r in resp.resources {
+                if r.error != 0 {
+                    debug!(
+                        "alter_configs: failed to alter {}: {}",
+                        r.resource_name,
+                        r.error_message
+                    );
+                    failure = failure.or(Some(r.error));
+                }
+            }
+        }
+        match failure {
+            None => Ok(()),
+            Some(code) => Err(protocol::admin::topic_error(code)),
+        }
+    }
+
+    // ~ determines the broker to send requests concerning the given
+    // config resource to: broker configs are served only by the
+    // broker itself, topic configs by any broker
+    fn config_resource_host(
+        &mut self,
+        resource_type: ConfigResourceType,
+        name: &str,
+    ) -> Result<String> {
+        match resource_type {
+            ConfigResourceType::Broker => {
+                let id = name.parse::<i32>().ok();
+                match self.state.brokers().iter().find(|b| Some(b.id()) == id) {
+                    Some(broker) => Ok(broker.host().to_owned()),
+                    None => bail!(ErrorKind::Kafka(KafkaCode::BrokerNotAvailable)),
+                }
+            }
+            ConfigResourceType::Topic => {
+                match self.state.brokers().first() {
+                    Some(broker) => Ok(broker.host().to_owned()),
+                    None => self.find_controller(),
+                }
+            }
+        }
+    }
+
+    /// Determines the "host:port" of the controller of the cluster
+    /// by asking the first reachable of the underlying brokers
+    /// (`self.hosts`) and remembers it as the client's controller.
+    /// Fails with the retriable `KafkaCode::NotController` if no
+    /// controller is elected.
+    fn find_controller(&mut self) -> Result<String> {
+        let correlation = self.state.next_correlation_id();
+        let now = Instant::now();
+        let mut failed = Vec::new();
+
+        for host in &self.config.hosts {
+            debug!("find_controller: requesting metadata from {}", host);
+            match self.conn_pool.get_conn(host, now) {
+                Ok(mut conn) => {
+                    let req = protocol::ControllerMetadataRequest::new(
+                        correlation,
+                        &self.config.client_id,
+                    );
+                    match __send_request(&mut conn, req) {
+                        Ok(cid) => {
+                            let r = __get_response::<protocol::ControllerMetadataResponse>(
+                                &mut conn,
+                                cid,
+                            );
+                            let resp = try!(__close_desynced(&mut self.conn_pool, host, r));
+                            let controller = resp.controller_host();
+                            self.state.set_controller(
+                                controller.clone().map(|host| (resp.controller_id, host)),
+                            );
+                            return controller.ok_or_else(|| {
+                                debug!(
+                                    "find_controller: no controller elected (id: {})",
+                                    resp.controller_id
+                                );
+                                ErrorKind::Kafka(KafkaCode::NotController).into()
+                            });
+                        }
+                        Err(e) => {
+                            debug!(
+                                "find_controller: failed to request metadata from {}: {}",
+                                host,
+                                e
+                            );
+                            failed.push((host.clone(), e));
+                        }
+                    }
+                }
+                Err(e) => {
+                    debug!("find_controller: failed to connect to {}: {}", host, e);
+                    failed.push((host.clone(), e));
+                }
+            }
+        }
+        Err(__no_host_reachable(failed))
+    }
+
+    /// Determines the broker coordinating the specified consumer
+    /// group.  The coordinator is looked up only once and then
+    /// remembered by this client until Kafka reports it has moved
+    /// (`KafkaCode::NotCoordinatorForGroup`).  The group related
+    /// methods of this client, e.g. `KafkaClient::commit_offsets`,
+    /// transparently communicate with this broker.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let coordinator = client.find_group_coordinator("my-group").unwrap();
+    /// println!("coordinator: #{} => {}", coordinator.id(), coordinator.host());
+    /// ```
+    pub fn find_group_coordinator(&mut self, group: &str) -> Result<&metadata::Broker> {
+        let now = Instant::now();
+        try!(__get_group_coordinator(
+            group,
+            &mut self.state,
+            &mut self.conn_pool,
+            &self.config,
+            self.config.retry_max_attempts,
+            now,
+        ));
+        Ok(self.state.group_coordinator_broker(group).expect("registered group coordinator"))
+    }
+
+    /// Commit offset for a topic partitions on behalf of a consumer group.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, CommitOffset};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// client.commit_offsets("my-group",
+    ///     &[CommitOffset::new("my-topic", 0, 100),
+    ///       CommitOffset::new("my-topic", 1, 99)])
+    ///    .unwrap();
+    /// ```
+    ///
+    /// In this example, we commit the offset 100 for the topic
+    /// partition "my-topic:0" and 99 for the topic partition
+    /// "my-topic:1".  Once successfully committed, these can then be
+    /// retrieved using `fetch_group_offsets` even from another
+    /// process or at much later point in time to resume comusing the
+    /// topic partitions as of these offsets.
+    ///
+    /// The committed offsets expire after the retention time
+    /// configured at the brokers; see
+    /// `KafkaClient::commit_offsets_with_retention`.
+    pub fn commit_offsets<'a, J, I>(&mut self, group: &str, offsets: I) -> Result<()>
+    where
+        J: AsRef<CommitOffset<'a>>,
+        I: IntoIterator<Item = J>,
+    {
+        self.commit_offsets_with_retention(group, offsets, None)
+    }
+
+    /// Commit offsets for topic partitions on behalf of a consumer
+    /// group just like `KafkaClient::commit_offsets`, but lets the
+    /// committed offsets expire after the given retention time.
+    /// `None` applies the retention time configured at the brokers
+    /// (`offsets.retention.minutes`.)
+    ///
+    /// Beware that a short retention can make the committed offsets
+    /// vanish for consumers running only infrequently; these will
+    /// then have to start over from their fallback offset (see
+    /// `consumer::Builder::with_fallback_offset`.)
+    ///
+    /// The retention time is supported by version 2 of the offset
+    /// commit API (Kafka 0.9) which is used for the request if a
+    /// retention time is specified and the offsets are stored in
+    /// Kafka (see `KafkaClient::set_group_offset_storage`.)  Offsets
+    /// stored in Zookeeper do not expire; the retention time is then
+    /// ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kafka::client::{KafkaClient, CommitOffset};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// client.commit_offsets_with_retention(
+    ///     "my-group",
+    ///     &[CommitOffset::new("my-topic", 0, 100)],
+    ///     Some(Duration::from_secs(7 * 24 * 3600)),
+    /// ).unwrap();
+    /// ```
+    pub fn commit_offsets_with_retention<'a, J, I>(
+        &mut self,
+        group: &str,
+        offsets: I,
+        retention: Option<Duration>,
+    ) -> Result<()>
+    where
+        J: AsRef<CommitOffset<'a>>,
+        I: IntoIterator<Item = J>,
+    {
+        let retention_time = match retention {
+            Some(d) => try!(protocol::to_millis_i64(d)),
+            None => -1,
+        };
+        // ~ only v2 of the api carries the retention time
+        let version = match (self.config.offset_commit_version, retention) {
+            (protocol::OffsetCommitVersion::V1, Some(_)) => protocol::OffsetCommitVersion::V2,
+            (v, _) => v,
+        };
+        let mut req = protocol::OffsetCommitRequest::new(
+            group,
+            version,
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+        );
+        req.retention_time = retention_time;
+        for o in offsets {
+            let o = o.as_ref();
+            if self.state.contains_topic_partition(o.topic, o.partition) {
+                req.add(o.topic, o.partition, o.offset, "");
+            } else {
+                bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition));
+            }
+        }
+        if req.topic_partitions.is_empty() {
+            debug!("commit_offsets: no offsets provided");
+            Ok(())
+        } else {
+            __commit_offsets(req, &mut self.state, &mut self.conn_pool, &self.config)
+        }
+    }
+
+    /// Commit offset of a particular topic partition on behalf of a
+    /// consumer group.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// client.commit_offset("my-group", "my-topic", 0, 100).unwrap();
+    /// ```
+    ///
+    /// See also `KafkaClient::commit_offsets`.
+    pub fn commit_offset(
+        &mut self,
+        group: &str,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<()> {
+        self.commit_offsets(group, &[CommitOffset::new(topic, partition, offset)])
+    }
+
+    /// Fetch offset for a specified list of topic partitions of a consumer group
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::{KafkaClient, FetchGroupOffset};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    ///
+    /// let offsets =
+    ///      client.fetch_group_offsets("my-group",
+    ///             &[FetchGroupOffset::new("my-topic", 0),
+    ///               FetchGroupOffset::new("my-topic", 1)])
+    ///             .unwrap();
+    /// ```
+    ///
+    /// See also `KafkaClient::fetch_group_topic_offsets`.
+    pub fn fetch_group_offsets<'a, J, I>(
+        &mut self,
+        group: &str,
+        partitions: I,
+    ) -> Result<HashMap<String, Vec<PartitionOffset>>>
+    where
+        J: AsRef<FetchGroupOffset<'a>>,
+        I: IntoIterator<Item = J>,
+    {
+        let mut req = protocol::OffsetFetchRequest::new(
+            group,
+            self.config.offset_fetch_version,
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+        );
+        for p in partitions {
+            let p = p.as_ref();
+            if self.state.contains_topic_partition(p.topic, p.partition) {
+                req.add(p.topic, p.partition);
+            } else {
+                bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition));
+            }
+        }
+        __fetch_group_offsets(req, &mut self.state, &mut self.conn_pool, &self.config)
+    }
+
+    /// Fetch offset for all partitions of a particular topic of a consumer group
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let offsets = client.fetch_group_topic_offsets("my-group", "my-topic").unwrap();
+    /// ```
+    pub fn fetch_group_topic_offsets(
+        &mut self,
+        group: &str,
+        topic: &str,
+    ) -> Result<Vec<PartitionOffset>> {
+        let mut req = protocol::OffsetFetchRequest::new(
+            group,
+            self.config.offset_fetch_version,
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+        );
+
+        match self.state.partitions_for(topic) {
+            None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+            Some(tp) => {
+                for (id, _) in tp {
+                    req.add(topic, id);
+                }
+            }
+        }
+
+        Ok(
+            try!(__fetch_group_offsets(req, &mut self.state, &mut self.conn_pool, &self.config))
+                .remove(topic)
+                .unwrap_or_else(Vec::new),
+        )
+    }
+    /// Joins the specified consumer group as a member subscribing to
+    /// the given topics.  An empty `member_id` makes the group
+    /// coordinator assign a new id to this member; clients rejoining
+    /// a group, e.g. after a rebalance, should pass the id they were
+    /// previously assigned.
+    ///
+    /// `session_timeout` - the time after which the coordinator will
+    /// remove this member from the group if it fails to hear from it
+    ///
+    /// The coordinator responds only once all members of the group
+    /// have (re-)joined or the session timeout has elapsed; hence,
+    /// the client's `socket_timeout` should exceed `session_timeout`.
+    ///
+    /// This member advertises `GROUP_ASSIGNMENT_STRATEGY` as the
+    /// only supported assignment strategy.  If it is elected the
+    /// group's leader (see `GroupMembership::is_leader`), it is
+    /// responsible for computing the partition assignment of the
+    /// whole group and distributing it through
+    /// `KafkaClient::sync_group`.  See `consumer::ConsumerGroup` for
+    /// a higher level API taking care of all this.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let m = client.join_group("my-group", "", Duration::from_secs(30), &["my-topic"]).unwrap();
+    /// println!("joined generation {} as {} (leader: {})",
+    ///          m.generation_id, m.member_id, m.is_leader());
+    /// ```
+    pub fn join_group<T: AsRef<str>>(
+        &mut self,
+        group: &str,
+        member_id: &str,
+        session_timeout: Duration,
+        topics: &[T],
+    ) -> Result<GroupMembership> {
+        let mut metadata = Vec::new();
+        try!(
+            protocol::group::ConsumerGroupMemberMetadata {
+                version: protocol::group::CONSUMER_PROTOCOL_VERSION,
+                topics: topics.iter().map(|t| t.as_ref().to_owned()).collect(),
+                user_data: vec![],
+            }.encode(&mut metadata)
+        );
+        let mut req = protocol::JoinGroupRequest::new(
+            group,
+            try!(protocol::to_millis_i32(session_timeout)),
+            member_id,
+            GROUP_PROTOCOL_TYPE,
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+        );
+        req.add(GROUP_ASSIGNMENT_STRATEGY, metadata);
+
+        let r = try!(__send_group_request::<_, protocol::JoinGroupResponse, _>(
+            group,
+            &req,
+            &mut self.state,
+            &mut self.conn_pool,
+            &self.config,
+            self.config.retry_max_attempts,
+            protocol::JoinGroupResponse::to_result,
+        ));
+
+        let mut members = Vec::with_capacity(r.members.len());
+        for m in r.members {
+            let mut metadata = Cursor::new(m.metadata);
+            let metadata =
+                try!(protocol::group::ConsumerGroupMemberMetadata::decode_new(&mut metadata));
+            members.push(GroupMember {
+                member_id: m.member_id,
+                topics: metadata.topics,
+            });
+        }
+        Ok(GroupMembership {
+            generation_id: r.generation_id,
+            member_id: r.member_id,
+            leader_id: r.leader_id,
+            protocol: r.group_protocol,
+            members: members,
+        })
+    }
+
+    /// Completes joining a consumer group by retrieving the
+    /// `(topic, partition)` pairs assigned to this member for the
+    /// given generation of the group.
+    ///
+    /// The group's leader must pass the assignment for all members of
+    /// the group, while all other members are expected to pass an
+    /// empty list.  See `KafkaClient::join_group`.
+    ///
+    /// Note: Kafka reports `KafkaCode::RebalanceInProgress` if the
+    /// group started rebalancing in the meantime; the member is
+    /// expected to rejoin the group in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let m = client.join_group("my-group", "", Duration::from_secs(30), &["my-topic"]).unwrap();
+    /// assert!(!m.is_leader());
+    /// let assignment = client.sync_group("my-group", m.generation_id, &m.member_id, &[]).unwrap();
+    /// for &(ref topic, partition) in &assignment {
+    ///     println!("assigned: {}:{}", topic, partition);
+    /// }
+    /// ```
+    pub fn sync_group(
+        &mut self,
         group: &str,
-        partitions: I,
-    ) -> Result<HashMap<String, Vec<PartitionOffset>>>
-    where
-        J: AsRef<FetchGroupOffset<'a>>,
-        I: IntoIterator<Item = J>,
-    {
-        let mut req = protocol::OffsetFetchRequest::new(
+        generation_id: i32,
+        member_id: &str,
+        assignments: &[GroupAssignment],
+    ) -> Result<Vec<(String, i32)>> {
+        let mut req = protocol::SyncGroupRequest::new(
             group,
-            self.config.offset_fetch_version,
+            generation_id,
+            member_id,
             self.state.next_correlation_id(),
             &self.config.client_id,
         );
-        for p in partitions {
-            let p = p.as_ref();
-            if self.state.contains_topic_partition(p.topic, p.partition) {
-                req.add(p.topic, p.partition);
-            } else {
-                bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition));
-            }
+        for a in assignments {
+            let mut assignment = Vec::new();
+            try!(__to_member_assignment(&a.partitions).encode(&mut assignment));
+            req.add(&a.member_id, assignment);
         }
-        __fetch_group_offsets(req, &mut self.state, &mut self.conn_pool, &self.config)
+
+        let r = try!(__send_group_request::<_, protocol::SyncGroupResponse, _>(
+            group,
+            &req,
+            &mut self.state,
+            &mut self.conn_pool,
+            &self.config,
+            self.config.retry_max_attempts,
+            protocol::SyncGroupResponse::to_result,
+        ));
+
+        __from_member_assignment(r.member_assignment)
     }
 
-    /// Fetch offset for all partitions of a particular topic of a consumer group
+    /// Signals the coordinator of the specified consumer group that
+    /// the given member is still alive.  A member must send
+    /// heartbeats more often than the session timeout it joined the
+    /// group with (see `KafkaClient::join_group`), otherwise the
+    /// coordinator removes it from the group and rebalances the
+    /// group's partitions among the remaining members.  Sending a
+    /// heartbeat every third of the session timeout is a reasonable
+    /// choice.
+    ///
+    /// Note: Kafka reports `KafkaCode::RebalanceInProgress` if the
+    /// group started rebalancing; the member is expected to rejoin
+    /// the group in that case.  `KafkaCode::IllegalGeneration` or
+    /// `KafkaCode::UnknownMemberId` indicate that the membership is no
+    /// longer valid and the group needs to be joined afresh.
+    ///
+    /// Unlike the other group requests, a heartbeat is not retried:
+    /// retrying for up to `retry_max_attempts` times would easily
+    /// outlast the session timeout.  Hence, a coordinator which
+    /// moved or is not available is reported right away as
+    /// `KafkaCode::NotCoordinatorForGroup`,
+    /// `KafkaCode::GroupCoordinatorNotAvailable`, or
+    /// `KafkaCode::GroupLoadInProgress`; the member is expected to
+    /// rejoin the group in these cases as well.
     ///
     /// # Examples
     ///
     /// ```no_run
+    /// use std::time::Duration;
     /// use kafka::client::KafkaClient;
     ///
     /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
     /// client.load_metadata_all().unwrap();
-    /// let offsets = client.fetch_group_topic_offsets("my-group", "my-topic").unwrap();
+    /// let m = client.join_group("my-group", "", Duration::from_secs(30), &["my-topic"]).unwrap();
+    /// client.heartbeat("my-group", m.generation_id, &m.member_id).unwrap();
     /// ```
-    pub fn fetch_group_topic_offsets(
+    pub fn heartbeat(&mut self, group: &str, generation_id: i32, member_id: &str) -> Result<()> {
+        let req = protocol::HeartbeatRequest::new(
+            group,
+            generation_id,
+            member_id,
+            self.state.next_correlation_id(),
+            &self.config.client_id,
+        );
+        try!(__send_group_request::<_, protocol::HeartbeatResponse, _>(
+            group,
+            &req,
+            &mut self.state,
+            &mut self.conn_pool,
+            &self.config,
+            1,
+            protocol::HeartbeatResponse::to_result,
+        ));
+        Ok(())
+    }
+
+    /// Removes the given member from the specified consumer group.
+    /// This makes the group coordinator rebalance the group's
+    /// partitions among the remaining members immediately, rather
+    /// than only after the member's session timeout expired.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let m = client.join_group("my-group", "", Duration::from_secs(30), &["my-topic"]).unwrap();
+    /// client.leave_group("my-group", &m.member_id).unwrap();
+    /// ```
+    pub fn leave_group(&mut self, group: &str, member_id: &str) -> Result<()> {
+        let max_attempts = self.config.retry_max_attempts;
+        self.leave_group_attempts(group, member_id, max_attempts)
+    }
+
+    fn leave_group_attempts(
         &mut self,
         group: &str,
-        topic: &str,
-    ) -> Result<Vec<PartitionOffset>> {
-        let mut req = protocol::OffsetFetchRequest::new(
+        member_id: &str,
+        max_attempts: u32,
+    ) -> Result<()> {
+        let req = protocol::LeaveGroupRequest::new(
             group,
-            self.config.offset_fetch_version,
+            member_id,
             self.state.next_correlation_id(),
             &self.config.client_id,
         );
+        try!(__send_group_request::<_, protocol::LeaveGroupResponse, _>(
+            group,
+            &req,
+            &mut self.state,
+            &mut self.conn_pool,
+            &self.config,
+            max_attempts,
+            protocol::LeaveGroupResponse::to_result,
+        ));
+        Ok(())
+    }
 
-        match self.state.partitions_for(topic) {
-            None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
-            Some(tp) => {
-                for (id, _) in tp {
-                    req.add(topic, id);
-                }
+    /// Retrieves the state, members, and partition assignments of the
+    /// specified consumer groups from their coordinators.  A group
+    /// not known to its coordinator is described as being in the
+    /// "Dead" state without any members.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// for g in client.describe_groups(&["my-group"]).unwrap() {
+    ///     println!("{}: {}", g.group, g.state);
+    ///     for m in &g.members {
+    ///         println!("  {} ({}): {:?}", m.member_id, m.client_host, m.assignment);
+    ///     }
+    /// }
+    /// ```
+    pub fn describe_groups(&mut self, groups: &[&str]) -> Result<Vec<GroupDescription>> {
+        let mut descriptions = Vec::with_capacity(groups.len());
+        // ~ the groups may be coordinated by different brokers;
+        // describe each group through its own coordinator
+        for &group in groups {
+            let mut req = protocol::DescribeGroupsRequest::new(
+                self.state.next_correlation_id(),
+                &self.config.client_id,
+            );
+            req.add(group);
+            let r = try!(__send_group_request::<_, protocol::DescribeGroupsResponse, _>(
+                group,
+                &req,
+                &mut self.state,
+                &mut self.conn_pool,
+                &self.config,
+                self.config.retry_max_attempts,
+                protocol::DescribeGroupsResponse::to_result,
+            ));
+            for g in r.groups {
+                descriptions.push(try!(__to_group_description(g)));
             }
         }
+        Ok(descriptions)
+    }
 
-        Ok(
-            try!(__fetch_group_offsets(req, &mut self.state, &mut self.conn_pool, &self.config))
-                .remove(topic)
-                .unwrap_or_else(Vec::new),
-        )
+    /// Lists the consumer groups known to the cluster as `(group,
+    /// protocol_type)` pairs, sorted by the name of the group.
+    ///
+    /// Each broker knows only about the groups it coordinates; this
+    /// method hence asks all brokers discovered through the loaded
+    /// metadata (loading it first if not done so yet) and merges
+    /// their answers.  Brokers whose group coordinator is not
+    /// available yet are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use kafka::client::KafkaClient;
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// for (group, protocol_type) in client.list_groups().unwrap() {
+    ///     println!("{} ({})", group, protocol_type);
+    /// }
+    /// ```
+    pub fn list_groups(&mut self) -> Result<Vec<(String, String)>> {
+        if self.state.brokers().is_empty() {
+            try!(self.load_metadata_all());
+        }
+        let hosts: Vec<String> = self.state.brokers().iter().map(|b| b.host().to_owned()).collect();
+        let mut groups = Vec::new();
+        for host in &hosts {
+            let req = protocol::ListGroupsRequest::new(
+                self.state.next_correlation_id(),
+                &self.config.client_id,
+            );
+            debug!("list_groups: sending request {:?} to: {}", req, host);
+            let r = try!(__send_receive::<_, protocol::ListGroupsResponse>(
+                &mut self.conn_pool,
+                host,
+                Instant::now(),
+                req,
+            ));
+            match r.to_result() {
+                Ok(r) => groups.extend(r.groups.into_iter().map(|g| (g.group, g.protocol_type))),
+                Err(Error(ErrorKind::Kafka(KafkaCode::GroupCoordinatorNotAvailable), _)) => {
+                    debug!("list_groups: skipping {} without available coordinator", host);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        groups.sort();
+        groups.dedup();
+        Ok(groups)
     }
 }
 
@@ -1301,15 +3827,281 @@ impl KafkaClientInternals for KafkaClient {
         J: AsRef<ProduceMessage<'a, 'b>>,
         I: IntoIterator<Item = J>,
     {
+        self.with_operation_deadline(|client| {
+            try!(client.refresh_stale_metadata());
+            let mut sequences = HashMap::new();
+            client.do_produce_messages(required_acks, ack_timeout, messages, &mut sequences, false)
+        })
+    }
+
+    fn internal_produce_messages_resendable<'a, 'b, I, J>(
+        &mut self,
+        required_acks: i16,
+        ack_timeout: i32,
+        messages: I,
+        sequences: &mut HashMap<(String, i32), i32>,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        J: AsRef<ProduceMessage<'a, 'b>>,
+        I: IntoIterator<Item = J>,
+    {
+        self.with_operation_deadline(|client| {
+            try!(client.refresh_stale_metadata());
+            client.do_produce_messages(required_acks, ack_timeout, messages, sequences, true)
+        })
+    }
+
+    fn internal_reset_producer_id(&mut self) {
+        self.state.set_producer_id(None);
+    }
+
+    fn internal_leave_group_once(&mut self, group: &str, member_id: &str) -> Result<()> {
+        self.leave_group_attempts(group, member_id, 1)
+    }
+}
+
+impl KafkaClient {
+    // ~ runs the given operation bounded by the configured operation
+    // deadline (if any)
+    fn with_operation_deadline<T, F>(&mut self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut KafkaClient) -> Result<T>,
+    {
+        let deadline = match self.config.operation_deadline {
+            None => return op(self),
+            Some(d) => Instant::now() + d,
+        };
+        self.conn_pool.set_deadline(Some(deadline));
+        let r = op(self);
+        self.conn_pool.set_deadline(None);
+        r.map_err(|e| __deadline_error(e, deadline))
+    }
+
+    // ~ reloads the metadata of the known topics if it exceeds the
+    // configured max. age
+    fn refresh_stale_metadata(&mut self) -> Result<()> {
+        let loaded_at = self.state.metadata_loaded_at();
+        if !__metadata_stale(loaded_at, self.config.metadata_max_age, Instant::now()) {
+            return Ok(());
+        }
+        let topics: Vec<String> = self.state.topic_names().map(|t| t.to_owned()).collect();
+        if topics.is_empty() {
+            return Ok(());
+        }
+        debug!("refresh_stale_metadata: reloading metadata of {} topics", topics.len());
+        self.load_metadata(&topics)
+    }
+
+    // ~ as an idempotent producer, sends the messages of partitions
+    // found in `sequences` under the recorded sequence numbers.  unless
+    // `resendable`, the producer id is reset after a failure since the
+    // sequences of the failed partitions are then out of sync with the
+    // brokers; otherwise the caller is expected to either re-send the
+    // failed messages or to reset the producer id.
+    fn do_produce_messages<'a, 'b, I, J>(
+        &mut self,
+        required_acks: i16,
+        ack_timeout: i32,
+        messages: I,
+        sequences: &mut HashMap<(String, i32), i32>,
+        resendable: bool,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        J: AsRef<ProduceMessage<'a, 'b>>,
+        I: IntoIterator<Item = J>,
+    {
+        let messages: Vec<J> = messages.into_iter().collect();
+        if !self.config.idempotent {
+            return self.produce_messages_retrying(
+                required_acks,
+                ack_timeout,
+                &messages,
+                &mut HashMap::new(),
+            );
+        }
+        if required_acks != -1 {
+            bail!(ErrorKind::InvalidRequest("idempotence requires RequiredAcks::All"));
+        }
+        if __negotiated_message_version(&self.state, &self.config, protocol::API_KEY_PRODUCE) < 2 {
+            bail!(ErrorKind::InvalidRequest("idempotence requires the message format v2"));
+        }
+        if self.state.producer_id().is_none() {
+            try!(self.init_producer_id());
+        }
+        // ~ `sequences` holds the base sequences assigned to the
+        // produced partitions; messages re-sent to moved leaders keep
+        // their sequences
+        let r = self.produce_messages_retrying(required_acks, ack_timeout, &messages, sequences);
+        match r {
+            Ok(mut confirms) => {
+                if !__accept_duplicate_confirms(&mut confirms) && !resendable {
+                    // ~ the sequences of the failed partitions are
+                    // out of sync with the brokers; start over
+                    self.state.set_producer_id(None);
+                }
+                Ok(confirms)
+            }
+            Err(e) => {
+                if !resendable {
+                    self.state.set_producer_id(None);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // ~ sends the given messages re-sending those rejected due to a
+    // moved partition leader (see `set_max_leader_retries`)
+    fn produce_messages_retrying<'a, 'b, J>(
+        &mut self,
+        required_acks: i16,
+        ack_timeout: i32,
+        messages: &[J],
+        sequences: &mut HashMap<(String, i32), i32>,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        J: AsRef<ProduceMessage<'a, 'b>>,
+    {
+        let mut confirms = try!(self.produce_messages_once(
+            required_acks,
+            ack_timeout,
+            messages.iter().map(AsRef::as_ref),
+            false,
+            sequences,
+        ));
+        let mut attempt = 0;
+        while attempt < self.config.max_leader_retries {
+            // ~ determine the partitions which failed due to outdated
+            // leadership information
+            let failed = __leader_errors(&confirms);
+            if failed.is_empty() {
+                break;
+            }
+            attempt += 1;
+            debug!(
+                "produce_messages: refreshing metadata due to leader errors (attempt: {}): {:?}",
+                attempt,
+                failed
+            );
+            try!(__retry_sleep_until(&self.config, self.conn_pool.deadline()));
+            {
+                let mut topics: Vec<&str> = failed.iter().map(|&(ref t, _)| t.as_str()).collect();
+                topics.sort();
+                topics.dedup();
+                try!(self.load_metadata(&topics));
+            }
+            let retried = try!(self.produce_messages_once(
+                required_acks,
+                ack_timeout,
+                messages.iter().map(AsRef::as_ref).filter(|m| {
+                    failed.iter().any(|&(ref t, p)| t == m.topic && p == m.partition)
+                }),
+                true,
+                sequences,
+            ));
+            __merge_produce_confirms(&mut confirms, retried);
+        }
+        Ok(confirms)
+    }
+
+    // ~ obtains a producer id and epoch for the idempotent producer
+    // from any of the known brokers
+    fn init_producer_id(&mut self) -> Result<()> {
+        if self.state.brokers().is_empty() {
+            try!(self.load_metadata_all());
+        }
+        let hosts: Vec<String> = self.state.brokers().iter().map(|b| b.host().to_owned()).collect();
+        let mut failures = Vec::new();
+        for host in hosts {
+            let req = protocol::InitProducerIdRequest::new(
+                self.state.next_correlation_id(),
+                &self.config.client_id,
+            );
+            debug!("init_producer_id: sending request {:?} to: {}", req, host);
+            let r = __send_receive::<_, protocol::InitProducerIdResponse>(
+                &mut self.conn_pool,
+                &host,
+                Instant::now(),
+                req,
+            ).and_then(|r| r.to_result());
+            match r {
+                Ok(r) => {
+                    debug!(
+                        "init_producer_id: obtained producer id {} (epoch: {})",
+                        r.producer_id,
+                        r.producer_epoch
+                    );
+                    self.state.set_producer_id(Some((r.producer_id, r.producer_epoch)));
+                    return Ok(());
+                }
+                Err(e @ Error(ErrorKind::Kafka(_), _)) => return Err(e),
+                Err(e) => failures.push((host, e)),
+            }
+        }
+        Err(__no_host_reachable(failures))
+    }
+
+    // ~ sends the given messages to the leaders of their partitions as
+    // currently known to this client; if `skip_unknown` is true,
+    // messages for partitions without a known leader are silently
+    // skipped instead of failing the whole operation.  as an
+    // idempotent producer, the messages of partitions not yet found
+    // in `sequences` get new sequence numbers reserved for them.
+    fn produce_messages_once<'a, 'b, 'c, I>(
+        &mut self,
+        required_acks: i16,
+        ack_timeout: i32,
+        messages: I,
+        skip_unknown: bool,
+        sequences: &mut HashMap<(String, i32), i32>,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        'a: 'c,
+        'b: 'c,
+        I: Iterator<Item = &'c ProduceMessage<'a, 'b>>,
+    {
+        let messages: Vec<&ProduceMessage> = messages.collect();
+        let producer_id = self.state.producer_id();
+        if producer_id.is_some() {
+            let mut counts: HashMap<(&str, i32), i32> = HashMap::new();
+            for msg in &messages {
+                *counts.entry((msg.topic, msg.partition)).or_insert(0) += 1;
+            }
+            for ((topic, partition), n) in counts {
+                let key = (topic.to_owned(), partition);
+                if !sequences.contains_key(&key) {
+                    let base = self.state.reserve_sequences(topic, partition, n);
+                    sequences.insert(key, base);
+                }
+            }
+        }
+
         let state = &mut self.state;
         let correlation = state.next_correlation_id();
 
         // ~ map topic and partition to the corresponding brokers
         let config = &self.config;
+        let message_version =
+            __negotiated_message_version(state, config, protocol::API_KEY_PRODUCE);
+        // ~ zstd compressed batches require a produce api version
+        // older brokers do not support; fail before encoding anything
+        #[cfg(feature = "zstd")]
+        {
+            if let Compression::ZSTD = config.compression {
+                if let Some(versions) = state.api_versions() {
+                    use protocol::produce::{MAX_API_VERSION, ZSTD_MIN_API_VERSION};
+                    let ours = (ZSTD_MIN_API_VERSION, MAX_API_VERSION);
+                    let key = protocol::API_KEY_PRODUCE;
+                    if protocol::api_versions::select_version(versions, key, ours).is_none() {
+                        bail!(ErrorKind::UnsupportedCompression);
+                    }
+                }
+            }
+        }
         let mut reqs: HashMap<&str, protocol::ProduceRequest> = HashMap::new();
         for msg in messages {
-            let msg = msg.as_ref();
             match state.find_broker(msg.topic, msg.partition) {
+                None if skip_unknown => {}
                 None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
                 Some(broker) => {
                     reqs.entry(broker)
@@ -1320,21 +4112,205 @@ impl KafkaClientInternals for KafkaClient {
                                 correlation,
                                 &config.client_id,
                                 config.compression,
-                            )
+                                config.compression_level,
+                                message_version,
+                            ).with_compression_threshold(config.compression_threshold)
                         })
-                        .add(msg.topic, msg.partition, msg.key, msg.value)
+                        .add(
+                            msg.topic,
+                            msg.partition,
+                            msg.key,
+                            msg.value,
+                            msg.timestamp,
+                            msg.headers,
+                        )
+                }
+            }
+        }
+        if let Some((producer_id, producer_epoch)) = producer_id {
+            for req in reqs.values_mut() {
+                req.set_producer(producer_id, producer_epoch, |topic, partition| {
+                    sequences[&(topic.to_owned(), partition)]
+                });
+            }
+        }
+        __produce_messages(&mut self.conn_pool, config, reqs, required_acks == 0)
+    }
+}
+
+/// ~ determines the message format to use with the api identified by
+/// `api_key` (either produce or fetch); this is the configured
+/// message version downgraded to the newest one supported by the
+/// brokers if their api versions are known
+fn __negotiated_message_version(
+    state: &state::ClientState,
+    config: &ClientConfig,
+    api_key: i16,
+) -> i8 {
+    let versions = match state.api_versions() {
+        None => return config.message_version,
+        Some(versions) => versions,
+    };
+    let (max_api_version, max_message_version): (i16, fn(i16) -> i8) =
+        if api_key == protocol::API_KEY_PRODUCE {
+            (protocol::produce::MAX_API_VERSION, protocol::produce::max_message_version)
+        } else {
+            (protocol::fetch::MAX_API_VERSION, protocol::fetch::max_message_version)
+        };
+    match protocol::api_versions::select_version(versions, api_key, (0, max_api_version)) {
+        Some(v) if max_message_version(v) < config.message_version => max_message_version(v),
+        _ => config.message_version,
+    }
+}
+
+/// ~ reports the given offset for each of the topics' partitions with
+/// a known leader
+fn __exact_offsets<T: AsRef<str>>(
+    state: &state::ClientState,
+    topics: &[T],
+    offset: i64,
+) -> HashMap<String, Vec<PartitionOffset>> {
+    let mut res = HashMap::with_capacity(topics.len());
+    for topic in topics {
+        let topic = topic.as_ref();
+        if let Some(ps) = state.partitions_for(topic) {
+            let offsets = ps.iter()
+                .filter(|&(_, p)| p.broker(state).is_some())
+                .map(|(id, _)| {
+                    PartitionOffset {
+                        offset: offset,
+                        partition: id,
+                    }
+                })
+                .collect();
+            res.insert(topic.to_owned(), offsets);
+        }
+    }
+    res
+}
+
+fn __to_topic_result(t: &protocol::admin::TopicError) -> TopicResult {
+    TopicResult {
+        topic: t.topic.clone(),
+        result: t.to_result().map_err(|e| protocol::kafka_code(&e)),
+        error_code: t.error,
+    }
+}
+
+fn __to_config_entry(e: protocol::admin::ConfigEntryResponse) -> ConfigEntry {
+    ConfigEntry {
+        name: e.name,
+        value: e.value,
+        read_only: e.read_only != 0,
+        is_default: e.is_default != 0,
+        sensitive: e.is_sensitive != 0,
+    }
+}
+
+/// ~ attributes the given error reported by Kafka to the given topic
+/// partition; errors with codes unknown to this library are passed
+/// on as they are to preserve the code
+fn __partition_error(topic: String, partition: i32, e: Error) -> Error {
+    match e {
+        Error(ErrorKind::Kafka(code), _) => {
+            ErrorKind::TopicPartitionError(topic, partition, code).into()
+        }
+        e => e,
+    }
+}
+
+/// ~ collects the topic partitions which failed to accept messages due
+/// to the client having outdated information about their leaders
+fn __leader_errors(confirms: &[ProduceConfirm]) -> Vec<(String, i32)> {
+    let mut failed = Vec::new();
+    for confirm in confirms {
+        for pc in &confirm.partition_confirms {
+            match pc.offset {
+                Err(KafkaCode::NotLeaderForPartition) |
+                Err(KafkaCode::LeaderNotAvailable) |
+                Err(KafkaCode::UnknownTopicOrPartition) => {
+                    failed.push((confirm.topic.clone(), pc.partition));
+                }
+                _ => {}
+            }
+        }
+    }
+    failed
+}
+
+/// ~ collects the fetched topic partitions which failed due to the
+/// client having outdated information about their leaders
+fn __fetch_leader_errors(resps: &[fetch::Response]) -> Vec<(String, i32)> {
+    let mut failed = Vec::new();
+    for t in resps.iter().flat_map(|r| r.topics()) {
+        for p in t.partitions() {
+            match *p.data() {
+                Err(Error(ErrorKind::Kafka(KafkaCode::NotLeaderForPartition), _)) |
+                Err(Error(ErrorKind::Kafka(KafkaCode::LeaderNotAvailable), _)) => {
+                    failed.push((t.topic().to_owned(), p.partition()));
+                }
+                _ => {}
+            }
+        }
+    }
+    failed
+}
+
+/// ~ determines whether the given responses contain the given topic
+/// partition
+fn __fetched(resps: &[fetch::Response], topic: &str, partition: i32) -> bool {
+    resps
+        .iter()
+        .flat_map(|r| r.topics())
+        .filter(|t| t.topic() == topic)
+        .flat_map(|t| t.partitions())
+        .any(|p| p.partition() == partition)
+}
+
+/// ~ replaces the partition confirmations in `confirms` with the
+/// corresponding ones from `retried`
+fn __merge_produce_confirms(confirms: &mut Vec<ProduceConfirm>, retried: Vec<ProduceConfirm>) {
+    for r in retried {
+        let pos = confirms.iter().position(|c| c.topic == r.topic);
+        match pos {
+            None => confirms.push(r),
+            Some(i) => {
+                let pcs = &mut confirms[i].partition_confirms;
+                for rpc in r.partition_confirms {
+                    match pcs.iter().position(|pc| pc.partition == rpc.partition) {
+                        Some(j) => pcs[j] = rpc,
+                        None => pcs.push(rpc),
+                    }
                 }
             }
         }
-        __produce_messages(&mut self.conn_pool, reqs, required_acks == 0)
     }
 }
 
+/// ~ confirms the partitions whose messages the brokers rejected as
+/// duplicates of messages written earlier by the idempotent
+/// producer; reports whether all partitions are confirmed
+fn __accept_duplicate_confirms(confirms: &mut [ProduceConfirm]) -> bool {
+    let mut all_ok = true;
+    for pc in confirms.iter_mut().flat_map(|c| c.partition_confirms.iter_mut()) {
+        match pc.offset {
+            Err(KafkaCode::DuplicateSequenceNumber) => {
+                pc.offset = Ok(-1);
+                pc.error_code = 0;
+            }
+            Err(_) => all_ok = false,
+            Ok(_) => {}
+        }
+    }
+    all_ok
+}
+
 fn __get_group_coordinator<'a>(
     group: &str,
     state: &'a mut state::ClientState,
     conn_pool: &mut network::Connections,
     config: &ClientConfig,
+    max_attempts: u32,
     now: Instant,
 ) -> Result<&'a str> {
     if let Some(host) = state.group_coordinator(group) {
@@ -1352,9 +4328,13 @@ fn __get_group_coordinator<'a>(
         // been called yet; if there are no connections available we can
         // try connecting to the user specified bootstrap server similar
         // to the way `load_metadata` works.
-        let conn = conn_pool.get_conn_any(now).expect("available connection");
+        let mut conn = match conn_pool.get_conn_any(now) {
+            Some(conn) => conn,
+            None => bail!(ErrorKind::NoHostReachable(vec![])),
+        };
         debug!("get_group_coordinator: asking for coordinator of '{}' on: {:?}", group, conn);
-        let r = try!(__send_receive_conn::<_, protocol::GroupCoordinatorResponse>(conn, &req));
+        let r =
+            try!(__send_receive_conn::<_, protocol::GroupCoordinatorResponse>(&mut conn, &req));
         let retry_code;
         match r.to_result() {
             Ok(r) => {
@@ -1367,7 +4347,7 @@ fn __get_group_coordinator<'a>(
                 return Err(e);
             }
         }
-        if attempt < config.retry_max_attempts {
+        if attempt < max_attempts {
             debug!(
                 "get_group_coordinator: will retry request (c: {}) due to: {:?}",
                 req.header.correlation_id,
@@ -1392,7 +4372,14 @@ fn __commit_offsets(
         let now = Instant::now();
 
         let tps = {
-            let host = try!(__get_group_coordinator(req.group, state, conn_pool, config, now));
+            let host = try!(__get_group_coordinator(
+                req.group,
+                state,
+                conn_pool,
+                config,
+                config.retry_max_attempts,
+                now,
+            ));
             debug!("__commit_offsets: sending offset commit request '{:?}' to: {}", req, host);
             try!(__send_receive::<_, protocol::OffsetCommitResponse>(conn_pool, host, now, &req))
                 .topic_partitions
@@ -1404,19 +4391,19 @@ fn __commit_offsets(
             for p in tp.partitions {
                 match p.to_error() {
                     None => {}
-                    Some(e @ KafkaCode::GroupLoadInProgress) => {
+                    Some(Error(ErrorKind::Kafka(e @ KafkaCode::GroupLoadInProgress), _)) => {
                         retry_code = Some(e);
                         break 'rproc;
                     }
-                    Some(e @ KafkaCode::NotCoordinatorForGroup) => {
+                    Some(Error(ErrorKind::Kafka(e @ KafkaCode::NotCoordinatorForGroup), _)) => {
                         debug!("commit_offsets: resetting group coordinator for '{}'", req.group);
                         state.remove_group_coordinator(&req.group);
                         retry_code = Some(e);
                         break 'rproc;
                     }
-                    Some(code) => {
+                    Some(e) => {
                         // ~ immediately abort with the error
-                        bail!(ErrorKind::Kafka(code));
+                        return Err(e);
                     }
                 }
             }
@@ -1431,6 +4418,8 @@ fn __commit_offsets(
                     );
                     attempt += 1;
                     __retry_sleep(config);
+                } else {
+                    bail!(ErrorKind::Kafka(e));
                 }
             }
             None => {
@@ -1451,7 +4440,14 @@ fn __fetch_group_offsets(
         let now = Instant::now();
 
         let r = {
-            let host = try!(__get_group_coordinator(req.group, state, conn_pool, config, now));
+            let host = try!(__get_group_coordinator(
+                req.group,
+                state,
+                conn_pool,
+                config,
+                config.retry_max_attempts,
+                now,
+            ));
             debug!("fetch_group_offsets: sending request {:?} to: {}", req, host);
             try!(__send_receive::<_, protocol::OffsetFetchResponse>(conn_pool, host, now, &req))
         };
@@ -1515,6 +4511,199 @@ fn __fetch_group_offsets(
     }
 }
 
+/// ~ sends a request concerning the specified group to the group's
+/// coordinator, re-discovering the coordinator and retrying - up to
+/// `max_attempts` times - as necessary; `to_result` extracts the
+/// group level error from the response
+fn __send_group_request<T, V, F>(
+    group: &str,
+    req: T,
+    state: &mut state::ClientState,
+    conn_pool: &mut network::Connections,
+    config: &ClientConfig,
+    max_attempts: u32,
+    to_result: F,
+) -> Result<V::R>
+where
+    T: ToByte + std::fmt::Debug,
+    V: FromByte,
+    F: Fn(V::R) -> Result<V::R>,
+{
+    let mut attempt = 1;
+    loop {
+        let now = Instant::now();
+
+        let r = {
+            let host =
+                try!(__get_group_coordinator(group, state, conn_pool, config, max_attempts, now));
+            debug!("send_group_request: sending request {:?} to: {}", req, host);
+            try!(__send_receive::<_, V>(conn_pool, host, now, &req))
+        };
+
+        let retry_code;
+        match to_result(r) {
+            Ok(r) => {
+                return Ok(r);
+            }
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::NotCoordinatorForGroup), _)) => {
+                debug!("send_group_request: resetting group coordinator for '{}'", group);
+                state.remove_group_coordinator(group);
+                retry_code = e;
+            }
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::GroupCoordinatorNotAvailable), _)) |
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::GroupLoadInProgress), _)) => {
+                retry_code = e;
+            }
+            Err(e) => {
+                return Err(e);
+            }
+        }
+        if attempt < max_attempts {
+            debug!("send_group_request: will retry request {:?} due to: {:?}", req, retry_code);
+            attempt += 1;
+            __retry_sleep(config);
+        } else {
+            bail!(ErrorKind::Kafka(retry_code));
+        }
+    }
+}
+
+/// ~ decodes a member assignment of the "consumer" protocol into
+/// `(topic, partition)` pairs
+fn __from_member_assignment(assignment: Vec<u8>) -> Result<Vec<(String, i32)>> {
+    let mut partitions = Vec::new();
+    if !assignment.is_empty() {
+        let mut assignment = Cursor::new(assignment);
+        let assignment =
+            try!(protocol::group::ConsumerGroupMemberAssignment::decode_new(&mut assignment));
+        for tp in assignment.topic_partitions {
+            for p in tp.partitions {
+                partitions.push((tp.topic.clone(), p));
+            }
+        }
+    }
+    Ok(partitions)
+}
+
+/// ~ converts a described group into its public representation
+/// decoding the member metadata and assignments of groups of the
+/// "consumer" protocol type
+fn __to_group_description(g: protocol::group::DescribedGroup) -> Result<GroupDescription> {
+    let consumer_protocol = g.protocol_type == GROUP_PROTOCOL_TYPE;
+    let mut members = Vec::with_capacity(g.members.len());
+    for m in g.members {
+        let mut topics = Vec::new();
+        let mut assignment = Vec::new();
+        if consumer_protocol {
+            if !m.metadata.is_empty() {
+                let mut metadata = Cursor::new(m.metadata);
+                topics = try!(
+                    protocol::group::ConsumerGroupMemberMetadata::decode_new(&mut metadata)
+                ).topics;
+            }
+            assignment = try!(__from_member_assignment(m.assignment));
+        }
+        members.push(GroupMemberDescription {
+            member_id: m.member_id,
+            client_id: m.client_id,
+            client_host: m.client_host,
+            topics: topics,
+            assignment: assignment,
+        });
+    }
+    Ok(GroupDescription {
+        group: g.group,
+        state: g.state,
+        protocol_type: g.protocol_type,
+        protocol: g.protocol,
+        members: members,
+    })
+}
+
+/// ~ groups the given `(topic, partition)` pairs by topic into the
+/// assignment structure of the "consumer" group protocol
+fn __to_member_assignment(
+    partitions: &[(String, i32)],
+) -> protocol::group::ConsumerGroupMemberAssignment {
+    let mut tps: Vec<protocol::group::ConsumerGroupTopicAssignment> = Vec::new();
+    for &(ref topic, partition) in partitions {
+        match tps.iter().position(|tp| tp.topic == *topic) {
+            Some(i) => tps[i].partitions.push(partition),
+            None => {
+                tps.push(protocol::group::ConsumerGroupTopicAssignment {
+                    topic: topic.clone(),
+                    partitions: vec![partition],
+                })
+            }
+        }
+    }
+    protocol::group::ConsumerGroupMemberAssignment {
+        version: protocol::group::CONSUMER_PROTOCOL_VERSION,
+        topic_partitions: tps,
+        user_data: vec![],
+    }
+}
+
+/// ~ copies the data fetched for the requested topic partition out of
+/// the given responses
+fn __topic_messages(
+    state: &state::ClientState,
+    resps: &[fetch::Response],
+    req: &FetchPartition,
+) -> TopicMessages {
+    let mut tm = TopicMessages {
+        topic: req.topic.to_owned(),
+        partition: req.partition,
+        highwatermark_offset: -1,
+        truncated: false,
+        messages: Err(KafkaCode::UnknownTopicOrPartition),
+    };
+    let found = resps
+        .iter()
+        .flat_map(|r| r.topics())
+        .filter(|t| t.topic() == req.topic)
+        .flat_map(|t| t.partitions())
+        .find(|p| p.partition() == req.partition);
+    match found.map(|p| p.data()) {
+        Some(&Ok(ref data)) => {
+            tm.highwatermark_offset = data.highwatermark_offset();
+            tm.truncated = data.is_truncated();
+            tm.messages = Ok(data.messages()
+                .iter()
+                .map(|m| {
+                    FetchedMessage {
+                        offset: m.offset,
+                        key: m.key.to_vec(),
+                        value: m.value.to_vec(),
+                        timestamp: m.timestamp,
+                        headers: m.headers
+                            .iter()
+                            .map(|&(k, v)| (k.to_owned(), v.to_vec()))
+                            .collect(),
+                    }
+                })
+                .collect());
+        }
+        Some(&Err(ref e)) => {
+            tm.messages = Err(match *e.kind() {
+                ErrorKind::Kafka(code) => code,
+                _ => KafkaCode::Unknown,
+            });
+        }
+        None => {
+            // ~ not fetched at all since there is no known leader
+            let known = state
+                .partitions_for(req.topic)
+                .and_then(|tp| tp.partition(req.partition))
+                .is_some();
+            if known {
+                tm.messages = Err(KafkaCode::LeaderNotAvailable);
+            }
+        }
+    }
+    tm
+}
+
 /// ~ carries out the given fetch requests and returns the response
 fn __fetch_messages(
     conn_pool: &mut network::Connections,
@@ -1526,16 +4715,23 @@ fn __fetch_messages(
     for (host, req) in reqs {
         let p = protocol::fetch::ResponseParser {
             validate_crc: config.fetch_crc_validation,
+            max_message_set_depth: config.max_message_set_depth,
             requests: Some(&req),
         };
-        res.push(try!(__z_send_receive(conn_pool, host, now, &req, &p)));
+        let resp: fetch::Response = try!(__z_send_receive(conn_pool, host, now, &req, &p));
+        let stats = conn_pool.stats_mut();
+        stats.fetch_requests += 1;
+        stats.messages_fetched += __count_messages(&resp);
+        res.push(resp);
     }
+    __throttle(config, res.iter().map(|r| r.throttle_time()));
     Ok(res)
 }
 
 /// ~ carries out the given produce requests and returns the response
 fn __produce_messages(
     conn_pool: &mut network::Connections,
+    config: &ClientConfig,
     reqs: HashMap<&str, protocol::ProduceRequest>,
     no_acks: bool,
 ) -> Result<Vec<ProduceConfirm>> {
@@ -1543,21 +4739,53 @@ fn __produce_messages(
     if no_acks {
         for (host, req) in reqs {
             try!(__send_noack::<_, protocol::ProduceResponse>(conn_pool, host, now, req));
+            conn_pool.stats_mut().produce_requests += 1;
         }
         Ok(vec![])
     } else {
         let mut res: Vec<ProduceConfirm> = vec![];
+        let mut throttle_times = Vec::new();
         for (host, req) in reqs {
-            let resp =
-                try!(__send_receive::<_, protocol::ProduceResponse>(conn_pool, &host, now, req));
+            let p = protocol::produce::ResponseParser { api_version: req.header.api_version };
+            let resp = try!(__z_send_receive(conn_pool, host, now, req, &p));
+            conn_pool.stats_mut().produce_requests += 1;
+            throttle_times.push(resp.throttle_time);
             for tpo in resp.get_response() {
                 res.push(tpo);
             }
         }
+        __throttle(config, throttle_times.into_iter());
         Ok(res)
     }
 }
 
+/// ~ counts the messages delivered by the given fetch response
+fn __count_messages(resp: &fetch::Response) -> u64 {
+    let mut n = 0;
+    for t in resp.topics() {
+        for p in t.partitions() {
+            if let Ok(ref data) = *p.data() {
+                n += data.messages().len() as u64;
+            }
+        }
+    }
+    n
+}
+
+/// ~ sleeps for the longest of the given throttle times (in
+/// milliseconds) if the client is configured to honor these
+fn __throttle<I: Iterator<Item = i32>>(config: &ClientConfig, throttle_times: I) {
+    if !config.honor_throttle {
+        return;
+    }
+    if let Some(t) = throttle_times.max() {
+        if t > 0 {
+            debug!("throttled by broker; backing off for {}ms", t);
+            thread::sleep(Duration::from_millis(t as u64));
+        }
+    }
+}
+
 fn __send_receive<T, V>(
     conn_pool: &mut network::Connections,
     host: &str,
@@ -1568,16 +4796,20 @@ where
     T: ToByte,
     V: FromByte,
 {
-    __send_receive_conn::<T, V>(try!(conn_pool.get_conn(host, now)), req)
+    let r = __send_receive_conn::<T, V>(&mut try!(conn_pool.get_conn(host, now)), req);
+    __close_desynced(conn_pool, host, r)
 }
 
-fn __send_receive_conn<T, V>(conn: &mut network::KafkaConnection, req: T) -> Result<V::R>
+fn __send_receive_conn<T, V>(
+    conn: &mut dyn network::KafkaConnection,
+    req: T,
+) -> Result<V::R>
 where
     T: ToByte,
     V: FromByte,
 {
-    try!(__send_request(conn, req));
-    __get_response::<V>(conn)
+    let correlation_id = try!(__send_request(conn, req));
+    __get_response::<V>(conn, correlation_id)
 }
 
 fn __send_noack<T, V>(
@@ -1585,16 +4817,20 @@ fn __send_noack<T, V>(
     host: &str,
     now: Instant,
     req: T,
-) -> Result<usize>
+) -> Result<()>
 where
     T: ToByte,
     V: FromByte,
 {
     let mut conn = try!(conn_pool.get_conn(host, now));
-    __send_request(&mut conn, req)
+    __send_request(&mut conn, req).map(|_| ())
 }
 
-fn __send_request<T: ToByte>(conn: &mut network::KafkaConnection, request: T) -> Result<usize> {
+/// ~ sends the given request returning its correlation id
+fn __send_request<T: ToByte>(
+    conn: &mut dyn network::KafkaConnection,
+    request: T,
+) -> Result<i32> {
     // ~ buffer to receive data to be sent
     let mut buffer = Vec::with_capacity(4);
     // ~ reserve bytes for the actual request size (we'll fill in that later)
@@ -1607,29 +4843,84 @@ fn __send_request<T: ToByte>(conn: &mut network::KafkaConnection, request: T) ->
 
     trace!("__send_request: Sending bytes: {:?}", &buffer);
 
+    // ~ the correlation id follows the size, the api key, and the api
+    // version of the request
+    let correlation_id = try!(i32::decode_new(&mut Cursor::new(&buffer[8..12])));
     // ~ send the prepared buffer
-    conn.send(&buffer)
+    try!(conn.send(&buffer));
+    Ok(correlation_id)
+}
+
+/// ~ verifies the given response to answer the request with the
+/// given correlation id.  a mismatch means that the connection got
+/// out of sync with the broker - e.g. through a response left unread
+/// after an error - such that every further response read from it
+/// would be misinterpreted; the connection is shut down in that case.
+fn __check_correlation_id(
+    conn: &mut dyn network::KafkaConnection,
+    resp: &[u8],
+    expected: i32,
+) -> Result<()> {
+    match __correlation_id(resp) {
+        Some(got) if got != expected => __correlation_id_mismatch(conn, expected, got),
+        _ => Ok(()),
+    }
 }
 
-fn __get_response<T: FromByte>(conn: &mut network::KafkaConnection) -> Result<T::R> {
-    let size = try!(__get_response_size(conn));
-    let resp = try!(conn.read_exact_alloc(size as u64));
+/// ~ the correlation id of the given response; `None` if the
+/// response is too short to carry one (decoding it will fail anyway)
+fn __correlation_id(resp: &[u8]) -> Option<i32> {
+    if resp.len() < 4 {
+        None
+    } else {
+        i32::decode_new(&mut Cursor::new(&resp[..4])).ok()
+    }
+}
 
-    trace!("__get_response: received bytes: {:?}", &resp);
+fn __correlation_id_mismatch<T>(
+    conn: &mut dyn network::KafkaConnection,
+    expected: i32,
+    got: i32,
+) -> Result<T> {
+    warn!("Correlation id mismatch on {:?}: expected {}, got {}", conn, expected, got);
+    let _ = conn.shutdown();
+    bail!(ErrorKind::CorrelationIdMismatch(expected, got));
+}
 
-    // {
-    //     use std::fs::OpenOptions;
-    //     use std::io::Write;
-    //     let mut f = OpenOptions::new()
-    //         .write(true)
-    //         .truncate(true)
-    //         .create(true)
-    //         .open("/tmp/dump.dat")
-    //         .unwrap();
-    //     f.write_all(&resp[..]).unwrap();
-    // }
+/// ~ discards the pooled connection to `host` if it got out of sync
+/// with the broker (see `__check_correlation_id`) or a response was
+/// left unread on it (see `KafkaClient::set_max_response_size`) such
+/// that the next request establishes a new one
+fn __close_desynced<T>(
+    conn_pool: &mut network::Connections,
+    host: &str,
+    r: Result<T>,
+) -> Result<T> {
+    match r {
+        Err(Error(ErrorKind::CorrelationIdMismatch(..), _)) |
+        Err(Error(ErrorKind::ResponseTooLarge(..), _)) => conn_pool.close_conn(host),
+        _ => {}
+    }
+    r
+}
 
-    T::decode_new(&mut Cursor::new(resp))
+fn __get_response<T: FromByte>(
+    conn: &mut dyn network::KafkaConnection,
+    correlation_id: i32,
+) -> Result<T::R> {
+    // ~ the response is decoded right from the connection's buffer;
+    // `T::R` owns its data
+    let got = {
+        let resp = try!(conn.read_response_buffered());
+        match __correlation_id(resp) {
+            Some(got) if got != correlation_id => got,
+            _ => {
+                trace!("__get_response: received bytes: {:?}", resp);
+                return T::decode_new(&mut Cursor::new(resp));
+            }
+        }
+    };
+    __correlation_id_mismatch(conn, correlation_id, got)
 }
 
 fn __z_send_receive<R, P>(
@@ -1643,17 +4934,27 @@ where
     R: ToByte,
     P: ResponseParser,
 {
-    let mut conn = try!(conn_pool.get_conn(host, now));
-    try!(__send_request(&mut conn, req));
-    __z_get_response(&mut conn, parser)
+    let r = {
+        let mut conn = try!(conn_pool.get_conn(host, now));
+        let correlation_id = try!(__send_request(&mut conn, req));
+        __z_get_response(&mut conn, correlation_id, parser)
+    };
+    __close_desynced(conn_pool, host, r)
 }
 
-fn __z_get_response<P>(conn: &mut network::KafkaConnection, parser: &P) -> Result<P::T>
+fn __z_get_response<P>(
+    conn: &mut dyn network::KafkaConnection,
+    correlation_id: i32,
+    parser: &P,
+) -> Result<P::T>
 where
     P: ResponseParser,
 {
-    let size = try!(__get_response_size(conn));
-    let resp = try!(conn.read_exact_alloc(size as u64));
+    // ~ the response is read into a buffer lent out by the
+    // connection; the buffer returns once the parsed response - or
+    // the parser - is done with it
+    let resp = try!(conn.read_response_lent());
+    try!(__check_correlation_id(conn, &resp, correlation_id));
 
     // {
     //     use std::fs::OpenOptions;
@@ -1670,10 +4971,42 @@ where
     parser.parse(resp)
 }
 
-fn __get_response_size(conn: &mut network::KafkaConnection) -> Result<i32> {
-    let mut buf = [0u8; 4];
-    try!(conn.read_exact(&mut buf));
-    i32::decode_new(&mut Cursor::new(&buf))
+/// ~ the `NoHostReachable` error listing the given hosts along with
+/// the kind of their failure; chains the error of the last of them
+fn __no_host_reachable(mut failed: Vec<(String, Error)>) -> Error {
+    let hosts = failed
+        .iter()
+        .map(|&(ref host, ref e)| {
+            (host.clone(), e.io_error().map(|e| e.kind()).unwrap_or(io::ErrorKind::Other))
+        })
+        .collect();
+    match failed.pop() {
+        Some((_, e)) => Error::with_chain(e, ErrorKind::NoHostReachable(hosts)),
+        None => ErrorKind::NoHostReachable(hosts).into(),
+    }
+}
+
+// ~ determines whether metadata loaded at `loaded_at` exceeds
+// `max_age` at `now`; a zero max. age never does
+fn __metadata_stale(loaded_at: Option<Instant>, max_age: Duration, now: Instant) -> bool {
+    match loaded_at {
+        Some(t) if max_age != Duration::from_secs(0) => now.duration_since(t) >= max_age,
+        _ => false,
+    }
+}
+
+// ~ determines the socket timeout required to long poll a broker
+// for up to `max_wait_time` when fetching messages
+fn __long_poll_rw_timeout(
+    rw_timeout: Option<Duration>,
+    max_wait_time: Duration,
+) -> Option<Duration> {
+    match rw_timeout {
+        Some(t) if t <= max_wait_time => {
+            Some(max_wait_time + Duration::from_millis(FETCH_MAX_WAIT_TIME_MARGIN_MILLIS))
+        }
+        t => t,
+    }
 }
 
 /// Suspends the calling thread for the configured "retry" time. This
@@ -1681,3 +5014,930 @@ fn __get_response_size(conn: &mut network::KafkaConnection) -> Result<i32> {
 fn __retry_sleep(cfg: &ClientConfig) {
     thread::sleep(cfg.retry_backoff_time)
 }
+
+/// Like `__retry_sleep` but fails with `DeadlineExceeded` instead of
+/// sleeping if the given deadline would pass meanwhile.
+fn __retry_sleep_until(cfg: &ClientConfig, deadline: Option<Instant>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        if Instant::now() + cfg.retry_backoff_time >= deadline {
+            bail!(ErrorKind::DeadlineExceeded);
+        }
+    }
+    __retry_sleep(cfg);
+    Ok(())
+}
+
+/// ~ reports the given error of an operation as `DeadlineExceeded`
+/// if the operation's deadline has passed meanwhile
+fn __deadline_error(e: Error, deadline: Instant) -> Error {
+    match *e.kind() {
+        ErrorKind::DeadlineExceeded => e,
+        _ if Instant::now() >= deadline => Error::with_chain(e, ErrorKind::DeadlineExceeded),
+        _ => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::time::{Duration, Instant};
+
+    use client_internals::KafkaClientInternals;
+    use error::{Error, ErrorKind, KafkaCode};
+    use protocol;
+    use super::network::KafkaConnection;
+    use super::{KafkaClient, ClientStats, FetchPartition, ProduceConfirm, ProduceMessage,
+                ProducePartitionConfirm, RequiredAcks, DEFAULT_COMPRESSION_LEVEL,
+                DEFAULT_MESSAGE_VERSION, __accept_duplicate_confirms, __check_correlation_id,
+                __leader_errors, __merge_produce_confirms, __metadata_stale,
+                __retry_sleep_until, __to_group_description, __to_member_assignment};
+
+    fn confirm(topic: &str, pcs: Vec<(i32, Result<i64, KafkaCode>)>) -> ProduceConfirm {
+        ProduceConfirm {
+            topic: topic.to_owned(),
+            partition_confirms: pcs.into_iter()
+                .map(|(partition, offset)| {
+                    ProducePartitionConfirm {
+                        offset: offset,
+                        error_code: offset.err().map_or(0, |code| code.to_i16()),
+                        partition: partition,
+                        log_append_time: None,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_leader_errors() {
+        let confirms = vec![
+            confirm("foo", vec![(0, Ok(1)), (1, Err(KafkaCode::NotLeaderForPartition))]),
+            confirm("bar", vec![(0, Err(KafkaCode::MessageSizeTooLarge))]),
+            confirm("baz", vec![(2, Err(KafkaCode::LeaderNotAvailable))]),
+        ];
+        assert_eq!(
+            vec![("foo".to_owned(), 1), ("baz".to_owned(), 2)],
+            __leader_errors(&confirms)
+        );
+    }
+
+    #[test]
+    fn test_merge_produce_confirms() {
+        let mut confirms = vec![
+            confirm("foo", vec![(0, Ok(1)), (1, Err(KafkaCode::NotLeaderForPartition))]),
+        ];
+        __merge_produce_confirms(
+            &mut confirms,
+            vec![confirm("foo", vec![(1, Ok(10))]), confirm("bar", vec![(0, Ok(5))])],
+        );
+        assert_eq!(2, confirms.len());
+        assert_eq!("foo", confirms[0].topic);
+        assert_eq!(Ok(1), confirms[0].partition_confirms[0].offset);
+        assert_eq!(Ok(10), confirms[0].partition_confirms[1].offset);
+        assert_eq!("bar", confirms[1].topic);
+        assert_eq!(Ok(5), confirms[1].partition_confirms[0].offset);
+    }
+
+    #[test]
+    fn test_accept_duplicate_confirms() {
+        let mut confirms = vec![
+            confirm("foo", vec![(0, Ok(1)), (1, Err(KafkaCode::DuplicateSequenceNumber))]),
+        ];
+        assert!(__accept_duplicate_confirms(&mut confirms));
+        assert_eq!(Ok(-1), confirms[0].partition_confirms[1].offset);
+
+        let mut confirms = vec![
+            confirm("foo", vec![(0, Err(KafkaCode::OutOfOrderSequenceNumber))]),
+        ];
+        assert!(!__accept_duplicate_confirms(&mut confirms));
+    }
+
+    #[test]
+    fn test_idempotent_requires_acks_all_and_v2() {
+        let mut client = KafkaClient::new(vec![]);
+        client.set_idempotent(true);
+        assert!(client.idempotent());
+        for &(acks, version) in &[(RequiredAcks::One, 2), (RequiredAcks::All, 1)] {
+            client.set_message_version(version).unwrap();
+            let msg = ProduceMessage::new("foo", 0, None, Some(b"a"));
+            match client.produce_messages(acks, Duration::from_millis(100), vec![msg]) {
+                Err(Error(ErrorKind::InvalidRequest(_), _)) => {}
+                r => panic!("Expected InvalidRequest, but got: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_retry_sleep_until() {
+        let mut client = KafkaClient::new(vec![]);
+        client.set_operation_deadline(Some(Duration::from_secs(1)));
+        assert_eq!(Some(Duration::from_secs(1)), client.operation_deadline());
+        assert!(__retry_sleep_until(&client.config, None).is_ok());
+        match __retry_sleep_until(&client.config, Some(Instant::now())) {
+            Err(Error(ErrorKind::DeadlineExceeded, _)) => {}
+            r => panic!("Expected DeadlineExceeded, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_metadata_stale() {
+        let now = Instant::now();
+        let max_age = Duration::from_secs(60);
+        assert!(!__metadata_stale(None, max_age, now));
+        assert!(!__metadata_stale(Some(now), max_age, now + Duration::from_secs(59)));
+        assert!(__metadata_stale(Some(now), max_age, now + Duration::from_secs(60)));
+        // ~ a zero max. age disables the refresh
+        let zero = Duration::from_secs(0);
+        assert!(!__metadata_stale(Some(now), zero, now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_fetch_max_wait_time_extends_socket_timeout() {
+        let mut client = KafkaClient::new(vec![]);
+        client.set_fetch_max_wait_time(Duration::from_secs(1)).unwrap();
+        assert_eq!(Some(Duration::from_secs(120)), client.socket_timeout());
+
+        client.set_socket_timeout(Some(Duration::from_secs(10)));
+        client.set_fetch_max_wait_time(Duration::from_secs(30)).unwrap();
+        assert_eq!(Duration::from_secs(30), client.fetch_max_wait_time());
+        assert_eq!(Some(Duration::from_secs(35)), client.socket_timeout());
+
+        client.set_socket_timeout(None);
+        client.set_fetch_max_wait_time(Duration::from_secs(60)).unwrap();
+        assert_eq!(None, client.socket_timeout());
+    }
+
+    #[test]
+    fn test_set_message_version() {
+        let mut client = KafkaClient::new(vec![]);
+        assert_eq!(DEFAULT_MESSAGE_VERSION, client.message_version());
+        client.set_message_version(1).unwrap();
+        assert_eq!(1, client.message_version());
+        client.set_message_version(2).unwrap();
+        assert_eq!(2, client.message_version());
+        for &v in &[3, -1] {
+            match client.set_message_version(v) {
+                Err(Error(ErrorKind::UnsupportedProtocol, _)) => {}
+                r => panic!("Expected UnsupportedProtocol, but got: {:?}", r),
+            }
+        }
+        assert_eq!(2, client.message_version());
+    }
+
+    #[test]
+    fn test_create_partitions_invalid_count() {
+        use protocol::metadata::{MetadataResponse, TopicMetadata, PartitionMetadata};
+
+        let mut client = KafkaClient::new(vec![]);
+        let mut md = MetadataResponse::default();
+        md.topics.push(TopicMetadata {
+            error: 0,
+            topic: "foo".to_owned(),
+            partitions: (0..2)
+                .map(|id| PartitionMetadata { id: id, ..Default::default() })
+                .collect(),
+        });
+        client.state.update_metadata(md).unwrap();
+
+        for &n in &[2, 1] {
+            match client.create_partitions("foo", n) {
+                Err(Error(ErrorKind::InvalidPartitionCount(2, m), _)) if m == n => {}
+                r => panic!("Expected InvalidPartitionCount, but got: {:?}", r),
+            }
+        }
+        match client.create_partitions("bar", 4) {
+            Err(Error(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition), _)) => {}
+            r => panic!("Expected UnknownTopicOrPartition, but got: {:?}", r),
+        }
+    }
+
+    // ~ a connection serving canned responses instead of talking to
+    // a real broker
+    #[derive(Debug)]
+    pub struct MockConnection {
+        responses: VecDeque<Vec<u8>>,
+        // ~ the correlation id of the last sent request; echoed by
+        // the canned responses
+        correlation_id: [u8; 4],
+        buffer: Vec<u8>,
+    }
+
+    impl MockConnection {
+        pub fn new(responses: VecDeque<Vec<u8>>) -> MockConnection {
+            MockConnection {
+                responses: responses,
+                correlation_id: [0; 4],
+                buffer: Vec::new(),
+            }
+        }
+    }
+
+    impl KafkaConnection for MockConnection {
+        fn send(&mut self, msg: &[u8]) -> ::error::Result<usize> {
+            // ~ following the size, the api key, and the api version
+            self.correlation_id.copy_from_slice(&msg[8..12]);
+            Ok(msg.len())
+        }
+
+        fn read_response(&mut self) -> ::error::Result<Vec<u8>> {
+            match self.responses.pop_front() {
+                Some(mut resp) => {
+                    resp[..4].copy_from_slice(&self.correlation_id);
+                    Ok(resp)
+                }
+                None => bail!(ErrorKind::UnexpectedEOF),
+            }
+        }
+
+        fn read_response_buffered(&mut self) -> ::error::Result<&[u8]> {
+            self.buffer = try!(self.read_response());
+            Ok(&self.buffer)
+        }
+    }
+
+    // ~ a metadata (v0) response describing one broker
+    // ("kafka-7:9092") leading both partitions of the topic "foo"
+    fn mock_metadata_response() -> Vec<u8> {
+        let mut resp = vec![
+            0, 0, 0, 0, // correlation id
+            0, 0, 0, 1, // brokers: array length
+            0, 0, 0, 7, // node id
+            0, 7, // host: string length
+        ];
+        resp.extend_from_slice(b"kafka-7");
+        resp.extend_from_slice(&[
+            0, 0, 0x23, 0x84, // port: 9092
+            0, 0, 0, 1, // topics: array length
+            0, 0, // error
+            0, 3, // topic: string length
+        ]);
+        resp.extend_from_slice(b"foo");
+        resp.extend_from_slice(&[0, 0, 0, 2]); // partitions: array length
+        for id in 0..2 {
+            resp.extend_from_slice(&[
+                0, 0, // error
+                0, 0, 0, id, // partition id
+                0, 0, 0, 7, // leader
+                0, 0, 0, 1, 0, 0, 0, 7, // replicas
+                0, 0, 0, 1, 0, 0, 0, 7, // isr
+            ]);
+        }
+        resp
+    }
+
+    // ~ a client which loaded its metadata from a mock connection;
+    // see `mock_metadata_response`
+    pub fn mock_client() -> KafkaClient {
+        mock_client_with_versions(&[(protocol::API_KEY_METADATA, (0, 0))])
+    }
+
+    // ~ a client which negotiated the given api versions upon loading
+    // its metadata (in version 0) from a mock connection
+    fn mock_client_with_versions(apis: &[(i16, (i16, i16))]) -> KafkaClient {
+        let host = "mock:9092";
+        let mut client = KafkaClient::new(vec![host.to_owned()]);
+        let responses = vec![mock_api_versions_response(apis), mock_metadata_response()];
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn(host, Box::new(conn), Instant::now());
+        client.load_metadata_all().unwrap();
+        client
+    }
+
+    #[test]
+    fn test_negotiated_metadata_version() {
+        use codecs::ToByte;
+
+        // ~ a metadata (v5) response describing what
+        // `mock_metadata_response` describes
+        let mut md = Vec::new();
+        (0i32).encode(&mut md).unwrap(); // ~ correlation id
+        (0i32).encode(&mut md).unwrap(); // ~ throttle time
+        (1i32).encode(&mut md).unwrap();
+        (7i32).encode(&mut md).unwrap();
+        "kafka-7".encode(&mut md).unwrap();
+        (9092i32).encode(&mut md).unwrap();
+        (-1i16).encode(&mut md).unwrap(); // ~ rack
+        "cluster".encode(&mut md).unwrap();
+        (7i32).encode(&mut md).unwrap(); // ~ controller id
+        (1i32).encode(&mut md).unwrap();
+        (0i16).encode(&mut md).unwrap();
+        "foo".encode(&mut md).unwrap();
+        (0i8).encode(&mut md).unwrap(); // ~ is internal
+        (2i32).encode(&mut md).unwrap();
+        for id in 0..2 {
+            (0i16).encode(&mut md).unwrap();
+            (id as i32).encode(&mut md).unwrap();
+            (7i32).encode(&mut md).unwrap(); // ~ leader
+            for _ in 0..2 {
+                (1i32).encode(&mut md).unwrap();
+                (7i32).encode(&mut md).unwrap();
+            }
+            (0i32).encode(&mut md).unwrap(); // ~ offline replicas
+        }
+
+        let host = "mock:9092";
+        let mut client = KafkaClient::new(vec![host.to_owned()]);
+        let versions = mock_api_versions_response(&[(protocol::API_KEY_METADATA, (0, 9))]);
+        let conn = MockConnection::new(vec![versions, md].into_iter().collect());
+        client.conn_pool.insert_conn(host, Box::new(conn), Instant::now());
+        client.load_metadata_all().unwrap();
+        let topics = client.topics();
+        let partitions = topics.partitions("foo").unwrap();
+        assert_eq!(vec![0, 1], partitions.available_ids());
+        assert_eq!("kafka-7:9092", partitions.partition(1).unwrap().leader().unwrap().host());
+    }
+
+    #[test]
+    fn test_api_versions_not_supported() {
+        // ~ the first broker closes the connection upon the api
+        // versions request; it's not reachable thereafter
+        let hosts = vec!["127.0.0.1:1".to_owned(), "mock:9092".to_owned()];
+        let mut client = KafkaClient::new(hosts);
+        let old = MockConnection::new(VecDeque::new());
+        client.conn_pool.insert_conn("127.0.0.1:1", Box::new(old), Instant::now());
+        let responses = vec![mock_metadata_response(), mock_metadata_response()];
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn("mock:9092", Box::new(conn), Instant::now());
+
+        // ~ metadata is loaded in version 0 and the api versions are
+        // not requested again
+        for _ in 0..2 {
+            client.load_metadata_all().unwrap();
+            assert!(client.topics().contains("foo"));
+        }
+        assert!(client.state.api_versions().is_none());
+    }
+
+    #[test]
+    fn test_load_metadata_from_mock_connection() {
+        let client = mock_client();
+
+        let topics = client.topics();
+        let partitions = topics.partitions("foo").unwrap();
+        assert_eq!(vec![0, 1], partitions.available_ids());
+        let leader = partitions.partition(1).unwrap().leader().unwrap();
+        assert_eq!("kafka-7:9092", leader.host());
+        assert!(!topics.contains("bar"));
+
+        let md = client.topic_metadata("foo").unwrap();
+        assert_eq!(2, md.len());
+        assert_eq!((1, 7), (md[1].id, md[1].leader));
+        assert_eq!((vec![7], vec![7]), (md[1].replicas.clone(), md[1].isr.clone()));
+        assert_eq!(None, md[1].error);
+        assert!(!md[1].is_under_replicated());
+        assert!(client.topic_metadata("bar").is_none());
+    }
+
+    // ~ a client knowing "kafka-7:9092" as the coordinator of the
+    // group "g"; the coordinator answers with the given responses
+    fn mock_group_client(responses: Vec<Vec<u8>>) -> KafkaClient {
+        let mut client = mock_client();
+        let gc = protocol::GroupCoordinatorResponse {
+            header: protocol::HeaderResponse { correlation: 0 },
+            error: 0,
+            broker_id: 7,
+            port: 9092,
+            host: "kafka-7".to_owned(),
+        };
+        assert_eq!("kafka-7:9092", client.state.set_group_coordinator("g", &gc));
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+        client
+    }
+
+    #[test]
+    fn test_heartbeat_is_not_retried() {
+        // ~ a single response: the coordinator is not available; a
+        // retry would fail with `UnexpectedEOF`
+        let mut client = mock_group_client(vec![vec![0, 0, 0, 0, 0, 15]]);
+        match client.heartbeat("g", 1, "m") {
+            Err(Error(ErrorKind::Kafka(KafkaCode::GroupCoordinatorNotAvailable), _)) => {}
+            r => panic!("Expected GroupCoordinatorNotAvailable, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_leave_group_once() {
+        let mut client = mock_group_client(vec![vec![0, 0, 0, 0, 0, 15]]);
+        match client.internal_leave_group_once("g", "m") {
+            Err(Error(ErrorKind::Kafka(KafkaCode::GroupCoordinatorNotAvailable), _)) => {}
+            r => panic!("Expected GroupCoordinatorNotAvailable, but got: {:?}", r),
+        }
+    }
+
+    // ~ renders a produce response (v3) reporting the given error for
+    // the partition "foo/1"
+    pub fn mock_produce_response(error: i16) -> Vec<u8> {
+        use codecs::ToByte;
+
+        let mut data = Vec::new();
+        (0i32).encode(&mut data).unwrap(); // ~ correlation id
+        (1i32).encode(&mut data).unwrap();
+        "foo".encode(&mut data).unwrap();
+        (1i32).encode(&mut data).unwrap();
+        (1i32).encode(&mut data).unwrap(); // ~ partition
+        error.encode(&mut data).unwrap();
+        (100i64).encode(&mut data).unwrap(); // ~ offset
+        (-1i64).encode(&mut data).unwrap(); // ~ log append time
+        (0i32).encode(&mut data).unwrap(); // ~ throttle time
+        data
+    }
+
+    #[test]
+    fn test_produce_messages_resendable() {
+        let mut client = mock_client();
+        client.set_message_version(2).unwrap();
+        client.set_idempotent(true);
+        client.state.set_producer_id(Some((5, 0)));
+        let responses = vec![
+            mock_produce_response(KafkaCode::NotEnoughReplicas as i16),
+            mock_produce_response(0),
+            mock_produce_response(KafkaCode::NotEnoughReplicas as i16),
+        ];
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+        let msgs = vec![ProduceMessage::new("foo", 1, None, Some(b"a"))];
+
+        // ~ a failed attempt keeps the producer id and the sequences
+        let mut sequences = HashMap::new();
+        let cs = client.internal_produce_messages_resendable(-1, 1000, &msgs, &mut sequences);
+        assert_eq!(Err(KafkaCode::NotEnoughReplicas), cs.unwrap()[0].partition_confirms[0].offset);
+        assert_eq!(Some((5, 0)), client.state.producer_id());
+        assert_eq!(Some(&0), sequences.get(&("foo".to_owned(), 1)));
+
+        // ~ re-sending does not reserve new sequences
+        let cs = client.internal_produce_messages_resendable(-1, 1000, &msgs, &mut sequences);
+        assert_eq!(Ok(100), cs.unwrap()[0].partition_confirms[0].offset);
+        assert_eq!(Some(&0), sequences.get(&("foo".to_owned(), 1)));
+        assert_eq!(Some((5, 0)), client.state.producer_id());
+
+        // ~ without re-sending, a failure discards the producer id
+        let cs = client.internal_produce_messages(-1, 1000, &msgs);
+        assert_eq!(Err(KafkaCode::NotEnoughReplicas), cs.unwrap()[0].partition_confirms[0].offset);
+        assert_eq!(None, client.state.producer_id());
+    }
+
+    // ~ renders an api versions response (v0) announcing the given
+    // ranges of versions of the given apis
+    fn mock_api_versions_response(apis: &[(i16, (i16, i16))]) -> Vec<u8> {
+        use codecs::ToByte;
+
+        let mut data = Vec::new();
+        (0i32).encode(&mut data).unwrap(); // ~ correlation id
+        (0i16).encode(&mut data).unwrap(); // ~ error
+        (apis.len() as i32).encode(&mut data).unwrap();
+        for &(key, (min, max)) in apis {
+            key.encode(&mut data).unwrap();
+            min.encode(&mut data).unwrap();
+            max.encode(&mut data).unwrap();
+        }
+        data
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_requires_produce_v7() {
+        let mut client = mock_client_with_versions(&[(protocol::API_KEY_PRODUCE, (0, 5))]);
+        client.set_message_version(2).unwrap();
+        client.set_compression(super::Compression::ZSTD);
+        assert_eq!(Some(&(0, 5)), client.api_versions().unwrap().get(&protocol::API_KEY_PRODUCE));
+        // ~ the leader never responds; the request must not be sent
+        let leader = MockConnection::new(VecDeque::new());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(leader), Instant::now());
+
+        let msgs = vec![ProduceMessage::new("foo", 1, None, Some(b"a"))];
+        match client.internal_produce_messages(-1, 1000, &msgs) {
+            Err(Error(ErrorKind::UnsupportedCompression, _)) => {}
+            r => panic!("Expected UnsupportedCompression, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fetch_messages_multi() {
+        use codecs::ToByte;
+
+        let mut client = mock_client();
+        client.set_fetch_crc_validation(false);
+        // ~ report the leader errors rather than re-fetching
+        client.set_max_leader_retries(0);
+        // ~ a fetch response (v0) delivering one message for "foo/1"
+        // and an error for "foo/0"
+        let mut resp = Vec::new();
+        (0i32).encode(&mut resp).unwrap(); // ~ correlation id
+        (1i32).encode(&mut resp).unwrap();
+        "foo".encode(&mut resp).unwrap();
+        (2i32).encode(&mut resp).unwrap();
+        (1i32).encode(&mut resp).unwrap(); // ~ partition
+        (0i16).encode(&mut resp).unwrap(); // ~ error
+        (42i64).encode(&mut resp).unwrap(); // ~ highwatermark offset
+        (29i32).encode(&mut resp).unwrap(); // ~ message set size
+        (5i64).encode(&mut resp).unwrap(); // ~ offset
+        (17i32).encode(&mut resp).unwrap(); // ~ message size
+        (0i32).encode(&mut resp).unwrap(); // ~ crc
+        resp.extend_from_slice(&[0, 0]); // ~ magic and attributes
+        (-1i32).encode(&mut resp).unwrap(); // ~ key
+        (&b"abc"[..]).encode(&mut resp).unwrap(); // ~ value
+        (0i32).encode(&mut resp).unwrap(); // ~ partition
+        (KafkaCode::NotLeaderForPartition as i16).encode(&mut resp).unwrap();
+        (-1i64).encode(&mut resp).unwrap(); // ~ highwatermark offset
+        (0i32).encode(&mut resp).unwrap(); // ~ message set size
+        let conn = MockConnection::new(vec![resp].into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+
+        let reqs = [
+            FetchPartition::new("foo", 1, 0),
+            FetchPartition::new("bar", 0, 0),
+            FetchPartition::new("foo", 0, 0),
+        ];
+        let tms = client.fetch_messages_multi(&reqs).unwrap();
+        // ~ both partitions of "foo" fetched through a single request
+        assert_eq!(1, client.stats().fetch_requests);
+        // ~ delivered in the requested order
+        let tps: Vec<_> = tms.iter().map(|tm| (&tm.topic[..], tm.partition)).collect();
+        assert_eq!(vec![("foo", 1), ("bar", 0), ("foo", 0)], tps);
+        assert_eq!(42, tms[0].highwatermark_offset);
+        let msgs = tms[0].messages.as_ref().unwrap();
+        assert_eq!(
+            vec![(5, &b""[..], &b"abc"[..])],
+            msgs.iter().map(|m| (m.offset, &m.key[..], &m.value[..])).collect::<Vec<_>>()
+        );
+        assert_eq!(Err(KafkaCode::UnknownTopicOrPartition), tms[1].messages);
+        assert_eq!(Err(KafkaCode::NotLeaderForPartition), tms[2].messages);
+    }
+
+    #[test]
+    fn test_fetch_messages_max_message_set_depth() {
+        use codecs::ToByte;
+
+        let mut client = mock_client();
+        client.set_fetch_crc_validation(false);
+        client.set_max_message_set_depth(0);
+        assert_eq!(0, client.max_message_set_depth());
+        // ~ a fetch response (v0) delivering a single gzip compressed
+        // message for "foo/1"
+        let mut resp = Vec::new();
+        (0i32).encode(&mut resp).unwrap(); // ~ correlation id
+        (1i32).encode(&mut resp).unwrap();
+        "foo".encode(&mut resp).unwrap();
+        (1i32).encode(&mut resp).unwrap();
+        (1i32).encode(&mut resp).unwrap(); // ~ partition
+        (0i16).encode(&mut resp).unwrap(); // ~ error
+        (42i64).encode(&mut resp).unwrap(); // ~ highwatermark offset
+        (29i32).encode(&mut resp).unwrap(); // ~ message set size
+        (5i64).encode(&mut resp).unwrap(); // ~ offset
+        (17i32).encode(&mut resp).unwrap(); // ~ message size
+        (0i32).encode(&mut resp).unwrap(); // ~ crc
+        resp.extend_from_slice(&[0, 1]); // ~ magic and attributes
+        (-1i32).encode(&mut resp).unwrap(); // ~ key
+        (&b"abc"[..]).encode(&mut resp).unwrap(); // ~ value
+        let conn = MockConnection::new(vec![resp].into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+
+        // ~ the configured limit reaches the decoder, which rejects
+        // the compressed message without decompressing it
+        match client.fetch_messages(&[FetchPartition::new("foo", 1, 0)]) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fetch_messages_after_leader_move() {
+        use codecs::ToByte;
+
+        // ~ renders a fetch response (v0) for "foo/1" carrying either
+        // the given error or - if none - a single message at offset 5
+        fn fetch_response(error: i16) -> Vec<u8> {
+            let mut resp = Vec::new();
+            (0i32).encode(&mut resp).unwrap(); // ~ correlation id
+            (1i32).encode(&mut resp).unwrap();
+            "foo".encode(&mut resp).unwrap();
+            (1i32).encode(&mut resp).unwrap();
+            (1i32).encode(&mut resp).unwrap(); // ~ partition
+            error.encode(&mut resp).unwrap();
+            if error != 0 {
+                (-1i64).encode(&mut resp).unwrap(); // ~ highwatermark offset
+                (0i32).encode(&mut resp).unwrap(); // ~ message set size
+                return resp;
+            }
+            (6i64).encode(&mut resp).unwrap(); // ~ highwatermark offset
+            (29i32).encode(&mut resp).unwrap(); // ~ message set size
+            (5i64).encode(&mut resp).unwrap(); // ~ offset
+            (17i32).encode(&mut resp).unwrap(); // ~ message size
+            (0i32).encode(&mut resp).unwrap(); // ~ crc
+            resp.extend_from_slice(&[0, 0]); // ~ magic and attributes
+            (-1i32).encode(&mut resp).unwrap(); // ~ key
+            (&b"abc"[..]).encode(&mut resp).unwrap(); // ~ value
+            resp
+        }
+
+        let mut client = mock_client();
+        client.set_fetch_crc_validation(false);
+        client.set_retry_backoff_time(Duration::from_millis(1));
+        // ~ the former leader of "foo/1" rejects the fetch
+        let responses = vec![fetch_response(KafkaCode::NotLeaderForPartition as i16)];
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+        // ~ the refreshed metadata names "kafka-8" the new leader
+        let mut md = Vec::new();
+        (0i32).encode(&mut md).unwrap(); // ~ correlation id
+        (2i32).encode(&mut md).unwrap();
+        for &(id, host) in &[(7i32, "kafka-7"), (8, "kafka-8")] {
+            id.encode(&mut md).unwrap();
+            host.encode(&mut md).unwrap();
+            (9092i32).encode(&mut md).unwrap();
+        }
+        (1i32).encode(&mut md).unwrap();
+        (0i16).encode(&mut md).unwrap(); // ~ error
+        "foo".encode(&mut md).unwrap();
+        (2i32).encode(&mut md).unwrap();
+        for &(id, leader) in &[(0i32, 7i32), (1, 8)] {
+            (0i16).encode(&mut md).unwrap(); // ~ error
+            id.encode(&mut md).unwrap();
+            leader.encode(&mut md).unwrap();
+            (&[leader][..]).encode(&mut md).unwrap(); // ~ replicas
+            (&[leader][..]).encode(&mut md).unwrap(); // ~ isr
+        }
+        let conn = MockConnection::new(vec![md].into_iter().collect());
+        client.conn_pool.insert_conn("mock:9092", Box::new(conn), Instant::now());
+        let conn = MockConnection::new(vec![fetch_response(0)].into_iter().collect());
+        client.conn_pool.insert_conn("kafka-8:9092", Box::new(conn), Instant::now());
+
+        let resps = client.fetch_messages(&[FetchPartition::new("foo", 1, 0)]).unwrap();
+        // ~ the failed partition is replaced by the re-fetched one
+        let ps: Vec<_> = resps
+            .iter()
+            .flat_map(|r| r.topics())
+            .flat_map(|t| t.partitions())
+            .collect();
+        assert_eq!(1, ps.len());
+        assert_eq!(1, ps[0].partition());
+        let data = ps[0].data().as_ref().unwrap();
+        assert_eq!(6, data.highwatermark_offset());
+        assert_eq!(vec![5], data.messages().iter().map(|m| m.offset).collect::<Vec<_>>());
+        assert_eq!(2, client.stats().fetch_requests);
+        let topics = client.topics();
+        let leader = topics.partitions("foo").unwrap().partition(1).unwrap().leader().unwrap();
+        assert_eq!("kafka-8:9092", leader.host());
+    }
+
+    #[test]
+    fn test_topic_partition_count() {
+        let host = "mock:9092";
+        let mut client = mock_client();
+        // ~ answered from the loaded metadata
+        let received = client.stats().bytes_received;
+        assert_eq!(Some(2), client.topic_partition_count("foo").unwrap());
+        assert_eq!(received, client.stats().bytes_received);
+
+        // ~ an unknown topic triggers a refresh
+        let responses = vec![mock_metadata_response()].into_iter().collect();
+        let conn = MockConnection::new(responses);
+        client.conn_pool.insert_conn(host, Box::new(conn), Instant::now());
+        let loaded_at = client.state.metadata_loaded_at();
+        assert_eq!(None, client.topic_partition_count("bar").unwrap());
+        let md_size = mock_metadata_response().len() as u64 + 4;
+        assert_eq!(received + md_size, client.stats().bytes_received);
+        assert!(!client.topics().contains("bar"));
+        // ~ the metadata of the other topics survives the refresh
+        // without appearing any fresher
+        assert_eq!(loaded_at, client.state.metadata_loaded_at());
+        {
+            let topics = client.topics();
+            assert_eq!(vec![0, 1], topics.partitions("foo").unwrap().available_ids());
+        }
+        assert_eq!(Some(2), client.topic_partition_count("foo").unwrap());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut client = mock_client();
+        let stats = client.stats();
+        assert!(stats.bytes_sent > 0);
+        let versions = mock_api_versions_response(&[(protocol::API_KEY_METADATA, (0, 0))]);
+        assert_eq!(
+            (versions.len() + 4 + mock_metadata_response().len() + 4) as u64,
+            stats.bytes_received
+        );
+        assert_eq!(0, stats.produce_requests);
+        assert_eq!(0, stats.fetch_requests);
+
+        client.reset_stats();
+        assert_eq!(ClientStats::default(), client.stats());
+
+        let leader = MockConnection::new(VecDeque::new());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(leader), Instant::now());
+        let msgs = [ProduceMessage::new("foo", 0, None, Some(b"a"))];
+        client.produce_messages(RequiredAcks::None, Duration::from_secs(1), &msgs).unwrap();
+        let stats = client.stats();
+        assert_eq!(1, stats.produce_requests);
+        assert!(stats.bytes_sent > 0);
+        assert_eq!(0, stats.bytes_received);
+    }
+
+    #[test]
+    fn test_oversized_response_closes_connection() {
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+        use std::thread;
+
+        // ~ reads the next request and answers with the given data
+        // prefixed with the request's correlation id
+        fn respond(stream: &mut TcpStream, data: &[u8]) {
+            let mut size = [0u8; 4];
+            stream.read_exact(&mut size).unwrap();
+            let size = (size[0] as usize) << 24 | (size[1] as usize) << 16 |
+                (size[2] as usize) << 8 | size[3] as usize;
+            let mut req = vec![0u8; size];
+            stream.read_exact(&mut req).unwrap();
+            let mut resp = data.to_vec();
+            resp[..4].copy_from_slice(&req[4..8]);
+            let n = resp.len();
+            stream.write_all(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]).unwrap();
+            stream.write_all(&resp).unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let md = mock_metadata_response();
+        let max = md.len() - 1;
+        let server = thread::spawn(move || {
+            // ~ the first response exceeds the limit; the second
+            // request arrives on a new connection
+            let (mut first, _) = listener.accept().unwrap();
+            let mut oversized = md.clone();
+            oversized.push(0);
+            respond(&mut first, &oversized);
+            let (mut second, _) = listener.accept().unwrap();
+            respond(&mut second, &md);
+        });
+
+        let mut client = KafkaClient::new(vec![host]);
+        // ~ don't negotiate the api versions
+        client.state.set_api_versions(HashMap::new());
+        client.set_max_response_size(max);
+        match client.load_metadata_all() {
+            Err(Error(ErrorKind::ResponseTooLarge(size, m), _)) => {
+                assert_eq!((max as i32 + 2, max), (size, m));
+            }
+            r => panic!("Expected ResponseTooLarge, but got: {:?}", r),
+        }
+        client.set_max_response_size(max + 1);
+        client.load_metadata_all().unwrap();
+        assert!(client.topics().contains("foo"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_check_correlation_id() {
+        let mut conn = MockConnection::new(VecDeque::new());
+        let resp = [0, 0, 0, 7, 0, 0];
+        __check_correlation_id(&mut conn, &resp, 7).unwrap();
+        match __check_correlation_id(&mut conn, &resp, 8) {
+            Err(Error(ErrorKind::CorrelationIdMismatch(8, 7), _)) => {}
+            r => panic!("Expected CorrelationIdMismatch, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fetch_from_replica() {
+        let mut client = mock_client_with_versions(&[(protocol::API_KEY_FETCH, (0, 11))]);
+        match client.fetch_from_replica(8, &[FetchPartition::new("foo", 0, 0)]) {
+            Err(Error(ErrorKind::Kafka(KafkaCode::BrokerNotAvailable), _)) => {}
+            r => panic!("Expected BrokerNotAvailable, but got: {:?}", r),
+        }
+
+        // ~ a fetch (v11) response delivering no messages for "foo:0"
+        let mut resp = vec![
+            0, 0, 0, 0, // correlation id
+            0, 0, 0, 0, // throttle time
+            0, 0, // error
+            0, 0, 0, 0, // session id
+            0, 0, 0, 1, // topics: array length
+            0, 3, // topic: string length
+        ];
+        resp.extend_from_slice(b"foo");
+        resp.extend_from_slice(&[
+            0, 0, 0, 1, // partitions: array length
+            0, 0, 0, 0, // partition id
+            0, 0, // error
+            0, 0, 0, 0, 0, 0, 0, 42, // highwatermark offset
+            0, 0, 0, 0, 0, 0, 0, 42, // last stable offset
+            0, 0, 0, 0, 0, 0, 0, 0, // log start offset
+            0, 0, 0, 0, // aborted transactions: array length
+            0xff, 0xff, 0xff, 0xff, // preferred read replica
+            0, 0, 0, 0, // message set size
+        ]);
+        let replica = MockConnection::new(vec![resp].into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(replica), Instant::now());
+
+        let resps = client.fetch_from_replica(7, &[FetchPartition::new("foo", 0, 0)]).unwrap();
+        assert_eq!(1, resps.len());
+        let partition = &resps[0].topics()[0].partitions()[0];
+        assert_eq!(0, partition.partition());
+        assert_eq!(42, partition.data().as_ref().unwrap().highwatermark_offset());
+        assert_eq!(1, client.stats().fetch_requests);
+    }
+
+    #[test]
+    fn test_fetch_from_replica_requires_fetch_v11() {
+        let mut client = mock_client_with_versions(&[(protocol::API_KEY_FETCH, (0, 10))]);
+        // ~ the replica never responds; the request must not be sent
+        let replica = MockConnection::new(VecDeque::new());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(replica), Instant::now());
+
+        match client.fetch_from_replica(7, &[FetchPartition::new("foo", 0, 0)]) {
+            Err(Error(ErrorKind::UnsupportedProtocol, _)) => {}
+            r => panic!("Expected UnsupportedProtocol, but got: {:?}", r),
+        }
+        assert_eq!(0, client.stats().fetch_requests);
+    }
+
+    #[test]
+    fn test_produce_messages_without_acks() {
+        let mut client = mock_client();
+        // ~ the leader never responds; any attempt to read a response
+        // fails
+        let leader = MockConnection::new(VecDeque::new());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(leader), Instant::now());
+
+        let msgs = [ProduceMessage::new("foo", 0, None, Some(b"a"))];
+        for _ in 0..2 {
+            let confirms = client
+                .produce_messages(RequiredAcks::None, Duration::from_secs(1), &msgs)
+                .unwrap();
+            assert!(confirms.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_set_compression_level() {
+        let mut client = KafkaClient::new(vec![]);
+        assert_eq!(DEFAULT_COMPRESSION_LEVEL, client.compression_level());
+        client.set_compression_level(0).unwrap();
+        assert_eq!(0, client.compression_level());
+        client.set_compression_level(9).unwrap();
+        assert_eq!(9, client.compression_level());
+        match client.set_compression_level(10) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r),
+        }
+        assert_eq!(9, client.compression_level());
+    }
+
+    #[test]
+    fn test_to_group_description() {
+        use codecs::ToByte;
+        use protocol::group::{ConsumerGroupMemberMetadata, DescribedGroup, DescribedGroupMember,
+                              CONSUMER_PROTOCOL_VERSION};
+
+        let mut metadata = Vec::new();
+        ConsumerGroupMemberMetadata {
+            version: CONSUMER_PROTOCOL_VERSION,
+            topics: vec!["a".to_owned()],
+            user_data: vec![],
+        }.encode(&mut metadata)
+            .unwrap();
+        let mut assignment = Vec::new();
+        __to_member_assignment(&[("a".to_owned(), 0), ("a".to_owned(), 2)])
+            .encode(&mut assignment)
+            .unwrap();
+        let member = |metadata: &[u8], assignment: &[u8]| {
+            DescribedGroupMember {
+                member_id: "m".to_owned(),
+                client_id: "c".to_owned(),
+                client_host: "/127.0.0.1".to_owned(),
+                metadata: metadata.to_vec(),
+                assignment: assignment.to_vec(),
+            }
+        };
+
+        let d = __to_group_description(DescribedGroup {
+            error: 0,
+            group: "g".to_owned(),
+            state: "Stable".to_owned(),
+            protocol_type: "consumer".to_owned(),
+            protocol: "range".to_owned(),
+            members: vec![member(&metadata, &assignment)],
+        }).unwrap();
+        assert_eq!(("g", "Stable", "range"), (&d.group[..], &d.state[..], &d.protocol[..]));
+        assert_eq!(1, d.members.len());
+        assert_eq!(vec!["a".to_owned()], d.members[0].topics);
+        assert_eq!(
+            vec![("a".to_owned(), 0), ("a".to_owned(), 2)],
+            d.members[0].assignment
+        );
+
+        // ~ the member data of other protocol types is opaque
+        let d = __to_group_description(DescribedGroup {
+            error: 0,
+            group: "g".to_owned(),
+            state: "Stable".to_owned(),
+            protocol_type: "connect".to_owned(),
+            protocol: "default".to_owned(),
+            members: vec![member(b"xyz", b"xyz")],
+        }).unwrap();
+        assert!(d.members[0].topics.is_empty());
+        assert!(d.members[0].assignment.is_empty());
+    }
+}
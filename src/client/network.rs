@@ -6,15 +6,27 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use std::io::{Read, Write};
+use std::io::{self, Cursor, Read, Write};
 use std::mem;
-use std::net::{TcpStream, Shutdown};
+use std::net::{SocketAddr, TcpStream, Shutdown, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
 use std::time::{Instant, Duration};
 
+use byteorder::{BigEndian, ByteOrder};
 #[cfg(feature = "security")]
 use openssl::ssl::SslConnector;
 
-use error::Result;
+use codecs::FromByte;
+use error::{Error, ErrorKind, Result};
+use protocol::ResponseBuffer;
+
+use super::{ClientStats, DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_NODELAY};
+use super::sasl::{self, SaslConfig};
+
+// ~ the max. capacity of the response buffer a `TcpConnection`
+// retains across reads; see `read_response_buffered` and
+// `read_response_lent`
+const MAX_RETAINED_BUFFER_SIZE: usize = 1024 * 1024;
 
 // --------------------------------------------------------------------
 
@@ -74,36 +86,114 @@ impl<T: fmt::Debug> fmt::Debug for Pooled<T> {
     }
 }
 
+/// Maps the host name and port of a broker - as advertised by the
+/// cluster's metadata or given as a bootstrap host - to the address
+/// to actually connect to.  See `KafkaClient::set_address_resolver`.
+pub struct AddressResolver(Box<dyn Fn(&str, i32) -> SocketAddr + Send>);
+
+impl AddressResolver {
+    pub fn new(resolver: Box<dyn Fn(&str, i32) -> SocketAddr + Send>) -> AddressResolver {
+        AddressResolver(resolver)
+    }
+
+    // ~ resolves the given "host:port"; `None` if it doesn't denote
+    // a port
+    fn resolve(&self, host: &str) -> Option<SocketAddr> {
+        let port = match host.rfind(':') {
+            Some(i) if !host[i..].contains(']') => host[i + 1..].parse::<i32>().ok(),
+            _ => None,
+        };
+        port.map(|port| (self.0)(host_name(host), port))
+    }
+}
+
+impl fmt::Debug for AddressResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AddressResolver")
+    }
+}
+
 #[derive(Debug)]
 pub struct Config {
     rw_timeout: Option<Duration>,
+    // ~ the point in time by which the current operation must
+    // complete; see `Connections::set_deadline`
+    deadline: Option<Instant>,
     idle_timeout: Duration,
+    max_response_size: usize,
+    // ~ the socket options applied to newly established connections
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    address_resolver: Option<AddressResolver>,
+    // ~ whether to log the bytes sent and received; see `PooledConn`
+    wire_debug: bool,
     #[cfg(feature = "security")]
     security_config: Option<SecurityConfig>,
+    sasl_config: Option<SaslConfig>,
 }
 
 impl Config {
+    // ~ the read/write timeout to apply to connections; bounded by
+    // the time left until the deadline (if any.)  fails with
+    // `DeadlineExceeded` if the deadline has already passed.
+    fn effective_rw_timeout(&self) -> Result<Option<Duration>> {
+        let deadline = match self.deadline {
+            None => return Ok(self.rw_timeout),
+            Some(deadline) => deadline,
+        };
+        let now = Instant::now();
+        if now >= deadline {
+            bail!(ErrorKind::DeadlineExceeded);
+        }
+        let left = deadline - now;
+        Ok(Some(match self.rw_timeout {
+            Some(t) if t < left => t,
+            _ => left,
+        }))
+    }
+
     #[cfg(not(feature = "security"))]
-    fn new_conn(&self, id: u32, host: &str) -> Result<KafkaConnection> {
-        KafkaConnection::new(id, host, self.rw_timeout).map(|c| {
-            debug!("Established: {:?}", c);
-            c
-        })
+    fn new_conn(&self, id: u32, host: &str) -> Result<Box<dyn KafkaConnection>> {
+        let rw_timeout = try!(self.effective_rw_timeout());
+        let connect_timeout = self.deadline.and(rw_timeout);
+        let conn = try!(TcpConnection::new(
+            id,
+            host,
+            self.address_resolver.as_ref(),
+            rw_timeout,
+            connect_timeout,
+        ));
+        self.establish(conn)
     }
 
     #[cfg(feature = "security")]
-    fn new_conn(&self, id: u32, host: &str) -> Result<KafkaConnection> {
-        KafkaConnection::new(
+    fn new_conn(&self, id: u32, host: &str) -> Result<Box<dyn KafkaConnection>> {
+        let rw_timeout = try!(self.effective_rw_timeout());
+        let conn = try!(TcpConnection::new(
             id,
             host,
-            self.rw_timeout,
+            self.address_resolver.as_ref(),
+            rw_timeout,
+            self.deadline.and(rw_timeout),
             self.security_config.as_ref().map(|c| {
                 (c.connector.clone(), c.verify_hostname)
             }),
-        ).map(|c| {
-            debug!("Established: {:?}", c);
-            c
-        })
+        ));
+        self.establish(conn)
+    }
+
+    // ~ authenticates the given connection if so configured
+    fn establish(&self, mut conn: TcpConnection) -> Result<Box<dyn KafkaConnection>> {
+        conn.set_max_response_size(self.max_response_size);
+        try!(conn.set_socket_options(self.nodelay, self.keepalive));
+        if let Some(ref sasl_config) = self.sasl_config {
+            if let Err(e) = sasl::authenticate(&mut conn, sasl_config) {
+                let _ = conn.shutdown();
+                return Err(e);
+            }
+        }
+        debug!("Established: {:?}", conn);
+        Ok(Box::new(conn))
     }
 }
 
@@ -126,9 +216,12 @@ impl State {
 
 #[derive(Debug)]
 pub struct Connections {
-    conns: HashMap<String, Pooled<KafkaConnection>>,
+    conns: HashMap<String, Pooled<Box<dyn KafkaConnection>>>,
     state: State,
     config: Config,
+    // ~ the statistics about the traffic through the pooled
+    // connections; see `PooledConn`
+    stats: ClientStats,
 }
 
 impl Connections {
@@ -139,8 +232,16 @@ impl Connections {
             state: State::new(),
             config: Config {
                 rw_timeout: rw_timeout,
+                deadline: None,
                 idle_timeout: idle_timeout,
+                max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+                nodelay: DEFAULT_NODELAY,
+                keepalive: None,
+                address_resolver: None,
+                wire_debug: false,
+                sasl_config: None,
             },
+            stats: ClientStats::default(),
         }
     }
 
@@ -160,9 +261,17 @@ impl Connections {
             state: State::new(),
             config: Config {
                 rw_timeout: rw_timeout,
+                deadline: None,
                 idle_timeout: idle_timeout,
+                max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+                nodelay: DEFAULT_NODELAY,
+                keepalive: None,
+                address_resolver: None,
+                wire_debug: false,
                 security_config: security,
+                sasl_config: None,
             },
+            stats: ClientStats::default(),
         }
     }
 
@@ -174,7 +283,136 @@ impl Connections {
         self.config.idle_timeout
     }
 
-    pub fn get_conn<'a>(&'a mut self, host: &str, now: Instant) -> Result<&'a mut KafkaConnection> {
+    pub fn set_rw_timeout(&mut self, rw_timeout: Option<Duration>) {
+        self.config.rw_timeout = rw_timeout;
+        // ~ apply the new setting to the already pooled connections
+        // as well
+        for conn in self.conns.values() {
+            if let Err(e) = conn.item.set_rw_timeout(rw_timeout) {
+                warn!("Failed to set rw timeout on {:?}: {}", conn.item, e);
+            }
+        }
+    }
+
+    pub fn rw_timeout(&self) -> Option<Duration> {
+        self.config.rw_timeout
+    }
+
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.config.nodelay = nodelay;
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.config.nodelay
+    }
+
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) {
+        self.config.keepalive = keepalive;
+    }
+
+    pub fn keepalive(&self) -> Option<Duration> {
+        self.config.keepalive
+    }
+
+    /// Bounds the time spent establishing, writing to, and reading
+    /// from connections until the given deadline; checking out a
+    /// connection fails with `ErrorKind::DeadlineExceeded` once it
+    /// has passed.  `None` restores the plain read/write timeout.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        let restore = self.config.deadline.is_some() && deadline.is_none();
+        self.config.deadline = deadline;
+        if restore {
+            for conn in self.conns.values() {
+                if let Err(e) = conn.item.set_rw_timeout(self.config.rw_timeout) {
+                    warn!("Failed to set rw timeout on {:?}: {}", conn.item, e);
+                }
+            }
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.config.deadline
+    }
+
+    pub fn set_max_response_size(&mut self, max_response_size: usize) {
+        self.config.max_response_size = max_response_size;
+        for conn in self.conns.values_mut() {
+            conn.item.set_max_response_size(max_response_size);
+        }
+    }
+
+    pub fn max_response_size(&self) -> usize {
+        self.config.max_response_size
+    }
+
+    /// Applies the given sasl config to connections established
+    /// from now on; the currently pooled connections are closed.
+    pub fn set_sasl_config(&mut self, sasl_config: Option<SaslConfig>) {
+        self.config.sasl_config = sasl_config;
+        for (_, mut conn) in self.conns.drain() {
+            let _ = conn.item.shutdown();
+        }
+    }
+
+    pub fn sasl_config(&self) -> Option<&SaslConfig> {
+        self.config.sasl_config.as_ref()
+    }
+
+    /// Applies the given address resolver to connections established
+    /// from now on; `None` resolves the hosts through the system's
+    /// resolver.
+    pub fn set_address_resolver(&mut self, resolver: Option<AddressResolver>) {
+        self.config.address_resolver = resolver;
+    }
+
+    pub fn set_wire_debug(&mut self, wire_debug: bool) {
+        self.config.wire_debug = wire_debug;
+    }
+
+    pub fn wire_debug(&self) -> bool {
+        self.config.wire_debug
+    }
+
+    /// Shuts down and discards the pooled connection to `host` - if
+    /// any - such that the next request to it establishes a new one.
+    pub fn close_conn(&mut self, host: &str) {
+        if let Some(mut conn) = self.conns.remove(host) {
+            let _ = conn.item.shutdown();
+        }
+    }
+
+    /// Shuts down and discards all pooled connections.  All of them
+    /// are shut down even if some fail to; the first failure is
+    /// reported.
+    pub fn close_all(&mut self) -> Result<()> {
+        let mut first_err = None;
+        for (_, mut conn) in self.conns.drain() {
+            match conn.item.shutdown() {
+                // ~ the broker closed the connection already
+                Err(Error(ErrorKind::Io(ref e), _)) if e.kind() == io::ErrorKind::NotConnected => {}
+                Err(e) => {
+                    if first_err.is_none() {
+                        first_err = Some(e);
+                    }
+                }
+                Ok(_) => {}
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> &ClientStats {
+        &self.stats
+    }
+
+    pub fn stats_mut(&mut self) -> &mut ClientStats {
+        &mut self.stats
+    }
+
+    pub fn get_conn<'a>(&'a mut self, host: &str, now: Instant) -> Result<PooledConn<'a>> {
         if let Some(conn) = self.conns.get_mut(host) {
             if now.duration_since(conn.last_checkout) >= self.config.idle_timeout {
                 debug!("Idle timeout reached: {:?}", conn.item);
@@ -183,12 +421,20 @@ impl Connections {
                 conn.item = new_conn;
             }
             conn.last_checkout = now;
-            let kconn: &mut KafkaConnection = &mut conn.item;
+            if self.config.deadline.is_some() {
+                try!(conn.item.set_rw_timeout(try!(self.config.effective_rw_timeout())));
+            }
+            let kconn: &mut dyn KafkaConnection = &mut *conn.item;
             // ~ decouple the lifetimes to make the borrowck happy;
             // this is safe since we're immediatelly returning the
             // reference and the rest of the code in this method is
             // not affected
-            return Ok(unsafe { mem::transmute(kconn) });
+            return Ok(PooledConn {
+                conn: unsafe { mem::transmute(kconn) },
+                stats: &mut self.stats,
+                wire_debug: self.config.wire_debug,
+                last_request: None,
+            });
         }
         let cid = self.state.next_conn_id();
         self.conns.insert(
@@ -198,10 +444,15 @@ impl Connections {
                 try!(self.config.new_conn(cid, host)),
             ),
         );
-        Ok(&mut self.conns.get_mut(host).unwrap().item)
+        Ok(PooledConn {
+            conn: &mut *self.conns.get_mut(host).unwrap().item,
+            stats: &mut self.stats,
+            wire_debug: self.config.wire_debug,
+            last_request: None,
+        })
     }
 
-    pub fn get_conn_any(&mut self, now: Instant) -> Option<&mut KafkaConnection> {
+    pub fn get_conn_any(&mut self, now: Instant) -> Option<PooledConn> {
         for (host, conn) in &mut self.conns {
             if now.duration_since(conn.last_checkout) >= self.config.idle_timeout {
                 debug!("Idle timeout reached: {:?}", conn.item);
@@ -219,11 +470,263 @@ impl Connections {
                 conn.item = new_conn;
             }
             conn.last_checkout = now;
-            let kconn: &mut KafkaConnection = &mut conn.item;
-            return Some(kconn);
+            if self.config.deadline.is_some() {
+                match self.config.effective_rw_timeout() {
+                    Ok(t) => {
+                        if let Err(e) = conn.item.set_rw_timeout(t) {
+                            warn!("Failed to set rw timeout on {:?}: {}", conn.item, e);
+                        }
+                    }
+                    Err(_) => return None,
+                }
+            }
+            return Some(PooledConn {
+                conn: &mut *conn.item,
+                stats: &mut self.stats,
+                wire_debug: self.config.wire_debug,
+                last_request: None,
+            });
         }
         None
     }
+
+    /// Places the given, already established connection into the
+    /// pool as the connection to `host`, replacing any existing one.
+    #[cfg(test)]
+    pub fn insert_conn(&mut self, host: &str, conn: Box<dyn KafkaConnection>, now: Instant) {
+        self.conns.insert(host.to_owned(), Pooled::new(now, conn));
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// A connection checked out of `Connections` accounting the data
+/// sent and received through it with the pool's statistics.  If
+/// enabled through `Connections::set_wire_debug`, the sent requests
+/// and the received responses are logged as hex dumps at the trace
+/// level.
+pub struct PooledConn<'a> {
+    conn: &'a mut dyn KafkaConnection,
+    stats: &'a mut ClientStats,
+    wire_debug: bool,
+    // ~ the api key and version of the last request sent through this
+    // connection; used to describe the corresponding response
+    last_request: Option<(i16, i16)>,
+}
+
+impl<'a> fmt::Debug for PooledConn<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.conn)
+    }
+}
+
+impl<'a> PooledConn<'a> {
+    // ~ the description of the connection for the wire debug log;
+    // `None` if wire debugging is disabled
+    fn wire_debug_name(&self) -> Option<String> {
+        if self.wire_debug {
+            Some(format!("{:?}", self.conn))
+        } else {
+            None
+        }
+    }
+}
+
+// ~ accounts for the given response in the statistics and - if the
+// connection's name is given - logs it as a hex dump
+fn received(
+    stats: &mut ClientStats,
+    name: Option<String>,
+    last_request: Option<(i16, i16)>,
+    resp: &[u8],
+) {
+    // ~ including the size prefix
+    stats.bytes_received += resp.len() as u64 + 4;
+    if let Some(name) = name {
+        if resp.len() >= 4 {
+            let (api_key, api_version) = last_request.unwrap_or((-1, -1));
+            trace!(
+                "Response from {}: api key {}, version {}, correlation id {}, {} bytes\n{}",
+                name,
+                api_key,
+                api_version,
+                BigEndian::read_i32(resp),
+                resp.len(),
+                hex_dump(resp)
+            );
+        }
+    }
+}
+
+impl<'a> KafkaConnection for PooledConn<'a> {
+    fn send(&mut self, msg: &[u8]) -> Result<usize> {
+        if self.wire_debug && msg.len() >= 12 {
+            // ~ the size is followed by the api key, the api version,
+            // and the correlation id
+            let api_key = BigEndian::read_i16(&msg[4..]);
+            let api_version = BigEndian::read_i16(&msg[6..]);
+            self.last_request = Some((api_key, api_version));
+            trace!(
+                "Request to {:?}: api key {}, version {}, correlation id {}, {} bytes\n{}",
+                self.conn,
+                api_key,
+                api_version,
+                BigEndian::read_i32(&msg[8..]),
+                msg.len(),
+                hex_dump(msg)
+            );
+        }
+        let n = try!(self.conn.send(msg));
+        self.stats.bytes_sent += n as u64;
+        Ok(n)
+    }
+
+    fn read_response(&mut self) -> Result<Vec<u8>> {
+        let name = self.wire_debug_name();
+        let resp = try!(self.conn.read_response());
+        received(self.stats, name, self.last_request, &resp);
+        Ok(resp)
+    }
+
+    fn read_response_buffered(&mut self) -> Result<&[u8]> {
+        let name = self.wire_debug_name();
+        let resp = try!(self.conn.read_response_buffered());
+        received(self.stats, name, self.last_request, resp);
+        Ok(resp)
+    }
+
+    fn read_response_lent(&mut self) -> Result<ResponseBuffer> {
+        let name = self.wire_debug_name();
+        let resp = try!(self.conn.read_response_lent());
+        received(self.stats, name, self.last_request, &resp);
+        Ok(resp)
+    }
+
+    fn set_rw_timeout(&self, rw_timeout: Option<Duration>) -> Result<()> {
+        self.conn.set_rw_timeout(rw_timeout)
+    }
+
+    fn set_max_response_size(&mut self, max_response_size: usize) {
+        self.conn.set_max_response_size(max_response_size)
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.conn.shutdown()
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// A connection to a remote Kafka broker through which `KafkaClient`
+/// sends its requests and receives the corresponding responses.
+///
+/// Besides the real `TcpConnection`, this allows plugging in mock
+/// connections serving canned responses for testing purposes.
+pub trait KafkaConnection: fmt::Debug + Send {
+    /// Sends the given, already size-prefixed request data.
+    /// Implementations must either send the data completely or fail;
+    /// a partially sent request would corrupt the stream of
+    /// subsequent requests.
+    fn send(&mut self, msg: &[u8]) -> Result<usize>;
+
+    /// Reads the next response, returning its data without the
+    /// leading size.
+    fn read_response(&mut self) -> Result<Vec<u8>>;
+
+    /// Reads the next response like `read_response` but into a
+    /// buffer owned by the connection and reused across reads.  The
+    /// returned data is valid only until the next read.
+    fn read_response_buffered(&mut self) -> Result<&[u8]>;
+
+    /// Reads the next response like `read_response` but into a
+    /// buffer lent out by the connection.  The buffer returns to the
+    /// connection for reuse once the `ResponseBuffer` - and anything
+    /// parsed from it holding on to it - is dropped.
+    fn read_response_lent(&mut self) -> Result<ResponseBuffer> {
+        self.read_response().map(ResponseBuffer::from)
+    }
+
+    /// Applies the given read/write timeout to the connection.
+    fn set_rw_timeout(&self, _rw_timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Limits the size of the responses `read_response` is willing
+    /// to read; larger responses are to be rejected with
+    /// `ErrorKind::ResponseTooLarge`.
+    fn set_max_response_size(&mut self, _max_response_size: usize) {}
+
+    /// Closes the connection.
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Enables `SO_KEEPALIVE` on the given stream with the given idle time
+/// (in whole seconds, at least one) before the first keep-alive probe;
+/// `None` disables it.  The idle time is applied on Linux, Android,
+/// FreeBSD, DragonFly, macOS, and iOS; elsewhere on unix the system's
+/// default applies.  Enabling keep-alive fails on other platforms.
+#[cfg(unix)]
+fn set_keepalive(stream: &TcpStream, keepalive: Option<Duration>) -> io::Result<()> {
+    use std::cmp;
+    use std::os::unix::io::AsRawFd;
+    use libc::c_int;
+
+    let fd = stream.as_raw_fd();
+    try!(setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, keepalive.is_some() as c_int));
+    if let (Some(idle), Some(option)) = (keepalive, KEEPALIVE_IDLE_OPTION) {
+        let secs = cmp::min(cmp::max(idle.as_secs(), 1), c_int::max_value() as u64);
+        try!(setsockopt(fd, libc::IPPROTO_TCP, option, secs as c_int));
+    }
+    Ok(())
+}
+
+// ~ the socket option specifying the idle time before the first
+// keep-alive probe; `None` if not available
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+          target_os = "dragonfly"))]
+const KEEPALIVE_IDLE_OPTION: Option<libc::c_int> = Some(libc::TCP_KEEPIDLE);
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const KEEPALIVE_IDLE_OPTION: Option<libc::c_int> = Some(libc::TCP_KEEPALIVE);
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android", target_os = "freebsd",
+                        target_os = "dragonfly", target_os = "macos", target_os = "ios"))))]
+const KEEPALIVE_IDLE_OPTION: Option<libc::c_int> = None;
+
+#[cfg(not(unix))]
+fn set_keepalive(_stream: &TcpStream, keepalive: Option<Duration>) -> io::Result<()> {
+    match keepalive {
+        None => Ok(()),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "TCP keep-alive is not supported on this platform",
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn setsockopt(
+    fd: ::std::os::unix::io::RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let r = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if r == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
 }
 
 // --------------------------------------------------------------------
@@ -285,6 +788,14 @@ mod openssled {
             self.get_ref().set_write_timeout(dur)
         }
 
+        pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+            self.get_ref().set_nodelay(nodelay)
+        }
+
+        pub fn set_keepalive(&self, keepalive: Option<Duration>) -> io::Result<()> {
+            super::set_keepalive(self.get_ref(), keepalive)
+        }
+
         pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
             self.get_ref().shutdown(how)
         }
@@ -316,7 +827,7 @@ mod openssled {
 }
 
 /// A TCP stream to a remote Kafka broker.
-pub struct KafkaConnection {
+pub struct TcpConnection {
     // a surrogate identifier to distinguish between
     // connections to the same host in debug messages
     id: u32,
@@ -324,13 +835,20 @@ pub struct KafkaConnection {
     host: String,
     // the (wrapped) tcp stream
     stream: KafkaStream,
+    // the max. size of a response we're willing to allocate memory for
+    max_response_size: usize,
+    // the buffer reused across reads through `read_response_buffered`
+    buffer: Vec<u8>,
+    // the slot buffers lent out through `read_response_lent` return
+    // to once released
+    returned: Arc<Mutex<Vec<u8>>>,
 }
 
-impl fmt::Debug for KafkaConnection {
+impl fmt::Debug for TcpConnection {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "KafkaConnection {{ id: {}, secured: {}, host: \"{}\" }}",
+            "TcpConnection {{ id: {}, secured: {}, host: \"{}\" }}",
             self.id,
             self.stream.is_secured(),
             self.host
@@ -338,20 +856,121 @@ impl fmt::Debug for KafkaConnection {
     }
 }
 
-impl KafkaConnection {
-    pub fn send(&mut self, msg: &[u8]) -> Result<usize> {
-        let r = self.stream.write(&msg[..]).map_err(From::from);
+impl KafkaConnection for TcpConnection {
+    fn send(&mut self, msg: &[u8]) -> Result<usize> {
+        // ~ a single `write` may send the data only partially; for
+        // requests without a response (no acks) nobody would notice
+        let r = match self.stream.write_all(msg).and_then(|_| self.stream.flush()) {
+            Ok(_) => Ok(msg.len()),
+            Err(e) => Err(self.to_error(e)),
+        };
         trace!("Sent {} bytes to: {:?} => {:?}", msg.len(), self, r);
         r
     }
 
-    pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        let r = (&mut self.stream).read_exact(buf).map_err(From::from);
+    fn read_response(&mut self) -> Result<Vec<u8>> {
+        let size = try!(self.read_response_size());
+        self.read_exact_alloc(size as u64)
+    }
+
+    fn read_response_buffered(&mut self) -> Result<&[u8]> {
+        try!(self.read_into_buffer());
+        Ok(&self.buffer)
+    }
+
+    fn read_response_lent(&mut self) -> Result<ResponseBuffer> {
+        try!(self.read_into_buffer());
+        let data = mem::replace(&mut self.buffer, Vec::new());
+        Ok(ResponseBuffer::new(data, self.returned.clone()))
+    }
+
+    fn set_rw_timeout(&self, rw_timeout: Option<Duration>) -> Result<()> {
+        try!(self.stream.set_read_timeout(rw_timeout));
+        try!(self.stream.set_write_timeout(rw_timeout));
+        Ok(())
+    }
+
+    fn set_max_response_size(&mut self, max_response_size: usize) {
+        self.max_response_size = max_response_size;
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        let r = self.stream.shutdown(Shutdown::Both);
+        debug!("Shut down: {:?} => {:?}", self, r);
+        r.map_err(From::from)
+    }
+}
+
+impl TcpConnection {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let r = (&mut self.stream).read_exact(buf).map_err(|e| self.to_error(e));
         trace!("Read {} bytes from: {:?} => {:?}", buf.len(), self, r);
         r
     }
 
-    pub fn read_exact_alloc(&mut self, size: u64) -> Result<Vec<u8>> {
+    // ~ applies `TCP_NODELAY` and - if given - enables `SO_KEEPALIVE`
+    // with the given idle time before the first keep-alive probe
+    fn set_socket_options(&self, nodelay: bool, keepalive: Option<Duration>) -> Result<()> {
+        try!(self.stream.set_nodelay(nodelay));
+        #[cfg(not(feature = "security"))]
+        try!(set_keepalive(&self.stream, keepalive));
+        #[cfg(feature = "security")]
+        try!(self.stream.set_keepalive(keepalive));
+        Ok(())
+    }
+
+    // ~ reads the size of the next response
+    fn read_response_size(&mut self) -> Result<usize> {
+        let mut buf = [0u8; 4];
+        try!(self.read_exact(&mut buf));
+        let size = try!(i32::decode_new(&mut Cursor::new(&buf)));
+        // ~ a corrupted stream (or a misbehaving peer) must not make
+        // us allocate arbitrary amounts of memory; the unread response
+        // leaves the stream out of sync, hence, shut it down
+        if size < 0 || size as u64 > self.max_response_size as u64 {
+            warn!(
+                "Rejecting response of {} bytes (max: {}) from: {:?}",
+                size,
+                self.max_response_size,
+                self
+            );
+            let _ = self.shutdown();
+            if size < 0 {
+                bail!(ErrorKind::CodecError);
+            }
+            bail!(ErrorKind::ResponseTooLarge(size, self.max_response_size));
+        }
+        Ok(size as usize)
+    }
+
+    // ~ reads the next response into `self.buffer`; picks up the
+    // buffer lent out last if it has been returned meanwhile
+    fn read_into_buffer(&mut self) -> Result<()> {
+        let size = try!(self.read_response_size());
+        if self.buffer.capacity() == 0 {
+            if let Ok(mut returned) = self.returned.lock() {
+                mem::swap(&mut self.buffer, &mut *returned);
+            }
+        }
+        // ~ don't hold on to the memory of an exceptionally large
+        // response beyond the next read
+        if self.buffer.capacity() > MAX_RETAINED_BUFFER_SIZE {
+            self.buffer = Vec::new();
+        }
+        // ~ grow the buffer only if needed; the data previously read
+        // into it gets overwritten anyway
+        if self.buffer.len() < size {
+            self.buffer.resize(size, 0);
+        } else {
+            self.buffer.truncate(size);
+        }
+        let mut buffer = mem::replace(&mut self.buffer, Vec::new());
+        let r = self.read_exact(&mut buffer);
+        self.buffer = buffer;
+        r
+    }
+
+    fn read_exact_alloc(&mut self, size: u64) -> Result<Vec<u8>> {
         let size: usize = size as usize;
         let mut buffer: Vec<u8> = Vec::with_capacity(size);
         // this is safe actually: we are setting the len to the
@@ -363,10 +982,20 @@ impl KafkaConnection {
         Ok(buffer)
     }
 
-    fn shutdown(&mut self) -> Result<()> {
-        let r = self.stream.shutdown(Shutdown::Both);
-        debug!("Shut down: {:?} => {:?}", self, r);
-        r.map_err(From::from)
+    // ~ depending on the platform, an elapsed read/write timeout is
+    // reported as either `WouldBlock` or `TimedOut`; we unify these to
+    // the latter and name the affected host
+    fn to_error(&self, e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::WouldBlock |
+            io::ErrorKind::TimedOut => {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Timed out communicating with {}", self.host),
+                ).into()
+            }
+            _ => e.into(),
+        }
     }
 
     fn from_stream(
@@ -374,38 +1003,45 @@ impl KafkaConnection {
         id: u32,
         host: &str,
         rw_timeout: Option<Duration>,
-    ) -> Result<KafkaConnection> {
-        try!(stream.set_read_timeout(rw_timeout));
-        try!(stream.set_write_timeout(rw_timeout));
-        Ok(KafkaConnection {
+    ) -> Result<TcpConnection> {
+        let conn = TcpConnection {
             id: id,
             host: host.to_owned(),
             stream: stream,
-        })
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            buffer: Vec::new(),
+            returned: Arc::new(Mutex::new(Vec::new())),
+        };
+        try!(conn.set_rw_timeout(rw_timeout));
+        Ok(conn)
     }
 
     #[cfg(not(feature = "security"))]
-    fn new(id: u32, host: &str, rw_timeout: Option<Duration>) -> Result<KafkaConnection> {
-        KafkaConnection::from_stream(try!(TcpStream::connect(host)), id, host, rw_timeout)
+    fn new(
+        id: u32,
+        host: &str,
+        resolver: Option<&AddressResolver>,
+        rw_timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<TcpConnection> {
+        let stream = try!(connect(host, resolver, connect_timeout));
+        TcpConnection::from_stream(stream, id, host, rw_timeout)
     }
 
     #[cfg(feature = "security")]
     fn new(
         id: u32,
         host: &str,
+        resolver: Option<&AddressResolver>,
         rw_timeout: Option<Duration>,
+        connect_timeout: Option<Duration>,
         security: Option<(SslConnector, bool)>,
-    ) -> Result<KafkaConnection> {
-        let stream = try!(TcpStream::connect(host));
+    ) -> Result<TcpConnection> {
+        let stream = try!(connect(host, resolver, connect_timeout));
         let stream = match security {
             Some((connector, verify_hostname)) => {
                 let connection = if verify_hostname {
-                    let domain = match host.rfind(':') {
-                        None => host,
-                        Some(i) => &host[..i],
-                    };
-
-                    try!(connector.connect(domain, stream))
+                    try!(connector.connect(host_name(host), stream))
                 } else {
                     try!(connector.danger_connect_without_providing_domain_for_certificate_verification_and_server_name_indication(stream))
                 };
@@ -413,6 +1049,324 @@ impl KafkaConnection {
             }
             None => KafkaStream::Plain(stream),
         };
-        KafkaConnection::from_stream(stream, id, host, rw_timeout)
+        TcpConnection::from_stream(stream, id, host, rw_timeout)
+    }
+}
+
+/// Connects to the given "host:port" trying each of the addresses
+/// the host resolves to in turn.  Fails with the error of the last
+/// tried address if none of them is reachable.  A given timeout
+/// bounds each of the connection attempts.  A given resolver
+/// supersedes the system's resolver.
+fn connect(
+    host: &str,
+    resolver: Option<&AddressResolver>,
+    timeout: Option<Duration>,
+) -> Result<TcpStream> {
+    let addrs = match resolver.and_then(|r| r.resolve(host)) {
+        Some(addr) => vec![addr],
+        None => try!(host.to_socket_addrs()).collect(),
+    };
+    let mut last_err = None;
+    for addr in addrs {
+        let r = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        match r {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                debug!("Failed to connect to {} at {}: {}", host, addr, e);
+                last_err = Some(e);
+            }
+        }
+    }
+    Err(
+        last_err
+            .unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("Failed to resolve {}", host))
+            })
+            .into(),
+    )
+}
+
+/// Renders the given bytes as a hex dump; sixteen bytes per line
+/// prefixed by their offset and followed by their printable ascii
+/// characters.
+fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::with_capacity((data.len() / 16 + 1) * 78);
+    for (i, line) in data.chunks(16).enumerate() {
+        dump.push_str(&format!("{:08x} ", i * 16));
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => dump.push_str(&format!(" {:02x}", b)),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(line.iter().map(|&b| if b >= 0x20 && b < 0x7f { b as char } else { '.' }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+/// Extracts the host name from the given "host:port" stripping the
+/// brackets of ipv6 literals, e.g. "[::1]:9092" results in "::1".
+fn host_name(host: &str) -> &str {
+    let name = match host.rfind(':') {
+        // ~ a colon inside brackets is part of an ipv6 literal
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    if name.starts_with('[') && name.ends_with(']') {
+        &name[1..name.len() - 1]
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use error::{Error, ErrorKind};
+    use super::{AddressResolver, Connections, KafkaConnection, connect, hex_dump, host_name};
+
+    #[test]
+    fn test_host_name() {
+        assert_eq!("localhost", host_name("localhost:9092"));
+        assert_eq!("localhost", host_name("localhost"));
+        assert_eq!("10.0.0.1", host_name("10.0.0.1:9092"));
+        assert_eq!("::1", host_name("[::1]:9092"));
+        assert_eq!("::1", host_name("[::1]"));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!("", hex_dump(&[]));
+        assert_eq!(
+            concat!(
+                "00000000  00 01 02 6b 61 66 6b 61 2d 72 75 73 74 0a 7f 41  |...kafka-rust..A|\n",
+                "00000010  42                                               |B|\n"
+            ),
+            hex_dump(b"\x00\x01\x02kafka-rust\n\x7fAB")
+        );
+    }
+
+    #[test]
+    fn test_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // ~ "localhost" may resolve to ::1 as well as to 127.0.0.1;
+        // only the latter is reachable
+        connect(&format!("localhost:{}", port), None, None).unwrap();
+        assert!(connect("kafka-rust.invalid:9092", None, None).is_err());
+        // ~ ipv6 literals; provided ipv6 is available at all
+        if let Ok(listener) = TcpListener::bind("[::1]:0") {
+            let port = listener.local_addr().unwrap().port();
+            connect(&format!("[::1]:{}", port), None, Some(Duration::from_secs(1))).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_connect_through_address_resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let resolver = AddressResolver::new(Box::new(move |host, port| {
+            assert_eq!(("kafka-rust.invalid", 9092), (host, port));
+            addr
+        }));
+        connect("kafka-rust.invalid:9092", Some(&resolver), None).unwrap();
+        // ~ hosts without a port are resolved by the system
+        assert!(connect("kafka-rust.invalid", Some(&resolver), None).is_err());
+    }
+
+    #[test]
+    fn test_close_all() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        conns.get_conn(&host, Instant::now()).unwrap();
+        assert_eq!(1, conns.conns.len());
+        conns.close_all().unwrap();
+        assert!(conns.conns.is_empty());
+        // ~ the peer observes the end of the stream
+        let (mut stream, _) = listener.accept().unwrap();
+        assert_eq!(0, stream.read(&mut [0u8; 1]).unwrap());
+    }
+
+    #[test]
+    fn test_get_conn_reuses_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        let now = Instant::now();
+        let c1 = format!("{:?}", conns.get_conn(&host, now).unwrap());
+        let c2 = format!("{:?}", conns.get_conn(&host, now + Duration::from_secs(1)).unwrap());
+        assert_eq!(c1, c2);
+        assert_eq!(1, conns.state.num_conns);
+    }
+
+    #[test]
+    fn test_get_conn_applies_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        conns.set_nodelay(false);
+        conns.set_keepalive(Some(Duration::from_secs(30)));
+        assert!(!conns.nodelay());
+        assert_eq!(Some(Duration::from_secs(30)), conns.keepalive());
+        conns.get_conn(&host, Instant::now()).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_set_keepalive() {
+        use std::mem;
+        use std::net::TcpStream;
+        use std::os::unix::io::AsRawFd;
+        use libc::{self, c_int};
+
+        fn getsockopt(stream: &TcpStream, level: c_int, name: c_int) -> c_int {
+            let mut value: c_int = 0;
+            let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+            let r = unsafe {
+                libc::getsockopt(
+                    stream.as_raw_fd(),
+                    level,
+                    name,
+                    &mut value as *mut c_int as *mut libc::c_void,
+                    &mut len,
+                )
+            };
+            assert_eq!(0, r);
+            value
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        super::set_keepalive(&stream, Some(Duration::from_secs(45))).unwrap();
+        assert_eq!(1, getsockopt(&stream, libc::SOL_SOCKET, libc::SO_KEEPALIVE));
+        assert_eq!(45, getsockopt(&stream, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE));
+        super::set_keepalive(&stream, None).unwrap();
+        assert_eq!(0, getsockopt(&stream, libc::SOL_SOCKET, libc::SO_KEEPALIVE));
+    }
+
+    #[test]
+    fn test_get_conn_evicts_idle_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        let now = Instant::now();
+        let c1 = format!("{:?}", conns.get_conn(&host, now).unwrap());
+        let c2 = format!("{:?}", conns.get_conn(&host, now + Duration::from_secs(61)).unwrap());
+        assert!(c1 != c2);
+        assert_eq!(2, conns.state.num_conns);
+        assert_eq!(1, conns.conns.len());
+    }
+
+    #[test]
+    fn test_read_response_rejects_oversized_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // ~ announce a response of 1000 bytes
+            stream.write_all(&[0, 0, 3, 232]).unwrap();
+        });
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        conns.set_max_response_size(999);
+        let mut conn = conns.get_conn(&host, Instant::now()).unwrap();
+        match conn.read_response() {
+            Err(Error(ErrorKind::ResponseTooLarge(1000, 999), _)) => {}
+            r => panic!("Expected ResponseTooLarge, but got: {:?}", r),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_response_buffered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // ~ a response exceeding the retained buffer size
+            // followed by smaller ones
+            let large = super::MAX_RETAINED_BUFFER_SIZE as u32 + 1;
+            let size = [(large >> 24) as u8, (large >> 16) as u8, (large >> 8) as u8, large as u8];
+            stream.write_all(&size).unwrap();
+            stream.write_all(&vec![7; large as usize]).unwrap();
+            stream.write_all(&[0, 0, 0, 3, 1, 2, 3]).unwrap();
+            stream.write_all(&[0, 0, 0, 2, 4, 5]).unwrap();
+        });
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        let mut conn = conns.get_conn(&host, Instant::now()).unwrap();
+        {
+            let resp = conn.read_response_buffered().unwrap();
+            assert_eq!(super::MAX_RETAINED_BUFFER_SIZE + 1, resp.len());
+            assert!(resp.iter().all(|&b| b == 7));
+        }
+        assert_eq!(&[1u8, 2, 3][..], conn.read_response_buffered().unwrap());
+        assert_eq!(&[4u8, 5][..], conn.read_response_buffered().unwrap());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_response_lent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&[0, 0, 0, 3, 1, 2, 3]).unwrap();
+            stream.write_all(&[0, 0, 0, 2, 4, 5]).unwrap();
+            stream.write_all(&[0, 0, 0, 1, 6]).unwrap();
+        });
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        let mut conn = conns.get_conn(&host, Instant::now()).unwrap();
+        let first = conn.read_response_lent().unwrap();
+        assert_eq!(&[1u8, 2, 3][..], &*first);
+        // ~ a buffer still in use is not reused
+        let second = conn.read_response_lent().unwrap();
+        assert_eq!(&[4u8, 5][..], &*second);
+        assert!(first.as_ptr() != second.as_ptr());
+        let data = second.as_ptr();
+        drop(first);
+        drop(second);
+        // ~ the buffer released last is picked up by the next read
+        let third = conn.read_response_lent().unwrap();
+        assert_eq!(&[6u8][..], &*third);
+        assert_eq!(data, third.as_ptr());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_conn_honors_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let mut conns = Connections::new(None, Duration::from_secs(60));
+        conns.set_deadline(Some(Instant::now() + Duration::from_millis(100)));
+        {
+            // ~ a response which never arrives times out by the
+            // deadline
+            let mut conn = conns.get_conn(&host, Instant::now()).unwrap();
+            assert!(conn.read_response().is_err());
+        }
+        match conns.get_conn(&host, Instant::now()) {
+            Err(Error(ErrorKind::DeadlineExceeded, _)) => {}
+            r => panic!("Expected DeadlineExceeded, but got: {:?}", r),
+        }
+        conns.set_deadline(None);
+        assert!(conns.get_conn(&host, Instant::now()).is_ok());
     }
 }
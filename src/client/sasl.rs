@@ -0,0 +1,311 @@
+//! SASL authentication of broker connections.
+//!
+//! This module is crate private and not exposed to the public except
+//! through re-exports of individual items from within
+//! `kafka::client`.
+
+use std::fmt;
+use std::io;
+
+use codecs::ToByte;
+use error::{Error, ErrorKind, Result};
+use protocol;
+use protocol::api_versions::select_version;
+use protocol::sasl::SASL_HANDSHAKE_AUTHENTICATE_VERSION;
+
+use super::network::KafkaConnection;
+#[cfg(feature = "security")]
+use super::scram::{ScramClient, ScramDigest};
+use super::{__send_receive_conn, DEFAULT_CLIENT_ID};
+
+/// The SASL mechanism and credentials with which `KafkaClient`
+/// authenticates its connections to the brokers.  See
+/// `KafkaClient::set_sasl_config`.
+#[derive(Clone, PartialEq, Eq)]
+pub enum SaslConfig {
+    /// The `PLAIN` mechanism transmitting the given credentials as
+    /// they are.  Unless the connections are secured through TLS
+    /// (see `SecurityConfig`), this exposes the password to anybody
+    /// watching the network traffic.
+    Plain { username: String, password: String },
+
+    /// The `SCRAM-SHA-256` mechanism (RFC 5802, RFC 7677) proving
+    /// the knowledge of the password without transmitting it.
+    /// Supported by brokers as of Kafka 0.10.2.  The password is
+    /// used as is, i.e. without SASLprep normalization.
+    #[cfg(feature = "security")]
+    ScramSha256 { username: String, password: String },
+
+    /// The `SCRAM-SHA-512` mechanism; like `ScramSha256` but based on
+    /// SHA-512.  Supported by brokers as of Kafka 0.10.2.
+    #[cfg(feature = "security")]
+    ScramSha512 { username: String, password: String },
+}
+
+impl SaslConfig {
+    /// Creates a config for the `PLAIN` mechanism.
+    pub fn plain<U: Into<String>, P: Into<String>>(username: U, password: P) -> SaslConfig {
+        SaslConfig::Plain {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Creates a config for the `SCRAM-SHA-256` mechanism.
+    #[cfg(feature = "security")]
+    pub fn scram_sha256<U: Into<String>, P: Into<String>>(username: U, password: P) -> SaslConfig {
+        SaslConfig::ScramSha256 {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Creates a config for the `SCRAM-SHA-512` mechanism.
+    #[cfg(feature = "security")]
+    pub fn scram_sha512<U: Into<String>, P: Into<String>>(username: U, password: P) -> SaslConfig {
+        SaslConfig::ScramSha512 {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Retrieves the name of the mechanism as announced to the
+    /// brokers.
+    pub fn mechanism(&self) -> &'static str {
+        match *self {
+            SaslConfig::Plain { .. } => "PLAIN",
+            #[cfg(feature = "security")]
+            SaslConfig::ScramSha256 { .. } => "SCRAM-SHA-256",
+            #[cfg(feature = "security")]
+            SaslConfig::ScramSha512 { .. } => "SCRAM-SHA-512",
+        }
+    }
+
+    /// Retrieves the name of the user to authenticate as.
+    pub fn username(&self) -> &str {
+        match *self {
+            SaslConfig::Plain { ref username, .. } => username,
+            #[cfg(feature = "security")]
+            SaslConfig::ScramSha256 { ref username, .. } |
+            SaslConfig::ScramSha512 { ref username, .. } => username,
+        }
+    }
+}
+
+// ~ never render the password
+impl fmt::Debug for SaslConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SaslConfig {{ mechanism: {:?}, username: {:?} }}",
+            self.mechanism(),
+            self.username()
+        )
+    }
+}
+
+// ~ the authentication bytes of the PLAIN mechanism:
+// "<authzid>\0<username>\0<password>" with an empty authorization id
+fn plain_auth_bytes(username: &str, password: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(2 + username.len() + password.len());
+    bytes.push(0);
+    bytes.extend_from_slice(username.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(password.as_bytes());
+    bytes
+}
+
+/// Authenticates the given, freshly established connection.
+///
+/// Brokers supporting version 1 of the sasl handshake (Kafka 1.0) are
+/// sent the authentication bytes wrapped in `SaslAuthenticate`
+/// requests; older brokers expect them as raw, size-prefixed data.
+/// Fails with `ErrorKind::AuthenticationFailed` if the broker rejects
+/// the mechanism or the credentials.
+pub fn authenticate(conn: &mut dyn KafkaConnection, config: &SaslConfig) -> Result<()> {
+    let versions = try!(try!(__send_receive_conn::<_, protocol::ApiVersionsResponse>(
+        conn,
+        protocol::ApiVersionsRequest::new(0, DEFAULT_CLIENT_ID),
+    )).into_versions());
+    let version = select_version(
+        &versions,
+        protocol::API_KEY_SASL_HANDSHAKE,
+        (0, SASL_HANDSHAKE_AUTHENTICATE_VERSION),
+    ).unwrap_or(0);
+    debug!("Authenticating {:?} through sasl handshake v{}", conn, version);
+
+    try!(try!(__send_receive_conn::<_, protocol::SaslHandshakeResponse>(
+        conn,
+        protocol::SaslHandshakeRequest::new(version, config.mechanism(), 1, DEFAULT_CLIENT_ID),
+    )).to_result());
+
+    match *config {
+        SaslConfig::Plain {
+            ref username,
+            ref password,
+        } => {
+            try!(exchange(conn, version, &plain_auth_bytes(username, password)));
+            Ok(())
+        }
+        #[cfg(feature = "security")]
+        SaslConfig::ScramSha256 {
+            ref username,
+            ref password,
+        } => scram_exchange(conn, version, ScramDigest::Sha256, username, password),
+        #[cfg(feature = "security")]
+        SaslConfig::ScramSha512 {
+            ref username,
+            ref password,
+        } => scram_exchange(conn, version, ScramDigest::Sha512, username, password),
+    }
+}
+
+#[cfg(feature = "security")]
+fn scram_exchange(
+    conn: &mut dyn KafkaConnection,
+    version: i16,
+    digest: ScramDigest,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    let mut client = try!(ScramClient::new(digest, username, password));
+    let server_first = try!(exchange(conn, version, &client.client_first()));
+    let client_final = try!(client.client_final(&server_first));
+    let server_final = try!(exchange(conn, version, &client_final));
+    client.verify_server_final(&server_final)
+}
+
+// ~ sends the given authentication bytes to the broker and returns
+// the broker's reply
+fn exchange(conn: &mut dyn KafkaConnection, version: i16, auth_bytes: &[u8]) -> Result<Vec<u8>> {
+    if version >= SASL_HANDSHAKE_AUTHENTICATE_VERSION {
+        let resp = try!(try!(__send_receive_conn::<_, protocol::SaslAuthenticateResponse>(
+            conn,
+            protocol::SaslAuthenticateRequest::new(auth_bytes, 2, DEFAULT_CLIENT_ID),
+        )).to_result());
+        return Ok(resp.auth_bytes);
+    }
+    // ~ sasl handshake v0: the authentication bytes are exchanged
+    // without any kafka request framing
+    let mut buf = Vec::with_capacity(4 + auth_bytes.len());
+    try!(auth_bytes.encode(&mut buf));
+    try!(conn.send(&buf));
+    match conn.read_response() {
+        Ok(resp) => Ok(resp),
+        // ~ the broker closes the connection upon rejecting the
+        // credentials
+        Err(Error(ErrorKind::Io(ref e), _)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            bail!(ErrorKind::AuthenticationFailed(
+                "connection closed by the broker".to_owned(),
+            ))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io;
+
+    use error::{Error, ErrorKind, Result};
+    use client::network::KafkaConnection;
+    use super::{SaslConfig, authenticate, plain_auth_bytes};
+
+    #[derive(Debug)]
+    struct MockConnection {
+        sent: Vec<Vec<u8>>,
+        responses: VecDeque<Vec<u8>>,
+        buffer: Vec<u8>,
+    }
+
+    impl MockConnection {
+        fn new(responses: Vec<Vec<u8>>) -> MockConnection {
+            MockConnection {
+                sent: vec![],
+                responses: responses.into_iter().collect(),
+                buffer: Vec::new(),
+            }
+        }
+    }
+
+    impl KafkaConnection for MockConnection {
+        fn send(&mut self, msg: &[u8]) -> Result<usize> {
+            self.sent.push(msg.to_vec());
+            Ok(msg.len())
+        }
+
+        fn read_response(&mut self) -> Result<Vec<u8>> {
+            match self.responses.pop_front() {
+                Some(resp) => Ok(resp),
+                None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed").into()),
+            }
+        }
+
+        fn read_response_buffered(&mut self) -> Result<&[u8]> {
+            self.buffer = try!(self.read_response());
+            Ok(&self.buffer)
+        }
+    }
+
+    // ~ an api versions response announcing the given max. version
+    // of the sasl handshake api
+    fn api_versions(handshake_version: u8) -> Vec<u8> {
+        vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 17, 0, 0, 0, handshake_version]
+    }
+
+    static HANDSHAKE_OK: &'static [u8] = &[
+        0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 5, b'P', b'L', b'A', b'I', b'N',
+    ];
+
+    #[test]
+    fn test_plain_auth_bytes() {
+        assert_eq!(&b"\0user\0secret"[..], &plain_auth_bytes("user", "secret")[..]);
+        let cfg = SaslConfig::plain("user", "secret");
+        assert!(!format!("{:?}", cfg).contains("secret"));
+    }
+
+    #[test]
+    fn test_authenticate_v1() {
+        let mut conn = MockConnection::new(vec![
+            api_versions(1),
+            HANDSHAKE_OK.to_vec(),
+            vec![0, 0, 0, 2, 0, 0, 255, 255, 0, 0, 0, 0],
+        ]);
+        authenticate(&mut conn, &SaslConfig::plain("u", "p")).unwrap();
+        assert_eq!(3, conn.sent.len());
+        // ~ size, api key, api version
+        assert_eq!(&[0, 17, 0, 1], &conn.sent[1][4..8]);
+        assert_eq!(&[0, 36, 0, 0], &conn.sent[2][4..8]);
+        assert!(conn.sent[2].ends_with(b"\0\0\0\x04\0u\0p"));
+    }
+
+    #[test]
+    fn test_authenticate_v1_rejected() {
+        let mut conn = MockConnection::new(vec![
+            api_versions(1),
+            HANDSHAKE_OK.to_vec(),
+            vec![0, 0, 0, 2, 0, 58, 0, 3, b'b', b'a', b'd', 0, 0, 0, 0],
+        ]);
+        match authenticate(&mut conn, &SaslConfig::plain("u", "p")) {
+            Err(Error(ErrorKind::AuthenticationFailed(ref r), _)) if r == "bad" => {}
+            r => panic!("Expected AuthenticationFailed, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_authenticate_v0() {
+        let mut conn = MockConnection::new(vec![api_versions(0), HANDSHAKE_OK.to_vec(), vec![]]);
+        authenticate(&mut conn, &SaslConfig::plain("u", "p")).unwrap();
+        assert_eq!(&[0, 17, 0, 0], &conn.sent[1][4..8]);
+        // ~ the raw, size-prefixed authentication bytes
+        assert_eq!(&b"\0\0\0\x04\0u\0p"[..], &conn.sent[2][..]);
+
+        // ~ the broker closes the connection on rejection
+        let mut conn = MockConnection::new(vec![api_versions(0), HANDSHAKE_OK.to_vec()]);
+        match authenticate(&mut conn, &SaslConfig::plain("u", "p")) {
+            Err(Error(ErrorKind::AuthenticationFailed(_), _)) => {}
+            r => panic!("Expected AuthenticationFailed, but got: {:?}", r),
+        }
+    }
+}
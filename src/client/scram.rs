@@ -0,0 +1,366 @@
+//! The client side of the SCRAM authentication mechanism as
+//! specified by [RFC 5802](https://tools.ietf.org/html/rfc5802);
+//! used by `SaslConfig::ScramSha256` and `SaslConfig::ScramSha512`.
+//!
+//! The cryptographic primitives are provided by openssl, hence this
+//! module is available only with the `security` feature.
+
+use std::str;
+
+use openssl::hash::{hash, MessageDigest};
+use openssl::memcmp;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+
+use error::{ErrorKind, Result};
+
+/// The highest iteration count accepted from a server; a malicious
+/// or misconfigured server could otherwise have the client spend
+/// arbitrary time computing the salted password.
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// The hash function underlying a SCRAM mechanism.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScramDigest {
+    Sha256,
+    Sha512,
+}
+
+impl ScramDigest {
+    fn message_digest(&self) -> MessageDigest {
+        match *self {
+            ScramDigest::Sha256 => MessageDigest::sha256(),
+            ScramDigest::Sha512 => MessageDigest::sha512(),
+        }
+    }
+
+    // ~ the output size of the hash function in bytes
+    fn output_size(&self) -> usize {
+        match *self {
+            ScramDigest::Sha256 => 32,
+            ScramDigest::Sha512 => 64,
+        }
+    }
+
+    fn hash(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(try!(hash(self.message_digest(), data)).to_vec())
+    }
+
+    // ~ HMAC as specified by RFC 2104
+    fn hmac(&self, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        let key = try!(PKey::hmac(key));
+        let mut signer = try!(Signer::new(self.message_digest(), &key));
+        try!(signer.update(data));
+        Ok(try!(signer.sign_to_vec()))
+    }
+
+    // ~ "Hi()" of RFC 5802, i.e. PBKDF2 with hmac as the
+    // pseudorandom function and the output length of the hash
+    fn salted_password(&self, password: &[u8], salt: &[u8], iterations: u32) -> Result<Vec<u8>> {
+        let mut result = vec![0; self.output_size()];
+        try!(pbkdf2_hmac(
+            password,
+            salt,
+            iterations as usize,
+            self.message_digest(),
+            &mut result,
+        ));
+        Ok(result)
+    }
+}
+
+/// Drives one SCRAM authentication exchange: `client_first`,
+/// (server-first), `client_final`, (server-final),
+/// `verify_server_final`.
+pub struct ScramClient<'a> {
+    digest: ScramDigest,
+    username: &'a str,
+    password: &'a str,
+    nonce: String,
+    // ~ the signature expected in the server-final message; known
+    // once the client-final message has been created
+    server_signature: Option<Vec<u8>>,
+}
+
+impl<'a> ScramClient<'a> {
+    /// Prepares an exchange authenticating with the given
+    /// credentials under a freshly generated nonce.
+    pub fn new(
+        digest: ScramDigest,
+        username: &'a str,
+        password: &'a str,
+    ) -> Result<ScramClient<'a>> {
+        let mut nonce = [0u8; 24];
+        try!(rand_bytes(&mut nonce));
+        Ok(ScramClient::with_nonce(digest, username, password, base64_encode(&nonce)))
+    }
+
+    fn with_nonce(
+        digest: ScramDigest,
+        username: &'a str,
+        password: &'a str,
+        nonce: String,
+    ) -> ScramClient<'a> {
+        ScramClient {
+            digest: digest,
+            username: username,
+            password: password,
+            nonce: nonce,
+            server_signature: None,
+        }
+    }
+
+    // ~ the client-first message without the gs2 header
+    fn client_first_bare(&self) -> String {
+        format!("n={},r={}", escape_username(self.username), self.nonce)
+    }
+
+    /// Creates the client-first message.
+    pub fn client_first(&self) -> Vec<u8> {
+        // ~ no channel binding and no authorization identity
+        format!("n,,{}", self.client_first_bare()).into_bytes()
+    }
+
+    /// Creates the client-final message in response to the given
+    /// server-first message.
+    pub fn client_final(&mut self, server_first: &[u8]) -> Result<Vec<u8>> {
+        let server_first = try!(str::from_utf8(server_first));
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            let (name, value) = match attr.find('=') {
+                Some(i) => (&attr[..i], &attr[i + 1..]),
+                None => continue,
+            };
+            match name {
+                "r" => nonce = Some(value),
+                "s" => salt = base64_decode(value),
+                "i" => iterations = value.parse::<u32>().ok(),
+                "m" => return Err(failed("unsupported mandatory extension")),
+                "e" => return Err(failed(value)),
+                _ => {}
+            }
+        }
+        let nonce = match nonce {
+            // ~ the server must extend the nonce we've sent
+            Some(n) if n.starts_with(&self.nonce) && n.len() > self.nonce.len() => n,
+            Some(_) => return Err(failed("invalid server nonce")),
+            None => return Err(failed("missing server nonce")),
+        };
+        let salt = match salt {
+            Some(s) => s,
+            None => return Err(failed("missing or invalid salt")),
+        };
+        let iterations = match iterations {
+            Some(i) if i > MAX_ITERATIONS => return Err(failed("iteration count too large")),
+            Some(i) if i > 0 => i,
+            _ => return Err(failed("missing or invalid iteration count")),
+        };
+
+        let d = self.digest;
+        let salted_password = try!(d.salted_password(self.password.as_bytes(), &salt, iterations));
+        let client_key = try!(d.hmac(&salted_password, b"Client Key"));
+        let stored_key = try!(d.hash(&client_key));
+        // ~ "biws" is the base64 encoded gs2 header "n,,"
+        let without_proof = format!("c=biws,r={}", nonce);
+        let auth_message =
+            format!("{},{},{}", self.client_first_bare(), server_first, without_proof);
+        let client_signature = try!(d.hmac(&stored_key, auth_message.as_bytes()));
+        let mut client_proof = client_key;
+        xor(&mut client_proof, &client_signature);
+
+        let server_key = try!(d.hmac(&salted_password, b"Server Key"));
+        self.server_signature = Some(try!(d.hmac(&server_key, auth_message.as_bytes())));
+
+        Ok(format!("{},p={}", without_proof, base64_encode(&client_proof)).into_bytes())
+    }
+
+    /// Verifies the server-final message, i.e. that the server
+    /// accepted the client's proof and knows the credentials itself.
+    pub fn verify_server_final(&self, server_final: &[u8]) -> Result<()> {
+        let server_final = try!(str::from_utf8(server_final));
+        for attr in server_final.split(',') {
+            if attr.starts_with("e=") {
+                return Err(failed(&attr[2..]));
+            }
+            if attr.starts_with("v=") {
+                return match (base64_decode(&attr[2..]), self.server_signature.as_ref()) {
+                    // ~ compared in constant time
+                    (Some(ref v), Some(s)) if v.len() == s.len() && memcmp::eq(v, s) => Ok(()),
+                    _ => Err(failed("invalid server signature")),
+                };
+            }
+        }
+        Err(failed("missing server signature"))
+    }
+}
+
+fn failed(reason: &str) -> ::error::Error {
+    ErrorKind::AuthenticationFailed(format!("scram: {}", reason)).into()
+}
+
+fn xor(target: &mut [u8], other: &[u8]) {
+    for (t, o) in target.iter_mut().zip(other) {
+        *t ^= *o;
+    }
+}
+
+// ~ "=" and "," are the only characters requiring escaping in the
+// "saslname" production of RFC 5802
+fn escape_username(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+// ~ openssl 0.9 provides no base64 functions; `openssl::base64` is
+// available only as of openssl 0.10
+static BASE64_CHARS: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_right_matches('=');
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let v = match BASE64_CHARS.iter().position(|&x| x == c) {
+            Some(v) => v as u32,
+            None => return None,
+        };
+        n = n << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use error::{Error, ErrorKind};
+    use super::{ScramClient, ScramDigest, base64_decode, base64_encode, escape_username};
+
+    fn to_hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_base64() {
+        for &(plain, encoded) in &[
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("n,,", "biws"),
+        ]
+        {
+            assert_eq!(encoded, base64_encode(plain.as_bytes()));
+            assert_eq!(Some(plain.as_bytes().to_vec()), base64_decode(encoded));
+        }
+        assert_eq!(None, base64_decode("Zm9v,"));
+    }
+
+    #[test]
+    fn test_escape_username() {
+        assert_eq!("a=3Db=2Cc", escape_username("a=b,c"));
+    }
+
+    // ~ test case 2 of RFC 4231
+    #[test]
+    fn test_hmac() {
+        let key = b"Jefe";
+        let data = b"what do ya want for nothing?";
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            to_hex(&ScramDigest::Sha256.hmac(key, data).unwrap())
+        );
+        assert_eq!(
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            to_hex(&ScramDigest::Sha512.hmac(key, data).unwrap())
+        );
+    }
+
+    // ~ the example exchange of RFC 7677
+    #[test]
+    fn test_scram_sha256_exchange() {
+        let mut c = ScramClient::with_nonce(
+            ScramDigest::Sha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO".to_owned(),
+        );
+        assert_eq!(&b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO"[..], &c.client_first()[..]);
+        let client_final = c.client_final(
+            b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+              s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+        ).unwrap();
+        assert_eq!(
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+             p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+            String::from_utf8(client_final).unwrap()
+        );
+        c.verify_server_final(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .unwrap();
+        assert!(c.verify_server_final(b"v=AAAA").is_err());
+    }
+
+    #[test]
+    fn test_scram_failures() {
+        let mut c = ScramClient::new(ScramDigest::Sha512, "user", "pencil").unwrap();
+        // ~ the server did not extend our nonce
+        assert!(c.client_final(b"r=foo,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096").is_err());
+        match c.verify_server_final(b"e=invalid-proof") {
+            Err(Error(ErrorKind::AuthenticationFailed(ref r), _)) => {
+                assert_eq!("scram: invalid-proof", r)
+            }
+            r => panic!("Expected AuthenticationFailed, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_scram_iteration_count_limit() {
+        let server_first = |i: u32| {
+            format!("r=rOprNGfwEbeRWgbNEkqO%hvY,s=W22ZaJ0SNY7soEsUEjb6gQ==,i={}", i).into_bytes()
+        };
+        let mut c = ScramClient::with_nonce(
+            ScramDigest::Sha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO".to_owned(),
+        );
+        match c.client_final(&server_first(1_000_001)) {
+            Err(Error(ErrorKind::AuthenticationFailed(ref r), _)) => {
+                assert_eq!("scram: iteration count too large", r)
+            }
+            r => panic!("Expected AuthenticationFailed, but got: {:?}", r),
+        }
+        assert!(c.client_final(&server_first(4096)).is_ok());
+    }
+}
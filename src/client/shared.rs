@@ -0,0 +1,184 @@
+//! A `KafkaClient` shareable among multiple threads.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use error::Result;
+
+use super::{fetch, FetchOffset, FetchPartition, KafkaClient, PartitionOffset, ProduceConfirm,
+            ProduceMessage, RequiredAcks};
+
+/// A handle to a `KafkaClient` which can be cloned and shared among
+/// threads.
+///
+/// `KafkaClient` itself is `Send` but requires `&mut self` for nearly
+/// all of its operations since these update its metadata and
+/// connections.  `SharedClient` serializes the access to the wrapped
+/// client through a mutex: the methods of this type - as well as
+/// `SharedClient::lock` - block until the client is available and
+/// hold on to it for the duration of one complete operation
+/// (including any retries.)  Concurrent operations are hence safe but
+/// do not run in parallel; threads requiring a high throughput are
+/// better off with a client of their own.
+///
+/// If a thread panics while holding the lock, the client remains
+/// usable for the other threads; its metadata is, however, best
+/// reloaded in that case.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::thread;
+/// use std::time::Duration;
+/// use kafka::client::{KafkaClient, ProduceMessage, RequiredAcks, SharedClient};
+///
+/// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+/// client.load_metadata_all().unwrap();
+/// let client = SharedClient::new(client);
+///
+/// let threads: Vec<_> = (0..2).map(|i| {
+///     let client = client.clone();
+///     thread::spawn(move || {
+///         let value = format!("from thread {}", i);
+///         let msg = ProduceMessage::new("my-topic", i, None, Some(value.as_bytes()));
+///         client.produce_messages(RequiredAcks::One, Duration::from_secs(1), &[msg]).unwrap();
+///     })
+/// }).collect();
+/// for t in threads {
+///     t.join().unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedClient {
+    client: Arc<Mutex<KafkaClient>>,
+}
+
+impl SharedClient {
+    /// Wraps the given client for sharing.
+    pub fn new(client: KafkaClient) -> SharedClient {
+        SharedClient { client: Arc::new(Mutex::new(client)) }
+    }
+
+    /// Obtains exclusive access to the underlying client, blocking
+    /// until it is available.  The access is released once the
+    /// returned guard is dropped.
+    pub fn lock(&self) -> MutexGuard<KafkaClient> {
+        match self.client.lock() {
+            Ok(guard) => guard,
+            // ~ the client maintains no invariants spanning its
+            // operations which a panic could have broken
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// See `KafkaClient::load_metadata_all`.
+    pub fn load_metadata_all(&self) -> Result<()> {
+        self.lock().load_metadata_all()
+    }
+
+    /// See `KafkaClient::load_metadata`.
+    pub fn load_metadata<T: AsRef<str>>(&self, topics: &[T]) -> Result<()> {
+        self.lock().load_metadata(topics)
+    }
+
+    /// See `KafkaClient::fetch_offsets`.
+    pub fn fetch_offsets<T: AsRef<str>>(
+        &self,
+        topics: &[T],
+        offset: FetchOffset,
+    ) -> Result<HashMap<String, Vec<PartitionOffset>>> {
+        self.lock().fetch_offsets(topics, offset)
+    }
+
+    /// See `KafkaClient::fetch_messages`.
+    pub fn fetch_messages<'a, I, J>(&self, input: I) -> Result<Vec<fetch::Response>>
+    where
+        J: AsRef<FetchPartition<'a>>,
+        I: IntoIterator<Item = J>,
+    {
+        self.lock().fetch_messages(input)
+    }
+
+    /// See `KafkaClient::produce_messages`.
+    pub fn produce_messages<'a, 'b, I, J>(
+        &self,
+        acks: RequiredAcks,
+        ack_timeout: Duration,
+        messages: I,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        J: AsRef<ProduceMessage<'a, 'b>>,
+        I: IntoIterator<Item = J>,
+    {
+        self.lock().produce_messages(acks, ack_timeout, messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::SharedClient;
+    use client::{KafkaClient, ProduceMessage, RequiredAcks};
+    use client::tests::{mock_client, mock_produce_response, MockConnection};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_shared_client_is_send_sync() {
+        assert_send_sync::<SharedClient>();
+    }
+
+    #[test]
+    fn test_shared_client_serializes_access() {
+        let client = SharedClient::new(KafkaClient::new(vec![]));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let mut c = client.lock();
+                    let id = format!("{}-{}", c.client_id(), i);
+                    c.set_client_id(id);
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        // ~ no thread's update got lost
+        let id = client.lock().client_id().to_owned();
+        assert_eq!("kafka-rust".len() + 4 * 2, id.len());
+        for i in 0..4 {
+            assert!(id.contains(&format!("-{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_shared_client_concurrent_produce() {
+        let mut client = mock_client();
+        client.set_message_version(2).unwrap();
+        // ~ the leader of "foo/1" acknowledges one request per thread
+        let responses = vec![mock_produce_response(0), mock_produce_response(0)];
+        let conn = MockConnection::new(responses.into_iter().collect());
+        client.conn_pool.insert_conn("kafka-7:9092", Box::new(conn), Instant::now());
+        let client = SharedClient::new(client);
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let client = client.clone();
+                thread::spawn(move || {
+                    let msgs = vec![ProduceMessage::new("foo", 1, None, Some(b"a"))];
+                    client.produce_messages(RequiredAcks::One, Duration::from_secs(1), msgs)
+                })
+            })
+            .collect();
+        for t in threads {
+            let cs = t.join().unwrap().unwrap();
+            assert_eq!(1, cs.len());
+            assert_eq!(Ok(100), cs[0].partition_confirms[0].offset);
+        }
+        assert_eq!(2, client.lock().stats().produce_requests);
+    }
+}
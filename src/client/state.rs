@@ -1,9 +1,10 @@
 use std::collections::hash_map::{HashMap, Entry, Keys};
 use std::convert::AsRef;
 use std::slice;
+use std::time::Instant;
 use std::u32;
 
-use error::Result;
+use error::{Error, ErrorKind, KafkaCode, Result};
 use protocol;
 
 #[derive(Debug)]
@@ -24,8 +25,35 @@ pub struct ClientState {
     // ~ a mapping of topic to information about its partitions
     topic_partitions: HashMap<String, TopicPartitions>,
 
+    // ~ the time at which metadata was last loaded; `None` if no
+    // metadata is loaded (see `ClientState::update_metadata`)
+    metadata_loaded_at: Option<Instant>,
+
     // ~ a mapping of groups to their coordinators
     group_coordinators: HashMap<String, BrokerRef>,
+
+    // ~ the controller of the cluster as last discovered; `None` if
+    // not (yet) known (see `KafkaClient::load_controller`)
+    controller: Option<Broker>,
+
+    // ~ the api versions supported by the brokers mapped by api key
+    // to the inclusive range `(min, max)`; `None` until loaded (see
+    // `KafkaClient::api_versions`)
+    api_versions: Option<HashMap<i16, (i16, i16)>>,
+
+    // ~ whether the api versions were requested upon loading metadata;
+    // if that failed (e.g. for brokers older than 0.10) they are not
+    // requested again implicitly
+    api_versions_requested: bool,
+
+    // ~ the id and epoch assigned to this client as an idempotent
+    // producer; `None` until obtained (see
+    // `KafkaClient::set_idempotent`)
+    producer_id: Option<(i64, i16)>,
+
+    // ~ the sequence number of the next message produced to a topic
+    // partition under `producer_id`
+    sequences: HashMap<(String, i32), i32>,
 }
 
 // --------------------------------------------------------------------
@@ -148,16 +176,48 @@ impl<'a> IntoIterator for &'a TopicPartitions {
 #[derive(Debug)]
 pub struct TopicPartition {
     broker: BrokerRef,
+    // ~ the node id of the leader as reported by the cluster; -1 if
+    // there is none
+    leader: i32,
+    replicas: Vec<i32>,
+    isr: Vec<i32>,
+    error: Option<Error>,
 }
 
 impl TopicPartition {
     fn new() -> TopicPartition {
-        TopicPartition { broker: BrokerRef::new(UNKNOWN_BROKER_INDEX) }
+        TopicPartition {
+            broker: BrokerRef::new(UNKNOWN_BROKER_INDEX),
+            leader: -1,
+            replicas: vec![],
+            isr: vec![],
+            error: None,
+        }
     }
 
     pub fn broker<'a>(&self, state: &'a ClientState) -> Option<&'a Broker> {
         state.brokers.get(self.broker.index())
     }
+
+    pub fn leader_id(&self) -> i32 {
+        self.leader
+    }
+
+    pub fn replicas(&self) -> &[i32] {
+        &self.replicas
+    }
+
+    pub fn isr(&self) -> &[i32] {
+        &self.isr
+    }
+
+    pub fn error(&self) -> Option<KafkaCode> {
+        self.error.as_ref().map(protocol::kafka_code)
+    }
+
+    pub fn error_code(&self) -> i16 {
+        self.error.as_ref().and_then(Error::kafka_code).unwrap_or(0)
+    }
 }
 
 /// An iterator over a topic's partitions.
@@ -201,7 +261,13 @@ impl ClientState {
             correlation: 0,
             brokers: Vec::new(),
             topic_partitions: HashMap::new(),
+            metadata_loaded_at: None,
             group_coordinators: HashMap::new(),
+            controller: None,
+            api_versions: None,
+            api_versions_requested: false,
+            producer_id: None,
+            sequences: HashMap::new(),
         }
     }
 
@@ -229,10 +295,75 @@ impl ClientState {
         &self.topic_partitions
     }
 
+    pub fn brokers(&self) -> &[Broker] {
+        &self.brokers
+    }
+
     pub fn partitions_for<'a>(&'a self, topic: &str) -> Option<&'a TopicPartitions> {
         self.topic_partitions.get(topic)
     }
 
+    pub fn metadata_loaded_at(&self) -> Option<Instant> {
+        self.metadata_loaded_at
+    }
+
+    pub fn controller(&self) -> Option<&Broker> {
+        self.controller.as_ref()
+    }
+
+    /// Remembers the given node id and "host:port" as the cluster's
+    /// controller; `None` forgets the current one.
+    pub fn set_controller(&mut self, controller: Option<(i32, String)>) {
+        self.controller = controller.map(|(node_id, host)| {
+            Broker {
+                node_id: node_id,
+                host: host,
+            }
+        });
+    }
+
+    pub fn api_versions(&self) -> Option<&HashMap<i16, (i16, i16)>> {
+        self.api_versions.as_ref()
+    }
+
+    pub fn set_api_versions(&mut self, versions: HashMap<i16, (i16, i16)>) {
+        self.api_versions = Some(versions);
+    }
+
+    pub fn api_versions_requested(&self) -> bool {
+        self.api_versions_requested
+    }
+
+    pub fn set_api_versions_requested(&mut self) {
+        self.api_versions_requested = true;
+    }
+
+    pub fn producer_id(&self) -> Option<(i64, i16)> {
+        self.producer_id
+    }
+
+    /// Sets the idempotent producer id and epoch; the sequence
+    /// numbers of all partitions start over.  `None` forgets the
+    /// current producer id.
+    pub fn set_producer_id(&mut self, producer_id: Option<(i64, i16)>) {
+        self.producer_id = producer_id;
+        self.sequences.clear();
+    }
+
+    /// Reserves `n` sequence numbers for the given topic partition
+    /// and returns the first of them.  Sequence numbers wrap around
+    /// to zero after `i32::MAX` (just like Kafka expects them to.)
+    pub fn reserve_sequences(&mut self, topic: &str, partition: i32, n: i32) -> i32 {
+        let seq = self.sequences.entry((topic.to_owned(), partition)).or_insert(0);
+        let base = *seq;
+        *seq = if base > i32::max_value() - n {
+            n - (i32::max_value() - base) - 1
+        } else {
+            base + n
+        };
+        base
+    }
+
     pub fn next_correlation_id(&mut self) -> i32 {
         self.correlation = (self.correlation + 1) % (1i32 << 30);
         self.correlation
@@ -252,11 +383,22 @@ impl ClientState {
         // through `BrokerIndex`
         self.topic_partitions.clear();
         self.brokers.clear();
+        self.controller = None;
+        self.metadata_loaded_at = None;
     }
 
     /// Loads new and updates existing metadata from the given
     /// metadata response.
     pub fn update_metadata(&mut self, md: protocol::MetadataResponse) -> Result<()> {
+        self.metadata_loaded_at = Some(Instant::now());
+        self.merge_metadata(md)
+    }
+
+    /// Like `update_metadata` but for a response covering only some
+    /// of the topics; leaves the time of the last metadata load
+    /// untouched such that the metadata of the other topics does
+    /// not appear any fresher.
+    pub fn merge_metadata(&mut self, md: protocol::MetadataResponse) -> Result<()> {
         debug!("updating metadata from: {:?}", md);
 
         // ~ register new brokers with self.brokers and obtain an
@@ -289,12 +431,24 @@ impl ClientState {
             };
             // ~ sync the partitions vector with the new information
             for partition in t.partitions {
+                let error = partition.to_error();
+                // ~ a partition reported with an error (e.g. during a
+                // leader election) is considered leaderless; except
+                // if only some of its replicas are not available
+                let has_leader = match error {
+                    None |
+                    Some(Error(ErrorKind::Kafka(KafkaCode::ReplicaNotAvailable), _)) => true,
+                    Some(_) => false,
+                };
                 let tp = &mut tps[partition.id as usize];
-                if let Some(bref) = brokers.get(&partition.leader) {
-                    tp.broker.set(*bref)
-                } else {
-                    tp.broker.set_unknown()
+                match brokers.get(&partition.leader) {
+                    Some(bref) if has_leader => tp.broker.set(*bref),
+                    _ => tp.broker.set_unknown(),
                 }
+                tp.leader = partition.leader;
+                tp.replicas = partition.replicas;
+                tp.isr = partition.isr;
+                tp.error = error;
             }
         }
         Ok(())
@@ -341,10 +495,13 @@ impl ClientState {
     /// ~ Retrieves the host:port of the coordinator for the specified
     /// group - if any.
     pub fn group_coordinator<'a>(&'a self, group: &str) -> Option<&'a str> {
-        self.group_coordinators
-            .get(group)
-            .and_then(|b| self.brokers.get(b.index()))
-            .map(|b| &b.host[..])
+        self.group_coordinator_broker(group).map(|b| &b.host[..])
+    }
+
+    /// ~ Retrieves the coordinator broker for the specified group - if
+    /// any.
+    pub fn group_coordinator_broker<'a>(&'a self, group: &str) -> Option<&'a Broker> {
+        self.group_coordinators.get(group).and_then(|b| self.brokers.get(b.index()))
     }
 
     /// ~ Removes the current coordinator - if any - for the specified
@@ -402,6 +559,7 @@ impl ClientState {
 #[cfg(test)]
 mod tests {
     use super::ClientState;
+    use error::KafkaCode;
     use protocol;
     use protocol::metadata as md;
 
@@ -636,4 +794,141 @@ mod tests {
         state.update_metadata(metadata_response_update()).unwrap();
         assert_updated_metadata_load(&state);
     }
+
+    #[test]
+    fn test_loading_metadata_with_partition_errors() {
+        let mut md = metadata_response_initial();
+        {
+            let ps = &mut md.topics[0].partitions;
+            ps[0].error = KafkaCode::LeaderNotAvailable.to_i16();
+            ps[1].error = KafkaCode::ReplicaNotAvailable.to_i16();
+        }
+        let mut state = ClientState::new();
+        state.update_metadata(md).unwrap();
+
+        let ps = state.partitions_for("tee-one").unwrap();
+        let p0 = ps.partition(0).unwrap();
+        assert!(p0.broker(&state).is_none());
+        assert_eq!(50, p0.leader_id());
+        assert_eq!(Some(KafkaCode::LeaderNotAvailable), p0.error());
+        let p1 = ps.partition(1).unwrap();
+        assert_eq!(Some(10), p1.broker(&state).map(|b| b.id()));
+        assert_eq!(Some(KafkaCode::ReplicaNotAvailable), p1.error());
+        assert_eq!(None, ps.partition(2).unwrap().error());
+
+        // ~ the partition becomes available again once its leader is
+        // reported without error
+        state.update_metadata(metadata_response_initial()).unwrap();
+        let p0 = state.partitions_for("tee-one").unwrap().partition(0).unwrap();
+        assert_eq!(Some(50), p0.broker(&state).map(|b| b.id()));
+        assert_eq!(None, p0.error());
+    }
+
+    #[test]
+    fn test_group_coordinator() {
+        let mut state = ClientState::new();
+        state.update_metadata(metadata_response_initial()).unwrap();
+        assert!(state.group_coordinator("foo").is_none());
+
+        // ~ a coordinator among the already known brokers
+        let gc = protocol::GroupCoordinatorResponse {
+            header: protocol::HeaderResponse { correlation: 3 },
+            error: 0,
+            broker_id: 50,
+            port: 9876,
+            host: "gin2.dev".to_owned(),
+        };
+        assert_eq!("gin2.dev:9876", state.set_group_coordinator("foo", &gc));
+        assert_eq!(Some("gin2.dev:9876"), state.group_coordinator("foo"));
+        assert_eq!(Some(50), state.group_coordinator_broker("foo").map(|b| b.id()));
+        assert_eq!(3, state.brokers().len());
+
+        // ~ a coordinator not yet known as a broker
+        let gc = protocol::GroupCoordinatorResponse {
+            header: protocol::HeaderResponse { correlation: 4 },
+            error: 0,
+            broker_id: 70,
+            port: 9092,
+            host: "gin4.dev".to_owned(),
+        };
+        assert_eq!("gin4.dev:9092", state.set_group_coordinator("bar", &gc));
+        assert_eq!(Some(70), state.group_coordinator_broker("bar").map(|b| b.id()));
+        assert_eq!(4, state.brokers().len());
+
+        state.remove_group_coordinator("foo");
+        assert!(state.group_coordinator_broker("foo").is_none());
+        assert!(state.group_coordinator_broker("bar").is_some());
+    }
+
+    #[test]
+    fn test_brokers() {
+        fn brokers(state: &ClientState) -> Vec<(i32, &str)> {
+            state.brokers().iter().map(|b| (b.id(), b.host())).collect()
+        }
+
+        let mut state = ClientState::new();
+        assert!(state.brokers().is_empty());
+
+        state.update_metadata(metadata_response_initial()).unwrap();
+        assert_eq!(
+            vec![(10, "gin1.dev:1234"), (50, "gin2.dev:9876"), (30, "gin3.dev:9092")],
+            brokers(&state)
+        );
+
+        state.update_metadata(metadata_response_update()).unwrap();
+        assert_eq!(
+            vec![(10, "gin1.dev:1234"), (50, "aladin1.dev:9091"), (30, "gin3.dev:9092")],
+            brokers(&state)
+        );
+
+        state.clear_metadata();
+        assert!(state.brokers().is_empty());
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut state = ClientState::new();
+        assert!(state.controller().is_none());
+        state.set_controller(Some((2, "localhost:9093".to_owned())));
+        assert_eq!(
+            Some((2, "localhost:9093")),
+            state.controller().map(|b| (b.id(), b.host()))
+        );
+        // ~ resetting the metadata forgets the controller as well
+        state.clear_metadata();
+        assert!(state.controller().is_none());
+
+        // ~ a controller id of -1 denotes no elected controller
+        let resp = md::ControllerMetadataResponse {
+            header: protocol::HeaderResponse { correlation: 1 },
+            brokers: vec![
+                md::RackBrokerMetadata {
+                    node_id: 1,
+                    host: "localhost".to_owned(),
+                    port: 9092,
+                    rack: String::new(),
+                },
+            ],
+            controller_id: -1,
+        };
+        assert!(resp.controller_host().is_none());
+    }
+
+    #[test]
+    fn test_reserve_sequences() {
+        let mut state = ClientState::new();
+        state.set_producer_id(Some((1, 0)));
+        assert_eq!(0, state.reserve_sequences("foo", 0, 3));
+        assert_eq!(3, state.reserve_sequences("foo", 0, 2));
+        assert_eq!(0, state.reserve_sequences("foo", 1, 1));
+
+        // ~ sequences wrap around to zero
+        state.sequences.insert(("bar".to_owned(), 0), i32::max_value() - 1);
+        assert_eq!(i32::max_value() - 1, state.reserve_sequences("bar", 0, 2));
+        assert_eq!(0, state.reserve_sequences("bar", 0, 1));
+
+        // ~ a new producer id starts over
+        state.set_producer_id(Some((2, 0)));
+        assert_eq!(0, state.reserve_sequences("foo", 0, 1));
+    }
 }
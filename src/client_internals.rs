@@ -1,6 +1,8 @@
 //! A crate private module to expose `KafkaClient` internals for use
 //! within this crate but not outside of it.
 
+use std::collections::HashMap;
+
 use client::ProduceMessage;
 use producer::ProduceConfirm;
 use error::Result;
@@ -15,4 +17,30 @@ pub trait KafkaClientInternals {
     where
         J: AsRef<ProduceMessage<'a, 'b>>,
         I: IntoIterator<Item = J>;
+
+    /// Like `internal_produce_messages` but as an idempotent producer
+    /// sends the messages of the partitions found in `sequences` under
+    /// the recorded sequence numbers; the sequence numbers reserved
+    /// for other partitions are recorded.  The producer id is kept
+    /// after a failure such that re-sending the failed messages
+    /// through this method lets the brokers discard duplicates.  Once
+    /// giving up on such messages, `internal_reset_producer_id` must
+    /// be called.
+    fn internal_produce_messages_resendable<'a, 'b, I, J>(
+        &mut self,
+        required_acks: i16,
+        ack_timeout: i32,
+        messages: I,
+        sequences: &mut HashMap<(String, i32), i32>,
+    ) -> Result<Vec<ProduceConfirm>>
+    where
+        J: AsRef<ProduceMessage<'a, 'b>>,
+        I: IntoIterator<Item = J>;
+
+    /// Discards the producer id of an idempotent producer; the next
+    /// messages are sent under a new one.
+    fn internal_reset_producer_id(&mut self);
+
+    /// Like `KafkaClient::leave_group` but without retrying.
+    fn internal_leave_group_once(&mut self, group: &str, member_id: &str) -> Result<()>;
 }
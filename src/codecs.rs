@@ -1,8 +1,11 @@
-use std::io::{Read, Write};
+use std::cmp;
+use std::io::{self, Read, Write};
 use std::default::Default;
+use std::str;
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use error::{Result, ErrorKind};
+use crc::crc32;
+use error::{Error, Result, ErrorKind};
 
 // Helper macro to safely convert an usize expression into a signed
 // integer.  If the conversion is not possible the macro issues a
@@ -22,6 +25,10 @@ macro_rules! try_usize_to_int {
     }}
 }
 
+// ~ the max. number of elements to reserve space for upfront when
+// decoding a length prefixed array
+const MAX_PREALLOCATED_LEN: usize = 1024;
+
 pub trait ToByte {
     fn encode<T: Write>(&self, buffer: &mut T) -> Result<()>;
 }
@@ -75,8 +82,6 @@ impl ToByte for str {
 
 #[test]
 fn test_string_too_long() {
-    use std::str;
-
     let s = vec![b'a'; i16::max_value() as usize + 1];
     let s = unsafe { str::from_utf8_unchecked(&s) };
     let mut buf = Vec::new();
@@ -203,15 +208,37 @@ impl FromByte for String {
         if length <= 0 {
             return Ok(());
         }
-        self.reserve(length as usize);
-        let _ = buffer.take(length as u64).read_to_string(self);
-        if self.len() != length as usize {
+        let mut buf = Vec::with_capacity(length as usize);
+        let _ = buffer.take(length as u64).read_to_end(&mut buf);
+        if buf.len() != length as usize {
             bail!(ErrorKind::UnexpectedEOF);
         }
+        self.push_str(try!(str::from_utf8(&buf)));
         Ok(())
     }
 }
 
+/// Decodes a string just like `String::decode` but reports invalid
+/// utf8 as `ErrorKind::FieldDecodeError` naming the given field.
+pub fn decode_string_field<T: Read>(
+    s: &mut String,
+    buffer: &mut T,
+    field: &'static str,
+) -> Result<()> {
+    s.decode(buffer).map_err(|e| name_field(e, field))
+}
+
+/// Attributes a `StringDecodeError` to the given field; any other
+/// error is passed through as it is.
+pub fn name_field(e: Error, field: &'static str) -> Error {
+    match e {
+        Error(ErrorKind::StringDecodeError(valid_up_to), _) => {
+            ErrorKind::FieldDecodeError(field, valid_up_to).into()
+        }
+        e => e,
+    }
+}
+
 impl<V: FromByte + Default> FromByte for Vec<V> {
     type R = Vec<V>;
 
@@ -223,7 +250,10 @@ impl<V: FromByte + Default> FromByte for Vec<V> {
         if length <= 0 {
             return Ok(());
         }
-        self.reserve(length as usize);
+        // ~ don't trust the declared length blindly; a corrupted one
+        // would otherwise trigger a huge allocation before running
+        // out of input
+        self.reserve(cmp::min(length as usize, MAX_PREALLOCATED_LEN));
         for _ in 0..length {
             let mut e: V = Default::default();
             try!(e.decode(buffer));
@@ -245,7 +275,7 @@ impl FromByte for Vec<u8> {
         if length <= 0 {
             return Ok(());
         }
-        self.reserve(length as usize);
+        self.reserve(cmp::min(length as usize, MAX_PREALLOCATED_LEN));
         match buffer.take(length as u64).read_to_end(self) {
             Ok(size) => {
                 if size < length as usize {
@@ -259,6 +289,389 @@ impl FromByte for Vec<u8> {
     }
 }
 
+// --------------------------------------------------------------------
+
+// ~ the variable length integers used by the record batch format
+// (message format v2) are zigzag encoded, i.e. the sign bit is moved
+// to the least significant bit such that small negative numbers
+// render into few bytes as well
+
+/// Maps the signed `v` onto an unsigned integer such that numbers of
+/// a small magnitude map onto small numbers: 0 => 0, -1 => 1, 1 => 2,
+/// -2 => 3, etc.
+pub fn zigzag_encode(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// The reverse of `zigzag_encode`.
+pub fn zigzag_decode(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// Renders `v` to `buffer` as a zigzag encoded, variable length
+/// integer.  Values of the `i32` range render into the same bytes as
+/// expected by Kafka for its "varint" type; others as "varlong".
+pub fn write_varint<W: Write>(buffer: &mut W, v: i64) -> Result<()> {
+    let mut v = zigzag_encode(v);
+    while v >= 0x80 {
+        try!(buffer.write_u8(v as u8 | 0x80));
+        v >>= 7;
+    }
+    buffer.write_u8(v as u8).or_else(|e| Err(From::from(e)))
+}
+
+/// Reads a zigzag encoded, variable length 32 bit integer from
+/// `buffer`; fails with `CodecError` if the encoded number spans more
+/// than five bytes and with `UnexpectedEOF` if `buffer` ends before
+/// the number.
+pub fn read_varint_i32<R: Read>(buffer: &mut R) -> Result<i32> {
+    let v = try!(read_unsigned_varint(buffer, 5));
+    Ok(zigzag_decode(v as u32 as u64) as i32)
+}
+
+/// Reads a zigzag encoded, variable length 64 bit integer from
+/// `buffer`; fails like `read_varint_i32` but allows for ten bytes.
+pub fn read_varint_i64<R: Read>(buffer: &mut R) -> Result<i64> {
+    let v = try!(read_unsigned_varint(buffer, 10));
+    Ok(zigzag_decode(v))
+}
+
+fn read_unsigned_varint<R: Read>(buffer: &mut R, max_bytes: usize) -> Result<u64> {
+    let mut v = 0u64;
+    for i in 0..max_bytes {
+        let b = match buffer.read_u8() {
+            Ok(b) => b,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                bail!(ErrorKind::UnexpectedEOF)
+            }
+            Err(e) => return Err(From::from(e)),
+        };
+        v |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    bail!(ErrorKind::CodecError)
+}
+
+// --------------------------------------------------------------------
+
+// ~ the "flexible versions" of the kafka apis (KIP-482) prefix
+// strings, bytes, and arrays with their length plus one as an
+// unsigned (i.e. not zigzag encoded) varint, zero denoting null, and
+// end structures with a section of tagged fields.  (none of the api
+// versions spoken by this library is flexible yet.)
+
+/// Reads an unsigned, variable length 32 bit integer from `buffer`;
+/// fails like `read_varint_i32`.
+pub fn read_unsigned_varint_u32<R: Read>(buffer: &mut R) -> Result<u32> {
+    let v = try!(read_unsigned_varint(buffer, 5));
+    if v > u32::max_value() as u64 {
+        bail!(ErrorKind::CodecError);
+    }
+    Ok(v as u32)
+}
+
+// ~ reads the length of a compact string, bytes, or array; `None`
+// denotes null
+fn read_compact_len<R: Read>(buffer: &mut R) -> Result<Option<usize>> {
+    match try!(read_unsigned_varint_u32(buffer)) {
+        0 => Ok(None),
+        n => Ok(Some((n - 1) as usize)),
+    }
+}
+
+/// Reads a compact string; a null string is read as an empty one
+/// (just like `String::decode` does for non-compact strings.)
+#[allow(dead_code)]
+pub fn read_compact_string<R: Read>(buffer: &mut R) -> Result<String> {
+    let bytes = try!(read_compact_bytes(buffer));
+    Ok(try!(String::from_utf8(bytes)))
+}
+
+/// Reads compact bytes; null bytes are read as empty ones.
+#[allow(dead_code)]
+pub fn read_compact_bytes<R: Read>(buffer: &mut R) -> Result<Vec<u8>> {
+    let length = match try!(read_compact_len(buffer)) {
+        None | Some(0) => return Ok(Vec::new()),
+        Some(n) => n,
+    };
+    let mut buf = Vec::with_capacity(cmp::min(length, MAX_PREALLOCATED_LEN));
+    try!(buffer.take(length as u64).read_to_end(&mut buf));
+    if buf.len() != length {
+        bail!(ErrorKind::UnexpectedEOF);
+    }
+    Ok(buf)
+}
+
+/// Reads a compact array decoding its elements through `f`; a null
+/// array is read as an empty one.
+#[allow(dead_code)]
+pub fn read_compact_array<R, V, F>(buffer: &mut R, mut f: F) -> Result<Vec<V>>
+where
+    R: Read,
+    F: FnMut(&mut R) -> Result<V>,
+{
+    let length = match try!(read_compact_len(buffer)) {
+        None => return Ok(Vec::new()),
+        Some(n) => n,
+    };
+    let mut xs = Vec::with_capacity(cmp::min(length, MAX_PREALLOCATED_LEN));
+    for _ in 0..length {
+        xs.push(try!(f(buffer)));
+    }
+    Ok(xs)
+}
+
+/// Skips a section of tagged fields; none of them is known to this
+/// library.
+#[allow(dead_code)]
+pub fn skip_tagged_fields<R: Read>(buffer: &mut R) -> Result<()> {
+    let n = try!(read_unsigned_varint_u32(buffer));
+    for _ in 0..n {
+        let _tag = try!(read_unsigned_varint_u32(buffer));
+        let size = try!(read_unsigned_varint_u32(buffer)) as u64;
+        if try!(io::copy(&mut buffer.take(size), &mut io::sink())) != size {
+            bail!(ErrorKind::UnexpectedEOF);
+        }
+    }
+    Ok(())
+}
+
+// --------------------------------------------------------------------
+
+/// Computes the CRC32C (Castagnoli) checksum of `data` as used by the
+/// v2 record batch format.  (Messages of the v0 and v1 formats are
+/// checksummed using the plain IEEE CRC32 instead.)
+///
+/// On x86_64 CPUs supporting SSE4.2 the checksum is computed using
+/// the dedicated `crc32` instructions; otherwise a table based
+/// implementation is used.
+pub fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.2") {
+            // ~ safe since the cpu has just been detected to support
+            // the required instructions
+            return unsafe { crc32c_sse42(data) };
+        }
+    }
+    crc32c_table(data)
+}
+
+fn crc32c_table(data: &[u8]) -> u32 {
+    crc32::checksum_castagnoli(data)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc: u64 = 0xffff_ffff;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(word));
+    }
+    let mut crc = crc as u32;
+    for &b in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, b);
+    }
+    !crc
+}
+
+#[test]
+fn codec_zigzag() {
+    let cases: &[(i64, u64)] = &[
+        (0, 0),
+        (-1, 1),
+        (1, 2),
+        (-2, 3),
+        (i32::MAX as i64, 0xffff_fffe),
+        (i32::MIN as i64, 0xffff_ffff),
+        (i64::MAX, u64::MAX - 1),
+        (i64::MIN, u64::MAX),
+    ];
+    for &(v, zz) in cases {
+        assert_eq!(zz, zigzag_encode(v));
+        assert_eq!(v, zigzag_decode(zz));
+    }
+}
+
+#[test]
+fn codec_varint() {
+    use std::io::Cursor;
+
+    // ~ the byte sequences as rendered by Kafka's `ByteUtils`
+    let cases: &[(i32, &[u8])] = &[
+        (0, &[0x00]),
+        (-1, &[0x01]),
+        (1, &[0x02]),
+        (63, &[0x7e]),
+        (-64, &[0x7f]),
+        (64, &[0x80, 0x01]),
+        (300, &[0xd8, 0x04]),
+        (i32::MAX, &[0xfe, 0xff, 0xff, 0xff, 0x0f]),
+        (i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+    ];
+    for &(v, bytes) in cases {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, v as i64).unwrap();
+        assert_eq!(bytes, &buf[..]);
+        assert_eq!(v, read_varint_i32(&mut Cursor::new(bytes)).unwrap());
+        assert_eq!(v as i64, read_varint_i64(&mut Cursor::new(bytes)).unwrap());
+    }
+}
+
+#[test]
+fn codec_varlong() {
+    use std::io::Cursor;
+
+    let max: &[u8] = &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    let min: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    let cases: &[(i64, &[u8])] = &[
+        (0, &[0x00]),
+        (-1, &[0x01]),
+        (1_500_000_000_000, &[0x80, 0xe0, 0xbc, 0xef, 0xa7, 0x57]),
+        (i64::MAX, max),
+        (i64::MIN, min),
+    ];
+    for &(v, bytes) in cases {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, v).unwrap();
+        assert_eq!(bytes, &buf[..]);
+        assert_eq!(v, read_varint_i64(&mut Cursor::new(bytes)).unwrap());
+    }
+}
+
+#[test]
+fn codec_varint_invalid() {
+    use std::io::Cursor;
+    use error::Error;
+
+    // ~ incomplete input
+    match read_varint_i32(&mut Cursor::new(&[0x80u8, 0x80])) {
+        Err(Error(ErrorKind::UnexpectedEOF, _)) => {}
+        e => panic!("Expected UnexpectedEOF, but got: {:?}", e),
+    }
+    // ~ too many bytes for the type
+    let data: &[u8] = &[0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+    match read_varint_i32(&mut Cursor::new(data)) {
+        Err(Error(ErrorKind::CodecError, _)) => {}
+        e => panic!("Expected CodecError, but got: {:?}", e),
+    }
+    assert_eq!(1 << 34, read_varint_i64(&mut Cursor::new(data)).unwrap());
+}
+
+#[test]
+fn codec_compact() {
+    use std::io::Cursor;
+
+    let mut c = Cursor::new(&[0x04, b'a', b'b', b'c', 0x00, 0x01][..]);
+    assert_eq!("abc", read_compact_string(&mut c).unwrap());
+    // ~ null and empty
+    assert_eq!("", read_compact_string(&mut c).unwrap());
+    assert_eq!("", read_compact_string(&mut c).unwrap());
+
+    let mut c = Cursor::new(&[0x03, 1, 2, 0x00][..]);
+    assert_eq!(vec![1, 2], read_compact_bytes(&mut c).unwrap());
+    assert!(read_compact_bytes(&mut c).unwrap().is_empty());
+
+    // ~ an array of two compact strings followed by a null array
+    let mut c = Cursor::new(&[0x03, 0x02, b'a', 0x03, b'b', b'c', 0x00][..]);
+    assert_eq!(
+        vec!["a".to_owned(), "bc".to_owned()],
+        read_compact_array(&mut c, read_compact_string).unwrap()
+    );
+    assert!(read_compact_array(&mut c, read_compact_string).unwrap().is_empty());
+
+    // ~ an unsigned varint spanning two bytes
+    let mut c = Cursor::new(&[0xac, 0x02][..]);
+    assert_eq!(300, read_unsigned_varint_u32(&mut c).unwrap());
+}
+
+#[test]
+fn codec_compact_invalid() {
+    use std::io::Cursor;
+    use error::Error;
+
+    match read_compact_string(&mut Cursor::new(&[0x05, b'a', b'b'][..])) {
+        Err(Error(ErrorKind::UnexpectedEOF, _)) => {}
+        e => panic!("Expected UnexpectedEOF, but got: {:?}", e),
+    }
+    // ~ a huge declared array length must not result in a huge
+    // allocation
+    let data: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x0f];
+    assert!(read_compact_array(&mut Cursor::new(data), read_compact_string).is_err());
+    // ~ more than 32 bits
+    let data: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x1f];
+    match read_unsigned_varint_u32(&mut Cursor::new(data)) {
+        Err(Error(ErrorKind::CodecError, _)) => {}
+        e => panic!("Expected CodecError, but got: {:?}", e),
+    }
+}
+
+#[test]
+fn codec_skip_tagged_fields() {
+    use std::io::Cursor;
+    use error::Error;
+
+    // ~ two tagged fields (tag 0 with two bytes, tag 5 with none)
+    // followed by an i16
+    let data: &[u8] = &[0x02, 0x00, 0x02, 0xaa, 0xbb, 0x05, 0x00, 0x00, 0x07];
+    let mut c = Cursor::new(data);
+    skip_tagged_fields(&mut c).unwrap();
+    assert_eq!(7, i16::decode_new(&mut c).unwrap());
+
+    // ~ no tagged fields
+    let mut c = Cursor::new(&[0x00][..]);
+    skip_tagged_fields(&mut c).unwrap();
+    assert_eq!(1, c.position());
+
+    // ~ a field exceeding the input
+    match skip_tagged_fields(&mut Cursor::new(&[0x01, 0x00, 0x03, 0xaa][..])) {
+        Err(Error(ErrorKind::UnexpectedEOF, _)) => {}
+        e => panic!("Expected UnexpectedEOF, but got: {:?}", e),
+    }
+}
+
+#[test]
+fn codec_crc32c() {
+    // ~ the check value of the algorithm
+    assert_eq!(0xe306_9283, crc32c(b"123456789"));
+    assert_eq!(0, crc32c(b""));
+    // ~ test vectors from RFC 3720 (iSCSI), appendix B.4
+    assert_eq!(0x8a91_36aa, crc32c(&[0u8; 32]));
+    assert_eq!(0x62a8_ab43, crc32c(&[0xffu8; 32]));
+    let asc: Vec<u8> = (0..32).collect();
+    assert_eq!(0x46dd_794e, crc32c(&asc));
+    let desc: Vec<u8> = (0..32).rev().collect();
+    assert_eq!(0x113f_db5c, crc32c(&desc));
+}
+
+#[test]
+fn codec_crc32c_table() {
+    assert_eq!(0xe306_9283, crc32c_table(b"123456789"));
+    assert_eq!(0x8a91_36aa, crc32c_table(&[0u8; 32]));
+    assert_eq!(0x62a8_ab43, crc32c_table(&[0xffu8; 32]));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn codec_crc32c_sse42() {
+    if !is_x86_feature_detected!("sse4.2") {
+        return;
+    }
+    // ~ cover all lengths around the 8 byte chunks
+    let data: Vec<u8> = (0..100u32).map(|i| (i * 7 + 3) as u8).collect();
+    for n in 0..data.len() {
+        let expected = crc32c_table(&data[..n]);
+        assert_eq!(expected, unsafe { crc32c_sse42(&data[..n]) });
+    }
+}
+
 #[test]
 fn codec_i8() {
     use std::io::Cursor;
@@ -379,6 +792,36 @@ fn codec_vec_u8() {
     assert_eq!(dec2, orig);
 }
 
+#[test]
+fn codec_string_invalid_utf8() {
+    use std::io::Cursor;
+    use error::Error;
+
+    let buf = [0, 4, b'a', b'b', b'c', 0xc0];
+    match String::decode_new(&mut Cursor::new(&buf[..])) {
+        Err(Error(ErrorKind::StringDecodeError(3), _)) => {}
+        e => panic!("Expected StringDecodeError(3), but got: {:?}", e),
+    }
+}
+
+#[test]
+fn codec_string_field_invalid_utf8() {
+    use std::io::Cursor;
+    use error::Error;
+
+    let buf = [0, 3, b'a', 0xc0, b'b'];
+    let mut s = String::new();
+    match decode_string_field(&mut s, &mut Cursor::new(&buf[..]), "topic name") {
+        Err(Error(ErrorKind::FieldDecodeError("topic name", 1), _)) => {}
+        e => panic!("Expected FieldDecodeError(\"topic name\", 1), but got: {:?}", e),
+    }
+    // ~ other errors are passed through
+    match decode_string_field(&mut s, &mut Cursor::new(&buf[..2]), "topic name") {
+        Err(Error(ErrorKind::UnexpectedEOF, _)) => {}
+        e => panic!("Expected UnexpectedEOF, but got: {:?}", e),
+    }
+}
+
 #[test]
 fn codec_as_strings() {
 
@@ -428,3 +871,13 @@ fn codec_as_strings() {
         enc_dec_cmp!(orig);
     }
 }
+
+#[test]
+fn codec_vec_with_corrupted_length() {
+    use std::io::Cursor;
+    // ~ a declared length of i32::MAX must not result in an attempt
+    // to allocate the corresponding amount of memory
+    let buf = [0x7f, 0xff, 0xff, 0xff, 0, 0, 0, 1];
+    assert!(Vec::<i32>::decode_new(&mut Cursor::new(&buf[..])).is_err());
+    assert!(Vec::<u8>::decode_new(&mut Cursor::new(&buf[..])).is_err());
+}
@@ -6,16 +6,18 @@ use flate2::write::GzEncoder;
 
 use error::Result;
 
-pub fn compress(src: &[u8]) -> Result<Vec<u8>> {
-    let mut e = GzEncoder::new(Vec::new(), Compression::Default);
+/// Compresses `src` at the given `level` ranging from `0` (no
+/// compression) to `9` (best compression).
+pub fn compress(src: &[u8], level: u32) -> Result<Vec<u8>> {
+    let mut e = GzEncoder::new(Vec::new(), Compression::new(level));
 
-    try!(e.write(src));
+    try!(e.write_all(src));
     let compressed_bytes = try!(e.finish());
-    Ok((compressed_bytes))
+    Ok(compressed_bytes)
 }
 
 pub fn uncompress<T: Read>(src: T) -> Result<Vec<u8>> {
-    let mut d = try!(GzDecoder::new(src));
+    let mut d = GzDecoder::new(src);
 
     let mut buffer: Vec<u8> = Vec::new();
     match d.read_to_end(&mut buffer) {
@@ -81,3 +83,17 @@ fn test_uncompress_panic() {
     let uncomp_msg = String::from_utf8(uncompress(Cursor::new(msg)).unwrap()).unwrap();
     assert_eq!(&uncomp_msg[..], "This is test");
 }
+
+#[test]
+fn test_compress_levels() {
+    use std::io::Cursor;
+
+    let msg: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+    let stored = compress(&msg, 0).unwrap();
+    let best = compress(&msg, 9).unwrap();
+    assert!(best.len() < stored.len());
+    assert!(stored.len() > msg.len());
+    for cdata in vec![stored, best] {
+        assert_eq!(msg, uncompress(Cursor::new(cdata)).unwrap());
+    }
+}
@@ -0,0 +1,136 @@
+use std::hash::Hasher;
+use std::io::{self, Cursor, Read, Write};
+
+use lz4_flex::frame::{BlockMode, BlockSize, FrameDecoder, FrameEncoder, FrameInfo};
+use twox_hash::XxHash32;
+
+use error::Result;
+
+// the magic number starting every lz4 frame (little endian)
+const MAGIC: &'static [u8] = &[0x04, 0x22, 0x4D, 0x18];
+
+// bits of the frame descriptor's FLG byte denoting optional fields
+const FLG_CONTENT_SIZE: u8 = 0x08;
+const FLG_DICT_ID: u8 = 0x01;
+
+/// Compresses `src` into a single lz4 frame.
+///
+/// Kafka versions prior to 0.10 compute the frame's header checksum
+/// incorrectly - over the magic number in addition to the frame
+/// descriptor - and later versions insist on this behavior for
+/// messages of the v0 format, while expecting a correct checksum for
+/// the newer formats.  If `broken_header_checksum` is set, the
+/// rendered frame carries such a "broken" checksum.
+pub fn compress(src: &[u8], broken_header_checksum: bool) -> Result<Vec<u8>> {
+    let info = FrameInfo::new()
+        .block_size(BlockSize::Max64KB)
+        .block_mode(BlockMode::Independent);
+    let mut e = FrameEncoder::with_frame_info(info, Vec::new());
+    try!(e.write_all(src));
+    let mut buf = try!(e.finish().map_err(io::Error::from));
+    if broken_header_checksum {
+        if let Some(pos) = header_checksum_pos(&buf) {
+            buf[pos] = header_checksum(&buf[..pos]);
+        }
+    }
+    Ok(buf)
+}
+
+/// Uncompresses the given lz4 frame accepting both, a correct and a
+/// Kafka's "broken" header checksum. See `compress`.
+pub fn uncompress(src: &[u8]) -> Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let r = match header_checksum_pos(src) {
+        Some(pos) if src[pos] == header_checksum(&src[..pos]) => {
+            // ~ fix up the checksum for the decoder to accept it
+            let mut header = src[..pos + 1].to_vec();
+            header[pos] = header_checksum(&src[MAGIC.len()..pos]);
+            FrameDecoder::new(Cursor::new(header).chain(&src[pos + 1..])).read_to_end(&mut buffer)
+        }
+        _ => FrameDecoder::new(src).read_to_end(&mut buffer),
+    };
+    match r {
+        Err(err) => Err(From::from(err)),
+        Ok(_) => Ok(buffer),
+    }
+}
+
+/// Determines the position of the header checksum within the given
+/// frame; `None` if `frame` does not start with a valid frame header.
+fn header_checksum_pos(frame: &[u8]) -> Option<usize> {
+    if frame.len() < MAGIC.len() + 3 || &frame[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+    let flg = frame[MAGIC.len()];
+    // ~ FLG and BD bytes followed by the optional fields
+    let mut pos = MAGIC.len() + 2;
+    if flg & FLG_CONTENT_SIZE != 0 {
+        pos += 8;
+    }
+    if flg & FLG_DICT_ID != 0 {
+        pos += 4;
+    }
+    if pos < frame.len() { Some(pos) } else { None }
+}
+
+fn header_checksum(data: &[u8]) -> u8 {
+    let mut h = XxHash32::with_seed(0);
+    h.write(data);
+    (h.finish() >> 8) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use lz4_flex::frame::FrameEncoder;
+
+    use super::{compress, uncompress, header_checksum, header_checksum_pos, MAGIC};
+
+    #[test]
+    fn test_compress_uncompress() {
+        let msg = "This is a test message".as_bytes();
+        let cdata = compress(msg, true).unwrap();
+        assert_eq!(MAGIC, &cdata[..MAGIC.len()]);
+        assert_eq!(msg, &uncompress(&cdata).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_compress_uncompress_multiple_blocks() {
+        let msg: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(msg, uncompress(&compress(&msg, true).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_compress_broken_header_checksum() {
+        let cdata = compress(b"test", true).unwrap();
+        let pos = header_checksum_pos(&cdata).unwrap();
+        assert_eq!(6, pos);
+        // ~ the checksum covers the magic number, too
+        assert_eq!(header_checksum(&cdata[..pos]), cdata[pos]);
+    }
+
+    #[test]
+    fn test_compress_correct_header_checksum() {
+        let cdata = compress(b"test", false).unwrap();
+        let pos = header_checksum_pos(&cdata).unwrap();
+        assert_eq!(header_checksum(&cdata[MAGIC.len()..pos]), cdata[pos]);
+        assert_eq!(b"test", &uncompress(&cdata).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_uncompress_correct_header_checksum() {
+        let mut e = FrameEncoder::new(Vec::new());
+        e.write_all(b"test").unwrap();
+        let cdata = e.finish().unwrap();
+        assert_eq!(b"test", &uncompress(&cdata).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_uncompress_invalid() {
+        assert!(uncompress(b"this is not lz4").is_err());
+        let mut cdata = compress(b"test", true).unwrap();
+        cdata[6] = cdata[6].wrapping_add(1);
+        assert!(uncompress(&cdata).is_err());
+    }
+}
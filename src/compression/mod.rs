@@ -4,6 +4,12 @@ pub mod gzip;
 #[cfg(feature = "snappy")]
 pub mod snappy;
 
+#[cfg(feature = "lz4")]
+pub mod lz4;
+
+#[cfg(feature = "zstd")]
+pub mod zstd;
+
 /// Compression types supported by kafka. The numeral values of this
 /// enumeration correspond to the compression encoding in the
 /// attributes of a Message in the protocol.
@@ -14,6 +20,14 @@ pub enum Compression {
     GZIP = 1,
     #[cfg(feature = "snappy")]
     SNAPPY = 2,
+    #[cfg(feature = "lz4")]
+    LZ4 = 3,
+    /// Requires Kafka 2.1 or newer.  Messages are produced through
+    /// version 7 of the produce API which in turn requires the
+    /// message format v2; producing zstd compressed messages in an
+    /// older format fails with `ErrorKind::UnsupportedCompression`.
+    #[cfg(feature = "zstd")]
+    ZSTD = 4,
 }
 
 impl Default for Compression {
@@ -159,6 +159,9 @@ impl<'a> SnappyReader<'a> {
             }));
         }
         let chunk_size = chunk_size as usize;
+        if chunk_size > self.compressed_data.len() {
+            bail!(ErrorKind::UnexpectedEOF);
+        }
         self.uncompressed_chunk.clear();
         try!(uncompress_to(&self.compressed_data[..chunk_size], &mut self.uncompressed_chunk));
         self.compressed_data = &self.compressed_data[chunk_size..];
@@ -182,6 +185,9 @@ impl<'a> SnappyReader<'a> {
                     header: false,
                 }));
             }
+            if chunk_size as usize > self.compressed_data.len() {
+                bail!(ErrorKind::UnexpectedEOF);
+            }
             let (c1, c2) = self.compressed_data.split_at(chunk_size as usize);
             try!(uncompress_to(c1, buf));
             self.compressed_data = c2;
@@ -190,6 +196,30 @@ impl<'a> SnappyReader<'a> {
     }
 }
 
+/// Uncompresses a stream of snappy compressed chunks as produced by
+/// org.xerial.snappy.SnappyOutputStream.  Unlike reading through
+/// `SnappyReader`'s `io::Read` implementation, this delivers the
+/// underlying snappy error - if any - unaltered.
+pub fn uncompress_chunked(src: &[u8]) -> Result<Vec<u8>> {
+    let mut v = Vec::new();
+    try!(try!(SnappyReader::new(src))._read_to_end(&mut v));
+    Ok(v)
+}
+
+/// Uncompresses snappy compressed data either in the framing of
+/// org.xerial.snappy.SnappyOutputStream - as produced by Kafka's Java
+/// client - or in the raw snappy format - as produced by `compress`.
+/// The former is recognized by its leading magic header.
+pub fn uncompress(src: &[u8]) -> Result<Vec<u8>> {
+    if src.starts_with(MAGIC) {
+        uncompress_chunked(src)
+    } else {
+        let mut v = Vec::new();
+        try!(uncompress_to(src, &mut v));
+        Ok(v)
+    }
+}
+
 macro_rules! to_io_error {
     ($expr:expr) => {
         match $expr {
@@ -197,7 +227,7 @@ macro_rules! to_io_error {
             // ~ pass io errors through directly
             Err(Error(ErrorKind::Io(io_error), _)) => Err(io_error),
             // ~ wrapp our other errors
-            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.description())),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
         }
     }
 }
@@ -219,16 +249,8 @@ mod tests {
     use std::str;
     use std::io::Read;
 
-    use error::{Error, ErrorKind, Result};
-    use super::{compress, uncompress_to, SnappyReader};
-
-    fn uncompress(src: &[u8]) -> Result<Vec<u8>> {
-        let mut v = Vec::new();
-        match uncompress_to(src, &mut v) {
-            Ok(_) => Ok(v),
-            Err(e) => Err(e),
-        }
-    }
+    use error::{Error, ErrorKind};
+    use super::{compress, uncompress, uncompress_chunked, SnappyReader, MAGIC};
 
     #[test]
     fn test_compress() {
@@ -329,4 +351,62 @@ mod tests {
         r.read_to_end(&mut buf).unwrap();
         assert_eq!(ORIGINAL, str::from_utf8(&buf[..]).unwrap());
     }
+
+    #[test]
+    fn test_uncompress_chunked() {
+        assert_eq!(ORIGINAL, str::from_utf8(&uncompress_chunked(COMPRESSED).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_uncompress_chunked_invalid() {
+        // ~ a valid stream header followed by a corrupt chunk
+        let stream = &[
+            0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0,
+            0, 0, 0, 1,
+            0, 0, 0, 1,
+            0, 0, 0, 3, 0x0c, 0x2c, 0x54,
+        ];
+        let err = match uncompress_chunked(stream) {
+            Err(e @ Error(ErrorKind::InvalidSnappy(_), _)) => e,
+            r => panic!("Expected InvalidSnappy error, but got: {:?}", r),
+        };
+        // ~ the snappy error details must survive reading through `io::Read`
+        let mut buf = Vec::new();
+        let ioerr = SnappyReader::new(stream).unwrap().read_to_end(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), ioerr.to_string());
+    }
+
+    #[test]
+    fn test_uncompress_chunked_truncated() {
+        // ~ a chunk announcing more data than available must not panic
+        let stream = &[
+            0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0,
+            0, 0, 0, 1,
+            0, 0, 0, 1,
+            0, 0, 0, 9, 0x0c, 0x2c, 0x54,
+        ];
+        match uncompress_chunked(stream) {
+            Err(Error(ErrorKind::UnexpectedEOF, _)) => {}
+            r => panic!("Expected UnexpectedEOF error, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_uncompress_detects_framing() {
+        // ~ a captured payload of the xerial framing
+        assert_eq!(ORIGINAL, str::from_utf8(&uncompress(COMPRESSED).unwrap()).unwrap());
+
+        // ~ raw snappy as well as xerial framed blocks compressed by
+        // ourselves must round-trip
+        let (a, b) = ORIGINAL.as_bytes().split_at(ORIGINAL.len() / 2);
+        assert_eq!(a, &uncompress(&compress(a).unwrap()).unwrap()[..]);
+        let mut framed = MAGIC.to_vec();
+        framed.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1]);
+        for block in &[compress(a).unwrap(), compress(b).unwrap()] {
+            let n = block.len() as u32;
+            framed.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+            framed.extend_from_slice(block);
+        }
+        assert_eq!(ORIGINAL, str::from_utf8(&uncompress(&framed).unwrap()).unwrap());
+    }
 }
@@ -0,0 +1,30 @@
+use std::io::Read;
+
+use zstd::stream;
+
+use error::Result;
+
+// ~ zero selects zstd's default compression level
+const COMPRESSION_LEVEL: i32 = 0;
+
+pub fn compress(src: &[u8]) -> Result<Vec<u8>> {
+    Ok(try!(stream::encode_all(src, COMPRESSION_LEVEL)))
+}
+
+pub fn uncompress<T: Read>(src: T) -> Result<Vec<u8>> {
+    Ok(try!(stream::decode_all(src)))
+}
+
+#[test]
+fn test_compress_uncompress() {
+    let msg = "This is a test message".as_bytes();
+    let cdata = compress(msg).unwrap();
+    // ~ the magic number of a zstd frame (little endian)
+    assert_eq!(&[0x28, 0xB5, 0x2F, 0xFD], &cdata[..4]);
+    assert_eq!(msg, &uncompress(&cdata[..]).unwrap()[..]);
+}
+
+#[test]
+fn test_uncompress_invalid() {
+    assert!(uncompress("this is not zstd".as_bytes()).is_err());
+}
@@ -4,7 +4,8 @@ use std::time::Duration;
 use client::{self, KafkaClient, FetchOffset, GroupOffsetStorage};
 use error::{ErrorKind, Result};
 
-use super::{Consumer, DEFAULT_FALLBACK_OFFSET, DEFAULT_RETRY_MAX_BYTES_LIMIT};
+use super::{Consumer, AutoOffsetReset, DEFAULT_FALLBACK_OFFSET, DEFAULT_RETRY_MAX_BYTES_LIMIT,
+            DEFAULT_AUTO_OFFSET_RESET};
 use super::config::Config;
 use super::state::State;
 use super::assignment;
@@ -24,11 +25,13 @@ pub struct Builder {
     group: String,
     assignments: HashMap<String, Vec<i32>>,
     fallback_offset: FetchOffset,
+    auto_offset_reset: AutoOffsetReset,
     fetch_max_wait_time: Duration,
     fetch_min_bytes: i32,
     fetch_max_bytes_per_partition: i32,
     retry_max_bytes_limit: i32,
     fetch_crc_validation: bool,
+    max_message_set_depth: u32,
     security_config: Option<SecurityConfig>,
     group_offset_storage: GroupOffsetStorage,
     conn_idle_timeout: Duration,
@@ -45,10 +48,12 @@ pub fn new(client: Option<KafkaClient>, hosts: Vec<String>) -> Builder {
         fetch_min_bytes: client::DEFAULT_FETCH_MIN_BYTES,
         fetch_max_bytes_per_partition: client::DEFAULT_FETCH_MAX_BYTES_PER_PARTITION,
         fetch_crc_validation: client::DEFAULT_FETCH_CRC_VALIDATION,
+        max_message_set_depth: client::DEFAULT_MAX_MESSAGE_SET_DEPTH,
         retry_max_bytes_limit: DEFAULT_RETRY_MAX_BYTES_LIMIT,
         group: String::new(),
         assignments: HashMap::new(),
         fallback_offset: DEFAULT_FALLBACK_OFFSET,
+        auto_offset_reset: DEFAULT_AUTO_OFFSET_RESET,
         security_config: None,
         group_offset_storage: client::DEFAULT_GROUP_OFFSET_STORAGE,
         conn_idle_timeout: Duration::from_millis(client::DEFAULT_CONNECTION_IDLE_TIMEOUT_MILLIS),
@@ -59,6 +64,7 @@ pub fn new(client: Option<KafkaClient>, hosts: Vec<String>) -> Builder {
         b.fetch_min_bytes = c.fetch_min_bytes();
         b.fetch_max_bytes_per_partition = c.fetch_max_bytes_per_partition();
         b.fetch_crc_validation = c.fetch_crc_validation();
+        b.max_message_set_depth = c.max_message_set_depth();
         b.group_offset_storage = c.group_offset_storage();
         b.conn_idle_timeout = c.connection_idle_timeout();
     }
@@ -129,6 +135,20 @@ impl Builder {
         self
     }
 
+    /// Specifies how to proceed when the offset to fetch from is no
+    /// longer available in a partition, e.g. because the messages
+    /// have been deleted due to retention.
+    ///
+    /// With `AutoOffsetReset::Earliest` or `AutoOffsetReset::Latest`
+    /// the consumer transparently repositions itself to the
+    /// partition's earliest or latest offset, respectively.  With
+    /// `AutoOffsetReset::None`, the default, polling fails with
+    /// `KafkaCode::OffsetOutOfRange`.
+    pub fn with_auto_offset_reset(mut self, auto_offset_reset: AutoOffsetReset) -> Builder {
+        self.auto_offset_reset = auto_offset_reset;
+        self
+    }
+
     /// See `KafkaClient::set_fetch_max_wait_time`
     pub fn with_fetch_max_wait_time(mut self, max_wait_time: Duration) -> Builder {
         self.fetch_max_wait_time = max_wait_time;
@@ -153,6 +173,12 @@ impl Builder {
         self
     }
 
+    /// See `KafkaClient::set_max_message_set_depth`
+    pub fn with_max_message_set_depth(mut self, depth: u32) -> Builder {
+        self.max_message_set_depth = depth;
+        self
+    }
+
     /// See `KafkaClient::set_group_offset_storage`
     pub fn with_offset_storage(mut self, storage: GroupOffsetStorage) -> Builder {
         self.group_offset_storage = storage;
@@ -234,6 +260,7 @@ impl Builder {
         try!(client.set_fetch_max_wait_time(self.fetch_max_wait_time));
         client.set_fetch_min_bytes(self.fetch_min_bytes);
         client.set_fetch_max_bytes_per_partition(self.fetch_max_bytes_per_partition);
+        client.set_max_message_set_depth(self.max_message_set_depth);
         client.set_group_offset_storage(self.group_offset_storage);
         client.set_connection_idle_timeout(self.conn_idle_timeout);
         if let Some(client_id) = self.client_id {
@@ -248,6 +275,7 @@ impl Builder {
             group: self.group,
             fallback_offset: self.fallback_offset,
             retry_max_bytes_limit: self.retry_max_bytes_limit,
+            auto_offset_reset: self.auto_offset_reset,
         };
         let state = try!(State::new(&mut client, &config, assignment::from_map(self.assignments)));
         debug!("initialized: Consumer {{ config: {:?}, state: {:?} }}", config, state);
@@ -1,8 +1,11 @@
 use client::FetchOffset;
 
+use super::AutoOffsetReset;
+
 #[derive(Debug)]
 pub struct Config {
     pub group: String,
     pub fallback_offset: FetchOffset,
     pub retry_max_bytes_limit: i32,
+    pub auto_offset_reset: AutoOffsetReset,
 }
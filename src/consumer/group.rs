@@ -0,0 +1,449 @@
+//! Membership in a Kafka managed consumer group.
+
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use client::{KafkaClient, GroupAssignment, GroupMember};
+use client_internals::KafkaClientInternals;
+use error::{Error, ErrorKind, KafkaCode, Result};
+
+/// The default value for `ConsumerGroup::set_session_timeout(..)`
+pub const DEFAULT_SESSION_TIMEOUT_MILLIS: u64 = 30_000;
+
+/// The outcome of `ConsumerGroup::heartbeat`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeartbeatStatus {
+    /// The membership is alive and the assignment of the member
+    /// remains valid.
+    Alive,
+    /// The group is rebalancing or the membership has expired.  The
+    /// member must stop consuming its current assignment and
+    /// `ConsumerGroup::join` the group again.
+    Rejoin,
+}
+
+/// A member of a consumer group managed by Kafka.  Kafka balances
+/// the partitions of the topics subscribed to by the group's members
+/// among these; this wrapper takes care of joining the group and
+/// provides the topic partitions assigned to this member.
+///
+/// # Example
+///
+/// ```no_run
+/// use kafka::client::KafkaClient;
+/// use kafka::consumer::ConsumerGroup;
+///
+/// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+/// client.load_metadata_all().unwrap();
+/// let mut group =
+///     ConsumerGroup::new(client, "my-group".to_owned(), vec!["my-topic".to_owned()]);
+/// for &(ref topic, partition) in group.join().unwrap() {
+///     println!("assigned: {}:{}", topic, partition);
+/// }
+/// group.close().unwrap();
+/// ```
+///
+/// A joined member leaves its group when dropped.  Unlike
+/// `ConsumerGroup::close`, dropping tries to leave the group only
+/// once, without retrying while the group coordinator is not
+/// available, and merely logs a failure to do so.
+#[derive(Debug)]
+pub struct ConsumerGroup {
+    client: KafkaClient,
+    group: String,
+    topics: Vec<String>,
+    session_timeout: Duration,
+    member_id: String,
+    generation_id: i32,
+    assignment: Vec<(String, i32)>,
+}
+
+impl ConsumerGroup {
+    /// Creates a new - not yet joined - member of the specified group
+    /// subscribing to the given topics.
+    pub fn new(client: KafkaClient, group: String, topics: Vec<String>) -> ConsumerGroup {
+        ConsumerGroup {
+            client: client,
+            group: group,
+            topics: topics,
+            session_timeout: Duration::from_millis(DEFAULT_SESSION_TIMEOUT_MILLIS),
+            member_id: String::new(),
+            generation_id: -1,
+            assignment: Vec::new(),
+        }
+    }
+
+    /// Specifies the time after which the group coordinator considers
+    /// this member dead if it fails to hear from it.  The setting
+    /// takes effect upon the next `ConsumerGroup::join`.
+    #[inline]
+    pub fn set_session_timeout(&mut self, timeout: Duration) {
+        self.session_timeout = timeout;
+    }
+
+    /// Retrieves the current `ConsumerGroup::set_session_timeout`
+    /// setting.
+    #[inline]
+    pub fn session_timeout(&self) -> Duration {
+        self.session_timeout
+    }
+
+    /// Retrieves the recommended interval for sending heartbeats,
+    /// i.e. a third of the session timeout.  See
+    /// `ConsumerGroup::heartbeat`.
+    #[inline]
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.session_timeout / 3
+    }
+
+    /// Retrieves the name of the group.
+    #[inline]
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// Retrieves the topics this member subscribes to.
+    #[inline]
+    pub fn topics(&self) -> &[String] {
+        &self.topics
+    }
+
+    /// Retrieves the id under which the group coordinator knows this
+    /// member; empty if the member has not joined the group.
+    #[inline]
+    pub fn member_id(&self) -> &str {
+        &self.member_id
+    }
+
+    /// Retrieves the generation of the group this member last joined;
+    /// `-1` if the member has not joined the group.
+    #[inline]
+    pub fn generation_id(&self) -> i32 {
+        self.generation_id
+    }
+
+    /// Retrieves the `(topic, partition)` pairs assigned to this
+    /// member as of the last successful `ConsumerGroup::join`.
+    #[inline]
+    pub fn assignment(&self) -> &[(String, i32)] {
+        &self.assignment
+    }
+
+    /// Provides access to the underlying kafka client.
+    #[inline]
+    pub fn client(&self) -> &KafkaClient {
+        &self.client
+    }
+
+    /// Provides mutable access to the underlying kafka client.
+    #[inline]
+    pub fn client_mut(&mut self) -> &mut KafkaClient {
+        &mut self.client
+    }
+
+    /// (Re-)joins the group and retrieves the topic partitions
+    /// assigned to this member.  Should this member be elected the
+    /// group's leader, it computes and distributes the assignment of
+    /// the whole group.
+    ///
+    /// If the group starts rebalancing meanwhile
+    /// (`KafkaCode::RebalanceInProgress`) the group is transparently
+    /// rejoined.  If the coordinator no longer recognizes this
+    /// membership (`KafkaCode::IllegalGeneration` or
+    /// `KafkaCode::UnknownMemberId`) the membership is reset and the
+    /// group joined afresh.  Both happen at most
+    /// `KafkaClient::retry_max_attempts` times.
+    pub fn join(&mut self) -> Result<&[(String, i32)]> {
+        let mut attempt = 1;
+        loop {
+            let retry_code;
+            match self.join_once() {
+                Ok(()) => {
+                    return Ok(&self.assignment);
+                }
+                Err(Error(ErrorKind::Kafka(e @ KafkaCode::RebalanceInProgress), _)) => {
+                    retry_code = e;
+                }
+                Err(Error(ErrorKind::Kafka(e @ KafkaCode::IllegalGeneration), _)) |
+                Err(Error(ErrorKind::Kafka(e @ KafkaCode::UnknownMemberId), _)) => {
+                    debug!("join: resetting membership in '{}' due to: {:?}", self.group, e);
+                    self.reset();
+                    retry_code = e;
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+            if attempt < self.client.retry_max_attempts() {
+                debug!("join: will rejoin '{}' due to: {:?}", self.group, retry_code);
+                attempt += 1;
+                thread::sleep(self.client.retry_backoff_time());
+            } else {
+                bail!(ErrorKind::Kafka(retry_code));
+            }
+        }
+    }
+
+    /// Signals the group coordinator that this member is still alive.
+    ///
+    /// The coordinator considers a member dead if it does not hear
+    /// from it within the session timeout (see
+    /// `ConsumerGroup::set_session_timeout`) and rebalances the group
+    /// without it.  Hence, this method must be called regularly -
+    /// e.g. on a timer within the loop consuming the assigned
+    /// partitions - at an interval well below the session timeout;
+    /// `ConsumerGroup::heartbeat_interval` provides a reasonable
+    /// value.  Note that the time spent processing fetched messages
+    /// counts towards the session timeout as well.
+    ///
+    /// Returns `HeartbeatStatus::Rejoin` if the group is rebalancing,
+    /// its coordinator is not available (the heartbeat is sent only
+    /// once; see `KafkaClient::heartbeat`), or the coordinator no
+    /// longer recognizes this membership (the latter resets the
+    /// membership.)  The caller must then stop
+    /// consuming its current assignment and call
+    /// `ConsumerGroup::join` to obtain a new one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Instant;
+    /// use kafka::client::KafkaClient;
+    /// use kafka::consumer::{ConsumerGroup, HeartbeatStatus};
+    ///
+    /// let mut client = KafkaClient::new(vec!["localhost:9092".to_owned()]);
+    /// client.load_metadata_all().unwrap();
+    /// let mut group =
+    ///     ConsumerGroup::new(client, "my-group".to_owned(), vec!["my-topic".to_owned()]);
+    /// group.join().unwrap();
+    /// let mut last_heartbeat = Instant::now();
+    /// loop {
+    ///     // ... consume the assigned partitions ...
+    ///     if last_heartbeat.elapsed() >= group.heartbeat_interval() {
+    ///         if group.heartbeat().unwrap() == HeartbeatStatus::Rejoin {
+    ///             group.join().unwrap();
+    ///         }
+    ///         last_heartbeat = Instant::now();
+    ///     }
+    /// }
+    /// ```
+    pub fn heartbeat(&mut self) -> Result<HeartbeatStatus> {
+        if self.generation_id < 0 {
+            return Ok(HeartbeatStatus::Rejoin);
+        }
+        match self.client.heartbeat(&self.group, self.generation_id, &self.member_id) {
+            Ok(()) => Ok(HeartbeatStatus::Alive),
+            Err(Error(ErrorKind::Kafka(KafkaCode::RebalanceInProgress), _)) => {
+                debug!("heartbeat: group '{}' is rebalancing", self.group);
+                Ok(HeartbeatStatus::Rejoin)
+            }
+            // ~ the heartbeat is not retried; rejoining the group
+            // re-discovers its coordinator
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::NotCoordinatorForGroup), _)) |
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::GroupCoordinatorNotAvailable), _)) |
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::GroupLoadInProgress), _)) => {
+                debug!("heartbeat: coordinator of '{}' not available: {:?}", self.group, e);
+                Ok(HeartbeatStatus::Rejoin)
+            }
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::IllegalGeneration), _)) |
+            Err(Error(ErrorKind::Kafka(e @ KafkaCode::UnknownMemberId), _)) => {
+                debug!("heartbeat: resetting membership in '{}' due to: {:?}", self.group, e);
+                self.reset();
+                Ok(HeartbeatStatus::Rejoin)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Leaves the group - if joined - making the group coordinator
+    /// rebalance the group's partitions among the remaining members
+    /// right away instead of waiting for the session timeout of this
+    /// member to expire.  The membership is reset even if leaving
+    /// the group fails; the group can be joined again afterwards.
+    pub fn close(&mut self) -> Result<()> {
+        self.leave(false)
+    }
+
+    // ~ leaves the group - if joined; a `best_effort` attempt is made
+    // only once rather than retried
+    fn leave(&mut self, best_effort: bool) -> Result<()> {
+        if self.member_id.is_empty() {
+            return Ok(());
+        }
+        let r = if best_effort {
+            self.client.internal_leave_group_once(&self.group, &self.member_id)
+        } else {
+            self.client.leave_group(&self.group, &self.member_id)
+        };
+        self.reset();
+        r
+    }
+
+    fn join_once(&mut self) -> Result<()> {
+        self.assignment.clear();
+        let m = try!(self.client.join_group(
+            &self.group,
+            &self.member_id,
+            self.session_timeout,
+            &self.topics,
+        ));
+        self.member_id = m.member_id.clone();
+        self.generation_id = m.generation_id;
+        debug!(
+            "join: joined '{}' (generation: {}) as '{}' (leader: {})",
+            self.group,
+            m.generation_id,
+            m.member_id,
+            m.is_leader()
+        );
+
+        let assignments = if m.is_leader() {
+            try!(self.assign(&m.members))
+        } else {
+            vec![]
+        };
+        self.assignment = try!(self.client.sync_group(
+            &self.group,
+            self.generation_id,
+            &self.member_id,
+            &assignments,
+        ));
+        Ok(())
+    }
+
+    /// Computes the assignment of the whole group as its leader.
+    fn assign(&mut self, members: &[GroupMember]) -> Result<Vec<GroupAssignment>> {
+        let mut unknown = Vec::new();
+        for m in members {
+            for t in &m.topics {
+                if !self.client.topics().contains(t) && !unknown.contains(t) {
+                    unknown.push(t.clone());
+                }
+            }
+        }
+        if !unknown.is_empty() {
+            try!(self.client.load_metadata(&unknown));
+        }
+        let mut partitions = HashMap::new();
+        for t in self.client.topics() {
+            partitions.insert(
+                t.name().to_owned(),
+                t.partitions().iter().map(|p| p.id()).collect(),
+            );
+        }
+        Ok(assign_round_robin(members, &partitions))
+    }
+
+    fn reset(&mut self) {
+        self.member_id.clear();
+        self.generation_id = -1;
+        self.assignment.clear();
+    }
+}
+
+impl Drop for ConsumerGroup {
+    fn drop(&mut self) {
+        // ~ dropping must not block for the whole retry period while
+        // the group coordinator is not available
+        if let Err(e) = self.leave(true) {
+            warn!("drop: failed to leave group '{}': {}", self.group, e);
+        }
+    }
+}
+
+/// Distributes the partitions of all subscribed topics in a round
+/// robin fashion among the members subscribing to them.  Members are
+/// ordered by their id and partitions by topic name and id.  See
+/// `client::GROUP_ASSIGNMENT_STRATEGY`.
+fn assign_round_robin(
+    members: &[GroupMember],
+    partitions: &HashMap<String, Vec<i32>>,
+) -> Vec<GroupAssignment> {
+    let mut members: Vec<&GroupMember> = members.iter().collect();
+    members.sort_by(|a, b| a.member_id.cmp(&b.member_id));
+
+    let mut tps = Vec::new();
+    for m in &members {
+        for t in &m.topics {
+            if let Some(ps) = partitions.get(t) {
+                for &p in ps {
+                    tps.push((t.as_str(), p));
+                }
+            }
+        }
+    }
+    tps.sort();
+    tps.dedup();
+
+    let mut assignments: Vec<GroupAssignment> = members
+        .iter()
+        .map(|m| {
+            GroupAssignment {
+                member_id: m.member_id.clone(),
+                partitions: vec![],
+            }
+        })
+        .collect();
+    let mut next = 0;
+    for (topic, partition) in tps {
+        // ~ there is at least one member subscribing to `topic`
+        while !members[next % members.len()].topics.iter().any(|t| t == topic) {
+            next += 1;
+        }
+        assignments[next % members.len()].partitions.push((topic.to_owned(), partition));
+        next += 1;
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use client::{GroupAssignment, GroupMember};
+    use super::assign_round_robin;
+
+    fn member(id: &str, topics: &[&str]) -> GroupMember {
+        GroupMember {
+            member_id: id.to_owned(),
+            topics: topics.iter().map(|t| (*t).to_owned()).collect(),
+        }
+    }
+
+    fn assignment(id: &str, partitions: &[(&str, i32)]) -> GroupAssignment {
+        GroupAssignment {
+            member_id: id.to_owned(),
+            partitions: partitions.iter().map(|&(t, p)| (t.to_owned(), p)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_assign_round_robin() {
+        let mut partitions = HashMap::new();
+        partitions.insert("foo".to_owned(), vec![0, 1, 2]);
+        partitions.insert("bar".to_owned(), vec![0, 1]);
+        partitions.insert("baz".to_owned(), vec![0]);
+
+        let members = vec![member("m2", &["foo"]), member("m1", &["foo", "bar"])];
+        assert_eq!(
+            vec![
+                assignment("m1", &[("bar", 0), ("bar", 1), ("foo", 1)]),
+                assignment("m2", &[("foo", 0), ("foo", 2)]),
+            ],
+            assign_round_robin(&members, &partitions)
+        );
+    }
+
+    #[test]
+    fn test_assign_round_robin_unknown_topic() {
+        let mut partitions = HashMap::new();
+        partitions.insert("foo".to_owned(), vec![0, 1]);
+
+        let members = vec![member("m1", &["foo", "unknown"])];
+        assert_eq!(
+            vec![assignment("m1", &[("foo", 0), ("foo", 1)])],
+            assign_round_robin(&members, &partitions)
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! A lazy iterator over the messages delivered by a `Consumer`.
+
+use error::Result;
+
+use super::{Consumer, MessageSets};
+
+/// A message delivered by `MessageIterator`.  Unlike
+/// `fetch::Message` this owns its data and is hence not bound to the
+/// lifetime of the fetch response it was delivered in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedMessage {
+    /// The topic the message was fetched from.
+    pub topic: String,
+    /// The partition the message was fetched from.
+    pub partition: i32,
+    /// The offset at which the message resides in its partition.
+    pub offset: i64,
+    /// The "key" data of the message; empty if there is none.
+    pub key: Vec<u8>,
+    /// The value data of the message; empty if there is none.
+    pub value: Vec<u8>,
+    /// The timestamp of the message; see `fetch::Message::timestamp`.
+    pub timestamp: i64,
+    /// The headers of the message; see `fetch::Message::headers`.
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+/// A message delivered by `MessageIterator::next_ref`.  Unlike
+/// `OwnedMessage` this borrows its data from the fetch response it
+/// was delivered in; no memory is allocated per message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    /// The topic the message was fetched from.
+    pub topic: &'a str,
+    /// The partition the message was fetched from.
+    pub partition: i32,
+    /// The offset at which the message resides in its partition.
+    pub offset: i64,
+    /// The "key" data of the message; empty if there is none.
+    pub key: &'a [u8],
+    /// The value data of the message; empty if there is none.
+    pub value: &'a [u8],
+    /// The timestamp of the message; see `fetch::Message::timestamp`.
+    pub timestamp: i64,
+    /// The headers of the message; see `fetch::Message::headers`.
+    pub headers: &'a [(&'a str, &'a [u8])],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Copies the message's data into an `OwnedMessage`.
+    pub fn into_owned(self) -> OwnedMessage {
+        OwnedMessage {
+            topic: self.topic.to_owned(),
+            partition: self.partition,
+            offset: self.offset,
+            key: self.key.to_vec(),
+            value: self.value.to_vec(),
+            timestamp: self.timestamp,
+            headers: self.headers.iter().map(|&(k, v)| (k.to_owned(), v.to_vec())).collect(),
+        }
+    }
+}
+
+// ~ the position of the next message to deliver from `MessageSets`
+#[derive(Default)]
+struct Position {
+    response: usize,
+    topic: usize,
+    partition: usize,
+    message: usize,
+}
+
+/// An iterator over the messages of the topic partitions consumed by
+/// a `Consumer`.  See `Consumer::iter`.
+pub struct MessageIterator<'a> {
+    consumer: &'a mut Consumer,
+    // ~ the fetched data currently being delivered; at most one
+    // batch is held at a time
+    sets: Option<MessageSets>,
+    pos: Position,
+    // ~ the max. number of bytes to fetch per batch; `None` for no
+    // limit beyond the per partition one
+    max_prefetch_bytes: Option<usize>,
+}
+
+// ~ public only to be shared inside the consumer module
+pub fn new(consumer: &mut Consumer) -> MessageIterator {
+    MessageIterator {
+        consumer: consumer,
+        sets: None,
+        pos: Position::default(),
+        max_prefetch_bytes: None,
+    }
+}
+
+impl<'a> MessageIterator<'a> {
+    /// Limits the amount of message data fetched at once to
+    /// (roughly) the given number of bytes; `None`, the default,
+    /// imposes no such limit.
+    ///
+    /// The iterator never fetches the next batch of messages before
+    /// having delivered all messages of the current one.  Without a
+    /// limit, a batch may grow up to the consumer's fetch max bytes
+    /// per partition (see `Builder::with_fetch_max_bytes_per_partition`)
+    /// times the number of consumed partitions.  With a limit, the
+    /// budget is split evenly among the consumed partitions, the
+    /// fetch max bytes per partition still applying as an upper
+    /// bound.  A single message exceeding its partition's share is
+    /// nevertheless fetched on its own in a subsequent poll (subject
+    /// to `Builder::with_retry_max_bytes_limit`.)  A consumer of a
+    /// single partition is bounded by the fetch max bytes per
+    /// partition alone.
+    pub fn set_max_prefetch_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_prefetch_bytes = max_bytes;
+    }
+
+    /// Retrieves the current `MessageIterator::set_max_prefetch_bytes`
+    /// setting.
+    pub fn max_prefetch_bytes(&self) -> Option<usize> {
+        self.max_prefetch_bytes
+    }
+
+    /// Delivers the next message like `Iterator::next` but without
+    /// copying the message's data; the returned message borrows from
+    /// the batch of fetched messages held by this iterator.  This
+    /// avoids allocating memory per message for processing which
+    /// does not need to keep the data beyond the next call to this
+    /// iterator.  Both methods can be used interchangeably on the
+    /// same iterator.
+    pub fn next_ref(&mut self) -> Option<Result<MessageRef>> {
+        loop {
+            if self.sets.is_none() {
+                match self.consumer.poll_bounded(self.max_prefetch_bytes) {
+                    Err(e) => return Some(Err(e)),
+                    Ok(ref sets) if sets.is_empty() => return None,
+                    Ok(sets) => {
+                        self.sets = Some(sets);
+                        self.pos = Position::default();
+                    }
+                }
+            }
+            if seek(self.sets.as_ref().unwrap(), &mut self.pos) {
+                break;
+            }
+            // ~ the batch is exhausted; release it before fetching the
+            // next one
+            self.sets = None;
+        }
+        let msg = message_at(self.sets.as_ref().unwrap(), &self.pos);
+        self.pos.message += 1;
+        if let Err(e) = self.consumer.consume_message(msg.topic, msg.partition, msg.offset) {
+            return Some(Err(e));
+        }
+        Some(Ok(msg))
+    }
+
+    /// Commits the offsets of the so far delivered messages.  See
+    /// `Consumer::commit_consumed`.
+    pub fn commit_consumed(&mut self) -> Result<()> {
+        self.consumer.commit_consumed()
+    }
+}
+
+impl<'a> Iterator for MessageIterator<'a> {
+    type Item = Result<OwnedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_ref().map(|r| r.map(MessageRef::into_owned))
+    }
+}
+
+// ~ advances the given position to the next message to deliver (if
+// it does not yet point to one); skips partitions which failed to
+// deliver data.  returns `false` if there is no such message.
+fn seek(sets: &MessageSets, pos: &mut Position) -> bool {
+    while let Some(resp) = sets.responses.get(pos.response) {
+        if let Some(topic) = resp.topics().get(pos.topic) {
+            if let Some(partition) = topic.partitions().get(pos.partition) {
+                if let Ok(ref data) = *partition.data() {
+                    if pos.message < data.messages().len() {
+                        return true;
+                    }
+                }
+                pos.partition += 1;
+                pos.message = 0;
+                continue;
+            }
+            pos.topic += 1;
+            pos.partition = 0;
+            pos.message = 0;
+            continue;
+        }
+        pos.response += 1;
+        pos.topic = 0;
+        pos.partition = 0;
+        pos.message = 0;
+    }
+    false
+}
+
+// ~ retrieves the message at the given position as established by
+// `seek`
+fn message_at<'a>(sets: &'a MessageSets, pos: &Position) -> MessageRef<'a> {
+    let topic = &sets.responses[pos.response].topics()[pos.topic];
+    let partition = &topic.partitions()[pos.partition];
+    match *partition.data() {
+        Ok(ref data) => {
+            let msg = &data.messages()[pos.message];
+            MessageRef {
+                topic: topic.topic(),
+                partition: partition.partition(),
+                offset: msg.offset,
+                key: msg.key,
+                value: msg.value,
+                timestamp: msg.timestamp,
+                headers: &msg.headers,
+            }
+        }
+        Err(_) => unreachable!("seek never stops at a failed partition"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::fetch::decode_fetch_response;
+    use super::super::MessageSets;
+    use super::{MessageRef, Position, message_at, seek};
+
+    static FETCH2_FETCH_RESPONSE_NOCOMPRESSION_K0900: &'static [u8] =
+        include_bytes!("../../test-data/fetch2.mytopic.nocompression.kafka.0900");
+
+    // ~ a batch made up of the given number of copies of the test
+    // fetch response
+    fn message_sets(copies: usize) -> MessageSets {
+        MessageSets {
+            responses: (0..copies)
+                .map(|_| {
+                    decode_fetch_response(FETCH2_FETCH_RESPONSE_NOCOMPRESSION_K0900, 0).unwrap()
+                })
+                .collect(),
+            empty: false,
+        }
+    }
+
+    // ~ visits all messages of the given batch in delivery order
+    fn for_each_ref<'a, F: FnMut(MessageRef<'a>)>(sets: &'a MessageSets, mut f: F) {
+        let mut pos = Position::default();
+        while seek(sets, &mut pos) {
+            f(message_at(sets, &pos));
+            pos.message += 1;
+        }
+    }
+
+    #[test]
+    fn test_message_refs() {
+        let sets = message_sets(2);
+        let mut expected = Vec::new();
+        for ms in sets.iter() {
+            for msg in ms.messages() {
+                expected.push((ms.topic().to_owned(), ms.partition(), msg.offset, msg.value));
+            }
+        }
+        assert!(!expected.is_empty());
+
+        let mut delivered = Vec::new();
+        for_each_ref(&sets, |msg| {
+            let owned = msg.into_owned();
+            assert_eq!(msg.key, &owned.key[..]);
+            assert_eq!(msg.value, &owned.value[..]);
+            delivered.push((owned.topic, owned.partition, msg.offset, msg.value));
+        });
+        assert_eq!(expected, delivered);
+    }
+
+    #[cfg(feature = "nightly")]
+    mod benches {
+        use test::{black_box, Bencher};
+
+        use super::{for_each_ref, message_sets};
+
+        // ~ compares the cost of delivering a large batch of messages
+        // as `OwnedMessage`s against delivering them as `MessageRef`s
+
+        #[bench]
+        fn bench_iter_owned_messages(b: &mut Bencher) {
+            let sets = message_sets(100);
+            b.iter(|| {
+                let mut n = 0;
+                for_each_ref(&sets, |msg| {
+                    black_box(msg.into_owned());
+                    n += 1;
+                });
+                n
+            });
+        }
+
+        #[bench]
+        fn bench_iter_message_refs(b: &mut Bencher) {
+            let sets = message_sets(100);
+            b.iter(|| {
+                let mut n = 0;
+                for_each_ref(&sets, |msg| {
+                    black_box(msg);
+                    n += 1;
+                });
+                n
+            });
+        }
+    }
+}
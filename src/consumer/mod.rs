@@ -61,23 +61,30 @@
 //! group configured, it will behave as if it had one, only that
 //! commiting consumed message offsets resolves into a void operation.
 
+use std::cmp;
 use std::collections::hash_map::{Entry, HashMap};
 use std::slice;
+use std::time::Duration;
 
-use client::{KafkaClient, FetchPartition, CommitOffset};
-use error::{ErrorKind, KafkaCode, Result};
+use client::{KafkaClient, FetchGroupOffset, FetchPartition, CommitOffset, PartitionOffset};
+use error::{Error, ErrorKind, KafkaCode, Result};
 use client::fetch;
 
 // public re-exports
 pub use client::fetch::Message;
 pub use client::FetchOffset;
 pub use client::GroupOffsetStorage;
+pub use client::IsolationLevel;
 pub use self::builder::Builder;
+pub use self::group::{ConsumerGroup, HeartbeatStatus, DEFAULT_SESSION_TIMEOUT_MILLIS};
+pub use self::iter::{MessageIterator, MessageRef, OwnedMessage};
 
 mod assignment;
 mod config;
 mod state;
 mod builder;
+mod group;
+mod iter;
 
 /// The default value for `Builder::with_retry_max_bytes_limit`.
 pub const DEFAULT_RETRY_MAX_BYTES_LIMIT: i32 = 0;
@@ -85,6 +92,66 @@ pub const DEFAULT_RETRY_MAX_BYTES_LIMIT: i32 = 0;
 /// The default value for `Builder::with_fallback_offset`.
 pub const DEFAULT_FALLBACK_OFFSET: FetchOffset = FetchOffset::Latest;
 
+/// The default value for `Builder::with_auto_offset_reset`.
+pub const DEFAULT_AUTO_OFFSET_RESET: AutoOffsetReset = AutoOffsetReset::None;
+
+/// Specifies how a consumer reacts to Kafka reporting the offset it
+/// is about to fetch from as no longer available, e.g. because the
+/// corresponding messages have been deleted due to retention.  This
+/// corresponds to the `auto.offset.reset` setting of Kafka's Java
+/// consumer.  See `Builder::with_auto_offset_reset`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutoOffsetReset {
+    /// Repositions the consumer to the earliest available offset of
+    /// the affected partition.
+    Earliest,
+    /// Repositions the consumer to the latest offset of the affected
+    /// partition, skipping all messages currently available in it.
+    Latest,
+    /// Fails the poll with `KafkaCode::OffsetOutOfRange`.
+    None,
+}
+
+impl AutoOffsetReset {
+    // ~ determines where to reposition a partition whose fetch
+    // `offset` has been reported out of range by kafka given the
+    // `latest` offset of the partition; `Ok(None)` denotes a
+    // partition positioned past its end (see `Consumer::seek`) which
+    // merely has no messages to deliver yet
+    fn reset_offset(&self, offset: i64, latest: i64) -> Result<Option<FetchOffset>> {
+        if offset > latest {
+            return Ok(None);
+        }
+        match *self {
+            AutoOffsetReset::Earliest => Ok(Some(FetchOffset::Earliest)),
+            AutoOffsetReset::Latest => Ok(Some(FetchOffset::Latest)),
+            AutoOffsetReset::None => bail!(ErrorKind::Kafka(KafkaCode::OffsetOutOfRange)),
+        }
+    }
+
+    // ~ determines where to position a partition without a committed
+    // group offset; without a reset policy the given `fallback`
+    // offset applies (see `Consumer::resume_from_group`)
+    fn uncommitted_offset(&self, fallback: FetchOffset) -> FetchOffset {
+        match *self {
+            AutoOffsetReset::Earliest => FetchOffset::Earliest,
+            AutoOffsetReset::Latest => FetchOffset::Latest,
+            AutoOffsetReset::None => fallback,
+        }
+    }
+}
+
+// ~ limits the max. bytes to fetch for one of `n` partitions such
+// that all of them together stay within the given budget
+fn budgeted_max_bytes(max_bytes: i32, budget: usize, n: usize) -> i32 {
+    let share = budget / cmp::max(n, 1);
+    if share < max_bytes as usize {
+        cmp::max(share, 1) as i32
+    } else {
+        max_bytes
+    }
+}
+
 /// The Kafka Consumer
 ///
 /// See module level documentation.
@@ -95,8 +162,7 @@ pub struct Consumer {
     config: config::Config,
 }
 
-// XXX 1) Allow returning to a previous offset (aka seeking)
-// XXX 2) Issue IO in a separate (background) thread and pre-fetch messagesets
+// XXX Issue IO in a separate (background) thread and pre-fetch messagesets
 
 impl Consumer {
     /// Starts building a consumer using the given kafka client.
@@ -150,18 +216,239 @@ impl Consumer {
         h
     }
 
+    /// Retrieves the current position of this consumer, i.e. the
+    /// offsets as of which the next `poll` will fetch messages from
+    /// the consumed topic partitions.
+    ///
+    /// Note: the position is advanced past the delivered messages
+    /// with each `poll` regardless of whether these have been marked
+    /// as consumed.  See `Consumer::last_consumed_message`.
+    pub fn current_offsets(&self) -> HashMap<String, Vec<PartitionOffset>> {
+        let mut h: HashMap<String, Vec<PartitionOffset>> =
+            HashMap::with_capacity(self.state.assignments.as_slice().len());
+        for (tp, fetch_state) in &self.state.fetch_offsets {
+            let po = PartitionOffset {
+                offset: fetch_state.offset,
+                partition: tp.partition,
+            };
+            let topic = self.state.topic_name(tp.topic_ref);
+            // ~ allocate topic-name only once per topic
+            if let Some(ps) = h.get_mut(topic) {
+                ps.push(po);
+                continue;
+            }
+            h.insert(topic.to_owned(), vec![po]);
+        }
+        h
+    }
+
+    /// Repositions this consumer within the specified topic
+    /// partition such that the next `poll` fetches messages as of the
+    /// given offset.
+    ///
+    /// `FetchOffset::Earliest` and `FetchOffset::Latest` are resolved
+    /// into the partition's real offset by asking Kafka right away.
+    /// `FetchOffset::ByTime` seeks to the first message with a
+    /// timestamp equal to or greater than the given one (see
+    /// `KafkaClient::fetch_offsets_by_time`), or to the latest offset
+    /// if there is no such message.  `FetchOffset::Exact` is taken as
+    /// is; seeking past the end of the partition makes subsequent
+    /// polls deliver no messages for it until the partition has
+    /// grown up to that offset.
+    ///
+    /// Seeking does not alter the offsets marked as consumed (see
+    /// `Consumer::consume_message`.)
+    ///
+    /// Results in an error if the specified topic partition is not
+    /// being consumed by this consumer.
+    pub fn seek(&mut self, topic: &str, partition: i32, offset: FetchOffset) -> Result<()> {
+        let tp = match self.state.topic_ref(topic) {
+            None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+            Some(topic_ref) => {
+                state::TopicPartition {
+                    topic_ref: topic_ref,
+                    partition: partition,
+                }
+            }
+        };
+        if !self.state.fetch_offsets.contains_key(&tp) {
+            bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition));
+        }
+        let offset = match offset {
+            FetchOffset::Exact(n) => n,
+            FetchOffset::ByTime(t) => {
+                let tps = [(topic.to_owned(), partition)];
+                match try!(self.client.fetch_offsets_by_time(&tps, t))[0].offset {
+                    -1 => try!(self.fetch_partition_offset(topic, partition, FetchOffset::Latest)),
+                    n => n,
+                }
+            }
+            other => try!(self.fetch_partition_offset(topic, partition, other)),
+        };
+        debug!("seek: {}:{} to offset {}", topic, partition, offset);
+        if let Some(fetch_state) = self.state.fetch_offsets.get_mut(&tp) {
+            fetch_state.offset = offset;
+        }
+        Ok(())
+    }
+
+    /// Repositions this consumer within all of its consumed topic
+    /// partitions to the offsets committed for the given consumer
+    /// group, i.e. resumes consumption where the group left off.
+    /// This is useful to take over the position of a group other than
+    /// the one the consumer was created with; its own group's
+    /// offsets are already applied when creating the consumer.
+    ///
+    /// Partitions for which the group has no committed offset are
+    /// positioned to their earliest or latest offset according to the
+    /// consumer's `AutoOffsetReset` setting; with `AutoOffsetReset::None`
+    /// the consumer's fallback offset applies instead (see
+    /// `Builder::with_fallback_offset`.)
+    ///
+    /// Like `Consumer::seek`, this does not alter the offsets marked
+    /// as consumed.
+    pub fn resume_from_group(&mut self, group: &str) -> Result<()> {
+        let tps: Vec<(String, i32)> = self.state
+            .fetch_offsets
+            .keys()
+            .map(|tp| (self.state.topic_name(tp.topic_ref).to_owned(), tp.partition))
+            .collect();
+        let committed = try!(self.client.fetch_group_offsets(
+            group,
+            tps.iter().map(|&(ref topic, partition)| FetchGroupOffset::new(topic, partition)),
+        ));
+        let uncommitted = self.config.auto_offset_reset.uncommitted_offset(
+            self.config.fallback_offset,
+        );
+        for (topic, partition) in tps {
+            let offset = committed
+                .get(&topic)
+                .and_then(|pos| pos.iter().find(|po| po.partition == partition))
+                .map_or(-1, |po| po.offset);
+            let offset = match offset {
+                // ~ the group has no offset committed for the partition
+                -1 => uncommitted,
+                n => FetchOffset::Exact(n),
+            };
+            debug!("resume_from_group: {}:{} of group {} at {:?}", topic, partition, group, offset);
+            try!(self.seek(&topic, partition, offset));
+        }
+        Ok(())
+    }
+
+    /// Asks Kafka for the earliest or latest offset of the specified
+    /// topic partition.
+    fn fetch_partition_offset(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: FetchOffset,
+    ) -> Result<i64> {
+        let offsets = try!(self.client.fetch_topic_offsets(topic, offset));
+        match offsets.iter().find(|po| po.partition == partition) {
+            Some(po) => Ok(po.offset),
+            None => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+        }
+    }
+
     /// Polls for the next available message data.
     pub fn poll(&mut self) -> Result<MessageSets> {
-        let (n, resps) = self.fetch_messages();
+        self.poll_bounded(None)
+    }
+
+    // ~ polls for the next available message data fetching (roughly)
+    // no more than `max_bytes` in total if given; see
+    // `MessageIterator::set_max_prefetch_bytes`
+    fn poll_bounded(&mut self, max_bytes: Option<usize>) -> Result<MessageSets> {
+        let (n, resps) = self.fetch_messages(max_bytes);
         self.process_fetch_responses(n, try!(resps))
     }
 
+    /// Iterates lazily over the messages of the consumed topic
+    /// partitions, transparently polling for the next batch of
+    /// messages once the current one is exhausted.  Only one batch
+    /// is held in memory at a time.
+    ///
+    /// Each delivered message is marked as consumed (see
+    /// `Consumer::consume_message`); committing is left to the
+    /// caller (see `MessageIterator::commit_consumed`.)  The
+    /// iteration ends as soon as a poll delivers no messages; a
+    /// subsequent call to `iter` continues where the previous
+    /// iteration stopped.  The iterator delivers copies of the
+    /// fetched messages as `OwnedMessage`s; see
+    /// `MessageIterator::next_ref` to process them without copying.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kafka::consumer::Consumer;
+    ///
+    /// let mut consumer = Consumer::from_hosts(vec!["localhost:9092".to_owned()])
+    ///     .with_topic("my-topic".to_owned())
+    ///     .with_group("my-group".to_owned())
+    ///     .create()
+    ///     .unwrap();
+    /// let mut iter = consumer.iter();
+    /// while let Some(msg) = iter.next() {
+    ///     let msg = msg.unwrap();
+    ///     println!("{}:{}@{}: {:?}", msg.topic, msg.partition, msg.offset, msg.value);
+    /// }
+    /// iter.commit_consumed().unwrap();
+    /// ```
+    pub fn iter(&mut self) -> MessageIterator {
+        iter::new(self)
+    }
+
     /// Determines whether this consumer is set up to consume only a
     /// single topic partition.
     fn single_partition_consumer(&self) -> bool {
         self.state.fetch_offsets.len() == 1
     }
 
+    /// Specifies which messages written by transactional producers
+    /// this consumer delivers; see `KafkaClient::set_isolation_level`.
+    /// With `IsolationLevel::ReadCommitted` messages of aborted
+    /// transactions are skipped and those of pending transactions
+    /// are delivered only once committed.
+    pub fn set_isolation_level(&mut self, isolation_level: IsolationLevel) {
+        self.client.set_isolation_level(isolation_level);
+    }
+
+    /// Retrieves the currently configured isolation level.  See
+    /// `Consumer::set_isolation_level`.
+    pub fn isolation_level(&self) -> IsolationLevel {
+        self.client.isolation_level()
+    }
+
+    /// Sets the minimum number of bytes of available data the brokers
+    /// wait for before responding to a fetch request of this
+    /// consumer; see `KafkaClient::set_fetch_min_bytes`.
+    pub fn set_fetch_min_bytes(&mut self, min_bytes: i32) {
+        self.client.set_fetch_min_bytes(min_bytes);
+    }
+
+    /// Retrieves the currently configured fetch min bytes.  See
+    /// `Consumer::set_fetch_min_bytes`.
+    pub fn fetch_min_bytes(&self) -> i32 {
+        self.client.fetch_min_bytes()
+    }
+
+    /// Sets the maximum time the brokers block a fetch request of
+    /// this consumer waiting for the configured min bytes to become
+    /// available.  Long polling idle partitions this way avoids
+    /// busy polling them.  The client's socket timeout is extended
+    /// as necessary to exceed the given time; see
+    /// `KafkaClient::set_fetch_max_wait_time`.
+    pub fn set_fetch_max_wait(&mut self, max_wait: Duration) -> Result<()> {
+        self.client.set_fetch_max_wait_time(max_wait)
+    }
+
+    /// Retrieves the currently configured fetch max wait time.  See
+    /// `Consumer::set_fetch_max_wait`.
+    pub fn fetch_max_wait(&self) -> Duration {
+        self.client.fetch_max_wait_time()
+    }
+
     /// Retrieves the group on which behalf this consumer is acting.
     /// The empty group name specifies a group-less consumer.
     pub fn group(&self) -> &str {
@@ -169,7 +456,7 @@ impl Consumer {
     }
 
     // ~ returns (number partitions queried, fecth responses)
-    fn fetch_messages(&mut self) -> (u32, Result<Vec<fetch::Response>>) {
+    fn fetch_messages(&mut self, max_bytes: Option<usize>) -> (u32, Result<Vec<fetch::Response>>) {
         // ~ if there's a retry partition ... fetch messages just for
         // that one. Otherwise try to fetch messages for all assigned
         // partitions.
@@ -200,9 +487,17 @@ impl Consumer {
                 let client = &mut self.client;
                 let state = &self.state;
                 debug!("fetching messages: (fetch-offsets: {:?})", state.fetch_offsets_debug());
+                let n = state.fetch_offsets.len();
                 let reqs = state.fetch_offsets.iter().map(|(tp, s)| {
                     let topic = state.topic_name(tp.topic_ref);
-                    FetchPartition::new(topic, tp.partition, s.offset).with_max_bytes(s.max_bytes)
+                    // ~ a single partition's data is bounded by its
+                    // max_bytes already; limiting it further could
+                    // keep oversized messages from being delivered
+                    let max_bytes = match max_bytes {
+                        Some(budget) if n > 1 => budgeted_max_bytes(s.max_bytes, budget, n),
+                        _ => s.max_bytes,
+                    };
+                    FetchPartition::new(topic, tp.partition, s.offset).with_max_bytes(max_bytes)
                 });
                 (state.fetch_offsets.len() as u32, client.fetch_messages(reqs))
             }
@@ -222,6 +517,9 @@ impl Consumer {
     ) -> Result<MessageSets> {
         let single_partition_consumer = self.single_partition_consumer();
         let mut empty = true;
+        // ~ partitions reported with an offset out of range; these
+        // are checked for having been positioned past their end
+        let mut out_of_range = Vec::new();
         let mut retry_partitions = &mut self.state.retry_partitions;
 
         for resp in &resps {
@@ -243,6 +541,10 @@ impl Consumer {
                     // certain errors and retry the fetch operation
                     // transparently for the caller.
                     let data = match p.data() {
+                        &Err(Error(ErrorKind::Kafka(KafkaCode::OffsetOutOfRange), _)) => {
+                            out_of_range.push((t.topic(), tp.partition));
+                            continue;
+                        }
                         // XXX need to prevent updating fetch_offsets in case we're gonna fail here
                         &Err(ref e) => return Err(e.clone()),
                         &Ok(ref data) => data,
@@ -270,6 +572,17 @@ impl Consumer {
                                 fetch_state.max_bytes
                             );
                         }
+                    } else if data.next_offset().map_or(false, |o| o > fetch_state.offset) {
+                        // ~ the fetched data consisted solely of
+                        // undeliverable entries, e.g. transaction
+                        // markers or messages of aborted transactions
+                        fetch_state.offset = data.next_offset().unwrap_or(fetch_state.offset);
+                        debug!(
+                            "skipped undeliverable data for {}:{} up to offset {}",
+                            t.topic(),
+                            tp.partition,
+                            fetch_state.offset
+                        );
                     } else {
                         debug!(
                             "no data received for {}:{} (max_bytes: {} / fetch_offset: {} / \
@@ -327,6 +640,44 @@ impl Consumer {
             }
         }
 
+        // ~ a partition positioned past its end (see `Consumer::seek`)
+        // merely has no messages to deliver yet; otherwise its offset
+        // is no longer available and subject to `auto_offset_reset`
+        for (topic, partition) in out_of_range {
+            let latest = try!(self.fetch_partition_offset(topic, partition, FetchOffset::Latest));
+            let tp = state::TopicPartition {
+                topic_ref: self.state.topic_ref(topic).expect("unknown topic in response"),
+                partition: partition,
+            };
+            let offset = self.state.fetch_offsets.get(&tp).expect("non-requested partition").offset;
+            let reset = try!(self.config.auto_offset_reset.reset_offset(offset, latest));
+            let new_offset = match reset {
+                None => {
+                    debug!(
+                        "no data available for {}:{} (fetch_offset: {} / latest offset: {})",
+                        topic,
+                        partition,
+                        offset,
+                        latest
+                    );
+                    continue;
+                }
+                Some(FetchOffset::Latest) => latest,
+                Some(reset) => try!(self.fetch_partition_offset(topic, partition, reset)),
+            };
+            debug!(
+                "reset offset for {}:{} from {} to {} (auto_offset_reset: {:?})",
+                topic,
+                partition,
+                offset,
+                new_offset,
+                self.config.auto_offset_reset
+            );
+            if let Some(fetch_state) = self.state.fetch_offsets.get_mut(&tp) {
+                fetch_state.offset = new_offset;
+            }
+        }
+
         // XXX in future, issue one more fetch_messages request in the
         // background such that the next time the client polls that
         // request's response will likely be already ready for
@@ -565,3 +916,61 @@ impl<'a> Iterator for MessageSetsIter<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use client::FetchOffset;
+    use error::{Error, ErrorKind, KafkaCode};
+    use super::{AutoOffsetReset, budgeted_max_bytes};
+
+    #[test]
+    fn test_auto_offset_reset_below_earliest() {
+        // ~ a consumer positioned at offset 0 of a partition whose
+        // earliest available offset is 5 and whose latest is 10
+        match AutoOffsetReset::Earliest.reset_offset(0, 10) {
+            Ok(Some(FetchOffset::Earliest)) => {}
+            r => panic!("Expected a reset to the earliest offset, but got: {:?}", r),
+        }
+        match AutoOffsetReset::Latest.reset_offset(0, 10) {
+            Ok(Some(FetchOffset::Latest)) => {}
+            r => panic!("Expected a reset to the latest offset, but got: {:?}", r),
+        }
+        match AutoOffsetReset::None.reset_offset(0, 10) {
+            Err(Error(ErrorKind::Kafka(KafkaCode::OffsetOutOfRange), _)) => {}
+            r => panic!("Expected OffsetOutOfRange, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_auto_offset_reset_uncommitted_offset() {
+        let fallback = FetchOffset::ByTime(1_000);
+        match AutoOffsetReset::Earliest.uncommitted_offset(fallback) {
+            FetchOffset::Earliest => {}
+            o => panic!("Expected the earliest offset, but got: {:?}", o),
+        }
+        match AutoOffsetReset::Latest.uncommitted_offset(fallback) {
+            FetchOffset::Latest => {}
+            o => panic!("Expected the latest offset, but got: {:?}", o),
+        }
+        match AutoOffsetReset::None.uncommitted_offset(fallback) {
+            FetchOffset::ByTime(1_000) => {}
+            o => panic!("Expected the fallback offset, but got: {:?}", o),
+        }
+    }
+
+    #[test]
+    fn test_budgeted_max_bytes() {
+        assert_eq!(1024, budgeted_max_bytes(1024, 1 << 20, 4));
+        assert_eq!(256, budgeted_max_bytes(1024, 1024, 4));
+        assert_eq!(1, budgeted_max_bytes(1024, 2, 4));
+        assert_eq!(512, budgeted_max_bytes(1024, 512, 0));
+    }
+
+    #[test]
+    fn test_auto_offset_reset_past_end() {
+        let policies = [AutoOffsetReset::Earliest, AutoOffsetReset::Latest, AutoOffsetReset::None];
+        for policy in &policies {
+            assert!(policy.reset_offset(11, 10).unwrap().is_none());
+        }
+    }
+}
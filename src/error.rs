@@ -1,6 +1,9 @@
 //! Error struct and methods
 
+use std::fmt;
 use std::io;
+use std::str::Utf8Error;
+use std::string::FromUtf8Error;
 
 #[cfg(feature = "security")]
 use openssl::ssl::{self, Error as SslError};
@@ -23,7 +26,16 @@ error_chain! {
         /// An error as reported by a remote Kafka server
         Kafka(error_code: KafkaCode) {
             description("Kafka Error")
-            display("Kafka Error ({:?})", error_code)
+            display("Kafka error: {}", error_code)
+        }
+
+        /// An error code reported by a remote Kafka server which is not
+        /// known to this library (e.g. introduced by a newer broker
+        /// version.)  Contains the raw error code as transmitted by the
+        /// server.
+        UnknownKafkaCode(error_code: i16) {
+            description("Unknown Kafka Error")
+            display("Kafka error: unknown error code {}", error_code)
         }
 
         /// An error when transmitting a request for a particular topic and partition.
@@ -42,11 +54,22 @@ error_chain! {
         }
 
         /// Failure to correctly parse the server response by this library
-        /// due to an unsupported compression format of the data
+        /// due to an unsupported compression format of the data, or to
+        /// produce messages using a compression the requested protocol
+        /// version does not support
         UnsupportedCompression {
             description("Unsupported compression format")
         }
 
+        /// A fetched message whose contents do not match its CRC
+        /// checksum; detected by this library if CRC validation is
+        /// enabled (see `KafkaClient::set_fetch_crc_validation`.)
+        /// Contains the offset of the corrupted message.
+        CrcMismatch(offset: i64) {
+            description("Message CRC mismatch")
+            display("CRC mismatch of the message at offset {}", offset)
+        }
+
         /// Failure to decode a response due to an insufficient number of bytes available
         UnexpectedEOF {
             description("Unexpected EOF")
@@ -57,14 +80,89 @@ error_chain! {
             description("Encoding/Decoding Error")
         }
 
-        /// Failure to decode a string into a valid utf8 byte sequence
-        StringDecodeError {
+        /// A response whose correlation id does not match the one of
+        /// the request it was read for.  The connection got out of sync
+        /// with the broker and has been closed; the request may be
+        /// repeated on a new connection.  Contains the expected and
+        /// the received correlation id, respectively.
+        CorrelationIdMismatch(expected: i32, got: i32) {
+            description("Correlation id mismatch")
+            display("correlation id mismatch: expected {}, got {}", expected, got)
+        }
+
+        /// A response announcing a size beyond the maximum accepted
+        /// by the client (see `KafkaClient::set_max_response_size`.)
+        /// The response has been left unread and its connection has
+        /// been closed.  Contains the announced and the maximum size,
+        /// respectively.
+        ResponseTooLarge(size: i32, max: usize) {
+            description("Response too large")
+            display("response of {} bytes exceeds the maximum size of {} bytes", size, max)
+        }
+
+        /// Failure to decode a string into a valid utf8 byte sequence.
+        /// Contains the number of leading bytes which formed valid
+        /// utf8.
+        StringDecodeError(valid_up_to: usize) {
             description("String decoding error")
+            display("string decode error at byte {}", valid_up_to)
+        }
+
+        /// Failure to decode a string field of a response into a valid
+        /// utf8 byte sequence.  Contains the name of the field - one of
+        /// "topic name", "group id", or "metadata" - and the number of
+        /// its leading bytes which formed valid utf8.  A corrupt frame
+        /// typically shows up in a "topic name" while a bad "metadata"
+        /// string rather points to the committing client.
+        FieldDecodeError(field: &'static str, valid_up_to: usize) {
+            description("Field decoding error")
+            display("invalid utf8 in the {} at byte {}", field, valid_up_to)
+        }
+
+        /// A requested partition count not exceeding the current
+        /// partition count of a topic (see
+        /// `KafkaClient::create_partitions`.)  Contains the current and
+        /// the requested partition count, respectively.
+        InvalidPartitionCount(current: i32, requested: i32) {
+            description("Invalid partition count")
+            display("invalid partition count {}; the topic has already {} partitions",
+                    requested, current)
+        }
+
+        /// A request assembled through one of the request builders
+        /// (e.g. `client::FetchRequestBuilder`) failed validation.
+        /// Contains a description of the offending input.
+        InvalidRequest(reason: &'static str) {
+            description("Invalid request")
+            display("invalid request: {}", reason)
+        }
+
+        /// A `client::ClientBuilder` was given conflicting or out of
+        /// range options.  Contains a description of the offending
+        /// options.
+        InvalidClientConfig(reason: &'static str) {
+            description("Invalid configuration")
+            display("invalid configuration: {}", reason)
+        }
+
+        /// A broker rejected the SASL authentication of a connection
+        /// (see `client::SaslConfig`.)  Contains the reason as far as
+        /// reported by the broker.
+        AuthenticationFailed(reason: String) {
+            description("Authentication failed")
+            display("authentication failed: {}", reason)
+        }
+
+        /// An operation did not complete within the deadline
+        /// configured through `KafkaClient::set_operation_deadline`.
+        DeadlineExceeded {
+            description("Operation deadline exceeded")
         }
 
         /// Unable to reach any host
-        NoHostReachable {
+        NoHostReachable(hosts: Vec<(String, io::ErrorKind)>) {
             description("No host reachable")
+            display("{}", no_host_reachable_display(hosts))
         }
 
         /// Unable to set up `Consumer` due to missing topic assignments
@@ -195,6 +293,237 @@ pub enum KafkaCode {
     IllegalSaslState = 34,
     /// The version of API is not supported.
     UnsupportedVersion = 35,
+    /// The topic to be created already exists.
+    TopicAlreadyExists = 36,
+    /// The number of partitions is invalid.
+    InvalidPartitions = 37,
+    /// The replication factor is invalid.
+    InvalidReplicationFactor = 38,
+    /// The replica assignment is invalid.
+    InvalidReplicaAssignment = 39,
+    /// The topic configuration is invalid.
+    InvalidConfig = 40,
+    /// The request was sent to a broker which is not the controller
+    /// of the cluster.
+    NotController = 41,
+    /// The request is malformed or not supported by the broker.
+    InvalidRequest = 42,
+    /// The message format version on the broker does not support
+    /// the request.
+    UnsupportedForMessageFormat = 43,
+    /// The request parameters do not satisfy the policy configured
+    /// on the broker.
+    PolicyViolation = 44,
+    /// The broker received an out of order sequence number from an
+    /// idempotent producer.
+    OutOfOrderSequenceNumber = 45,
+    /// The broker received a duplicate sequence number from an
+    /// idempotent producer, i.e. the messages have already been
+    /// written.
+    DuplicateSequenceNumber = 46,
+    /// The producer's epoch is older than the one known to the
+    /// broker; the producer has been fenced by a newer instance.
+    InvalidProducerEpoch = 47,
+}
+
+impl KafkaCode {
+    /// Retrieves the numeric code of this error as transmitted on the
+    /// wire by Kafka, e.g. `1` for `KafkaCode::OffsetOutOfRange`.
+    pub fn to_i16(&self) -> i16 {
+        *self as i16
+    }
+
+    /// Determines whether this error is of a transient nature, i.e.
+    /// whether retrying the failed request (possibly after refreshing
+    /// the client's metadata) has a chance to succeed.
+    ///
+    /// The following codes are considered retriable:
+    /// `LeaderNotAvailable`, `NotLeaderForPartition`,
+    /// `RequestTimedOut`, `NetworkException`, `GroupLoadInProgress`,
+    /// `GroupCoordinatorNotAvailable`, `NotCoordinatorForGroup`,
+    /// `NotEnoughReplicas`, `NotEnoughReplicasAfterAppend`,
+    /// `RebalanceInProgress`, and `NotController`.  All other codes - notably
+    /// `UnknownTopicOrPartition`, `InvalidTopic` and the authorization
+    /// failures - are considered permanent.
+    pub fn retriable(&self) -> bool {
+        match *self {
+            KafkaCode::LeaderNotAvailable |
+            KafkaCode::NotLeaderForPartition |
+            KafkaCode::RequestTimedOut |
+            KafkaCode::NetworkException |
+            KafkaCode::GroupLoadInProgress |
+            KafkaCode::GroupCoordinatorNotAvailable |
+            KafkaCode::NotCoordinatorForGroup |
+            KafkaCode::NotEnoughReplicas |
+            KafkaCode::NotEnoughReplicasAfterAppend |
+            KafkaCode::RebalanceInProgress |
+            KafkaCode::NotController => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for KafkaCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            KafkaCode::Unknown => "an unexpected server error occurred",
+            KafkaCode::OffsetOutOfRange => {
+                "the requested offset is outside the range of offsets maintained by the \
+                 server for the given topic/partition"
+            }
+            KafkaCode::CorruptMessage => "the message contents does not match its CRC",
+            KafkaCode::UnknownTopicOrPartition => {
+                "the requested topic or partition does not exist on this broker"
+            }
+            KafkaCode::InvalidMessageSize => "the message has a negative size",
+            KafkaCode::LeaderNotAvailable => {
+                "there is currently no leader for this partition and hence it is \
+                 unavailable for writes"
+            }
+            KafkaCode::NotLeaderForPartition => {
+                "the broker is not the leader for this partition; the client's metadata \
+                 is out of date"
+            }
+            KafkaCode::RequestTimedOut => "the request exceeded the user-specified time limit",
+            KafkaCode::BrokerNotAvailable => "the broker is not available",
+            KafkaCode::ReplicaNotAvailable => "the replica is not available on the broker",
+            KafkaCode::MessageSizeTooLarge => {
+                "the message is larger than the maximum message size configured on the server"
+            }
+            KafkaCode::StaleControllerEpoch => "the controller epoch is stale",
+            KafkaCode::OffsetMetadataTooLarge => {
+                "the offset metadata is larger than the configured maximum"
+            }
+            KafkaCode::NetworkException => {
+                "the server disconnected before a response was received"
+            }
+            KafkaCode::GroupLoadInProgress => "the coordinator is still loading group metadata",
+            KafkaCode::GroupCoordinatorNotAvailable => "the group coordinator is not available",
+            KafkaCode::NotCoordinatorForGroup => {
+                "the broker is not the coordinator for the requested group"
+            }
+            KafkaCode::InvalidTopic => "the topic is invalid or may not be written to",
+            KafkaCode::RecordListTooLarge => {
+                "the message batch exceeds the maximum configured segment size"
+            }
+            KafkaCode::NotEnoughReplicas => {
+                "the number of in-sync replicas is lower than the configured minimum"
+            }
+            KafkaCode::NotEnoughReplicasAfterAppend => {
+                "the message was written to the log, but with fewer in-sync replicas than \
+                 required"
+            }
+            KafkaCode::InvalidRequiredAcks => {
+                "the requested required acks is invalid (anything other than -1, 1, or 0)"
+            }
+            KafkaCode::IllegalGeneration => {
+                "the generation id provided in the request is not the current generation"
+            }
+            KafkaCode::InconsistentGroupProtocol => {
+                "the provided protocol type or set of protocols is not compatible with the \
+                 current group"
+            }
+            KafkaCode::InvalidGroupId => "the group id is empty or null",
+            KafkaCode::UnknownMemberId => "the member id is not in the current generation",
+            KafkaCode::InvalidSessionTimeout => {
+                "the requested session timeout is outside of the allowed range on the broker"
+            }
+            KafkaCode::RebalanceInProgress => "the coordinator has begun rebalancing the group",
+            KafkaCode::InvalidCommitOffsetSize => {
+                "the offset commit was rejected because of oversize metadata"
+            }
+            KafkaCode::TopicAuthorizationFailed => {
+                "the client is not authorized to access the requested topic"
+            }
+            KafkaCode::GroupAuthorizationFailed => {
+                "the client is not authorized to access the requested group"
+            }
+            KafkaCode::ClusterAuthorizationFailed => {
+                "the client is not authorized to use an inter-broker or administrative API"
+            }
+            KafkaCode::InvalidTimestamp => {
+                "the timestamp of the message is out of acceptable range"
+            }
+            KafkaCode::UnsupportedSaslMechanism => {
+                "the broker does not support the requested SASL mechanism"
+            }
+            KafkaCode::IllegalSaslState => "the request is not valid given the current SASL state",
+            KafkaCode::UnsupportedVersion => "the version of API is not supported",
+            KafkaCode::TopicAlreadyExists => "the topic already exists",
+            KafkaCode::InvalidPartitions => "the number of partitions is invalid",
+            KafkaCode::InvalidReplicationFactor => "the replication factor is invalid",
+            KafkaCode::InvalidReplicaAssignment => "the replica assignment is invalid",
+            KafkaCode::InvalidConfig => "the configuration is invalid",
+            KafkaCode::NotController => "the broker is not the controller of the cluster",
+            KafkaCode::InvalidRequest => {
+                "the request is malformed or not supported by the broker"
+            }
+            KafkaCode::UnsupportedForMessageFormat => {
+                "the message format version on the broker does not support the request"
+            }
+            KafkaCode::PolicyViolation => "the request violates a policy configured on the broker",
+            KafkaCode::OutOfOrderSequenceNumber => {
+                "the broker received an out of order sequence number"
+            }
+            KafkaCode::DuplicateSequenceNumber => "the broker received a duplicate sequence number",
+            KafkaCode::InvalidProducerEpoch => "the producer has been fenced by a newer instance",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error {
+    /// Retrieves the numeric Kafka error code carried by this error,
+    /// if any.  Only errors reported by a remote Kafka server carry
+    /// such a code - and `ErrorKind::CrcMismatch` which is reported
+    /// with the code of `KafkaCode::CorruptMessage`.
+    pub fn kafka_code(&self) -> Option<i16> {
+        match *self.kind() {
+            ErrorKind::Kafka(code) |
+            ErrorKind::TopicPartitionError(_, _, code) => Some(code.to_i16()),
+            ErrorKind::UnknownKafkaCode(code) => Some(code),
+            ErrorKind::CrcMismatch(_) => Some(KafkaCode::CorruptMessage.to_i16()),
+            _ => None,
+        }
+    }
+
+    /// Retrieves the underlying `io::Error` if this is an `Io` error.
+    ///
+    /// Note: the `std::error::Error` implementation of this type is
+    /// generated by `error_chain` (0.10) and cannot be customized: its
+    /// `cause()` forwards to the cause of the underlying foreign error
+    /// rather than yielding that error itself, and it does not
+    /// provide `source()`.  Use this method to get hold of the io
+    /// error.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match *self.kind() {
+            ErrorKind::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Determines whether the operation which failed with this error
+    /// may succeed when retried.  This is the case for Kafka errors
+    /// classified as retriable by `KafkaCode::retriable`, for `Io`
+    /// errors of kind `TimedOut`, `Interrupted`, or `WouldBlock`, and
+    /// for `CorrelationIdMismatch` (whose connection has been closed
+    /// already.)
+    pub fn is_retriable(&self) -> bool {
+        match *self.kind() {
+            ErrorKind::Kafka(code) |
+            ErrorKind::TopicPartitionError(_, _, code) => code.retriable(),
+            ErrorKind::Io(ref e) => {
+                match e.kind() {
+                    io::ErrorKind::TimedOut |
+                    io::ErrorKind::Interrupted |
+                    io::ErrorKind::WouldBlock => true,
+                    _ => false,
+                }
+            }
+            ErrorKind::CorrelationIdMismatch(..) => true,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(feature = "security")]
@@ -208,11 +537,24 @@ impl<S> From<ssl::HandshakeError<S>> for Error {
     }
 }
 
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Error {
+        ErrorKind::StringDecodeError(err.valid_up_to()).into()
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(err: FromUtf8Error) -> Error {
+        From::from(err.utf8_error())
+    }
+}
+
 impl Clone for Error {
     fn clone(&self) -> Error {
         match self {
             &Error(ErrorKind::Io(ref err), _) => ErrorKind::Io(clone_ioe(err)).into(),
             &Error(ErrorKind::Kafka(x), _) => ErrorKind::Kafka(x).into(),
+            &Error(ErrorKind::UnknownKafkaCode(x), _) => ErrorKind::UnknownKafkaCode(x).into(),
             &Error(ErrorKind::TopicPartitionError(ref topic, partition, error_code), _) => {
                 ErrorKind::TopicPartitionError(topic.clone(), partition, error_code).into()
             }
@@ -226,10 +568,33 @@ impl Clone for Error {
             }
             #[cfg(feature = "snappy")]
             &Error(ErrorKind::InvalidSnappy(ref err), _) => from_snap_error_ref(err).into(),
+            &Error(ErrorKind::CrcMismatch(offset), _) => ErrorKind::CrcMismatch(offset).into(),
             &Error(ErrorKind::UnexpectedEOF, _) => ErrorKind::UnexpectedEOF.into(),
             &Error(ErrorKind::CodecError, _) => ErrorKind::CodecError.into(),
-            &Error(ErrorKind::StringDecodeError, _) => ErrorKind::StringDecodeError.into(),
-            &Error(ErrorKind::NoHostReachable, _) => ErrorKind::NoHostReachable.into(),
+            &Error(ErrorKind::CorrelationIdMismatch(e, g), _) => {
+                ErrorKind::CorrelationIdMismatch(e, g).into()
+            }
+            &Error(ErrorKind::ResponseTooLarge(s, m), _) => {
+                ErrorKind::ResponseTooLarge(s, m).into()
+            }
+            &Error(ErrorKind::StringDecodeError(n), _) => ErrorKind::StringDecodeError(n).into(),
+            &Error(ErrorKind::FieldDecodeError(f, n), _) => {
+                ErrorKind::FieldDecodeError(f, n).into()
+            }
+            &Error(ErrorKind::InvalidPartitionCount(c, r), _) => {
+                ErrorKind::InvalidPartitionCount(c, r).into()
+            }
+            &Error(ErrorKind::InvalidRequest(r), _) => ErrorKind::InvalidRequest(r).into(),
+            &Error(ErrorKind::InvalidClientConfig(r), _) => {
+                ErrorKind::InvalidClientConfig(r).into()
+            }
+            &Error(ErrorKind::AuthenticationFailed(ref r), _) => {
+                ErrorKind::AuthenticationFailed(r.clone()).into()
+            }
+            &Error(ErrorKind::DeadlineExceeded, _) => ErrorKind::DeadlineExceeded.into(),
+            &Error(ErrorKind::NoHostReachable(ref hosts), _) => {
+                ErrorKind::NoHostReachable(hosts.clone()).into()
+            }
             &Error(ErrorKind::NoTopicsAssigned, _) => ErrorKind::NoTopicsAssigned.into(),
             &Error(ErrorKind::InvalidDuration, _) => ErrorKind::InvalidDuration.into(),
             &Error(ErrorKind::Msg(ref msg), _) => ErrorKind::Msg(msg.clone()).into(),
@@ -237,6 +602,72 @@ impl Clone for Error {
     }
 }
 
+// ~ `io::Error` and the errors of the underlying compression and ssl
+// libraries are not comparable themselves; for these we resort to
+// comparing their kind and/or their rendered description
+impl PartialEq for ErrorKind {
+    fn eq(&self, other: &ErrorKind) -> bool {
+        match (self, other) {
+            (&ErrorKind::Io(ref a), &ErrorKind::Io(ref b)) => {
+                a.kind() == b.kind() && a.to_string() == b.to_string()
+            }
+            #[cfg(feature = "security")]
+            (&ErrorKind::Ssl(ref a), &ErrorKind::Ssl(ref b)) => a.to_string() == b.to_string(),
+            #[cfg(feature = "security")]
+            (&ErrorKind::SslHandshake(ref a), &ErrorKind::SslHandshake(ref b)) => {
+                a.to_string() == b.to_string()
+            }
+            #[cfg(feature = "snappy")]
+            (&ErrorKind::InvalidSnappy(ref a), &ErrorKind::InvalidSnappy(ref b)) => {
+                a.to_string() == b.to_string()
+            }
+            (&ErrorKind::Kafka(a), &ErrorKind::Kafka(b)) => a == b,
+            (&ErrorKind::UnknownKafkaCode(a), &ErrorKind::UnknownKafkaCode(b)) => a == b,
+            (&ErrorKind::StringDecodeError(a), &ErrorKind::StringDecodeError(b)) => a == b,
+            (&ErrorKind::FieldDecodeError(f1, n1), &ErrorKind::FieldDecodeError(f2, n2)) => {
+                f1 == f2 && n1 == n2
+            }
+            (&ErrorKind::CrcMismatch(a), &ErrorKind::CrcMismatch(b)) => a == b,
+            (&ErrorKind::CorrelationIdMismatch(e1, g1),
+             &ErrorKind::CorrelationIdMismatch(e2, g2)) => e1 == e2 && g1 == g2,
+            (&ErrorKind::ResponseTooLarge(s1, m1), &ErrorKind::ResponseTooLarge(s2, m2)) => {
+                s1 == s2 && m1 == m2
+            }
+            (&ErrorKind::InvalidPartitionCount(c1, r1),
+             &ErrorKind::InvalidPartitionCount(c2, r2)) => c1 == c2 && r1 == r2,
+            (&ErrorKind::InvalidRequest(a), &ErrorKind::InvalidRequest(b)) => a == b,
+            (&ErrorKind::InvalidClientConfig(a), &ErrorKind::InvalidClientConfig(b)) => a == b,
+            (&ErrorKind::AuthenticationFailed(ref a), &ErrorKind::AuthenticationFailed(ref b)) => {
+                a == b
+            }
+            (&ErrorKind::NoHostReachable(ref a), &ErrorKind::NoHostReachable(ref b)) => a == b,
+            (&ErrorKind::TopicPartitionError(ref t1, p1, c1),
+             &ErrorKind::TopicPartitionError(ref t2, p2, c2)) => t1 == t2 && p1 == p2 && c1 == c2,
+            (&ErrorKind::UnsupportedProtocol, &ErrorKind::UnsupportedProtocol) |
+            (&ErrorKind::UnsupportedCompression, &ErrorKind::UnsupportedCompression) |
+            (&ErrorKind::UnexpectedEOF, &ErrorKind::UnexpectedEOF) |
+            (&ErrorKind::CodecError, &ErrorKind::CodecError) |
+            (&ErrorKind::DeadlineExceeded, &ErrorKind::DeadlineExceeded) |
+            (&ErrorKind::NoTopicsAssigned, &ErrorKind::NoTopicsAssigned) |
+            (&ErrorKind::InvalidDuration, &ErrorKind::InvalidDuration) => true,
+            (&ErrorKind::Msg(ref a), &ErrorKind::Msg(ref b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ErrorKind {}
+
+/// Two errors are considered equal if they are of the same kind.  The
+/// chained causes and backtraces - if any - are not compared.
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl Eq for Error {}
+
 #[cfg(feature = "security")]
 fn from_sslerror_ref(err: &ssl::Error) -> ErrorKind {
     match err {
@@ -332,6 +763,139 @@ fn from_snap_error_ref(err: &::snap::Error) -> ErrorKind {
 fn clone_ioe(e: &io::Error) -> io::Error {
     match e.raw_os_error() {
         Some(code) => io::Error::from_raw_os_error(code),
-        None => io::Error::new(e.kind(), format!("Io error: {}", e)),
+        None => io::Error::new(e.kind(), e.to_string()),
+    }
+}
+
+// ~ renders the hosts carried by `ErrorKind::NoHostReachable`
+fn no_host_reachable_display(hosts: &[(String, io::ErrorKind)]) -> String {
+    if hosts.is_empty() {
+        return "no host reachable".to_owned();
+    }
+    let hosts: Vec<_> = hosts.iter().map(|&(ref h, k)| format!("{} ({:?})", h, k)).collect();
+    format!("could not connect to any of: {}", hosts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{Error, ErrorKind, KafkaCode};
+
+    #[test]
+    fn test_error_eq() {
+        let e: Error = ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition).into();
+        assert_eq!(e, ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition).into());
+        assert!(e != ErrorKind::Kafka(KafkaCode::OffsetOutOfRange).into());
+        assert!(e != ErrorKind::NoHostReachable(vec![]).into());
+
+        let e: Error = ErrorKind::TopicPartitionError("foo".to_owned(), 1, KafkaCode::Unknown)
+            .into();
+        assert_eq!(e, e.clone());
+        assert!(
+            e != ErrorKind::TopicPartitionError("foo".to_owned(), 2, KafkaCode::Unknown).into()
+        );
+    }
+
+    #[test]
+    fn test_error_eq_io() {
+        let e: Error = io::Error::new(io::ErrorKind::TimedOut, "timed out").into();
+        assert_eq!(e, io::Error::new(io::ErrorKind::TimedOut, "timed out").into());
+        assert!(e != io::Error::new(io::ErrorKind::TimedOut, "too late").into());
+        assert!(e != io::Error::new(io::ErrorKind::Interrupted, "timed out").into());
+    }
+
+    #[test]
+    fn test_error_clone_io() {
+        let e: Error = io::Error::new(io::ErrorKind::PermissionDenied, "no access to foo").into();
+        let c = e.clone();
+        match *c.kind() {
+            ErrorKind::Io(ref ioe) => {
+                assert_eq!(io::ErrorKind::PermissionDenied, ioe.kind());
+                assert_eq!("no access to foo", ioe.to_string());
+            }
+            ref other => panic!("Expected an Io error, but got: {:?}", other),
+        }
+        assert_eq!(e.to_string(), c.to_string());
+    }
+
+    #[test]
+    fn test_io_error() {
+        let e: Error = io::Error::new(io::ErrorKind::BrokenPipe, "pipe").into();
+        assert_eq!(Some(io::ErrorKind::BrokenPipe), e.io_error().map(|e| e.kind()));
+        let e: Error = ErrorKind::UnexpectedEOF.into();
+        assert!(e.io_error().is_none());
+    }
+
+    #[test]
+    fn test_string_decode_error() {
+        let e: Error = String::from_utf8(vec![b'a', b'b', 0xff, b'c']).unwrap_err().into();
+        assert_eq!(e, ErrorKind::StringDecodeError(2).into());
+        assert_eq!("string decode error at byte 2", e.to_string());
+    }
+
+    #[test]
+    fn test_no_host_reachable_display() {
+        let e: Error = ErrorKind::NoHostReachable(vec![]).into();
+        assert_eq!("no host reachable", e.to_string());
+        let e: Error = ErrorKind::NoHostReachable(vec![
+            ("broker1:9092".to_owned(), io::ErrorKind::ConnectionRefused),
+            ("broker2:9092".to_owned(), io::ErrorKind::TimedOut),
+        ]).into();
+        assert_eq!(
+            "could not connect to any of: broker1:9092 (ConnectionRefused), \
+             broker2:9092 (TimedOut)",
+            e.to_string()
+        );
+        assert_eq!(e, e.clone());
+    }
+
+    #[test]
+    fn test_kafka_code() {
+        let e: Error = ErrorKind::Kafka(KafkaCode::OffsetOutOfRange).into();
+        assert_eq!(Some(1), e.kafka_code());
+        let e: Error = ErrorKind::TopicPartitionError("foo".to_owned(), 0, KafkaCode::Unknown)
+            .into();
+        assert_eq!(Some(-1), e.kafka_code());
+        let e: Error = ErrorKind::NoHostReachable(vec![]).into();
+        assert_eq!(None, e.kafka_code());
+    }
+
+    #[test]
+    fn test_is_retriable() {
+        assert!(KafkaCode::NotLeaderForPartition.retriable());
+        assert!(!KafkaCode::UnknownTopicOrPartition.retriable());
+        assert!(!KafkaCode::TopicAuthorizationFailed.retriable());
+
+        let e: Error = ErrorKind::Kafka(KafkaCode::LeaderNotAvailable).into();
+        assert!(e.is_retriable());
+        let e: Error = ErrorKind::TopicPartitionError("foo".to_owned(), 0, KafkaCode::InvalidTopic)
+            .into();
+        assert!(!e.is_retriable());
+        let e: Error = io::Error::new(io::ErrorKind::TimedOut, "timed out").into();
+        assert!(e.is_retriable());
+        let e: Error = io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into();
+        assert!(!e.is_retriable());
+        let e: Error = ErrorKind::NoHostReachable(vec![]).into();
+        assert!(!e.is_retriable());
+        let e: Error = ErrorKind::CorrelationIdMismatch(2, 1).into();
+        assert!(e.is_retriable());
+    }
+
+    #[test]
+    fn test_unknown_kafka_code() {
+        let e: Error = ErrorKind::UnknownKafkaCode(100).into();
+        assert_eq!(Some(100), e.kafka_code());
+        assert_eq!("Kafka error: unknown error code 100", e.to_string());
+        assert_eq!(e, e.clone());
+    }
+
+    #[test]
+    fn test_kafka_error_display() {
+        let e: Error = ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition).into();
+        assert_eq!(
+            "Kafka error: the requested topic or partition does not exist on this broker",
+            e.to_string()
+        );
     }
 }
@@ -6,8 +6,11 @@
 //! - `kafka::client::KafkaClient` - a lower-level, general purpose client leaving
 //!   you with more power but also more resposibility
 //!
+//! Transient failures of any of these can be retried through
+//! `kafka::retry::with_retries`.
+//!
 //! See module level documentation corresponding to each client individually.
-#![recursion_limit="128"]
+#![recursion_limit="256"]
 #![cfg_attr(feature = "nightly", feature(test))]
 
 extern crate byteorder;
@@ -15,6 +18,7 @@ extern crate crc;
 extern crate ref_slice;
 extern crate fnv;
 extern crate twox_hash;
+extern crate libc;
 
 #[macro_use]
 extern crate error_chain;
@@ -31,6 +35,12 @@ extern crate flate2;
 #[cfg(feature = "snappy")]
 extern crate snap;
 
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+
+#[cfg(feature = "zstd")]
+extern crate zstd;
+
 #[cfg(all(test, feature = "nightly"))]
 extern crate test;
 
@@ -39,6 +49,8 @@ pub mod client;
 mod client_internals;
 pub mod consumer;
 pub mod producer;
+pub mod partition;
+pub mod retry;
 mod utils;
 mod codecs;
 mod protocol;
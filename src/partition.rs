@@ -0,0 +1,117 @@
+//! Key based partitioning compatible with Kafka's Java client.
+//!
+//! Producers written in Java assign keyed messages to partitions
+//! based on the murmur2 hash of the key.  The functions in this
+//! module reproduce that assignment exactly, allowing producers
+//! based on this library to interoperate with Java producers and
+//! consumers relying on the placement of keys.  See
+//! `kafka::producer::HashPartitioner`.
+
+/// Computes the murmur2 hash of `data` exactly as Kafka's
+/// `org.apache.kafka.common.utils.Utils.murmur2` does.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(-790332482, kafka::partition::murmur2(b"foobar"));
+/// ```
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let len = data.len();
+    let mut h = SEED ^ len as u32;
+    for i in 0..len / 4 {
+        let i4 = i * 4;
+        let mut k = (data[i4] as u32) | (data[i4 + 1] as u32) << 8 |
+            (data[i4 + 2] as u32) << 16 | (data[i4 + 3] as u32) << 24;
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let tail = &data[len & !3..];
+    if tail.len() == 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// Determines the partition for a message with the given `key` out of
+/// `num_partitions` partitions of a topic just like Kafka's Java
+/// producer does: `(murmur2(key) & 0x7fffffff) % num_partitions`.
+///
+/// # Panics
+///
+/// Panics if `num_partitions` is not positive.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(1, kafka::partition::key_partition(b"foobar", 5));
+/// ```
+pub fn key_partition(key: &[u8], num_partitions: i32) -> i32 {
+    assert!(num_partitions > 0, "num_partitions must be positive");
+    (murmur2(key) & 0x7fff_ffff) % num_partitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{murmur2, key_partition};
+
+    #[test]
+    fn test_murmur2() {
+        // ~ test vectors of kafka's java client (see `UtilsTest`)
+        assert_eq!(-973932308, murmur2(b"21"));
+        assert_eq!(-790332482, murmur2(b"foobar"));
+        assert_eq!(-985981536, murmur2(b"a-little-bit-long-string"));
+        assert_eq!(-1486304829, murmur2(b"a-little-bit-longer-string"));
+        assert_eq!(-58897971, murmur2(b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8"));
+        assert_eq!(479470107, murmur2(b"abc"));
+    }
+
+    #[test]
+    fn test_murmur2_empty() {
+        assert_eq!(275646681, murmur2(b""));
+    }
+
+    #[test]
+    fn test_key_partition() {
+        // ~ (key, num_partitions, expected partition)
+        let cases: &[(&[u8], i32, i32)] = &[
+            (b"21", 1, 0),
+            (b"21", 3, 0),
+            (b"21", 10, 0),
+            (b"foobar", 5, 1),
+            (b"foobar", 12, 6),
+            (b"a-little-bit-long-string", 7, 1),
+            (b"a-little-bit-longer-string", 100, 19),
+            (b"abc", 6, 3),
+        ];
+        for &(key, num_partitions, expected) in cases {
+            assert_eq!(expected,
+                       key_partition(key, num_partitions),
+                       "key: {:?}, num_partitions: {}",
+                       key,
+                       num_partitions);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_key_partition_no_partitions() {
+        key_partition(b"foo", 0);
+    }
+}
@@ -48,13 +48,22 @@
 //!
 //! Beside the target topic, key, and the value of a `Record`, client
 //! code is allowed to specify the topic partition the message is
-//! supposed to be delivered to.  If the partition of a `Record` is
-//! not specified - more precisely speaking if it's negative -
-//! `Producer` will rely on its underlying `Partitioner` to find a
-//! suitable one.  A `Partitioner` implementation can be supplied by
+//! supposed to be delivered to.  An explicitly specified partition
+//! takes precedence over everything else; `Producer` delivers the
+//! record to exactly that partition without consulting its
+//! `Partitioner` and rejects partitions unknown to it with
+//! `KafkaCode::UnknownTopicOrPartition`.  If the partition of a
+//! `Record` is not specified - more precisely speaking if it's
+//! negative - `Producer` will rely on its underlying `Partitioner` to
+//! find a suitable one; `DefaultPartitioner` hashes the record's key
+//! if there is one and falls back to a round robin assignment
+//! otherwise.  A `Partitioner` implementation can be supplied by
 //! client code at the `Producer`'s construction time and defaults to
 //! `DefaultPartitioner`.  See that for more information for its
-//! strategy to find a partition.
+//! strategy to find a partition.  `HashPartitioner` and
+//! `RoundRobinPartitioner` are provided as alternatives; the former
+//! assigns keyed messages to the same partitions as Kafka's Java
+//! producer does.
 
 // XXX 1) rethink return values for the send_all() method
 // XXX 2) Handle recoverable errors behind the scenes through retry attempts
@@ -62,9 +71,11 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hasher, BuildHasher, BuildHasherDefault};
-use std::time::Duration;
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
 use client::{self, KafkaClient};
-use error::{ErrorKind, Result};
+use error::{ErrorKind, KafkaCode, Result};
 use ref_slice::ref_slice;
 use twox_hash::XxHash32;
 
@@ -74,6 +85,7 @@ use client::SecurityConfig;
 #[cfg(not(feature = "security"))]
 type SecurityConfig = ();
 use client_internals::KafkaClientInternals;
+use partition;
 use protocol;
 
 // public re-exports
@@ -85,6 +97,15 @@ pub const DEFAULT_ACK_TIMEOUT_MILLIS: u64 = 30 * 1000;
 /// The default value for `Builder::with_required_acks`.
 pub const DEFAULT_REQUIRED_ACKS: RequiredAcks = RequiredAcks::One;
 
+/// The default value for `BufferedProducer::set_batch_size`.
+pub const DEFAULT_BATCH_SIZE: usize = 16 * 1024;
+
+/// The default value for `BufferedProducer::set_linger`.
+pub const DEFAULT_LINGER_MILLIS: u64 = 5;
+
+/// The default value for `Producer::set_retries(..)`
+pub const DEFAULT_RETRIES: u32 = 0;
+
 // --------------------------------------------------------------------
 
 /// A trait used by `Producer` to obtain the bytes `Record::key` and
@@ -146,8 +167,20 @@ pub struct Record<'a, K, V> {
     /// The partition id of the topic to deliver this message to.
     /// This partition may be `< 0` in which case it is considered
     /// "unspecified".  A `Producer` will then typically try to derive
-    /// a partition on its own.
+    /// a partition on its own.  A non-negative partition is used as
+    /// is, bypassing the producer's `Partitioner` entirely; it must
+    /// exist in the cluster's metadata, otherwise sending the record
+    /// fails with `KafkaCode::UnknownTopicOrPartition`.
     pub partition: i32,
+
+    /// The (create) timestamp of this record in milliseconds since
+    /// the unix epoch.  If `None`, the record is assigned the time of
+    /// its sending.  See `ProduceMessage::timestamp`.
+    pub timestamp: Option<i64>,
+
+    /// The headers - key/value pairs - of this record.  See
+    /// `ProduceMessage::headers`.
+    pub headers: Vec<(String, Vec<u8>)>,
 }
 
 impl<'a, K, V> Record<'a, K, V> {
@@ -161,6 +194,8 @@ impl<'a, K, V> Record<'a, K, V> {
             value: value,
             topic: topic,
             partition: -1,
+            timestamp: None,
+            headers: Vec::new(),
         }
     }
 
@@ -170,6 +205,25 @@ impl<'a, K, V> Record<'a, K, V> {
         self.partition = partition;
         self
     }
+
+    /// Convenience method to set the (create) timestamp in
+    /// milliseconds since the unix epoch.
+    #[inline]
+    pub fn with_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Convenience method to append a header.
+    #[inline]
+    pub fn with_header<HK, HV>(mut self, key: HK, value: HV) -> Self
+    where
+        HK: Into<String>,
+        HV: Into<Vec<u8>>,
+    {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
 }
 
 impl<'a, V> Record<'a, (), V> {
@@ -183,6 +237,8 @@ impl<'a, V> Record<'a, (), V> {
             value: value,
             topic: topic,
             partition: -1,
+            timestamp: None,
+            headers: Vec::new(),
         }
     }
 }
@@ -224,7 +280,26 @@ struct Config {
     ack_timeout: i32,
     /// The number of acks to request. See
     /// `KafkaClient::produce_messages`.
-    required_acks: i16,
+    required_acks: RequiredAcks,
+    /// The number of times to re-send records which failed with a
+    /// retriable error.  See `Producer::set_retries`.
+    retries: u32,
+    /// Whether the user acknowledged that retries may duplicate
+    /// records.  See `Producer::allow_duplicates_on_retry`.
+    allow_duplicates_on_retry: bool,
+}
+
+impl Config {
+    // ~ the number of retries to actually perform; none unless
+    // possible duplicates have been acknowledged or are ruled out by
+    // an idempotent producer
+    fn effective_retries(&self, idempotent: bool) -> u32 {
+        if self.allow_duplicates_on_retry || idempotent {
+            self.retries
+        } else {
+            0
+        }
+    }
 }
 
 impl Producer {
@@ -252,6 +327,131 @@ impl Producer {
 
 
 impl<P: Partitioner> Producer<P> {
+    /// Sets how many acknowledgements the kafka brokers should
+    /// receive before responding to subsequently sent messages.
+    /// See `Builder::with_required_acks` and `RequiredAcks`.
+    ///
+    /// With `RequiredAcks::None` brokers send no response at all;
+    /// messages are then considered blindly as delivered, `send`
+    /// succeeds as soon as the data is written to the network and
+    /// `send_all` returns no confirms.  A broker rejecting the
+    /// requested value reports `KafkaCode::InvalidRequiredAcks`.
+    pub fn set_required_acks(&mut self, acks: RequiredAcks) {
+        self.config.required_acks = acks;
+    }
+
+    /// Retrieves the currently configured acknowledgement
+    /// requirement.  See `Producer::set_required_acks`.
+    pub fn required_acks(&self) -> RequiredAcks {
+        self.config.required_acks
+    }
+
+    /// Sets the maximum time the kafka brokers can await the receipt
+    /// of required acknowledgements for subsequently sent messages.
+    /// The value is transmitted - in milliseconds - as part of each
+    /// produce request and is unrelated to the socket timeout of the
+    /// underlying client (see `KafkaClient::set_socket_timeout`.)  See
+    /// also `Builder::with_ack_timeout`.
+    ///
+    /// The timeout is of relevance mainly with `RequiredAcks::All`
+    /// where brokers await the replication of the sent messages to
+    /// all in-sync replicas.  If that does not happen in time, the
+    /// affected partitions are reported with
+    /// `KafkaCode::RequestTimedOut`.  Note, the messages may still
+    /// eventually get committed.  With `RequiredAcks::None` the value
+    /// has no effect.
+    ///
+    /// Fails with `ErrorKind::InvalidDuration` if the given duration
+    /// doesn't fit into the protocol's 32-bit milliseconds field.
+    pub fn set_ack_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.config.ack_timeout = try!(protocol::to_millis_i32(timeout));
+        Ok(())
+    }
+
+    /// Retrieves the currently configured ack timeout.  See
+    /// `Producer::set_ack_timeout`.
+    pub fn ack_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.ack_timeout as u64)
+    }
+
+    /// Sets the size in bytes the uncompressed messages sent to a
+    /// single partition must exceed to get compressed; smaller batches
+    /// are sent uncompressed.  Defaults to
+    /// `client::DEFAULT_COMPRESSION_THRESHOLD`, i.e. all batches get
+    /// compressed.  Has no effect without a configured compression.
+    /// See `KafkaClient::set_compression_threshold`.
+    pub fn set_compression_threshold(&mut self, bytes: usize) {
+        self.client.set_compression_threshold(bytes);
+    }
+
+    /// Retrieves the currently configured compression threshold.  See
+    /// `Producer::set_compression_threshold`.
+    pub fn compression_threshold(&self) -> usize {
+        self.client.compression_threshold()
+    }
+
+    /// Sets whether to produce messages as an idempotent producer;
+    /// the brokers then discard duplicates of messages the producer
+    /// re-sends after a failure.  Enabling idempotence also requires
+    /// `RequiredAcks::All` for subsequently sent messages.  Requires
+    /// the message format v2 (see `KafkaClient::set_message_version`.)
+    /// See `KafkaClient::set_idempotent`.
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        if idempotent {
+            self.config.required_acks = RequiredAcks::All;
+        }
+        self.client.set_idempotent(idempotent);
+    }
+
+    /// Retrieves whether this producer is idempotent.  See
+    /// `Producer::set_idempotent`.
+    pub fn idempotent(&self) -> bool {
+        self.client.idempotent()
+    }
+
+    /// Sets how many times `send_all` (and hence `send`) re-sends
+    /// records which failed with a retriable error (see
+    /// `Error::is_retriable` and `KafkaCode::retriable`), waiting
+    /// `KafkaClient::retry_backoff_time` before each attempt.  Only
+    /// the records of partitions reporting a retriable error are
+    /// re-sent; a failure of the request as a whole - e.g. a timed
+    /// out connection - re-sends all records.  Defaults to
+    /// `DEFAULT_RETRIES`.
+    ///
+    /// A record whose request timed out may nevertheless have been
+    /// appended by the broker; re-sending it then duplicates it in
+    /// the partition.  Therefore, retries are performed only after
+    /// acknowledging this risk through
+    /// `Producer::allow_duplicates_on_retry`; until then, retriable
+    /// errors are reported right away.  Idempotent producers (see
+    /// `Producer::set_idempotent`) are exempt: they re-send records
+    /// under their original producer id, epoch and sequence numbers
+    /// such that the brokers discard the records they appended
+    /// already; duplicates are impossible in that mode.
+    pub fn set_retries(&mut self, retries: u32) {
+        self.config.retries = retries;
+    }
+
+    /// Retrieves the currently configured number of retries.  See
+    /// `Producer::set_retries`.
+    pub fn retries(&self) -> u32 {
+        self.config.retries
+    }
+
+    /// Acknowledges that retrying failed sends may duplicate records
+    /// and thereby enables the retries configured through
+    /// `Producer::set_retries`.  Disabled by default.  Not required
+    /// for idempotent producers which retry without duplicates.
+    pub fn allow_duplicates_on_retry(&mut self, allow: bool) {
+        self.config.allow_duplicates_on_retry = allow;
+    }
+
+    /// Retrieves whether possible duplicates on retries have been
+    /// acknowledged.  See `Producer::allow_duplicates_on_retry`.
+    pub fn duplicates_on_retry_allowed(&self) -> bool {
+        self.config.allow_duplicates_on_retry
+    }
+
     /// Synchronously send the specified message to Kafka.
     pub fn send<'a, K, V>(&mut self, rec: &Record<'a, K, V>) -> Result<()>
     where
@@ -260,7 +460,7 @@ impl<P: Partitioner> Producer<P> {
     {
         let mut rs = try!(self.send_all(ref_slice(rec)));
 
-        if self.config.required_acks == 0 {
+        if self.config.required_acks == RequiredAcks::None {
             // ~ with no required_acks we get no response and
             // consider the send-data request blindly as successful
             Ok(())
@@ -282,6 +482,20 @@ impl<P: Partitioner> Producer<P> {
     /// Synchronously send all of the specified messages to Kafka. To validate
     /// that all of the specified records have been successfully delivered,
     /// inspection of the offsets on the returned confirms is necessary.
+    ///
+    /// The records are grouped by the leaders of their destination
+    /// partitions such that only one produce request is issued per
+    /// involved broker.  Within a partition the records are appended
+    /// in the order they are given; the partition's confirm reports
+    /// the offset of the first of them, i.e. the n-th record sent to
+    /// a particular partition is assigned the confirmed offset plus
+    /// `n`.
+    ///
+    /// Records with an explicit (non-negative) partition are sent to
+    /// that partition without consulting the partitioner.  If any of
+    /// them names a partition unknown to this producer, nothing is
+    /// sent and the call fails with
+    /// `KafkaCode::UnknownTopicOrPartition`.
     pub fn send_all<'a, K, V>(&mut self, recs: &[Record<'a, K, V>]) -> Result<Vec<ProduceConfirm>>
     where
         K: AsBytes,
@@ -292,20 +506,61 @@ impl<P: Partitioner> Producer<P> {
         let client = &mut self.client;
         let config = &self.config;
 
-        client.internal_produce_messages(
-            config.required_acks,
-            config.ack_timeout,
-            recs.into_iter().map(|r| {
+        for r in recs {
+            if r.partition >= 0 {
+                try!(check_partition(partitions, r.topic, r.partition));
+            }
+        }
+
+        let mut msgs: Vec<_> = recs.iter()
+            .map(|r| {
                 let mut m = client::ProduceMessage {
                     key: to_option(r.key.as_bytes()),
                     value: to_option(r.value.as_bytes()),
                     topic: r.topic,
                     partition: r.partition,
+                    timestamp: r.timestamp,
+                    headers: &r.headers,
                 };
-                partitioner.partition(Topics::new(partitions), &mut m);
+                if m.partition < 0 {
+                    partitioner.partition(Topics::new(partitions), &mut m);
+                }
                 m
-            }),
-        )
+            })
+            .collect();
+
+        let retries = config.effective_retries(client.idempotent());
+        if config.retries > 0 && retries == 0 {
+            trace!("send_all: retries disabled; duplicates on retry not allowed");
+        }
+        // ~ the sequence numbers assigned to the records of each
+        // partition by an idempotent producer; retries re-send the
+        // records under the same numbers
+        let mut sequences = HashMap::new();
+        let r = send_retrying(client, config, &mut msgs, retries, &mut sequences);
+        let confirmed = match r {
+            Ok(ref confirms) => check_confirms(confirms).is_ok(),
+            Err(_) => false,
+        };
+        if !confirmed && !sequences.is_empty() {
+            // ~ giving up on records whose sequences may now be out
+            // of sync with the brokers
+            client.internal_reset_producer_id();
+        }
+        r
+    }
+
+    /// Turns this producer into a `BufferedProducer` accumulating
+    /// records and sending them in batches.
+    pub fn into_buffered(self) -> BufferedProducer<P> {
+        BufferedProducer {
+            producer: self,
+            batch_size: DEFAULT_BATCH_SIZE,
+            linger: Duration::from_millis(DEFAULT_LINGER_MILLIS),
+            records: Vec::new(),
+            sizes: HashMap::new(),
+            buffered_since: None,
+        }
     }
 }
 
@@ -313,8 +568,132 @@ fn to_option(data: &[u8]) -> Option<&[u8]> {
     if data.is_empty() { None } else { Some(data) }
 }
 
+// ~ fails with the first error reported in the given confirms
+fn check_confirms(confirms: &[ProduceConfirm]) -> Result<()> {
+    for pc in confirms.iter().flat_map(|c| &c.partition_confirms) {
+        if let Err(code) = pc.offset {
+            // ~ preserve codes unknown to this library
+            return Err(protocol::kafka_error(pc.error_code)
+                .unwrap_or_else(|| ErrorKind::Kafka(code).into()));
+        }
+    }
+    Ok(())
+}
+
+// ~ sends the given messages re-sending those which failed with a
+// retriable error up to `retries` times
+fn send_retrying(
+    client: &mut KafkaClient,
+    config: &Config,
+    msgs: &mut Vec<client::ProduceMessage>,
+    retries: u32,
+    sequences: &mut HashMap<(String, i32), i32>,
+) -> Result<Vec<ProduceConfirm>> {
+    let mut confirms = Vec::new();
+    let mut attempt = 0;
+    loop {
+        match client.internal_produce_messages_resendable(
+            config.required_acks as i16,
+            config.ack_timeout,
+            &*msgs,
+            sequences,
+        ) {
+            Err(ref e) if attempt < retries && e.is_retriable() => {
+                debug!("send_all: retrying {} records after: {}", msgs.len(), e);
+            }
+            Err(e) => return Err(e),
+            Ok(cs) => {
+                merge_confirms(&mut confirms, cs);
+                let failed = retriable_failures(&confirms);
+                if failed.is_empty() || attempt >= retries {
+                    return Ok(confirms);
+                }
+                msgs.retain(|m| failed.contains(&(m.topic, m.partition)));
+                debug!("send_all: retrying {} records to {:?}", msgs.len(), failed);
+            }
+        }
+        attempt += 1;
+        thread::sleep(client.retry_backoff_time());
+    }
+}
+
+// ~ merges the given confirms into `confirms` replacing the confirms
+// of re-sent partitions
+fn merge_confirms(confirms: &mut Vec<ProduceConfirm>, new: Vec<ProduceConfirm>) {
+    for c in new {
+        let i = match confirms.iter().position(|x| x.topic == c.topic) {
+            Some(i) => i,
+            None => {
+                confirms.push(c);
+                continue;
+            }
+        };
+        let pcs = &mut confirms[i].partition_confirms;
+        for pc in c.partition_confirms {
+            match pcs.iter().position(|x| x.partition == pc.partition) {
+                Some(j) => pcs[j] = pc,
+                None => pcs.push(pc),
+            }
+        }
+    }
+}
+
+// ~ collects the topic partitions whose confirms report an error
+fn failed_partitions(confirms: &[ProduceConfirm]) -> Vec<(&str, i32)> {
+    confirms
+        .iter()
+        .flat_map(|c| {
+            c.partition_confirms
+                .iter()
+                .filter(|pc| pc.offset.is_err())
+                .map(move |pc| (&c.topic[..], pc.partition))
+        })
+        .collect()
+}
+
+// ~ collects the topic partitions whose confirms report a retriable
+// error
+fn retriable_failures(confirms: &[ProduceConfirm]) -> Vec<(&str, i32)> {
+    let mut failed = Vec::new();
+    for c in confirms {
+        for pc in &c.partition_confirms {
+            if let Err(code) = pc.offset {
+                if code.retriable() {
+                    failed.push((&c.topic[..], pc.partition));
+                }
+            }
+        }
+    }
+    failed
+}
+
+// ~ verifies that an explicitly requested partition is known
+fn check_partition(
+    partitions: &HashMap<String, Partitions>,
+    topic: &str,
+    partition: i32,
+) -> Result<()> {
+    match partitions.get(topic) {
+        Some(ps) if (partition as u32) < ps.num_all_partitions => Ok(()),
+        _ => bail!(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition)),
+    }
+}
+
 // --------------------------------------------------------------------
 
+impl<P: Partitioner> State<P> {
+    // ~ determines the partition the given message is to be sent to;
+    // see `Producer::send_all`
+    fn assign_partition(&mut self, msg: &mut client::ProduceMessage) -> Result<()> {
+        if msg.partition >= 0 {
+            check_partition(&self.partitions, msg.topic, msg.partition)
+        } else {
+            self.partitioner.partition(Topics::new(&self.partitions), msg);
+            Ok(())
+        }
+    }
+}
+
 impl<P> State<P> {
     fn new(client: &mut KafkaClient, partitioner: P) -> Result<State<P>> {
         let ts = client.topics();
@@ -393,7 +772,8 @@ impl Builder {
     /// Sets the maximum time the kafka brokers can await the receipt
     /// of required acknowledgements (which is specified through
     /// `Builder::with_required_acks`.)  Note that Kafka explicitely
-    /// documents this not to be a hard limit.
+    /// documents this not to be a hard limit.  See
+    /// `Producer::set_ack_timeout`.
     pub fn with_ack_timeout(mut self, timeout: Duration) -> Self {
         self.ack_timeout = timeout;
         self
@@ -435,8 +815,8 @@ impl<P> Builder<P> {
             conn_idle_timeout: self.conn_idle_timeout,
             required_acks: self.required_acks,
             partitioner: partitioner,
-            security_config: None,
-            client_id: None,
+            security_config: self.security_config,
+            client_id: self.client_id,
         }
     }
 
@@ -470,7 +850,9 @@ impl<P> Builder<P> {
         }
         let producer_config = Config {
             ack_timeout: try!(protocol::to_millis_i32(self.ack_timeout)),
-            required_acks: self.required_acks as i16,
+            required_acks: self.required_acks,
+            retries: DEFAULT_RETRIES,
+            allow_duplicates_on_retry: false,
         };
         // ~ load metadata if necessary
         if need_metadata {
@@ -488,6 +870,210 @@ impl<P> Builder<P> {
 
 // --------------------------------------------------------------------
 
+/// A producer accumulating records and sending them in batches to
+/// trade a little latency for a better throughput and compression
+/// ratio (akin to the `batch.size` and `linger.ms` settings of
+/// Kafka's Java producer.)  Created through `Producer::into_buffered`.
+///
+/// Records are assigned their partition when being buffered.  All
+/// buffered records are sent - using `Producer::send_all`, i.e. with
+/// one request per involved broker - once the records buffered for
+/// one partition would exceed the batch size or once the oldest
+/// buffered record has lingered for the configured duration.  Since
+/// this producer does not run any background thread, the linger time
+/// is checked only when buffering further records; call `flush` to
+/// send the buffered records when no further records arrive.  Records
+/// which fail to be sent remain buffered.  The records still buffered
+/// when dropping the producer are flushed.
+pub struct BufferedProducer<P: Partitioner = DefaultPartitioner> {
+    producer: Producer<P>,
+    batch_size: usize,
+    linger: Duration,
+    records: Vec<BufferedRecord>,
+    // ~ the number of bytes buffered per topic partition
+    sizes: HashMap<(String, i32), usize>,
+    // ~ the time at which the oldest buffered record was buffered
+    buffered_since: Option<Instant>,
+}
+
+// ~ an owned copy of a buffered record; its partition already assigned
+struct BufferedRecord {
+    topic: String,
+    partition: i32,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    timestamp: Option<i64>,
+    headers: Vec<(String, Vec<u8>)>,
+}
+
+impl BufferedRecord {
+    // ~ the number of bytes accounted against the batch size
+    fn size(&self) -> usize {
+        self.key.len() + self.value.len() +
+            self.headers.iter().map(|h| h.0.len() + h.1.len()).sum::<usize>()
+    }
+}
+
+impl<P: Partitioner> BufferedProducer<P> {
+    /// Borrows the underlying producer.
+    pub fn producer(&self) -> &Producer<P> {
+        &self.producer
+    }
+
+    /// Sets the max. number of bytes - counting the keys, values,
+    /// and headers - to buffer for a single partition.  Records
+    /// larger than this are rejected by `buffer`.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+
+    /// Retrieves the current `BufferedProducer::set_batch_size`
+    /// setting.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Sets the max. time a record is buffered before all buffered
+    /// records are sent.  A zero duration sends every record as it
+    /// is buffered.
+    pub fn set_linger(&mut self, linger: Duration) {
+        self.linger = linger;
+    }
+
+    /// Retrieves the current `BufferedProducer::set_linger` setting.
+    pub fn linger(&self) -> Duration {
+        self.linger
+    }
+
+    /// Retrieves the number of currently buffered records.
+    pub fn num_buffered(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Discards all buffered records without sending them, e.g.
+    /// records which keep failing to be sent.  Returns the number of
+    /// discarded records.
+    pub fn discard_buffered(&mut self) -> usize {
+        self.sizes.clear();
+        self.buffered_since = None;
+        mem::replace(&mut self.records, Vec::new()).len()
+    }
+
+    /// Buffers a copy of the given record, sending all buffered
+    /// records if the batch size or the linger time is reached.
+    ///
+    /// Records which can never be sent are rejected immediately:
+    /// those larger than the batch size with
+    /// `KafkaCode::MessageSizeTooLarge` and those with an explicit
+    /// partition unknown to the producer with
+    /// `KafkaCode::UnknownTopicOrPartition`.  If sending the buffered
+    /// records fails, the first error is reported here; the records
+    /// which could not be sent remain buffered (see `flush`.)  The
+    /// given record is buffered unless sending fails while making
+    /// room for it in the batch of its partition.
+    pub fn buffer<'a, K, V>(&mut self, rec: &Record<'a, K, V>) -> Result<()>
+    where
+        K: AsBytes,
+        V: AsBytes,
+    {
+        let key = rec.key.as_bytes();
+        let value = rec.value.as_bytes();
+        let size = key.len() + value.len() +
+            rec.headers.iter().map(|h| h.0.len() + h.1.len()).sum::<usize>();
+        if size > self.batch_size {
+            bail!(ErrorKind::Kafka(KafkaCode::MessageSizeTooLarge));
+        }
+        let mut m = client::ProduceMessage {
+            key: to_option(key),
+            value: to_option(value),
+            topic: rec.topic,
+            partition: rec.partition,
+            timestamp: rec.timestamp,
+            headers: &rec.headers,
+        };
+        try!(self.producer.state.assign_partition(&mut m));
+
+        let tp = (rec.topic.to_owned(), m.partition);
+        if self.sizes.get(&tp).map_or(false, |&n| n + size > self.batch_size) {
+            try!(self.flush().and_then(|cs| check_confirms(&cs)));
+        }
+        *self.sizes.entry(tp).or_insert(0) += size;
+        self.records.push(BufferedRecord {
+            topic: rec.topic.to_owned(),
+            partition: m.partition,
+            key: key.to_owned(),
+            value: value.to_owned(),
+            timestamp: rec.timestamp,
+            headers: rec.headers.clone(),
+        });
+        let since = *self.buffered_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= self.linger {
+            try!(self.flush().and_then(|cs| check_confirms(&cs)));
+        }
+        Ok(())
+    }
+
+    /// Sends all buffered records through `Producer::send_all`
+    /// returning its confirms.  Records which could not be sent -
+    /// all of them if sending fails altogether, otherwise those of
+    /// the partitions the confirms report an error for - remain
+    /// buffered and are sent again with the next flush; see also
+    /// `discard_buffered`.  Within a flush, records are retried only
+    /// as configured through `Producer::set_retries`.
+    pub fn flush(&mut self) -> Result<Vec<ProduceConfirm>> {
+        if self.records.is_empty() {
+            return Ok(Vec::new());
+        }
+        let buffered = mem::replace(&mut self.records, Vec::new());
+        let buffered_since = self.buffered_since.take();
+        self.sizes.clear();
+        let r = {
+            let recs: Vec<_> = buffered
+                .iter()
+                .map(|r| {
+                    Record {
+                        key: &r.key[..],
+                        value: &r.value[..],
+                        topic: &r.topic[..],
+                        partition: r.partition,
+                        timestamp: r.timestamp,
+                        headers: r.headers.clone(),
+                    }
+                })
+                .collect();
+            self.producer.send_all(&recs)
+        };
+        let unsent: Vec<BufferedRecord> = match r {
+            Err(_) => buffered,
+            Ok(ref confirms) => {
+                let failed = failed_partitions(confirms);
+                buffered
+                    .into_iter()
+                    .filter(|r| failed.contains(&(&r.topic[..], r.partition)))
+                    .collect()
+            }
+        };
+        if !unsent.is_empty() {
+            for r in &unsent {
+                *self.sizes.entry((r.topic.clone(), r.partition)).or_insert(0) += r.size();
+            }
+            self.records = unsent;
+            self.buffered_since = buffered_since;
+        }
+        r
+    }
+}
+
+impl<P: Partitioner> Drop for BufferedProducer<P> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush().and_then(|cs| check_confirms(&cs)) {
+            warn!("Failed to flush buffered records on drop: {}", e);
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+
 /// A description of available topics and their available partitions.
 ///
 /// Indented for use by `Partitioner`s.
@@ -671,6 +1257,80 @@ impl<H: BuildHasher> Partitioner for DefaultPartitioner<H> {
 
 // --------------------------------------------------------------------
 
+/// A partitioner distributing messages with an "unspecified"
+/// partition across the "available" partitions of their topic in a
+/// round robin fashion - regardless of their key.  Messages with an
+/// explicit, non-negative partition are left untouched.
+///
+/// See `Builder::with_partitioner`.
+#[derive(Default, Debug)]
+pub struct RoundRobinPartitioner {
+    // ~ a counter incremented with each partitioned message
+    cntr: u32,
+}
+
+impl Partitioner for RoundRobinPartitioner {
+    fn partition(&mut self, topics: Topics, rec: &mut client::ProduceMessage) {
+        if rec.partition >= 0 {
+            return;
+        }
+        if let Some(partitions) = topics.partitions(rec.topic) {
+            let avail = partitions.available_ids();
+            if !avail.is_empty() {
+                rec.partition = avail[self.cntr as usize % avail.len()];
+                self.cntr = self.cntr.wrapping_add(1);
+            }
+        }
+    }
+}
+
+/// A partitioner compatible with the default partitioner of Kafka's
+/// Java producer.  Messages with a key but an "unspecified" partition
+/// are assigned to `(murmur2(key) & 0x7fffffff) % num_all_partitions`
+/// such that messages with the same key end up in the same partition
+/// no matter whether produced by this library or a Java client.
+/// See `kafka::partition::key_partition`.  Messages without a key
+/// are distributed across the "available" partitions in a round robin
+/// fashion and messages with an explicit, non-negative partition are
+/// left untouched.
+///
+/// # Example
+///
+/// ```no_run
+/// use kafka::producer::{HashPartitioner, Producer};
+///
+/// let producer = Producer::from_hosts(vec!["localhost:9092".to_owned()])
+///     .with_partitioner(HashPartitioner::default())
+///     .create()
+///     .unwrap();
+/// ```
+#[derive(Default, Debug)]
+pub struct HashPartitioner {
+    // ~ partitions key-less messages
+    round_robin: RoundRobinPartitioner,
+}
+
+impl Partitioner for HashPartitioner {
+    fn partition(&mut self, topics: Topics, rec: &mut client::ProduceMessage) {
+        if rec.partition >= 0 {
+            return;
+        }
+        match rec.key {
+            Some(key) => {
+                if let Some(partitions) = topics.partitions(rec.topic) {
+                    let num_partitions = partitions.num_all();
+                    if num_partitions > 0 {
+                        rec.partition = partition::key_partition(key, num_partitions as i32);
+                    }
+                }
+            }
+            None => self.round_robin.partition(topics, rec),
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+
 #[cfg(test)]
 mod default_partitioner_tests {
     use std::hash::{Hasher, BuildHasherDefault};
@@ -698,6 +1358,8 @@ mod default_partitioner_tests {
             value: None,
             topic: topic,
             partition: -1,
+            timestamp: None,
+            headers: &[],
         };
         p.partition(Topics::new(topics), &mut msg);
         let num_partitions = topics.get(topic).unwrap().num_all_partitions as i32;
@@ -788,3 +1450,278 @@ mod default_partitioner_tests {
         assert_eq!(6, p2);
     }
 }
+
+#[cfg(test)]
+mod builtin_partitioner_tests {
+    use std::collections::HashMap;
+
+    use client;
+    use error::{Error, ErrorKind, KafkaCode};
+    use super::{HashPartitioner, RoundRobinPartitioner, Partitioner, Partitions, Producer,
+                Topics, check_partition};
+
+    fn topics_map() -> HashMap<String, Partitions> {
+        let mut h = HashMap::new();
+        h.insert(
+            "foo".to_owned(),
+            Partitions {
+                available_ids: vec![0, 1, 4],
+                num_all_partitions: 5,
+            },
+        );
+        h
+    }
+
+    fn partition<P: Partitioner>(p: &mut P, key: Option<&str>, partition: i32) -> i32 {
+        let topics = topics_map();
+        let mut msg = client::ProduceMessage {
+            key: key.map(|k| k.as_bytes()),
+            value: None,
+            topic: "foo",
+            partition: partition,
+            timestamp: None,
+            headers: &[],
+        };
+        p.partition(Topics::new(&topics), &mut msg);
+        msg.partition
+    }
+
+    #[test]
+    fn test_hash_partitioner() {
+        let mut p = HashPartitioner::default();
+        // ~ `(murmur2("foobar") & 0x7fffffff) % 5`
+        assert_eq!(1357151166 % 5, partition(&mut p, Some("foobar"), -1));
+        // ~ consistent regardless of previously partitioned messages
+        assert_eq!(partition(&mut p, Some("21"), -1), partition(&mut p, Some("21"), -1));
+        // ~ explicit partitions are left untouched
+        assert_eq!(3, partition(&mut p, Some("foobar"), 3));
+        // ~ key-less messages are distributed round robin
+        assert_eq!(vec![0, 1, 4, 0],
+                   (0..4).map(|_| partition(&mut p, None, -1)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_with_partitioner_keeps_settings() {
+        let b = Producer::from_hosts(vec![])
+            .with_client_id("my-client".to_owned())
+            .with_partitioner(RoundRobinPartitioner::default());
+        assert_eq!(Some("my-client"), b.client_id.as_ref().map(|s| &s[..]));
+    }
+
+    #[test]
+    fn test_round_robin_partitioner() {
+        let mut p = RoundRobinPartitioner::default();
+        assert_eq!(vec![0, 1, 4, 0],
+                   (0..4).map(|_| partition(&mut p, Some("foobar"), -1)).collect::<Vec<_>>());
+        assert_eq!(2, partition(&mut p, None, 2));
+    }
+
+    #[test]
+    fn test_check_partition() {
+        let topics = topics_map();
+        assert!(check_partition(&topics, "foo", 0).is_ok());
+        // ~ partitions without a leader are still known
+        assert!(check_partition(&topics, "foo", 3).is_ok());
+        for &(topic, partition) in &[("foo", 5), ("bar", 0)] {
+            match check_partition(&topics, topic, partition) {
+                Err(Error(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition), _)) => {}
+                r => panic!("Expected UnknownTopicOrPartition, but got: {:?}", r),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod buffered_producer_tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use client::KafkaClient;
+    use error::{Error, ErrorKind, KafkaCode};
+    use super::{BufferedProducer, Config, DefaultPartitioner, Partitions, Producer, Record,
+                RequiredAcks, State};
+
+    fn buffered_producer() -> BufferedProducer {
+        let mut partitions = HashMap::new();
+        partitions.insert(
+            "foo".to_owned(),
+            Partitions {
+                available_ids: vec![0, 1],
+                num_all_partitions: 2,
+            },
+        );
+        let producer = Producer {
+            client: KafkaClient::new(vec![]),
+            state: State {
+                partitions: partitions,
+                partitioner: DefaultPartitioner::default(),
+            },
+            config: Config {
+                ack_timeout: 1000,
+                required_acks: RequiredAcks::One,
+                retries: 0,
+                allow_duplicates_on_retry: false,
+            },
+        };
+        let mut p = producer.into_buffered();
+        // ~ never flush on its own
+        p.set_linger(Duration::from_secs(3600));
+        p.set_batch_size(15);
+        p
+    }
+
+    fn assert_kafka_error<T: ::std::fmt::Debug>(code: KafkaCode, r: ::error::Result<T>) {
+        match r {
+            Err(Error(ErrorKind::Kafka(ref c), _)) if *c == code => {}
+            r => panic!("Expected {:?}, but got: {:?}", code, r),
+        }
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut p = buffered_producer();
+        p.buffer(&Record::from_key_value("foo", "key", "value")).unwrap();
+        p.buffer(&Record::from_value("foo", "value").with_partition(1)).unwrap();
+        assert_eq!(2, p.num_buffered());
+
+        // ~ rejected right away without affecting the buffer
+        assert_kafka_error(
+            KafkaCode::MessageSizeTooLarge,
+            p.buffer(&Record::from_value("foo", "very large value")),
+        );
+        assert_kafka_error(
+            KafkaCode::UnknownTopicOrPartition,
+            p.buffer(&Record::from_value("foo", "value").with_partition(2)),
+        );
+        assert_eq!(2, p.num_buffered());
+        // ~ the partitions are assigned upon buffering
+        assert!(p.records[0].partition >= 0);
+        assert_eq!(1, p.records[1].partition);
+
+        // ~ don't attempt to flush on drop
+        p.records.clear();
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_records() {
+        let mut p = buffered_producer();
+        p.buffer(&Record::from_key_value("foo", "key", "value").with_partition(0))
+            .unwrap();
+        p.buffer(&Record::from_value("foo", "value").with_partition(1)).unwrap();
+
+        // ~ the client knows no brokers; sending fails altogether
+        assert!(p.flush().is_err());
+        assert_eq!(2, p.num_buffered());
+        assert_eq!(b"key", &p.records[0].key[..]);
+        assert_eq!(8, p.sizes[&("foo".to_owned(), 0)]);
+        assert!(p.buffered_since.is_some());
+
+        // ~ the flush making room for another record fails, too; the
+        // record is not buffered
+        assert!(p.buffer(&Record::from_value("foo", "more value").with_partition(0)).is_err());
+        assert_eq!(2, p.num_buffered());
+
+        assert_eq!(2, p.discard_buffered());
+        assert_eq!(0, p.num_buffered());
+        assert!(p.sizes.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use client::{ProduceConfirm, ProducePartitionConfirm};
+    use error::{Error, ErrorKind, KafkaCode};
+    use protocol::produce::PartitionProduceResponse;
+    use super::{Config, RequiredAcks, check_confirms, failed_partitions, merge_confirms,
+                retriable_failures};
+
+    fn confirm(topic: &str, results: &[(i32, Result<i64, KafkaCode>)]) -> ProduceConfirm {
+        ProduceConfirm {
+            topic: topic.to_owned(),
+            partition_confirms: results
+                .iter()
+                .map(|&(partition, offset)| {
+                    ProducePartitionConfirm {
+                        offset: offset,
+                        error_code: offset.err().map_or(0, |code| code.to_i16()),
+                        partition: partition,
+                        log_append_time: None,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_effective_retries() {
+        let mut config = Config {
+            ack_timeout: 1000,
+            required_acks: RequiredAcks::One,
+            retries: 3,
+            allow_duplicates_on_retry: false,
+        };
+        assert_eq!(0, config.effective_retries(false));
+        // ~ idempotent producers retry without duplicates
+        assert_eq!(3, config.effective_retries(true));
+        config.allow_duplicates_on_retry = true;
+        assert_eq!(3, config.effective_retries(false));
+    }
+
+    #[test]
+    fn test_merge_confirms() {
+        let mut confirms = vec![
+            confirm(
+                "a",
+                &[
+                    (0, Ok(10)),
+                    (1, Err(KafkaCode::NotEnoughReplicas)),
+                    (2, Err(KafkaCode::MessageSizeTooLarge)),
+                ],
+            ),
+            confirm("b", &[(0, Err(KafkaCode::RequestTimedOut))]),
+        ];
+        assert_eq!(vec![("a", 1), ("b", 0)], retriable_failures(&confirms));
+        assert_eq!(vec![("a", 1), ("a", 2), ("b", 0)], failed_partitions(&confirms));
+
+        merge_confirms(
+            &mut confirms,
+            vec![confirm("a", &[(1, Ok(20))]), confirm("b", &[(0, Ok(30))])],
+        );
+        assert!(retriable_failures(&confirms).is_empty());
+        let offsets: Vec<_> = confirms
+            .iter()
+            .flat_map(|c| c.partition_confirms.iter().map(move |pc| (&c.topic[..], pc.offset)))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", Ok(10)),
+                ("a", Ok(20)),
+                ("a", Err(KafkaCode::MessageSizeTooLarge)),
+                ("b", Ok(30)),
+            ],
+            offsets
+        );
+    }
+
+    #[test]
+    fn test_check_confirms_preserves_unknown_codes() {
+        let resp = PartitionProduceResponse {
+            partition: 1,
+            error: 100,
+            ..Default::default()
+        };
+        let pc = resp.get_response();
+        assert_eq!((Err(KafkaCode::Unknown), 100), (pc.offset, pc.error_code));
+        let confirms = vec![
+            ProduceConfirm {
+                topic: "a".to_owned(),
+                partition_confirms: vec![pc],
+            },
+        ];
+        match check_confirms(&confirms) {
+            Err(Error(ErrorKind::UnknownKafkaCode(100), _)) => {}
+            r => panic!("Expected UnknownKafkaCode(100), but got: {:?}", r),
+        }
+        assert!(check_confirms(&[confirm("a", &[(0, Ok(10))])]).is_ok());
+    }
+}
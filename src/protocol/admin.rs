@@ -0,0 +1,921 @@
+use std::io::{Read, Write};
+
+use codecs::{decode_string_field, encode_as_array, AsStrings, ToByte, FromByte};
+use error::{Error, ErrorKind, KafkaCode, Result};
+
+use super::{HeaderRequest, HeaderResponse};
+use super::{API_KEY_CREATE_TOPICS, API_KEY_DELETE_TOPICS, API_KEY_CREATE_PARTITIONS,
+            API_KEY_DELETE_RECORDS, API_KEY_DESCRIBE_CONFIGS, API_KEY_ALTER_CONFIGS,
+            API_VERSION};
+
+#[derive(Debug)]
+pub struct CreateTopicsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub topics: Vec<CreateTopicRequest<'b>>,
+    pub timeout: i32,
+}
+
+#[derive(Debug)]
+pub struct CreateTopicRequest<'a> {
+    pub topic: &'a str,
+    pub num_partitions: i32,
+    pub replication_factor: i16,
+    pub configs: &'a [(&'a str, &'a str)],
+}
+
+impl<'a, 'b> CreateTopicsRequest<'a, 'b> {
+    pub fn new(
+        correlation_id: i32,
+        client_id: &'a str,
+        timeout: i32,
+    ) -> CreateTopicsRequest<'a, 'b> {
+        CreateTopicsRequest {
+            header: HeaderRequest::new(
+                API_KEY_CREATE_TOPICS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            topics: vec![],
+            timeout: timeout,
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        topic: &'b str,
+        num_partitions: i32,
+        replication_factor: i16,
+        configs: &'b [(&'b str, &'b str)],
+    ) {
+        self.topics.push(CreateTopicRequest {
+            topic: topic,
+            num_partitions: num_partitions,
+            replication_factor: replication_factor,
+            configs: configs,
+        });
+    }
+}
+
+impl<'a, 'b> ToByte for CreateTopicsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.topics.encode(buffer),
+            self.timeout.encode(buffer)
+        )
+    }
+}
+
+impl<'a> ToByte for CreateTopicRequest<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.topic.encode(buffer),
+            self.num_partitions.encode(buffer),
+            self.replication_factor.encode(buffer),
+            // ~ no explicit replica assignment
+            (0i32).encode(buffer),
+            encode_as_array(buffer, self.configs, |buffer, &(name, value)| {
+                try_multi!(name.encode(buffer), value.encode(buffer))
+            })
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Default, Debug)]
+pub struct CreateTopicsResponse {
+    pub header: HeaderResponse,
+    pub topics: Vec<TopicError>,
+}
+
+/// The outcome of an administrative operation on a single topic.
+#[derive(Default, Debug)]
+pub struct TopicError {
+    pub topic: String,
+    pub error: i16,
+}
+
+impl TopicError {
+    pub fn to_result(&self) -> Result<()> {
+        match Error::from_protocol(self.error) {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl FromByte for CreateTopicsResponse {
+    type R = CreateTopicsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.topics.decode(buffer))
+    }
+}
+
+impl FromByte for TopicError {
+    type R = TopicError;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.error.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct DeleteTopicsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub topics: &'b [&'b str],
+    pub timeout: i32,
+}
+
+impl<'a, 'b> DeleteTopicsRequest<'a, 'b> {
+    pub fn new(
+        correlation_id: i32,
+        client_id: &'a str,
+        topics: &'b [&'b str],
+        timeout: i32,
+    ) -> DeleteTopicsRequest<'a, 'b> {
+        DeleteTopicsRequest {
+            header: HeaderRequest::new(
+                API_KEY_DELETE_TOPICS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            topics: topics,
+            timeout: timeout,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for DeleteTopicsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            AsStrings(self.topics).encode(buffer),
+            self.timeout.encode(buffer)
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct DeleteTopicsResponse {
+    pub header: HeaderResponse,
+    pub topics: Vec<TopicError>,
+}
+
+impl FromByte for DeleteTopicsResponse {
+    type R = DeleteTopicsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.topics.decode(buffer))
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct CreatePartitionsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub topic: &'b str,
+    pub count: i32,
+    pub timeout: i32,
+}
+
+impl<'a, 'b> CreatePartitionsRequest<'a, 'b> {
+    pub fn new(
+        correlation_id: i32,
+        client_id: &'a str,
+        topic: &'b str,
+        count: i32,
+        timeout: i32,
+    ) -> CreatePartitionsRequest<'a, 'b> {
+        CreatePartitionsRequest {
+            header: HeaderRequest::new(
+                API_KEY_CREATE_PARTITIONS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            topic: topic,
+            count: count,
+            timeout: timeout,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for CreatePartitionsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            // ~ a single topic
+            (1i32).encode(buffer),
+            self.topic.encode(buffer),
+            self.count.encode(buffer),
+            // ~ no explicit replica assignment; null
+            (-1i32).encode(buffer),
+            self.timeout.encode(buffer),
+            // ~ validate only; false
+            (0i8).encode(buffer)
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct CreatePartitionsResponse {
+    pub header: HeaderResponse,
+    pub throttle_time: i32,
+    pub topics: Vec<TopicErrorMessage>,
+}
+
+/// The outcome of an administrative operation on a single topic
+/// along with an optional error message.
+#[derive(Default, Debug)]
+pub struct TopicErrorMessage {
+    pub topic: String,
+    pub error: i16,
+    pub message: String,
+}
+
+impl FromByte for CreatePartitionsResponse {
+    type R = CreatePartitionsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.throttle_time.decode(buffer),
+            self.topics.decode(buffer)
+        )
+    }
+}
+
+/// Converts the (non-zero) error code reported for a topic into an
+/// error; codes unknown to this library are preserved as
+/// `ErrorKind::UnknownKafkaCode`.
+pub fn topic_error(code: i16) -> Error {
+    Error::from_protocol(code).unwrap_or_else(|| ErrorKind::Kafka(KafkaCode::Unknown).into())
+}
+
+impl FromByte for TopicErrorMessage {
+    type R = TopicErrorMessage;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.error.decode(buffer),
+            self.message.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Requests the deletion of the records of the given partitions
+/// before the specified offsets.  Supported as of Kafka 0.11.
+#[derive(Debug)]
+pub struct DeleteRecordsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub topics: Vec<DeleteRecordsTopic<'b>>,
+    pub timeout: i32,
+}
+
+#[derive(Debug)]
+pub struct DeleteRecordsTopic<'a> {
+    pub topic: &'a str,
+    /// Pairs of partition id and the offset before which to delete
+    /// the records.
+    pub partitions: Vec<(i32, i64)>,
+}
+
+impl<'a, 'b> DeleteRecordsRequest<'a, 'b> {
+    pub fn new(
+        correlation_id: i32,
+        client_id: &'a str,
+        timeout: i32,
+    ) -> DeleteRecordsRequest<'a, 'b> {
+        DeleteRecordsRequest {
+            header: HeaderRequest::new(
+                API_KEY_DELETE_RECORDS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            topics: vec![],
+            timeout: timeout,
+        }
+    }
+
+    pub fn add(&mut self, topic: &'b str, partition: i32, offset: i64) {
+        for t in &mut self.topics {
+            if t.topic == topic {
+                t.partitions.push((partition, offset));
+                return;
+            }
+        }
+        self.topics.push(DeleteRecordsTopic {
+            topic: topic,
+            partitions: vec![(partition, offset)],
+        });
+    }
+}
+
+impl<'a, 'b> ToByte for DeleteRecordsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.topics.encode(buffer),
+            self.timeout.encode(buffer)
+        )
+    }
+}
+
+impl<'a> ToByte for DeleteRecordsTopic<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.topic.encode(buffer),
+            encode_as_array(buffer, &self.partitions, |buffer, &(partition, offset)| {
+                try_multi!(partition.encode(buffer), offset.encode(buffer))
+            })
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct DeleteRecordsResponse {
+    pub header: HeaderResponse,
+    pub throttle_time: i32,
+    pub topics: Vec<DeleteRecordsTopicResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct DeleteRecordsTopicResponse {
+    pub topic: String,
+    pub partitions: Vec<DeleteRecordsPartitionResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct DeleteRecordsPartitionResponse {
+    pub partition: i32,
+    pub low_watermark: i64,
+    pub error: i16,
+}
+
+impl DeleteRecordsPartitionResponse {
+    pub fn to_result(&self) -> Result<()> {
+        match Error::from_protocol(self.error) {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl FromByte for DeleteRecordsResponse {
+    type R = DeleteRecordsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.throttle_time.decode(buffer),
+            self.topics.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for DeleteRecordsTopicResponse {
+    type R = DeleteRecordsTopicResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for DeleteRecordsPartitionResponse {
+    type R = DeleteRecordsPartitionResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.partition.decode(buffer),
+            self.low_watermark.decode(buffer),
+            self.error.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Requests the configuration of topics or brokers.  Supported as of
+/// Kafka 0.11.
+#[derive(Debug)]
+pub struct DescribeConfigsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    /// Pairs of resource type and resource name.
+    pub resources: Vec<(i8, &'b str)>,
+}
+
+impl<'a, 'b> DescribeConfigsRequest<'a, 'b> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> DescribeConfigsRequest<'a, 'b> {
+        DescribeConfigsRequest {
+            header: HeaderRequest::new(
+                API_KEY_DESCRIBE_CONFIGS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            resources: vec![],
+        }
+    }
+
+    pub fn add(&mut self, resource_type: i8, name: &'b str) {
+        self.resources.push((resource_type, name));
+    }
+}
+
+impl<'a, 'b> ToByte for DescribeConfigsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            encode_as_array(buffer, &self.resources, |buffer, &(resource_type, name)| {
+                try_multi!(
+                    resource_type.encode(buffer),
+                    name.encode(buffer),
+                    // ~ the config names; null to describe all entries
+                    (-1i32).encode(buffer)
+                )
+            })
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct DescribeConfigsResponse {
+    pub header: HeaderResponse,
+    pub throttle_time: i32,
+    pub resources: Vec<DescribeConfigsResource>,
+}
+
+#[derive(Default, Debug)]
+pub struct DescribeConfigsResource {
+    pub error: i16,
+    pub error_message: String,
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub entries: Vec<ConfigEntryResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct ConfigEntryResponse {
+    pub name: String,
+    /// The value of the entry; empty if the entry is sensitive.
+    pub value: String,
+    pub read_only: i8,
+    pub is_default: i8,
+    pub is_sensitive: i8,
+}
+
+impl DescribeConfigsResource {
+    pub fn to_result(&self) -> Result<()> {
+        match Error::from_protocol(self.error) {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl FromByte for DescribeConfigsResponse {
+    type R = DescribeConfigsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.throttle_time.decode(buffer),
+            self.resources.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for DescribeConfigsResource {
+    type R = DescribeConfigsResource;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.error.decode(buffer),
+            self.error_message.decode(buffer),
+            self.resource_type.decode(buffer),
+            self.resource_name.decode(buffer),
+            self.entries.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for ConfigEntryResponse {
+    type R = ConfigEntryResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.name.decode(buffer),
+            self.value.decode(buffer),
+            self.read_only.decode(buffer),
+            self.is_default.decode(buffer),
+            self.is_sensitive.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Replaces the configuration of topics or brokers.  Supported as of
+/// Kafka 0.11.
+#[derive(Debug)]
+pub struct AlterConfigsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub resources: Vec<AlterConfigsResource<'b>>,
+}
+
+#[derive(Debug)]
+pub struct AlterConfigsResource<'a> {
+    pub resource_type: i8,
+    pub name: &'a str,
+    pub configs: &'a [(&'a str, &'a str)],
+}
+
+impl<'a, 'b> AlterConfigsRequest<'a, 'b> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> AlterConfigsRequest<'a, 'b> {
+        AlterConfigsRequest {
+            header: HeaderRequest::new(
+                API_KEY_ALTER_CONFIGS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            resources: vec![],
+        }
+    }
+
+    pub fn add(&mut self, resource_type: i8, name: &'b str, configs: &'b [(&'b str, &'b str)]) {
+        self.resources.push(AlterConfigsResource {
+            resource_type: resource_type,
+            name: name,
+            configs: configs,
+        });
+    }
+}
+
+impl<'a, 'b> ToByte for AlterConfigsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.resources.encode(buffer),
+            // ~ validate only; false
+            (0i8).encode(buffer)
+        )
+    }
+}
+
+impl<'a> ToByte for AlterConfigsResource<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.resource_type.encode(buffer),
+            self.name.encode(buffer),
+            encode_as_array(buffer, self.configs, |buffer, &(name, value)| {
+                try_multi!(name.encode(buffer), value.encode(buffer))
+            })
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AlterConfigsResponse {
+    pub header: HeaderResponse,
+    pub throttle_time: i32,
+    pub resources: Vec<AlterConfigsResourceResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct AlterConfigsResourceResponse {
+    pub error: i16,
+    pub error_message: String,
+    pub resource_type: i8,
+    pub resource_name: String,
+}
+
+impl FromByte for AlterConfigsResponse {
+    type R = AlterConfigsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.throttle_time.decode(buffer),
+            self.resources.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for AlterConfigsResourceResponse {
+    type R = AlterConfigsResourceResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.error.decode(buffer),
+            self.error_message.decode(buffer),
+            self.resource_type.decode(buffer),
+            self.resource_name.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{ToByte, FromByte};
+    use error::KafkaCode;
+    use protocol::kafka_code;
+    use super::{CreateTopicsRequest, CreateTopicsResponse, DeleteTopicsRequest,
+                DeleteTopicsResponse, CreatePartitionsRequest, CreatePartitionsResponse,
+                DeleteRecordsRequest, DeleteRecordsResponse, DescribeConfigsRequest,
+                DescribeConfigsResponse, AlterConfigsRequest, AlterConfigsResponse};
+
+    #[test]
+    fn test_encode_create_topics_request() {
+        let configs = [("cleanup.policy", "compact")];
+        let mut req = CreateTopicsRequest::new(1, "x", 500);
+        req.add("t", 3, 2, &configs);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 19, // api key
+            0, 0, // api version
+            0, 0, 0, 1, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 1, // topics
+            0, 1, b't', // topic
+            0, 0, 0, 3, // partitions
+            0, 2, // replication factor
+            0, 0, 0, 0, // replica assignment
+            0, 0, 0, 1, // configs
+            0, 14, b'c', b'l', b'e', b'a', b'n', b'u', b'p', b'.', b'p', b'o', b'l', b'i',
+            b'c', b'y',
+            0, 7, b'c', b'o', b'm', b'p', b'a', b'c', b't',
+            0, 0, 1, 244, // timeout
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_create_topics_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 1, // correlation
+            0, 0, 0, 3, // topics
+            0, 1, b'a', 0, 0, // ok
+            0, 1, b'b', 0, 36, // already exists
+            0, 1, b'c', 0, 17, // invalid topic
+        ];
+        let resp = CreateTopicsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        let rs: Vec<_> = resp.topics
+            .iter()
+            .map(|t| (&t.topic[..], t.to_result().map_err(|e| kafka_code(&e))))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", Ok(())),
+                ("b", Err(KafkaCode::TopicAlreadyExists)),
+                ("c", Err(KafkaCode::InvalidTopic)),
+            ],
+            rs
+        );
+    }
+
+    #[test]
+    fn test_encode_delete_topics_request() {
+        let topics = ["a", "bc"];
+        let req = DeleteTopicsRequest::new(2, "x", &topics, 1000);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 20, // api key
+            0, 0, // api version
+            0, 0, 0, 2, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 2, // topics
+            0, 1, b'a',
+            0, 2, b'b', b'c',
+            0, 0, 3, 232, // timeout
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_delete_topics_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 2, // correlation
+            0, 0, 0, 3, // topics
+            0, 1, b'a', 0, 0, // ok
+            0, 1, b'b', 0, 3, // unknown topic
+            0, 1, b'c', 0, 29, // not authorized
+        ];
+        let resp = DeleteTopicsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        let rs: Vec<_> = resp.topics
+            .iter()
+            .map(|t| (&t.topic[..], t.to_result().map_err(|e| kafka_code(&e))))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", Ok(())),
+                ("b", Err(KafkaCode::UnknownTopicOrPartition)),
+                ("c", Err(KafkaCode::TopicAuthorizationFailed)),
+            ],
+            rs
+        );
+    }
+
+    #[test]
+    fn test_encode_create_partitions_request() {
+        let req = CreatePartitionsRequest::new(3, "x", "t", 8, 1000);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 37, // api key
+            0, 0, // api version
+            0, 0, 0, 3, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 1, // topics
+            0, 1, b't', // topic
+            0, 0, 0, 8, // count
+            255, 255, 255, 255, // assignment
+            0, 0, 3, 232, // timeout
+            0, // validate only
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_create_partitions_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 3, // correlation
+            0, 0, 0, 0, // throttle time
+            0, 0, 0, 1, // topics
+            0, 1, b't', 0, 37, // invalid partitions
+            0, 3, b'b', b'a', b'd', // message
+        ];
+        let resp = CreatePartitionsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(1, resp.topics.len());
+        assert_eq!("t", resp.topics[0].topic);
+        assert_eq!(KafkaCode::InvalidPartitions as i16, resp.topics[0].error);
+        assert_eq!("bad", resp.topics[0].message);
+    }
+
+    #[test]
+    fn test_encode_delete_records_request() {
+        let mut req = DeleteRecordsRequest::new(4, "x", 1000);
+        req.add("t", 0, 5);
+        req.add("t", 2, 300);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 21, // api key
+            0, 0, // api version
+            0, 0, 0, 4, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 1, // topics
+            0, 1, b't', // topic
+            0, 0, 0, 2, // partitions
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
+            0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 1, 44,
+            0, 0, 3, 232, // timeout
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_delete_records_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 4, // correlation
+            0, 0, 0, 0, // throttle time
+            0, 0, 0, 1, // topics
+            0, 1, b't', // topic
+            0, 0, 0, 2, // partitions
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, // ok
+            0, 0, 0, 2, 255, 255, 255, 255, 255, 255, 255, 255, 0, 1, // offset out of range
+        ];
+        let resp = DeleteRecordsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(1, resp.topics.len());
+        let ps = &resp.topics[0].partitions;
+        assert_eq!((0, 5), (ps[0].partition, ps[0].low_watermark));
+        assert!(ps[0].to_result().is_ok());
+        assert_eq!(
+            (2, -1, Err(KafkaCode::OffsetOutOfRange)),
+            (ps[1].partition, ps[1].low_watermark, ps[1].to_result().map_err(|e| kafka_code(&e)))
+        );
+    }
+
+    #[test]
+    fn test_encode_describe_configs_request() {
+        let mut req = DescribeConfigsRequest::new(5, "x");
+        req.add(2, "t");
+        req.add(4, "1");
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 32, // api key
+            0, 0, // api version
+            0, 0, 0, 5, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 2, // resources
+            2, 0, 1, b't', 255, 255, 255, 255, // topic
+            4, 0, 1, b'1', 255, 255, 255, 255, // broker
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_describe_configs_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 5, // correlation
+            0, 0, 0, 0, // throttle time
+            0, 0, 0, 2, // resources
+            0, 0, // error
+            255, 255, // error message
+            2, 0, 1, b't', // resource
+            0, 0, 0, 1, // entries
+            0, 2, b'm', b's', 0, 3, b'1', b'0', b'0', 0, 1, 0, // entry
+            0, 29, // error: topic authorization failed
+            0, 1, b'!', // error message
+            2, 0, 1, b'u', // resource
+            0, 0, 0, 0, // entries
+        ];
+        let resp = DescribeConfigsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(2, resp.resources.len());
+        let r = &resp.resources[0];
+        assert!(r.to_result().is_ok());
+        assert_eq!((2, "t"), (r.resource_type, &r.resource_name[..]));
+        assert_eq!(1, r.entries.len());
+        let e = &r.entries[0];
+        assert_eq!(("ms", "100"), (&e.name[..], &e.value[..]));
+        assert_eq!((0, 1, 0), (e.read_only, e.is_default, e.is_sensitive));
+        let r = &resp.resources[1];
+        assert_eq!(
+            Err(KafkaCode::TopicAuthorizationFailed),
+            r.to_result().map_err(|e| kafka_code(&e))
+        );
+        assert_eq!("!", r.error_message);
+    }
+
+    #[test]
+    fn test_encode_alter_configs_request() {
+        let configs = [("a", "b")];
+        let mut req = AlterConfigsRequest::new(6, "x");
+        req.add(2, "t", &configs);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 33, // api key
+            0, 0, // api version
+            0, 0, 0, 6, // correlation
+            0, 1, b'x', // client id
+            0, 0, 0, 1, // resources
+            2, 0, 1, b't', // resource
+            0, 0, 0, 1, 0, 1, b'a', 0, 1, b'b', // configs
+            0, // validate only
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_alter_configs_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 6, // correlation
+            0, 0, 0, 0, // throttle time
+            0, 0, 0, 1, // resources
+            0, 31, // error: cluster authorization failed
+            255, 255, // error message
+            4, 0, 1, b'1', // resource
+        ];
+        let resp = AlterConfigsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(1, resp.resources.len());
+        assert_eq!(KafkaCode::ClusterAuthorizationFailed as i16, resp.resources[0].error);
+        let r = &resp.resources[0];
+        assert_eq!((4, "1"), (r.resource_type, &r.resource_name[..]));
+    }
+}
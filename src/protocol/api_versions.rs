@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use codecs::{ToByte, FromByte};
+use error::{Error, Result};
+
+use super::{HeaderRequest, HeaderResponse};
+use super::{API_KEY_API_VERSIONS, API_VERSION};
+
+#[derive(Debug)]
+pub struct ApiVersionsRequest<'a> {
+    pub header: HeaderRequest<'a>,
+}
+
+impl<'a> ApiVersionsRequest<'a> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> ApiVersionsRequest<'a> {
+        ApiVersionsRequest {
+            header: HeaderRequest::new(
+                API_KEY_API_VERSIONS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+        }
+    }
+}
+
+impl<'a> ToByte for ApiVersionsRequest<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        self.header.encode(buffer)
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Default, Debug)]
+pub struct ApiVersionsResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub api_versions: Vec<ApiVersion>,
+}
+
+#[derive(Default, Debug)]
+pub struct ApiVersion {
+    pub api_key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+impl ApiVersionsResponse {
+    /// Converts this response into a mapping of api key to the
+    /// `(min, max)` range of versions supported by the broker.
+    pub fn into_versions(self) -> Result<HashMap<i16, (i16, i16)>> {
+        if let Some(e) = Error::from_protocol(self.error) {
+            return Err(e);
+        }
+        Ok(
+            self.api_versions
+                .into_iter()
+                .map(|v| (v.api_key, (v.min_version, v.max_version)))
+                .collect(),
+        )
+    }
+}
+
+impl FromByte for ApiVersionsResponse {
+    type R = ApiVersionsResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.api_versions.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for ApiVersion {
+    type R = ApiVersion;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.api_key.decode(buffer),
+            self.min_version.decode(buffer),
+            self.max_version.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// Determines the highest version of the api identified by `api_key`
+/// supported by both, the broker (as described by `versions`) and
+/// this library (as described by the inclusive range `ours`.)
+/// Returns `None` if there is no such version.
+pub fn select_version(
+    versions: &HashMap<i16, (i16, i16)>,
+    api_key: i16,
+    ours: (i16, i16),
+) -> Option<i16> {
+    versions.get(&api_key).and_then(|&(min, max)| {
+        let v = if max < ours.1 { max } else { ours.1 };
+        if v < min || v < ours.0 { None } else { Some(v) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use codecs::FromByte;
+    use error::{Error, ErrorKind, KafkaCode};
+    use super::{ApiVersionsResponse, select_version};
+
+    #[test]
+    fn test_decode_api_versions_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 7, // correlation
+            0, 0, // error
+            0, 0, 0, 2, // array length
+            0, 0, 0, 0, 0, 3, // produce: 0..3
+            0, 1, 0, 0, 0, 5, // fetch: 0..5
+        ];
+        let resp = ApiVersionsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(7, resp.header.correlation);
+        let versions = resp.into_versions().unwrap();
+        assert_eq!(2, versions.len());
+        assert_eq!(Some(&(0, 3)), versions.get(&0));
+        assert_eq!(Some(&(0, 5)), versions.get(&1));
+    }
+
+    #[test]
+    fn test_decode_api_versions_response_error() {
+        static DATA: &'static [u8] = &[0, 0, 0, 1, 0, 35, 0, 0, 0, 0];
+        let resp = ApiVersionsResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        match resp.into_versions() {
+            Err(Error(ErrorKind::Kafka(KafkaCode::UnsupportedVersion), _)) => {}
+            r => panic!("Expected UnsupportedVersion, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_select_version() {
+        let mut versions = HashMap::new();
+        versions.insert(0, (0, 7));
+        versions.insert(1, (0, 1));
+        versions.insert(3, (2, 5));
+        assert_eq!(Some(3), select_version(&versions, 0, (0, 3)));
+        assert_eq!(Some(1), select_version(&versions, 1, (0, 4)));
+        assert_eq!(None, select_version(&versions, 3, (0, 1)));
+        assert_eq!(None, select_version(&versions, 2, (0, 0)));
+    }
+}
@@ -1,7 +1,7 @@
 use std::io::{Read, Write};
 
-use codecs::{self, ToByte, FromByte};
-use error::{self, Error, ErrorKind, Result, KafkaCode};
+use codecs::{self, decode_string_field, ToByte, FromByte};
+use error::{Error, ErrorKind, Result, KafkaCode};
 use utils::PartitionOffset;
 
 use super::{HeaderRequest, HeaderResponse};
@@ -232,7 +232,10 @@ impl FromByte for TopicPartitionOffsetFetchResponse {
     type R = TopicPartitionOffsetFetchResponse;
 
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
-        try_multi!(self.topic.decode(buffer), self.partitions.decode(buffer))
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
     }
 }
 
@@ -243,7 +246,7 @@ impl FromByte for PartitionOffsetFetchResponse {
         try_multi!(
             self.partition.decode(buffer),
             self.offset.decode(buffer),
-            self.metadata.decode(buffer),
+            decode_string_field(&mut self.metadata, buffer, "metadata"),
             self.error.decode(buffer)
         )
     }
@@ -278,6 +281,10 @@ impl OffsetCommitVersion {
 pub struct OffsetCommitRequest<'a, 'b> {
     pub header: HeaderRequest<'a>,
     pub group: &'b str,
+    /// The time in milliseconds after which the committed offsets
+    /// expire; -1 to apply the broker's configured retention.  Sent
+    /// only by `OffsetCommitVersion::V2`.
+    pub retention_time: i64,
     pub topic_partitions: Vec<TopicPartitionOffsetCommitRequest<'b>>,
 }
 
@@ -309,6 +316,7 @@ impl<'a, 'b> OffsetCommitRequest<'a, 'b> {
                 client_id,
             ),
             group: group,
+            retention_time: -1,
             topic_partitions: vec![],
         }
     }
@@ -366,7 +374,7 @@ impl<'a, 'b> ToByte for OffsetCommitRequest<'a, 'b> {
             OffsetCommitVersion::V2 => {
                 try!((-1i32).encode(buffer));
                 try!("".encode(buffer));
-                try!((-1i64).encode(buffer));
+                try!(self.retention_time.encode(buffer));
             }
             _ => {
                 // nothing to do
@@ -414,7 +422,10 @@ impl FromByte for TopicPartitionOffsetCommitResponse {
     type R = TopicPartitionOffsetCommitResponse;
 
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
-        try_multi!(self.topic.decode(buffer), self.partitions.decode(buffer))
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
     }
 }
 
@@ -425,8 +436,8 @@ pub struct PartitionOffsetCommitResponse {
 }
 
 impl PartitionOffsetCommitResponse {
-    pub fn to_error(&self) -> Option<error::KafkaCode> {
-        error::KafkaCode::from_protocol(self.error)
+    pub fn to_error(&self) -> Option<Error> {
+        Error::from_protocol(self.error)
     }
 }
 
@@ -437,3 +448,37 @@ impl FromByte for PartitionOffsetCommitResponse {
         try_multi!(self.partition.decode(buffer), self.error.decode(buffer))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use codecs::ToByte;
+    use super::{OffsetCommitRequest, OffsetCommitVersion};
+
+    #[test]
+    fn test_encode_offset_commit_request_retention_time() {
+        let mut req = OffsetCommitRequest::new("g", OffsetCommitVersion::V2, 1, "c");
+        req.retention_time = 86_400_000;
+        req.add("t", 0, 5, "");
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        assert_eq!(
+            &[
+                0, 8, 0, 2, 0, 0, 0, 1, 0, 1, b'c', // header
+                0, 1, b'g', // group
+                255, 255, 255, 255, // generation id
+                0, 0, // member id
+                0, 0, 0, 0, 5, 38, 92, 0, // retention time
+                0, 0, 0, 1, 0, 1, b't', // topics
+                0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, // partitions
+            ][..],
+            &buf[..]
+        );
+
+        // ~ the broker's configured retention by default
+        let mut req = OffsetCommitRequest::new("g", OffsetCommitVersion::V2, 1, "c");
+        req.add("t", 0, 5, "");
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        assert_eq!(&[255; 8], &buf[20..28]);
+    }
+}
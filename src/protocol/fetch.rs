@@ -3,31 +3,75 @@
 use std::borrow::Cow;
 use std::io::Write;
 use std::mem;
+use std::str;
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
 
 use fnv::FnvHasher;
 
-use codecs::ToByte;
-use error::{Error, ErrorKind, KafkaCode, Result};
+use codecs::{crc32c, ToByte};
+use error::{Error, ErrorKind, Result};
 use compression::Compression;
 #[cfg(feature = "gzip")]
 use compression::gzip;
 #[cfg(feature = "snappy")]
-use compression::snappy::SnappyReader;
+use compression::snappy;
+#[cfg(feature = "lz4")]
+use compression::lz4;
+#[cfg(feature = "zstd")]
+use compression::zstd;
 
-use super::{HeaderRequest, API_KEY_FETCH, API_VERSION};
+use super::{HeaderRequest, ResponseBuffer, API_KEY_FETCH, API_VERSION};
 use super::zreader::ZReader;
 use super::to_crc;
 
 pub type PartitionHasher = BuildHasherDefault<FnvHasher>;
 
+/// The version of the fetch API required to receive messages in the
+/// message format v1 (i.e. with the magic byte 1) without the broker
+/// converting them down to v0.
+const API_VERSION_MESSAGE_V1: i16 = 2;
+
+/// The version of the fetch API required to receive record batches
+/// (i.e. messages in the format v2) without the broker converting them
+/// down to an older format.
+const API_VERSION_MESSAGE_V2: i16 = 4;
+
+/// The newest version of the fetch API supported by this library.
+pub const MAX_API_VERSION: i16 = API_VERSION_MESSAGE_V2;
+
+/// The version of the fetch API required to fetch as a consumer from
+/// a follower replica (KIP-392, Kafka 2.4); brokers serve older
+/// versions only for the partitions they lead.  Used solely by
+/// `KafkaClient::fetch_from_replica`.
+pub const API_VERSION_FOLLOWER_FETCH: i16 = 11;
+
+/// The isolation level delivering all messages.
+pub const ISOLATION_READ_UNCOMMITTED: i8 = 0;
+
+/// The isolation level delivering only the messages of committed
+/// transactions.
+pub const ISOLATION_READ_COMMITTED: i8 = 1;
+
+/// Determines the newest message format which can be received through
+/// the given version of the fetch API.
+pub fn max_message_version(api_version: i16) -> i8 {
+    match api_version {
+        v if v >= API_VERSION_MESSAGE_V2 => 2,
+        v if v >= API_VERSION_MESSAGE_V1 => 1,
+        _ => 0,
+    }
+}
+
 #[derive(Debug)]
 pub struct FetchRequest<'a, 'b> {
     pub header: HeaderRequest<'a>,
     pub replica: i32,
     pub max_wait_time: i32,
     pub min_bytes: i32,
+    // ~ 0 for "read uncommitted", 1 for "read committed"; transmitted
+    // as of api version 4 (i.e. the message format v2)
+    pub isolation_level: i8,
     // topic -> partitions
     pub topic_partitions: HashMap<&'b str, TopicPartitionFetchRequest>,
 }
@@ -50,16 +94,30 @@ impl<'a, 'b> FetchRequest<'a, 'b> {
         client_id: &'a str,
         max_wait_time: i32,
         min_bytes: i32,
+        message_version: i8,
     ) -> FetchRequest<'a, 'b> {
+        let api_version = match message_version {
+            v if v >= 2 => API_VERSION_MESSAGE_V2,
+            1 => API_VERSION_MESSAGE_V1,
+            _ => API_VERSION,
+        };
         FetchRequest {
-            header: HeaderRequest::new(API_KEY_FETCH, API_VERSION, correlation_id, client_id),
+            header: HeaderRequest::new(API_KEY_FETCH, api_version, correlation_id, client_id),
             replica: -1,
             max_wait_time: max_wait_time,
             min_bytes: min_bytes,
+            isolation_level: ISOLATION_READ_UNCOMMITTED,
             topic_partitions: HashMap::new(),
         }
     }
 
+    /// Sets the isolation level of this request; see
+    /// `client::IsolationLevel`.
+    pub fn with_isolation_level(mut self, isolation_level: i8) -> Self {
+        self.isolation_level = isolation_level;
+        self
+    }
+
     pub fn add(&mut self, topic: &'b str, partition: i32, offset: i64, max_bytes: i32) {
         self.topic_partitions
             .entry(topic)
@@ -104,34 +162,62 @@ impl<'a, 'b> ToByte for FetchRequest<'a, 'b> {
         try!(self.replica.encode(buffer));
         try!(self.max_wait_time.encode(buffer));
         try!(self.min_bytes.encode(buffer));
+        let api_version = self.header.api_version;
+        if api_version >= API_VERSION_MESSAGE_V2 {
+            // ~ the maximum bytes of the whole response; effectively
+            // limited by the maximum bytes per partition
+            try!(i32::MAX.encode(buffer));
+            try!(self.isolation_level.encode(buffer));
+        }
+        if api_version >= 7 {
+            // ~ session id and epoch; a full fetch outside of any
+            // fetch session (KIP-227)
+            try!((0i32).encode(buffer));
+            try!((-1i32).encode(buffer));
+        }
         // encode the hashmap as a vector
         try!((self.topic_partitions.len() as i32).encode(buffer));
         for (name, tp) in self.topic_partitions.iter() {
-            try!(tp.encode(name, buffer));
+            try!(tp.encode(name, api_version, buffer));
+        }
+        if api_version >= 7 {
+            // ~ the partitions to remove from the fetch session
+            try!((0i32).encode(buffer));
+        }
+        if api_version >= API_VERSION_FOLLOWER_FETCH {
+            // ~ the rack of this client; only consulted by leaders
+            // selecting a preferred read replica
+            try!("".encode(buffer));
         }
         Ok(())
     }
 }
 
 impl TopicPartitionFetchRequest {
-    fn encode<W: Write>(&self, topic: &str, buffer: &mut W) -> Result<()> {
+    fn encode<W: Write>(&self, topic: &str, api_version: i16, buffer: &mut W) -> Result<()> {
         try!(topic.encode(buffer));
         // encode the hashmap as a vector
         try!((self.partitions.len() as i32).encode(buffer));
         for (&pid, p) in self.partitions.iter() {
-            try!(p.encode(pid, buffer));
+            try!(p.encode(pid, api_version, buffer));
         }
         Ok(())
     }
 }
 
 impl PartitionFetchRequest {
-    fn encode<T: Write>(&self, partition: i32, buffer: &mut T) -> Result<()> {
-        try_multi!(
-            partition.encode(buffer),
-            self.offset.encode(buffer),
-            self.max_bytes.encode(buffer)
-        )
+    fn encode<T: Write>(&self, partition: i32, api_version: i16, buffer: &mut T) -> Result<()> {
+        try!(partition.encode(buffer));
+        if api_version >= 9 {
+            // ~ the current leader epoch; not validated
+            try!((-1i32).encode(buffer));
+        }
+        try!(self.offset.encode(buffer));
+        if api_version >= 5 {
+            // ~ the log start offset; relevant to followers only
+            try!((-1i64).encode(buffer));
+        }
+        self.max_bytes.encode(buffer)
     }
 }
 
@@ -143,13 +229,21 @@ where
     'b: 'c,
 {
     pub validate_crc: bool,
+    // ~ the max. depth to which message sets may be nested; see
+    // `DEFAULT_MAX_MESSAGE_SET_DEPTH`
+    pub max_message_set_depth: u32,
     pub requests: Option<&'c FetchRequest<'a, 'b>>,
 }
 
 impl<'a, 'b, 'c> super::ResponseParser for ResponseParser<'a, 'b, 'c> {
     type T = Response;
-    fn parse(&self, response: Vec<u8>) -> Result<Self::T> {
-        Response::from_vec(response, self.requests, self.validate_crc)
+    fn parse(&self, response: ResponseBuffer) -> Result<Self::T> {
+        Response::from_buffer(
+            response,
+            self.requests,
+            self.validate_crc,
+            self.max_message_set_depth,
+        )
     }
 }
 
@@ -171,35 +265,71 @@ macro_rules! array_of {
 /// partitions.
 #[derive(Debug)]
 pub struct Response {
-    // used to "own" the data all other references of this struct
-    // point to.
-    #[allow(dead_code)]
-    raw_data: Vec<u8>,
-
     correlation_id: i32,
 
+    throttle_time: i32,
+
     // ~ Static is used here to get around the fact that we don't want
     // Response have to a lifetime parameter as well.  The field is
     // exposed only through an accessor which binds the exposed
     // lifetime to the lifetime of the Response instance.
     topics: Vec<Topic<'static>>,
+
+    // used to "own" the data all other references of this struct
+    // point to.  declared last to be dropped - and thereby returned
+    // to its connection - only after the references.
+    #[allow(dead_code)]
+    raw_data: ResponseBuffer,
 }
 
 impl Response {
     /// Parses a Response from binary data as defined by the
     /// Kafka Protocol.
+    #[cfg(test)]
     fn from_vec(
         response: Vec<u8>,
         reqs: Option<&FetchRequest>,
         validate_crc: bool,
+    ) -> Result<Response> {
+        Response::from_buffer(
+            ResponseBuffer::from(response),
+            reqs,
+            validate_crc,
+            DEFAULT_MAX_MESSAGE_SET_DEPTH,
+        )
+    }
+
+    /// Parses a Response from binary data as defined by the Kafka
+    /// Protocol keeping the buffer holding the data.
+    fn from_buffer(
+        response: ResponseBuffer,
+        reqs: Option<&FetchRequest>,
+        validate_crc: bool,
+        max_depth: u32,
     ) -> Result<Response> {
         let slice = unsafe { mem::transmute(&response[..]) };
         let mut r = ZReader::new(slice);
         let correlation_id = try!(r.read_i32());
-        let topics = array_of!(r, Topic::read(&mut r, reqs, validate_crc));
+        let api_version = reqs.map(|reqs| reqs.header.api_version).unwrap_or(API_VERSION);
+        let throttle_time = if api_version >= 1 {
+            try!(r.read_i32())
+        } else {
+            0
+        };
+        if api_version >= 7 {
+            // ~ the error of the request as a whole and the id of the
+            // fetch session; no session is ever requested
+            let error = try!(r.read_i16());
+            let _session_id = try!(r.read_i32());
+            if let Some(e) = Error::from_protocol(error) {
+                return Err(e);
+            }
+        }
+        let topics = array_of!(r, Topic::read(&mut r, reqs, validate_crc, max_depth));
         Ok(Response {
             raw_data: response,
             correlation_id: correlation_id,
+            throttle_time: throttle_time,
             topics: topics,
         })
     }
@@ -211,6 +341,14 @@ impl Response {
         self.correlation_id
     }
 
+    /// Retrieves the duration in milliseconds for which the broker
+    /// throttled the fetch request due to a quota violation.  Brokers
+    /// report this as of version 1 of the fetch api; zero otherwise.
+    #[inline]
+    pub fn throttle_time(&self) -> i32 {
+        self.throttle_time
+    }
+
     /// Provides an iterator over all the topics and the fetched data
     /// relative to these topics.
     #[inline]
@@ -219,6 +357,40 @@ impl Response {
     }
 }
 
+/// Removes the data of the given topic partitions from the given
+/// response.  Used to replace partitions with their data re-fetched
+/// from another broker.
+pub fn remove_partitions(resp: &mut Response, tps: &[(String, i32)]) {
+    for t in &mut resp.topics {
+        let topic = t.topic;
+        t.partitions.retain(|p| !tps.iter().any(|&(ref tt, tp)| tt == topic && tp == p.partition));
+    }
+}
+
+/// Decodes the given bytes as a fetch response of the given version
+/// of the fetch api without the need for a broker connection.  The
+/// bytes are expected in the form received from the broker, omitting
+/// the leading 4 byte size field.
+///
+/// This is the entry point for fuzzing the protocol decoder (see the
+/// `fuzz` directory of the repository.)  Arbitrary input is rejected
+/// with an error - typically `ErrorKind::CodecError` or
+/// `ErrorKind::UnexpectedEOF` - but never results in a panic.  To let
+/// mutated input reach the decoding of the messages, crc checksums
+/// are not validated and aborted transactions are skipped as for a
+/// "read committed" fetch.
+pub fn decode_fetch_response(bytes: &[u8], api_version: i16) -> Result<Response> {
+    let mut req = FetchRequest::new(0, "", 0, 0, 0)
+        .with_isolation_level(ISOLATION_READ_COMMITTED);
+    req.header.api_version = api_version;
+    Response::from_buffer(
+        ResponseBuffer::from(bytes.to_vec()),
+        Some(&req),
+        false,
+        DEFAULT_MAX_MESSAGE_SET_DEPTH,
+    )
+}
+
 /// The result of a "fetch messages" request from a particular Kafka
 /// broker for a single topic only.  Beside the name of the topic,
 /// this structure provides an iterator over the topic partitions from
@@ -234,10 +406,17 @@ impl<'a> Topic<'a> {
         r: &mut ZReader<'a>,
         reqs: Option<&FetchRequest>,
         validate_crc: bool,
+        max_depth: u32,
     ) -> Result<Topic<'a>> {
-        let name = try!(r.read_str());
+        let name = try!(r.read_str_field("topic name"));
         let preqs = reqs.and_then(|reqs| reqs.get(name));
-        let partitions = array_of!(r, Partition::read(r, preqs, validate_crc));
+        let api_version = reqs.map(|reqs| reqs.header.api_version).unwrap_or(API_VERSION);
+        let read_committed = api_version >= API_VERSION_MESSAGE_V2 &&
+            reqs.map(|reqs| reqs.isolation_level == ISOLATION_READ_COMMITTED).unwrap_or(false);
+        let partitions = array_of!(
+            r,
+            Partition::read(r, preqs, api_version, read_committed, validate_crc, max_depth)
+        );
         Ok(Topic {
             topic: name,
             partitions: partitions,
@@ -278,7 +457,10 @@ impl<'a> Partition<'a> {
     fn read(
         r: &mut ZReader<'a>,
         preqs: Option<&TopicPartitionFetchRequest>,
+        api_version: i16,
+        read_committed: bool,
         validate_crc: bool,
+        max_depth: u32,
     ) -> Result<Partition<'a>> {
         let partition = try!(r.read_i32());
         let proffs = preqs
@@ -289,7 +471,36 @@ impl<'a> Partition<'a> {
         // we need to parse the rest even if there was an error to
         // consume the input stream (zreader)
         let highwatermark = try!(r.read_i64());
-        let msgset = try!(MessageSet::from_slice(try!(r.read_bytes()), proffs, validate_crc));
+        let mut last_stable_offset = -1;
+        let mut aborted = Vec::new();
+        if api_version >= API_VERSION_MESSAGE_V2 {
+            last_stable_offset = try!(r.read_i64());
+            if api_version >= 5 {
+                let _log_start_offset = try!(r.read_i64());
+            }
+            // ~ reported by the brokers for "read committed" fetches
+            // only
+            let n_aborted = try!(r.read_array_len());
+            for _ in 0..n_aborted {
+                let producer_id = try!(r.read_i64());
+                aborted.push((try!(r.read_i64()), producer_id));
+            }
+            if api_version >= API_VERSION_FOLLOWER_FETCH {
+                // ~ the replica a leader suggests to fetch from
+                // instead; never suggested to a follower
+                let _preferred_read_replica = try!(r.read_i32());
+            }
+        }
+        let mut msgset = try!(MessageSet::from_slice_committed(
+            try!(r.read_bytes()),
+            proffs,
+            validate_crc,
+            &mut AbortedTransactions::new(aborted),
+            max_depth,
+        ));
+        if read_committed && last_stable_offset >= 0 {
+            msgset.messages.retain(|m| m.offset < last_stable_offset);
+        }
         Ok(Partition {
             partition: partition,
             data: match error {
@@ -297,6 +508,7 @@ impl<'a> Partition<'a> {
                 None => {
                     Ok(Data {
                         highwatermark_offset: highwatermark,
+                        last_stable_offset: last_stable_offset,
                         message_set: msgset,
                     })
                 }
@@ -320,6 +532,7 @@ impl<'a> Partition<'a> {
 #[derive(Debug)]
 pub struct Data<'a> {
     highwatermark_offset: i64,
+    last_stable_offset: i64,
     message_set: MessageSet<'a>,
 }
 
@@ -333,11 +546,50 @@ impl<'a> Data<'a> {
         self.highwatermark_offset
     }
 
+    /// Retrieves the "last stable offset" of this partition, i.e. the
+    /// offset up to which all transactions have been completed; `-1`
+    /// if not reported by the broker (prior to the message format
+    /// v2.)  Fetching with `IsolationLevel::ReadCommitted` delivers
+    /// messages only up to this offset.
+    #[inline]
+    pub fn last_stable_offset(&self) -> i64 {
+        self.last_stable_offset
+    }
+
+    /// Retrieves the offset following the last complete message (or
+    /// record batch) of the fetched data; `None` if the data contains
+    /// no complete message.  Unlike the offset following the last
+    /// delivered message, this accounts also for fetched data not
+    /// delivered as messages, e.g. transaction markers or messages of
+    /// aborted transactions.  Fetching as of this offset continues
+    /// right after the fetched data.
+    #[inline]
+    pub fn next_offset(&self) -> Option<i64> {
+        if self.message_set.next_offset < 0 {
+            None
+        } else {
+            Some(self.message_set.next_offset)
+        }
+    }
+
     /// Retrieves the fetched message data for this partition.
     #[inline]
     pub fn messages(&self) -> &[Message<'a>] {
         &self.message_set.messages
     }
+
+    /// Determines whether the fetched data ended with an incomplete
+    /// message.  Kafka delivers message data only up to the
+    /// requested maximum number of bytes per partition, so this is
+    /// usual for the last message of a chunk.  However, if
+    /// `messages()` is empty while this is `true`, the next message
+    /// in the partition is larger than the requested maximum and the
+    /// fetch request needs to be repeated with a higher limit (see
+    /// `FetchPartition::with_max_bytes`) to make progress.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.message_set.truncated
+    }
 }
 
 #[derive(Debug)]
@@ -345,6 +597,16 @@ struct MessageSet<'a> {
     #[allow(dead_code)]
     raw_data: Cow<'a, [u8]>, // ~ this field is used to potentially "own" the underlying vector
     messages: Vec<Message<'a>>,
+    // ~ the message sets decoded from the uncompressed data of
+    // compressed messages (or record batches); these "own" the data
+    // some of the `messages` point to
+    #[allow(dead_code)]
+    inner: Vec<MessageSet<'a>>,
+    // ~ whether the raw data ended with an incomplete message
+    truncated: bool,
+    // ~ the offset following the last complete entry of the raw
+    // data; -1 if there is none
+    next_offset: i64,
 }
 
 /// A fetched message from a remote Kafka broker for a particular
@@ -362,74 +624,216 @@ pub struct Message<'a> {
     /// The value data of this message.  Empty if there is no such
     /// data for this message.
     pub value: &'a [u8],
+
+    /// The timestamp of this message in milliseconds since the unix
+    /// epoch; `-1` if the message carries no timestamp (i.e. it was
+    /// delivered in the message format v0.)  Depending on the
+    /// configuration of its topic, this is either the time the
+    /// message was created by its producer or the time it was
+    /// appended to the log by the broker.
+    pub timestamp: i64,
+
+    /// The headers - key/value pairs - of this message.  Always
+    /// empty unless the message was delivered in the message format
+    /// v2.
+    pub headers: Vec<(&'a str, &'a [u8])>,
 }
 
+impl<'a> Message<'a> {
+    /// Retrieves the offset at which this message resides in its
+    /// topic partition.
+    #[inline]
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// Retrieves the timestamp of this message in milliseconds since
+    /// the unix epoch; `-1` if not available.
+    #[inline]
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    /// Retrieves the headers of this message; see `Message::headers`.
+    #[inline]
+    pub fn headers(&self) -> &[(&'a str, &'a [u8])] {
+        &self.headers
+    }
+}
+
+/// The timestamp of messages not carrying one.
+const NO_TIMESTAMP: i64 = -1;
+
+/// The bit of a message's attributes denoting its timestamp to be
+/// the log append time (as opposed to the create time.)
+const TIMESTAMP_TYPE_LOG_APPEND_TIME: i8 = 0x08;
+
+/// The magic byte identifying record batches (i.e. the message
+/// format v2.)
+const MAGIC_RECORD_BATCH: i8 = 2;
+
+/// The bit of a record batch's attributes denoting the batch to carry
+/// control records (e.g. transaction markers) instead of user data.
+const BATCH_CONTROL: i16 = 0x20;
+
+/// The bit of a record batch's attributes denoting the batch to be
+/// part of a transaction.
+const BATCH_TRANSACTIONAL: i16 = 0x10;
+
+/// The type of the control record marking a transaction as aborted.
+const CONTROL_TYPE_ABORT: i16 = 0;
+
+/// The default max. depth to which message sets may be nested inside
+/// compressed messages (see `KafkaClient::set_max_message_set_depth`.)
+/// Kafka nests them exactly one level deep; deeper nesting is found
+/// only in malformed data and is rejected with `ErrorKind::CodecError`
+/// rather than recursing without bound.
+pub const DEFAULT_MAX_MESSAGE_SET_DEPTH: u32 = 2;
+
 impl<'a> MessageSet<'a> {
     #[allow(dead_code)]
-    fn from_vec(data: Vec<u8>, req_offset: i64, validate_crc: bool) -> Result<MessageSet<'a>> {
+    fn from_vec(
+        data: Vec<u8>,
+        req_offset: i64,
+        validate_crc: bool,
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<MessageSet<'a>> {
         // since we're going to keep the original
         // uncompressed vector around without
         // further modifying it and providing
         // publicly no mutability possibilities
         // this is safe
-        let ms = try!(MessageSet::from_slice(
+        let ms = try!(MessageSet::from_slice_nested(
             unsafe { mem::transmute(&data[..]) },
             req_offset,
             validate_crc,
+            &mut AbortedTransactions::new(Vec::new()),
+            depth,
+            max_depth,
         ));
         return Ok(MessageSet {
             raw_data: Cow::Owned(data),
             messages: ms.messages,
+            inner: ms.inner,
+            truncated: ms.truncated,
+            next_offset: ms.next_offset,
         });
     }
 
+    // ~ decodes a message set outside of any transaction; used by the
+    // tests only
+    #[cfg(test)]
     fn from_slice<'b>(
         raw_data: &'b [u8],
         req_offset: i64,
         validate_crc: bool,
+    ) -> Result<MessageSet<'b>> {
+        let mut aborted = AbortedTransactions::new(Vec::new());
+        MessageSet::from_slice_committed(
+            raw_data,
+            req_offset,
+            validate_crc,
+            &mut aborted,
+            DEFAULT_MAX_MESSAGE_SET_DEPTH,
+        )
+    }
+
+    /// Decodes the given message set skipping the record batches of
+    /// the given aborted transactions.  Message sets nested deeper
+    /// than `max_depth` are rejected.
+    fn from_slice_committed<'b>(
+        raw_data: &'b [u8],
+        req_offset: i64,
+        validate_crc: bool,
+        aborted: &mut AbortedTransactions,
+        max_depth: u32,
+    ) -> Result<MessageSet<'b>> {
+        MessageSet::from_slice_nested(raw_data, req_offset, validate_crc, aborted, 0, max_depth)
+    }
+
+    /// Decodes the message set found at the given nesting `depth`,
+    /// i.e. inside `depth` compressed messages.
+    fn from_slice_nested<'b>(
+        raw_data: &'b [u8],
+        req_offset: i64,
+        validate_crc: bool,
+        aborted: &mut AbortedTransactions,
+        depth: u32,
+        max_depth: u32,
     ) -> Result<MessageSet<'b>> {
         let mut r = ZReader::new(raw_data);
         let mut msgs = Vec::new();
+        let mut inner = Vec::new();
+        let mut truncated = false;
+        let mut next_offset = -1;
         while !r.is_empty() {
-            match MessageSet::next_message(&mut r, validate_crc) {
+            match MessageSet::next_entry(&mut r, validate_crc) {
                 // this is the last messages which might be
                 // incomplete; a valid case to be handled by
                 // consumers
                 Err(Error(ErrorKind::UnexpectedEOF, _)) => {
+                    truncated = true;
                     break;
                 }
                 Err(e) => {
                     return Err(e);
                 }
-                Ok((offset, pmsg)) => {
+                Ok(Entry::Message(offset, pmsg)) => {
+                    // ~ the offset of a compressed message is the one
+                    // of its last inner message
+                    next_offset = offset.wrapping_add(1);
                     // handle compression (denoted by the last 3 bits
                     // of the attr field)
-                    match pmsg.attr & 0x07 {
-                        c if c == Compression::NONE as i8 => {
-                            // skip messages with a lower offset
-                            // than the request one
-                            if offset >= req_offset {
-                                msgs.push(Message {
-                                    offset: offset,
-                                    key: pmsg.key,
-                                    value: pmsg.value,
-                                });
-                            }
+                    let compression = pmsg.attr & 0x07;
+                    if compression == Compression::NONE as i8 {
+                        // skip messages with a lower offset than the
+                        // request one
+                        if offset >= req_offset {
+                            msgs.push(Message {
+                                offset: offset,
+                                key: pmsg.key,
+                                value: pmsg.value,
+                                timestamp: pmsg.timestamp,
+                                headers: Vec::new(),
+                            });
                         }
-                        // XXX handle recursive compression in future
-                        #[cfg(feature = "gzip")]
-                        c if c == Compression::GZIP as i8 => {
-                            let v = try!(gzip::uncompress(pmsg.value));
-                            return Ok(try!(MessageSet::from_vec(v, req_offset, validate_crc)));
+                    } else {
+                        if depth >= max_depth {
+                            warn!(
+                                "Rejecting message set nested deeper than {} levels at offset {}",
+                                max_depth,
+                                offset
+                            );
+                            bail!(ErrorKind::CodecError);
                         }
-                        #[cfg(feature = "snappy")]
-                        c if c == Compression::SNAPPY as i8 => {
-                            use std::io::Read;
-                            let mut v = Vec::new();
-                            try!(try!(SnappyReader::new(pmsg.value)).read_to_end(&mut v));
-                            return Ok(try!(MessageSet::from_vec(v, req_offset, validate_crc)));
-                        }
-                        _ => bail!(ErrorKind::UnsupportedCompression),
+                        let v = try!(uncompress(compression, pmsg.value));
+                        let mut ms = try!(MessageSet::from_compressed(
+                            v,
+                            offset,
+                            &pmsg,
+                            req_offset,
+                            validate_crc,
+                            depth + 1,
+                            max_depth,
+                        ));
+                        msgs.extend(ms.messages.drain(..));
+                        inner.push(ms);
+                    }
+                }
+                Ok(Entry::Batch(batch)) => {
+                    next_offset = batch.last_offset().wrapping_add(1);
+                    if aborted.is_aborted(&batch) {
+                        continue;
+                    }
+                    let compression = (batch.attr & 0x07) as i8;
+                    if compression == Compression::NONE as i8 {
+                        try!(batch.read_records(batch.records, req_offset, &mut msgs));
+                    } else {
+                        let v = try!(uncompress(compression, batch.records));
+                        let mut ms = try!(MessageSet::from_records(v, &batch, req_offset));
+                        msgs.extend(ms.messages.drain(..));
+                        inner.push(ms);
                     }
                 }
             };
@@ -437,64 +841,368 @@ impl<'a> MessageSet<'a> {
         Ok(MessageSet {
             raw_data: Cow::Borrowed(raw_data),
             messages: msgs,
+            inner: inner,
+            truncated: truncated,
+            next_offset: next_offset,
         })
     }
 
-    fn next_message<'b>(
-        r: &mut ZReader<'b>,
+    /// Decodes the given uncompressed data of the wrapper message
+    /// `wrapper` residing at `wrapper_offset`.  The inner messages of
+    /// a v1 wrapper carry offsets relative to the wrapper's last
+    /// message; these are translated into absolute offsets.  `depth`
+    /// denotes the nesting depth of the inner message set.
+    #[allow(dead_code)]
+    fn from_compressed(
+        data: Vec<u8>,
+        wrapper_offset: i64,
+        wrapper: &ProtocolMessage,
+        req_offset: i64,
         validate_crc: bool,
-    ) -> Result<(i64, ProtocolMessage<'b>)> {
+        depth: u32,
+        max_depth: u32,
+    ) -> Result<MessageSet<'a>> {
+        let mut ms = try!(MessageSet::from_vec(data, i64::MIN, validate_crc, depth, max_depth));
+        if wrapper.magic >= 1 {
+            let delta = ms.messages
+                .last()
+                .map(|m| wrapper_offset.wrapping_sub(m.offset))
+                .unwrap_or(0);
+            let log_append_time = wrapper.attr & TIMESTAMP_TYPE_LOG_APPEND_TIME != 0;
+            for m in &mut ms.messages {
+                m.offset = m.offset.wrapping_add(delta);
+                if log_append_time {
+                    m.timestamp = wrapper.timestamp;
+                }
+            }
+        }
+        // skip messages with a lower offset than the request one
+        ms.messages.retain(|m| m.offset >= req_offset);
+        Ok(ms)
+    }
+
+    /// Decodes the given uncompressed records of the record batch
+    /// `batch`.
+    fn from_records(data: Vec<u8>, batch: &RecordBatch, req_offset: i64) -> Result<MessageSet<'a>> {
+        let mut msgs = Vec::new();
+        // see `MessageSet::from_vec`
+        try!(batch.read_records(
+            unsafe { mem::transmute(&data[..]) },
+            req_offset,
+            &mut msgs,
+        ));
+        Ok(MessageSet {
+            raw_data: Cow::Owned(data),
+            messages: msgs,
+            inner: Vec::new(),
+            truncated: false,
+            next_offset: -1,
+        })
+    }
+
+    /// Reads the next entry from the given reader.  Fails with
+    /// `ErrorKind::UnexpectedEOF` only if the reader ends before the
+    /// entry does, i.e. if the entry was truncated by the broker.
+    fn next_entry<'b>(r: &mut ZReader<'b>, validate_crc: bool) -> Result<Entry<'b>> {
         let offset = try!(r.read_i64());
-        let msg_data = try!(r.read_bytes());
-        Ok((offset, try!(ProtocolMessage::from_slice(msg_data, validate_crc))))
+        let data = try!(r.read_bytes());
+        // ~ the magic byte resides at the same position in messages
+        // and record batches; preceded either by the message's crc or
+        // the batch's partition leader epoch
+        let entry = if data.len() > 4 && data[4] as i8 == MAGIC_RECORD_BATCH {
+            RecordBatch::from_slice(data, offset, validate_crc).map(Entry::Batch)
+        } else {
+            ProtocolMessage::from_slice(data, offset, validate_crc)
+                .map(|pmsg| Entry::Message(offset, pmsg))
+        };
+        // ~ the entry's data is complete; running out of it denotes a
+        // malformed entry rather than a truncated one
+        match entry {
+            Err(Error(ErrorKind::UnexpectedEOF, _)) => bail!(ErrorKind::CodecError),
+            entry => entry,
+        }
+    }
+}
+
+/// Uncompresses the data of a message or a record batch compressed
+/// using the given codec (as denoted by their attributes.)
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "snappy", feature = "lz4", feature = "zstd")),
+    allow(unused_variables)
+)]
+fn uncompress(compression: i8, data: &[u8]) -> Result<Vec<u8>> {
+    match compression {
+        #[cfg(feature = "gzip")]
+        c if c == Compression::GZIP as i8 => gzip::uncompress(data),
+        #[cfg(feature = "snappy")]
+        c if c == Compression::SNAPPY as i8 => snappy::uncompress(data),
+        #[cfg(feature = "lz4")]
+        c if c == Compression::LZ4 as i8 => lz4::uncompress(data),
+        #[cfg(feature = "zstd")]
+        c if c == Compression::ZSTD as i8 => zstd::uncompress(data),
+        _ => bail!(ErrorKind::UnsupportedCompression),
     }
 }
 
+/// An entry of a message set; either a message of the format v0/v1
+/// residing at the given offset or a record batch.
+enum Entry<'a> {
+    Message(i64, ProtocolMessage<'a>),
+    Batch(RecordBatch<'a>),
+}
+
 /// Represents a messages exactly as defined in the protocol.
 struct ProtocolMessage<'a> {
+    magic: i8,
     attr: i8,
+    timestamp: i64,
     key: &'a [u8],
     value: &'a [u8],
 }
 
 impl<'a> ProtocolMessage<'a> {
-    /// Parses a raw message from the given byte slice.  Does _not_
-    /// handle any compression.
-    fn from_slice<'b>(raw_data: &'b [u8], validate_crc: bool) -> Result<ProtocolMessage<'b>> {
+    /// Parses a raw message residing at `offset` from the given byte
+    /// slice.  Does _not_ handle any compression.
+    fn from_slice<'b>(
+        raw_data: &'b [u8],
+        offset: i64,
+        validate_crc: bool,
+    ) -> Result<ProtocolMessage<'b>> {
         let mut r = ZReader::new(raw_data);
 
         // ~ optionally validate the crc checksum
         let msg_crc = try!(r.read_i32());
         if validate_crc && to_crc(r.rest()) as i32 != msg_crc {
-            bail!(ErrorKind::Kafka(KafkaCode::CorruptMessage));
+            bail!(ErrorKind::CrcMismatch(offset));
         }
-        // ~ we support parsing only messages with the "zero"
-        // magic_byte; this covers kafka 0.8 and 0.9.
+        // ~ we support parsing messages with the "zero" magic_byte
+        // (kafka 0.8 and 0.9) and the "one" magic_byte (kafka 0.10)
+        // additionally carrying a timestamp
         let msg_magic = try!(r.read_i8());
-        if msg_magic != 0 {
+        if msg_magic != 0 && msg_magic != 1 {
             bail!(ErrorKind::UnsupportedProtocol);
         }
         let msg_attr = try!(r.read_i8());
+        let msg_timestamp = if msg_magic == 1 {
+            try!(r.read_i64())
+        } else {
+            NO_TIMESTAMP
+        };
         let msg_key = try!(r.read_bytes());
         let msg_val = try!(r.read_bytes());
-
-        debug_assert!(r.is_empty());
+        if !r.is_empty() {
+            bail!(ErrorKind::CodecError);
+        }
 
         Ok(ProtocolMessage {
+            magic: msg_magic,
             attr: msg_attr,
+            timestamp: msg_timestamp,
             key: msg_key,
             value: msg_val,
         })
     }
 }
 
+/// Represents a record batch (i.e. the message format v2 used by
+/// kafka 0.11 and later) exactly as defined in the protocol.
+struct RecordBatch<'a> {
+    base_offset: i64,
+    attr: i16,
+    last_offset_delta: i32,
+    first_timestamp: i64,
+    max_timestamp: i64,
+    producer_id: i64,
+    num_records: i32,
+    // ~ the raw, possibly compressed, records of the batch
+    records: &'a [u8],
+}
+
+impl<'a> RecordBatch<'a> {
+    /// Parses a raw record batch starting at `base_offset` from the
+    /// given byte slice (following the batch's length field.)  Does
+    /// _not_ decode the batch's records.
+    fn from_slice<'b>(
+        raw_data: &'b [u8],
+        base_offset: i64,
+        validate_crc: bool,
+    ) -> Result<RecordBatch<'b>> {
+        let mut r = ZReader::new(raw_data);
+
+        // ~ the partition leader epoch
+        try!(r.read_i32());
+        let magic = try!(r.read_i8());
+        debug_assert_eq!(MAGIC_RECORD_BATCH, magic);
+        // ~ optionally validate the crc checksum; unlike messages of
+        // the older formats, record batches use crc32c
+        let batch_crc = try!(r.read_i32());
+        if validate_crc && crc32c(r.rest()) as i32 != batch_crc {
+            bail!(ErrorKind::CrcMismatch(base_offset));
+        }
+        let attr = try!(r.read_i16());
+        let last_offset_delta = try!(r.read_i32());
+        let first_timestamp = try!(r.read_i64());
+        let max_timestamp = try!(r.read_i64());
+        let producer_id = try!(r.read_i64());
+        // ~ the producer epoch and base sequence
+        try!(r.read_i16());
+        try!(r.read_i32());
+        let num_records = try!(r.read_i32());
+        let records_len = r.rest().len();
+        let records = try!(r.read(records_len));
+
+        Ok(RecordBatch {
+            base_offset: base_offset,
+            attr: attr,
+            last_offset_delta: last_offset_delta,
+            first_timestamp: first_timestamp,
+            max_timestamp: max_timestamp,
+            producer_id: producer_id,
+            num_records: num_records,
+            records: records,
+        })
+    }
+
+    /// Retrieves the offset of the last record of this batch.
+    fn last_offset(&self) -> i64 {
+        self.base_offset.wrapping_add(self.last_offset_delta as i64)
+    }
+
+    /// Determines whether this is a control batch marking the
+    /// transaction of its producer as aborted.
+    fn is_abort_marker(&self) -> bool {
+        if self.attr & BATCH_CONTROL == 0 {
+            return false;
+        }
+        // ~ control batches hold a single, uncompressed record keyed
+        // by a version and the type of the control record
+        let control_type = || -> Result<i16> {
+            let mut r = ZReader::new(self.records);
+            // ~ the record's length, attributes, timestamp delta, and
+            // offset delta
+            try!(r.read_varint());
+            try!(r.read_i8());
+            try!(r.read_varlong());
+            try!(r.read_varint());
+            let mut key = ZReader::new(try!(r.read_varint_bytes()));
+            try!(key.read_i16());
+            key.read_i16()
+        };
+        control_type().map(|t| t == CONTROL_TYPE_ABORT).unwrap_or(false)
+    }
+
+    /// Decodes the given uncompressed records of this batch and
+    /// appends those residing at or after `req_offset` to `out`.  The
+    /// records of control batches are skipped.
+    fn read_records<'b>(
+        &self,
+        data: &'b [u8],
+        req_offset: i64,
+        out: &mut Vec<Message<'b>>,
+    ) -> Result<()> {
+        if self.attr & BATCH_CONTROL != 0 {
+            return Ok(());
+        }
+        let log_append_time = self.attr & TIMESTAMP_TYPE_LOG_APPEND_TIME as i16 != 0;
+        let mut r = ZReader::new(data);
+        for _ in 0..self.num_records {
+            let len = try!(r.read_varint());
+            if len < 0 {
+                bail!(ErrorKind::CodecError);
+            }
+            let mut rec = ZReader::new(try!(r.read(len as usize)));
+            // ~ the record attributes; unused
+            try!(rec.read_i8());
+            let timestamp_delta = try!(rec.read_varlong());
+            let offset_delta = try!(rec.read_varint());
+            let key = try!(rec.read_varint_bytes());
+            let value = try!(rec.read_varint_bytes());
+            let n_headers = try!(rec.read_varint());
+            // ~ each header occupies at least two bytes (the lengths
+            // of its key and value)
+            if n_headers > 0 && n_headers as usize > rec.rest().len() / 2 {
+                bail!(ErrorKind::CodecError);
+            }
+            let mut headers = Vec::with_capacity(if n_headers > 0 { n_headers as usize } else { 0 });
+            for _ in 0..n_headers {
+                let key = try!(str::from_utf8(try!(rec.read_varint_bytes())));
+                headers.push((key, try!(rec.read_varint_bytes())));
+            }
+
+            let offset = self.base_offset.wrapping_add(offset_delta as i64);
+            if offset >= req_offset {
+                out.push(Message {
+                    offset: offset,
+                    key: key,
+                    value: value,
+                    timestamp: if log_append_time {
+                        self.max_timestamp
+                    } else {
+                        self.first_timestamp.wrapping_add(timestamp_delta)
+                    },
+                    headers: headers,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the aborted transactions reported along with the fetched
+/// data of a partition in order to skip the record batches written
+/// by these transactions.
+struct AbortedTransactions {
+    // ~ the first offsets and producer ids of the aborted
+    // transactions not yet reached; sorted by descending offset
+    pending: Vec<(i64, i64)>,
+    // ~ the producers whose current transaction is aborted
+    producers: Vec<i64>,
+}
+
+impl AbortedTransactions {
+    /// Creates a tracker for the given (first offset, producer id)
+    /// pairs of aborted transactions.
+    fn new(mut txns: Vec<(i64, i64)>) -> AbortedTransactions {
+        txns.sort_by(|a, b| b.0.cmp(&a.0));
+        AbortedTransactions {
+            pending: txns,
+            producers: Vec::new(),
+        }
+    }
+
+    /// Determines whether the given batch - visited in the order of
+    /// offsets - was written by an aborted transaction.
+    fn is_aborted(&mut self, batch: &RecordBatch) -> bool {
+        let last_offset = batch.last_offset();
+        while let Some(&(first_offset, producer_id)) = self.pending.last() {
+            if first_offset > last_offset {
+                break;
+            }
+            self.pending.pop();
+            if !self.producers.contains(&producer_id) {
+                self.producers.push(producer_id);
+            }
+        }
+        if batch.attr & BATCH_TRANSACTIONAL == 0 {
+            return false;
+        }
+        if batch.is_abort_marker() {
+            // ~ the abort marker ends the aborted transaction; the
+            // marker itself is skipped as any other control batch
+            self.producers.retain(|&p| p != batch.producer_id);
+            return false;
+        }
+        batch.attr & BATCH_CONTROL == 0 && self.producers.contains(&batch.producer_id)
+    }
+}
+
 // tests --------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
     use std::str;
 
-    use super::{FetchRequest, Response, Message};
+    use super::{FetchRequest, Response, Message, MessageSet, decode_fetch_response};
     use error::{Error, ErrorKind, KafkaCode};
 
     static FETCH1_TXT: &'static str = include_str!("../../test-data/fetch1.txt");
@@ -573,6 +1281,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decode_fetch_response() {
+        let resp = decode_fetch_response(FETCH2_FETCH_RESPONSE_NOCOMPRESSION_K0900, 0).unwrap();
+        assert_eq!(1, into_messages(&resp).len());
+
+        // ~ malformed input must be rejected without panicking
+        let data = FETCH2_FETCH_RESPONSE_NOCOMPRESSION_INVALID_CRC_K0900;
+        for &api_version in &[0, 1, 4, 11] {
+            for n in 0..data.len() {
+                let _ = decode_fetch_response(&data[..n], api_version);
+                let mut mutated = data.to_owned();
+                mutated[n] ^= 0xff;
+                let _ = decode_fetch_response(&mutated, api_version);
+            }
+        }
+    }
+
     fn skip_lines(mut lines: &str, mut n: usize) -> &str {
         while n > 0 {
             n -= 1;
@@ -583,7 +1308,7 @@ mod tests {
 
     #[test]
     fn test_from_slice_nocompression_k0821() {
-        let mut req = FetchRequest::new(0, "test", -1, -1);
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 0, -1);
         req.add("foo-quux", 0, 100, -1);
         test_decode_new_fetch_response(
@@ -595,7 +1320,7 @@ mod tests {
 
         // ~ pretend we asked for messages as of offset five (while
         // the server delivered the zero-offset message as well)
-        req = FetchRequest::new(0, "test", -1, -1);
+        req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 5, -1);
         test_decode_new_fetch_response(
             skip_lines(FETCH1_TXT, 5),
@@ -610,7 +1335,7 @@ mod tests {
     #[cfg(not(feature = "snappy"))]
     #[test]
     fn test_unsupported_compression_snappy() {
-        let mut req = FetchRequest::new(0, "test", -1, -1);
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 0, -1);
         let r =
             Response::from_vec(FETCH1_FETCH_RESPONSE_SNAPPY_K0821.to_owned(), Some(&req), false);
@@ -623,7 +1348,7 @@ mod tests {
     #[cfg(feature = "snappy")]
     #[test]
     fn test_from_slice_snappy_k0821() {
-        let mut req = FetchRequest::new(0, "test", -1, -1);
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 0, -1);
         test_decode_new_fetch_response(
             FETCH1_TXT,
@@ -634,7 +1359,7 @@ mod tests {
 
         // ~ pretend we asked for messages as of offset three (while
         // the server delivered the zero-offset message as well)
-        req = FetchRequest::new(0, "test", -1, -1);
+        req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 3, -1);
         test_decode_new_fetch_response(
             skip_lines(FETCH1_TXT, 3),
@@ -647,7 +1372,7 @@ mod tests {
     #[cfg(feature = "snappy")]
     #[test]
     fn test_from_slice_snappy_k0822() {
-        let mut req = FetchRequest::new(0, "test", -1, -1);
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 0, -1);
         test_decode_new_fetch_response(
             FETCH1_TXT,
@@ -660,7 +1385,7 @@ mod tests {
     #[cfg(feature = "gzip")]
     #[test]
     fn test_from_slice_gzip_k0821() {
-        let mut req = FetchRequest::new(0, "test", -1, -1);
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
         req.add("my-topic", 0, 0, -1);
         test_decode_new_fetch_response(
             FETCH1_TXT,
@@ -717,8 +1442,897 @@ mod tests {
             true,
         ) {
             Ok(_) => panic!("Expected error, but got successful response!"),
-            Err(Error(ErrorKind::Kafka(KafkaCode::CorruptMessage), _)) => {}
-            Err(e) => panic!("Expected KafkaCode::CorruptMessage error, but got: {:?}", e),
+            Err(Error(ErrorKind::CrcMismatch(_), _)) => {}
+            Err(e) => panic!("Expected CrcMismatch error, but got: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_truncated_message_set() {
+        let ms = MessageSet::from_slice(&[], 0, true).unwrap();
+        assert!(ms.messages.is_empty());
+        assert!(!ms.truncated);
+
+        // ~ offset and size of a message whose data is cut off
+        let data = &[0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 100, 1, 2, 3];
+        let ms = MessageSet::from_slice(data, 0, true).unwrap();
+        assert!(ms.messages.is_empty());
+        assert!(ms.truncated);
+
+        // ~ a complete message whose key length exceeds the message
+        // is malformed rather than truncated
+        let data = &[0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 100];
+        match MessageSet::from_slice(data, 0, false) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r.map(|ms| ms.messages.len())),
+        }
+    }
+
+    #[test]
+    fn test_truncated_fetch_response() {
+        use byteorder::{BigEndian, ByteOrder};
+
+        // ~ cut off the end of the last message and adjust the size
+        // of the partition's message set (following the fixed size
+        // fields of the response for "my-topic" up to byte 40)
+        let mut data = FETCH1_FETCH_RESPONSE_NOCOMPRESSION_K0821.to_owned();
+        let len = data.len() - 10;
+        data.truncate(len);
+        BigEndian::write_i32(&mut data[36..40], (len - 40) as i32);
+
+        let mut req = FetchRequest::new(0, "test", -1, -1, 0);
+        req.add("my-topic", 0, 0, -1);
+        let resp = Response::from_vec(data, Some(&req), true).unwrap();
+        match resp.topics()[0].partitions()[0].data() {
+            &Ok(ref data) => assert!(data.is_truncated()),
+            &Err(ref e) => panic!("Unexpected error: {:?}", e),
+        }
+        // ~ all but the partial last message are delivered
+        let original: Vec<_> = FETCH1_TXT.lines().collect();
+        let msgs = into_messages(&resp);
+        assert_eq!(original.len() - 1, msgs.len());
+        for (msg, orig) in msgs.into_iter().zip(original.iter()) {
+            assert_eq!(str::from_utf8(msg.value).unwrap(), *orig);
+        }
+    }
+
+    /// Renders the given produce request (addressing a single topic
+    /// partition) and extracts the rendered message set.
+    fn encode_message_set(req: &::protocol::ProduceRequest) -> Vec<u8> {
+        use codecs::ToByte;
+        use protocol::zreader::ZReader;
+
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+
+        // ~ skip over the request header and the produce request
+        // fields up to the rendered message set
+        let mut r = ZReader::new(&buf);
+        r.read_i16().unwrap(); // ~ api key
+        let api_version = r.read_i16().unwrap();
+        r.read_i32().unwrap(); // ~ correlation id
+        r.read_str().unwrap(); // ~ client id
+        if api_version >= 3 {
+            r.read_str().unwrap(); // ~ transactional id
+        }
+        r.read_i16().unwrap(); // ~ required acks
+        r.read_i32().unwrap(); // ~ timeout
+        assert_eq!(1, r.read_array_len().unwrap());
+        assert_eq!("my-topic", r.read_str().unwrap());
+        assert_eq!(1, r.read_array_len().unwrap());
+        assert_eq!(0, r.read_i32().unwrap());
+        let ms = r.read_bytes().unwrap().to_vec();
+        assert!(r.is_empty());
+        ms
+    }
+
+    #[test]
+    fn test_message_key_round_trip() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        let msgs: &[(Option<&[u8]>, Option<&[u8]>)] = &[
+            (Some(b"key-1"), Some(b"value-1")),
+            (None, Some(b"value-2")),
+            (Some(b"key-3"), None),
+        ];
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::NONE, 0, 0);
+        for &(key, value) in msgs {
+            req.add("my-topic", 0, key, value, None, &[]);
+        }
+        let data = encode_message_set(&req);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let decoded: Vec<(&[u8], &[u8])> = ms.messages.iter().map(|m| (m.key, m.value)).collect();
+        // ~ a null key/value is surfaced as an empty slice
+        assert_eq!(
+            vec![
+                (&b"key-1"[..], &b"value-1"[..]),
+                (&b""[..], &b"value-2"[..]),
+                (&b"key-3"[..], &b""[..]),
+            ],
+            decoded
+        );
+    }
+
+    /// Renders a single message of the given format version as part
+    /// of a message set.
+    fn encode_message(
+        out: &mut Vec<u8>,
+        offset: i64,
+        magic: i8,
+        attr: i8,
+        timestamp: i64,
+        key: &[u8],
+        value: &[u8],
+    ) {
+        use codecs::ToByte;
+        use protocol::to_crc;
+
+        let mut msg = Vec::new();
+        magic.encode(&mut msg).unwrap();
+        attr.encode(&mut msg).unwrap();
+        if magic == 1 {
+            timestamp.encode(&mut msg).unwrap();
+        }
+        key.encode(&mut msg).unwrap();
+        value.encode(&mut msg).unwrap();
+
+        offset.encode(out).unwrap();
+        ((msg.len() + 4) as i32).encode(out).unwrap();
+        (to_crc(&msg) as i32).encode(out).unwrap();
+        out.extend_from_slice(&msg);
+    }
+
+    #[test]
+    fn test_message_timestamps() {
+        let mut data = Vec::new();
+        encode_message(&mut data, 5, 0, 0, 0, b"k0", b"v0");
+        encode_message(&mut data, 6, 1, 0, 1_500_000_000_000, b"k1", b"v1");
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset(), m.timestamp(), m.key, m.value))
+            .collect();
+        assert_eq!(
+            vec![
+                (5, -1, &b"k0"[..], &b"v0"[..]),
+                (6, 1_500_000_000_000, &b"k1"[..], &b"v1"[..]),
+            ],
+            msgs
+        );
+    }
+
+    #[test]
+    fn test_unsupported_magic() {
+        let mut data = Vec::new();
+        encode_message(&mut data, 0, 3, 0, 0, b"", b"foo");
+        match MessageSet::from_slice(&data, 0, true) {
+            Err(Error(ErrorKind::UnsupportedProtocol, _)) => {}
+            r => panic!("Expected UnsupportedProtocol, but got: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compressed_v1_relative_offsets() {
+        use compression::{gzip, Compression};
+        use super::TIMESTAMP_TYPE_LOG_APPEND_TIME;
+
+        // ~ inner messages carry offsets relative to the wrapper
+        let mut inner = Vec::new();
+        for i in 0..3 {
+            let value = format!("v{}", i);
+            encode_message(&mut inner, i, 1, 0, 1000 + i, b"", value.as_bytes());
+        }
+        let cdata = gzip::compress(&inner, 6).unwrap();
+
+        // ~ the wrapper carries the absolute offset of its last inner
+        // message
+        let mut data = Vec::new();
+        encode_message(&mut data, 12, 1, Compression::GZIP as i8, 2000, b"", &cdata);
+        let ms = MessageSet::from_slice(&data, 11, true).unwrap();
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset, m.timestamp, m.value))
+            .collect();
+        assert_eq!(vec![(11, 1001, &b"v1"[..]), (12, 1002, &b"v2"[..])], msgs);
+
+        // ~ with log append time the wrapper's timestamp applies
+        let mut data = Vec::new();
+        let attr = Compression::GZIP as i8 | TIMESTAMP_TYPE_LOG_APPEND_TIME;
+        encode_message(&mut data, 12, 1, attr, 2000, b"", &cdata);
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.timestamp)).collect();
+        assert_eq!(vec![(10, 2000), (11, 2000), (12, 2000)], msgs);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_nested_message_set_depth() {
+        use compression::{gzip, Compression};
+        use super::{AbortedTransactions, DEFAULT_MAX_MESSAGE_SET_DEPTH};
+
+        // ~ wraps the given message set into a compressed message
+        let wrap = |data: &[u8]| {
+            let cdata = gzip::compress(data, 6).unwrap();
+            let mut wrapped = Vec::new();
+            encode_message(&mut wrapped, 0, 0, Compression::GZIP as i8, 0, b"", &cdata);
+            wrapped
+        };
+        let mut data = Vec::new();
+        encode_message(&mut data, 0, 0, 0, 0, b"", b"v");
+        for _ in 0..DEFAULT_MAX_MESSAGE_SET_DEPTH {
+            data = wrap(&data);
+        }
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        assert_eq!(1, ms.messages.len());
+
+        match MessageSet::from_slice(&wrap(&data), 0, true) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r.map(|ms| ms.messages.len())),
+        }
+
+        // ~ a configured limit accepts message sets nested up to it
+        // and rejects those nested one level deeper
+        let decode = |data: &[u8], max_depth: u32| {
+            let mut aborted = AbortedTransactions::new(Vec::new());
+            MessageSet::from_slice_committed(data, 0, true, &mut aborted, max_depth)
+                .map(|ms| ms.messages.len())
+        };
+        let limit = DEFAULT_MAX_MESSAGE_SET_DEPTH + 1;
+        let data = wrap(&data);
+        assert_eq!(1, decode(&data, limit).unwrap());
+        match decode(&wrap(&data), limit) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r),
+        }
+        // ~ a limit of zero rejects compressed messages altogether
+        match decode(&wrap(b""), 0) {
+            Err(Error(ErrorKind::CodecError, _)) => {}
+            r => panic!("Expected CodecError, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_message_v1_round_trip() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::NONE, 0, 1);
+        assert_eq!(2, req.header.api_version);
+        req.add("my-topic", 0, Some(b"k"), Some(b"v1"), Some(1_234), &[]);
+        req.add("my-topic", 0, None, Some(b"v2"), None, &[]);
+        let data = encode_message_set(&req);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.timestamp, m.key, m.value)).collect();
+        assert_eq!(
+            vec![(1_234, &b"k"[..], &b"v1"[..]), (req.timestamp, &b""[..], &b"v2"[..])],
+            msgs
+        );
+        assert!(req.timestamp > 0);
+    }
+
+    #[test]
+    fn test_message_v0_drops_timestamps() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::NONE, 0, 0);
+        assert_eq!(0, req.header.api_version);
+        req.add("my-topic", 0, None, Some(b"v"), Some(1_234), &[]);
+        let data = encode_message_set(&req);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        assert_eq!(1, ms.messages.len());
+        assert_eq!(-1, ms.messages[0].timestamp);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_message_v1_compressed_round_trip() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::GZIP, 6, 1);
+        for i in 0..3 {
+            req.add("my-topic", 0, None, Some(b"value"), Some(100 + i), &[]);
+        }
+        let data = encode_message_set(&req);
+
+        // ~ the broker would assign the absolute offsets to the
+        // wrapper; as rendered by the producer the wrapper carries
+        // the relative offset of its last inner message
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.timestamp)).collect();
+        assert_eq!(vec![(0, 100), (1, 101), (2, 102)], msgs);
+    }
+
+    #[test]
+    fn test_crc_mismatch_offset() {
+        let mut data = Vec::new();
+        encode_message(&mut data, 41, 0, 0, 0, b"", b"first");
+        encode_message(&mut data, 42, 0, 0, 0, b"key", b"second");
+        assert_eq!(2, MessageSet::from_slice(&data, 0, true).unwrap().messages.len());
+
+        // ~ corrupt the last byte of the second message's value
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+        let err = MessageSet::from_slice(&data, 0, true).unwrap_err();
+        assert_eq!(&ErrorKind::CrcMismatch(42), err.kind());
+        assert_eq!(Some(KafkaCode::CorruptMessage.to_i16()), err.kafka_code());
+
+        // ~ without validation the corruption goes unnoticed
+        let ms = MessageSet::from_slice(&data, 0, false).unwrap();
+        assert_eq!(b"secone", ms.messages[1].value);
+    }
+
+    #[test]
+    fn test_crc_validation_v1() {
+        let mut data = Vec::new();
+        encode_message(&mut data, 0, 1, 0, 1_000, b"key", b"value");
+        assert!(MessageSet::from_slice(&data, 0, true).is_ok());
+
+        // ~ the crc covers the timestamp of v1 messages; offset,
+        // size, crc, magic, attributes precede it
+        data[8 + 4 + 4 + 1 + 1 + 7] ^= 0xff;
+        match MessageSet::from_slice(&data, 0, true) {
+            Err(Error(ErrorKind::CrcMismatch(0), _)) => {}
+            r => panic!("Expected CrcMismatch, but got: {:?}", r),
+        }
+        let ms = MessageSet::from_slice(&data, 0, false).unwrap();
+        assert_eq!(1_000 ^ 0xff, ms.messages[0].timestamp);
+    }
+
+    #[test]
+    fn test_response_v2_throttle_time() {
+        use codecs::ToByte;
+
+        let mut msgs = Vec::new();
+        encode_message(&mut msgs, 7, 1, 0, 1_000, b"", b"foo");
+
+        let mut data = Vec::new();
+        (42i32).encode(&mut data).unwrap(); // ~ correlation id
+        (100i32).encode(&mut data).unwrap(); // ~ throttle time
+        (1i32).encode(&mut data).unwrap();
+        "my-topic".encode(&mut data).unwrap();
+        (1i32).encode(&mut data).unwrap();
+        (0i32).encode(&mut data).unwrap(); // ~ partition
+        (0i16).encode(&mut data).unwrap(); // ~ error
+        (8i64).encode(&mut data).unwrap(); // ~ highwatermark
+        msgs[..].encode(&mut data).unwrap();
+
+        let mut req = FetchRequest::new(0, "test", -1, -1, 1);
+        assert_eq!(2, req.header.api_version);
+        req.add("my-topic", 0, 0, -1);
+        let resp = Response::from_vec(data, Some(&req), true).unwrap();
+        assert_eq!(42, resp.correlation_id());
+        assert_eq!(100, resp.throttle_time());
+        let msgs = into_messages(&resp);
+        assert_eq!(1, msgs.len());
+        assert_eq!((7, 1_000, &b"foo"[..]), (msgs[0].offset, msgs[0].timestamp, msgs[0].value));
+    }
+
+    /// Renders the given records - (offset delta, timestamp delta,
+    /// key, value) - as the (uncompressed) records of a record batch.
+    fn encode_records(records: &[(i32, i64, Option<&[u8]>, &[u8])]) -> Vec<u8> {
+        use codecs::write_varint;
+
+        let encode_varint = |out: &mut Vec<u8>, v: i64| write_varint(out, v).unwrap();
+        let mut out = Vec::new();
+        for &(offset_delta, timestamp_delta, key, value) in records {
+            let mut rec = vec![0]; // ~ attributes
+            encode_varint(&mut rec, timestamp_delta);
+            encode_varint(&mut rec, offset_delta as i64);
+            match key {
+                None => encode_varint(&mut rec, -1),
+                Some(key) => {
+                    encode_varint(&mut rec, key.len() as i64);
+                    rec.extend_from_slice(key);
+                }
+            }
+            encode_varint(&mut rec, value.len() as i64);
+            rec.extend_from_slice(value);
+            encode_varint(&mut rec, 0); // ~ headers
+            encode_varint(&mut out, rec.len() as i64);
+            out.extend_from_slice(&rec);
+        }
+        out
+    }
+
+    /// Renders a record batch of `num_records` records as part of a
+    /// message set.
+    fn encode_record_batch(
+        out: &mut Vec<u8>,
+        base_offset: i64,
+        attr: i16,
+        first_timestamp: i64,
+        max_timestamp: i64,
+        num_records: i32,
+        records: &[u8],
+    ) {
+        encode_producer_batch(
+            out,
+            base_offset,
+            attr,
+            -1,
+            first_timestamp,
+            max_timestamp,
+            num_records,
+            records,
+        )
+    }
+
+    /// Renders a record batch written by the given producer.
+    fn encode_producer_batch(
+        out: &mut Vec<u8>,
+        base_offset: i64,
+        attr: i16,
+        producer_id: i64,
+        first_timestamp: i64,
+        max_timestamp: i64,
+        num_records: i32,
+        records: &[u8],
+    ) {
+        use codecs::{crc32c, ToByte};
+
+        let mut batch = Vec::new();
+        attr.encode(&mut batch).unwrap();
+        (num_records - 1).encode(&mut batch).unwrap(); // ~ last offset delta
+        first_timestamp.encode(&mut batch).unwrap();
+        max_timestamp.encode(&mut batch).unwrap();
+        producer_id.encode(&mut batch).unwrap();
+        (-1i16).encode(&mut batch).unwrap(); // ~ producer epoch
+        (-1i32).encode(&mut batch).unwrap(); // ~ base sequence
+        num_records.encode(&mut batch).unwrap();
+        batch.extend_from_slice(records);
+
+        base_offset.encode(out).unwrap();
+        ((batch.len() + 9) as i32).encode(out).unwrap();
+        (0i32).encode(out).unwrap(); // ~ partition leader epoch
+        (2i8).encode(out).unwrap(); // ~ magic
+        (crc32c(&batch) as i32).encode(out).unwrap();
+        out.extend_from_slice(&batch);
+    }
+
+    #[test]
+    fn test_record_batches() {
+        let mut data = Vec::new();
+        let records = encode_records(&[
+            (0, 0, Some(b"k0"), b"v0"),
+            (1, 5, None, b"v1"),
+            (2, 7, Some(b"k2"), b""),
+        ]);
+        encode_record_batch(&mut data, 100, 0, 1_000, 1_007, 3, &records);
+        let records = encode_records(&[(0, 0, Some(b"k3"), b"v3")]);
+        encode_record_batch(&mut data, 103, 0, 2_000, 2_000, 1, &records);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        assert!(!ms.truncated);
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset, m.timestamp, m.key, m.value))
+            .collect();
+        assert_eq!(
+            vec![
+                (100, 1_000, &b"k0"[..], &b"v0"[..]),
+                (101, 1_005, &b""[..], &b"v1"[..]),
+                (102, 1_007, &b"k2"[..], &b""[..]),
+                (103, 2_000, &b"k3"[..], &b"v3"[..]),
+            ],
+            msgs
+        );
+
+        // ~ skip records with a lower offset than the requested one
+        let ms = MessageSet::from_slice(&data, 102, true).unwrap();
+        let offsets: Vec<_> = ms.messages.iter().map(|m| m.offset).collect();
+        assert_eq!(vec![102, 103], offsets);
+    }
+
+    #[test]
+    fn test_record_batch_log_append_time() {
+        use super::TIMESTAMP_TYPE_LOG_APPEND_TIME;
+
+        let mut data = Vec::new();
+        let records = encode_records(&[(0, 0, None, b"a"), (1, 3, None, b"b")]);
+        let attr = TIMESTAMP_TYPE_LOG_APPEND_TIME as i16;
+        encode_record_batch(&mut data, 0, attr, 1_000, 5_000, 2, &records);
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.timestamp)).collect();
+        assert_eq!(vec![(0, 5_000), (1, 5_000)], msgs);
+    }
+
+    #[test]
+    fn test_record_batch_control_skipped() {
+        use super::BATCH_CONTROL;
+
+        let mut data = Vec::new();
+        encode_record_batch(&mut data, 0, 0, 0, 0, 1, &encode_records(&[(0, 0, None, b"a")]));
+        // ~ a transaction marker
+        let records = encode_records(&[(0, 0, Some(b"\0\0\0\0"), b"\0\0\0\0\0\0")]);
+        encode_record_batch(&mut data, 1, BATCH_CONTROL, 0, 0, 1, &records);
+        encode_record_batch(&mut data, 2, 0, 0, 0, 1, &encode_records(&[(0, 0, None, b"b")]));
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.value)).collect();
+        assert_eq!(vec![(0, &b"a"[..]), (2, &b"b"[..])], msgs);
+    }
+
+    #[test]
+    fn test_record_batch_aborted_transactions_skipped() {
+        use super::{AbortedTransactions, BATCH_CONTROL, BATCH_TRANSACTIONAL};
+
+        let txn = BATCH_TRANSACTIONAL;
+        let record = |value: &[u8]| encode_records(&[(0, 0, None, value)]);
+        let marker = |control_type: u8| {
+            encode_records(&[(0, 0, Some(&[0, 0, 0, control_type][..]), b"\0\0\0\0\0\0")])
+        };
+        let mut data = Vec::new();
+        // ~ an aborted transaction of producer 7
+        encode_producer_batch(&mut data, 0, txn, 7, 0, 0, 1, &record(b"a"));
+        encode_producer_batch(&mut data, 1, 0, -1, 0, 0, 1, &record(b"b"));
+        encode_producer_batch(&mut data, 2, txn | BATCH_CONTROL, 7, 0, 0, 1, &marker(0));
+        // ~ a subsequent, committed transaction of producer 7 and a
+        // committed transaction of producer 8
+        encode_producer_batch(&mut data, 3, txn, 7, 0, 0, 1, &record(b"c"));
+        encode_producer_batch(&mut data, 4, txn, 8, 0, 0, 1, &record(b"d"));
+        encode_producer_batch(&mut data, 5, txn | BATCH_CONTROL, 7, 0, 0, 1, &marker(1));
+        encode_producer_batch(&mut data, 6, txn | BATCH_CONTROL, 8, 0, 0, 1, &marker(1));
+
+        let mut aborted = AbortedTransactions::new(vec![(0, 7)]);
+        let ms = MessageSet::from_slice_committed(
+            &data,
+            0,
+            true,
+            &mut aborted,
+            super::DEFAULT_MAX_MESSAGE_SET_DEPTH,
+        ).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.value)).collect();
+        assert_eq!(vec![(1, &b"b"[..]), (3, &b"c"[..]), (4, &b"d"[..])], msgs);
+        assert_eq!(7, ms.next_offset);
+
+        // ~ without aborted transactions all data is delivered
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        assert_eq!(4, ms.messages.len());
+    }
+
+    #[test]
+    fn test_record_batch_crc_validation() {
+        let mut data = Vec::new();
+        let records = encode_records(&[(0, 0, None, b"value")]);
+        encode_record_batch(&mut data, 33, 0, 0, 0, 1, &records);
+        assert!(MessageSet::from_slice(&data, 0, true).is_ok());
+
+        // ~ corrupt the last byte of the record's value
+        let last = data.len() - 2;
+        data[last] ^= 0x01;
+        let err = MessageSet::from_slice(&data, 0, true).unwrap_err();
+        assert_eq!(&ErrorKind::CrcMismatch(33), err.kind());
+        let ms = MessageSet::from_slice(&data, 0, false).unwrap();
+        assert_eq!(b"valud", ms.messages[0].value);
+    }
+
+    #[test]
+    fn test_record_batch_truncated() {
+        let mut data = Vec::new();
+        encode_record_batch(&mut data, 0, 0, 0, 0, 1, &encode_records(&[(0, 0, None, b"a")]));
+        encode_record_batch(&mut data, 1, 0, 0, 0, 1, &encode_records(&[(0, 0, None, b"b")]));
+        let len = data.len();
+        let ms = MessageSet::from_slice(&data[..len - 3], 0, true).unwrap();
+        assert!(ms.truncated);
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.value)).collect();
+        assert_eq!(vec![(0, &b"a"[..])], msgs);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_record_batch_compressed() {
+        use compression::{gzip, Compression};
+
+        // ~ a compressed batch followed by an uncompressed one
+        let mut data = Vec::new();
+        let records = encode_records(&[(0, 0, Some(b"k0"), b"v0"), (1, 1, None, b"v1")]);
+        let cdata = gzip::compress(&records, 6).unwrap();
+        encode_record_batch(&mut data, 10, Compression::GZIP as i16, 100, 101, 2, &cdata);
+        let records = encode_records(&[(0, 0, None, b"v2")]);
+        encode_record_batch(&mut data, 12, 0, 200, 200, 1, &records);
+
+        let ms = MessageSet::from_slice(&data, 11, true).unwrap();
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset, m.timestamp, m.key, m.value))
+            .collect();
+        assert_eq!(
+            vec![(11, 101, &b""[..], &b"v1"[..]), (12, 200, &b""[..], &b"v2"[..])],
+            msgs
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_multiple_compressed_messages() {
+        use compression::{gzip, Compression};
+
+        // ~ the messages of all compressed wrappers in a message set
+        // are delivered, including those of plain messages in between
+        let mut data = Vec::new();
+        for (wrapper_offset, values) in vec![(1i64, vec!["a", "b"]), (4, vec!["d", "e"])] {
+            let mut inner = Vec::new();
+            for (i, v) in values.iter().enumerate() {
+                encode_message(&mut inner, i as i64, 1, 0, 0, b"", v.as_bytes());
+            }
+            let cdata = gzip::compress(&inner, 6).unwrap();
+            encode_message(&mut data, wrapper_offset, 1, Compression::GZIP as i8, 0, b"", &cdata);
+            if wrapper_offset == 1 {
+                encode_message(&mut data, 2, 1, 0, 0, b"", b"c");
+            }
+        }
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages.iter().map(|m| (m.offset, m.value)).collect();
+        assert_eq!(
+            vec![
+                (0, &b"a"[..]),
+                (1, &b"b"[..]),
+                (2, &b"c"[..]),
+                (3, &b"d"[..]),
+                (4, &b"e"[..]),
+            ],
+            msgs
+        );
+    }
+
+    #[test]
+    fn test_record_batch_round_trip() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        let headers = vec![
+            ("trace-id".to_owned(), b"abc".to_vec()),
+            ("empty".to_owned(), Vec::new()),
+        ];
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::NONE, 0, 2);
+        assert_eq!(3, req.header.api_version);
+        req.add("my-topic", 0, Some(b"k"), Some(b"v1"), Some(1_234), &headers);
+        req.add("my-topic", 0, None, Some(b"v2"), Some(1_000), &[]);
+        req.add("my-topic", 0, Some(b"k3"), None, None, &headers[1..]);
+        let data = encode_message_set(&req);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset, m.timestamp, m.key, m.value, m.headers()))
+            .collect();
+        let h1: &[(&str, &[u8])] = &[("trace-id", b"abc"), ("empty", b"")];
+        let h3: &[(&str, &[u8])] = &[("empty", b"")];
+        assert_eq!(
+            vec![
+                (0, 1_234, &b"k"[..], &b"v1"[..], h1),
+                (1, 1_000, &b""[..], &b"v2"[..], &[][..]),
+                (2, req.timestamp, &b"k3"[..], &b""[..], h3),
+            ],
+            msgs
+        );
+    }
+
+    #[test]
+    fn test_binary_payload_round_trip() {
+        use compression::Compression;
+        use protocol::ProduceRequest;
+
+        // ~ neither keys, values, nor header values need to be utf8
+        let value: Vec<u8> = (0..256).map(|b| b as u8).rev().collect();
+        // ~ 0xff never occurs in utf8
+        let key: &[u8] = &[0xff, 0xfe, 0x00, 0xc3, 0x28];
+        assert!(str::from_utf8(&value).is_err());
+        let headers = vec![("bin".to_owned(), vec![0x80, 0x00, 0xff])];
+
+        #[cfg_attr(not(feature = "gzip"), allow(unused_mut))]
+        let mut compressions = vec![Compression::NONE];
+        #[cfg(feature = "gzip")]
+        compressions.push(Compression::GZIP);
+        for &compression in &compressions {
+            for message_version in 0..3 {
+                let mut req =
+                    ProduceRequest::new(1, 1000, 1, "test", compression, 6, message_version);
+                req.add("my-topic", 0, Some(key), Some(&value), None, &headers);
+                let data = encode_message_set(&req);
+
+                let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+                assert_eq!(1, ms.messages.len());
+                let m = &ms.messages[0];
+                assert_eq!(key, m.key);
+                assert_eq!(&value[..], m.value);
+                if message_version >= 2 {
+                    let h: &[(&str, &[u8])] = &[("bin", &[0x80, 0x00, 0xff])];
+                    assert_eq!(h, m.headers());
+                }
+            }
+        }
+    }
+
+    fn test_record_batch_compressed_round_trip(compression: ::compression::Compression) {
+        use protocol::ProduceRequest;
+
+        let headers = vec![("h".to_owned(), b"x".to_vec())];
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", compression, 6, 2);
+        for i in 0..3 {
+            req.add("my-topic", 0, None, Some(b"value"), Some(100 + i), &headers);
+        }
+        let data = encode_message_set(&req);
+
+        let ms = MessageSet::from_slice(&data, 0, true).unwrap();
+        let msgs: Vec<_> = ms.messages
+            .iter()
+            .map(|m| (m.offset, m.timestamp, m.value, m.headers()))
+            .collect();
+        let h: &[(&str, &[u8])] = &[("h", b"x")];
+        assert_eq!(
+            vec![(0, 100, &b"value"[..], h), (1, 101, &b"value"[..], h), (2, 102, &b"value"[..], h)],
+            msgs
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_record_batch_gzip_round_trip() {
+        test_record_batch_compressed_round_trip(::compression::Compression::GZIP);
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_record_batch_lz4_round_trip() {
+        test_record_batch_compressed_round_trip(::compression::Compression::LZ4);
+    }
+
+    #[test]
+    fn test_response_v4() {
+        use codecs::ToByte;
+
+        let mut req = FetchRequest::new(0, "test", -1, -1, 2);
+        assert_eq!(4, req.header.api_version);
+        req.add("my-topic", 0, 0, -1);
+
+        let mut msgs = Vec::new();
+        let records = encode_records(&[(0, 0, None, b"foo")]);
+        encode_record_batch(&mut msgs, 7, 0, 1_000, 1_000, 1, &records);
+
+        let mut data = Vec::new();
+        (42i32).encode(&mut data).unwrap(); // ~ correlation id
+        (0i32).encode(&mut data).unwrap(); // ~ throttle time
+        (1i32).encode(&mut data).unwrap();
+        "my-topic".encode(&mut data).unwrap();
+        (1i32).encode(&mut data).unwrap();
+        (0i32).encode(&mut data).unwrap(); // ~ partition
+        (0i16).encode(&mut data).unwrap(); // ~ error
+        (8i64).encode(&mut data).unwrap(); // ~ highwatermark
+        (8i64).encode(&mut data).unwrap(); // ~ last stable offset
+        (1i32).encode(&mut data).unwrap(); // ~ aborted transactions
+        (5i64).encode(&mut data).unwrap();
+        (3i64).encode(&mut data).unwrap();
+        msgs[..].encode(&mut data).unwrap();
+
+        let resp = Response::from_vec(data, Some(&req), true).unwrap();
+        let msgs = into_messages(&resp);
+        assert_eq!(1, msgs.len());
+        assert_eq!((7, 1_000, &b"foo"[..]), (msgs[0].offset, msgs[0].timestamp, msgs[0].value));
+        match *resp.topics()[0].partitions()[0].data() {
+            Ok(ref data) => {
+                assert_eq!(8, data.last_stable_offset());
+                assert_eq!(Some(8), data.next_offset());
+            }
+            Err(ref e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_request_v4() {
+        use codecs::ToByte;
+
+        let mut v2 = FetchRequest::new(0, "test", 100, 1, 1);
+        v2.add("my-topic", 0, 0, 1024);
+        let mut v4 = FetchRequest::new(0, "test", 100, 1, 2);
+        v4.add("my-topic", 0, 0, 1024);
+        let (mut buf2, mut buf4) = (Vec::new(), Vec::new());
+        v2.encode(&mut buf2).unwrap();
+        v4.encode(&mut buf4).unwrap();
+        // ~ the maximum bytes of the response and the isolation level
+        // follow the minimum bytes
+        let pos = 2 + 2 + 4 + (2 + 4) + 4 + 4 + 4;
+        assert_eq!(buf2.len() + 5, buf4.len());
+        assert_eq!(&[0x7f, 0xff, 0xff, 0xff, 0x00], &buf4[pos..pos + 5]);
+        assert_eq!(&buf2[pos..], &buf4[pos + 5..]);
+
+        let mut committed = FetchRequest::new(0, "test", 100, 1, 2)
+            .with_isolation_level(super::ISOLATION_READ_COMMITTED);
+        committed.add("my-topic", 0, 0, 1024);
+        let mut buf = Vec::new();
+        committed.encode(&mut buf).unwrap();
+        assert_eq!(1, buf[pos + 4]);
+    }
+
+    #[test]
+    fn test_request_v11() {
+        use codecs::ToByte;
+
+        let mut v4 = FetchRequest::new(0, "test", 100, 1, 2);
+        v4.add("my-topic", 0, 5, 1024);
+        let mut v11 = FetchRequest::new(0, "test", 100, 1, 2);
+        v11.header.api_version = super::API_VERSION_FOLLOWER_FETCH;
+        v11.add("my-topic", 0, 5, 1024);
+        let (mut buf4, mut buf11) = (Vec::new(), Vec::new());
+        v4.encode(&mut buf4).unwrap();
+        v11.encode(&mut buf11).unwrap();
+
+        let mut expected = Vec::new();
+        (-1i32).encode(&mut expected).unwrap(); // ~ replica id
+        (100i32).encode(&mut expected).unwrap();
+        (1i32).encode(&mut expected).unwrap();
+        i32::max_value().encode(&mut expected).unwrap();
+        (0i8).encode(&mut expected).unwrap(); // ~ isolation level
+        (0i32).encode(&mut expected).unwrap(); // ~ session id
+        (-1i32).encode(&mut expected).unwrap(); // ~ session epoch
+        (1i32).encode(&mut expected).unwrap();
+        "my-topic".encode(&mut expected).unwrap();
+        (1i32).encode(&mut expected).unwrap();
+        (0i32).encode(&mut expected).unwrap(); // ~ partition
+        (-1i32).encode(&mut expected).unwrap(); // ~ current leader epoch
+        (5i64).encode(&mut expected).unwrap(); // ~ offset
+        (-1i64).encode(&mut expected).unwrap(); // ~ log start offset
+        (1024i32).encode(&mut expected).unwrap();
+        (0i32).encode(&mut expected).unwrap(); // ~ forgotten topics
+        "".encode(&mut expected).unwrap(); // ~ rack id
+        // ~ only the version differs within the request header
+        let pos = 2 + 2 + 4 + (2 + 4);
+        assert_eq!(&buf4[..2], &buf11[..2]);
+        assert_eq!(&buf4[4..pos], &buf11[4..pos]);
+        assert_eq!(&expected[..], &buf11[pos..]);
+    }
+
+    #[test]
+    fn test_response_v11() {
+        use codecs::ToByte;
+
+        let mut req = FetchRequest::new(0, "test", -1, -1, 2);
+        req.header.api_version = super::API_VERSION_FOLLOWER_FETCH;
+        req.add("my-topic", 0, 0, -1);
+
+        let mut msgs = Vec::new();
+        let records = encode_records(&[(0, 0, None, b"foo")]);
+        encode_record_batch(&mut msgs, 7, 0, 1_000, 1_000, 1, &records);
+
+        let response = |error: i16| {
+            let mut data = Vec::new();
+            (42i32).encode(&mut data).unwrap(); // ~ correlation id
+            (0i32).encode(&mut data).unwrap(); // ~ throttle time
+            error.encode(&mut data).unwrap();
+            (0i32).encode(&mut data).unwrap(); // ~ session id
+            (1i32).encode(&mut data).unwrap();
+            "my-topic".encode(&mut data).unwrap();
+            (1i32).encode(&mut data).unwrap();
+            (0i32).encode(&mut data).unwrap(); // ~ partition
+            (0i16).encode(&mut data).unwrap(); // ~ error
+            (8i64).encode(&mut data).unwrap(); // ~ highwatermark
+            (8i64).encode(&mut data).unwrap(); // ~ last stable offset
+            (2i64).encode(&mut data).unwrap(); // ~ log start offset
+            (0i32).encode(&mut data).unwrap(); // ~ aborted transactions
+            (-1i32).encode(&mut data).unwrap(); // ~ preferred read replica
+            msgs[..].encode(&mut data).unwrap();
+            data
+        };
+
+        let resp = Response::from_vec(response(0), Some(&req), true).unwrap();
+        let msgs = into_messages(&resp);
+        assert_eq!(1, msgs.len());
+        assert_eq!((7, &b"foo"[..]), (msgs[0].offset, msgs[0].value));
+        match *resp.topics()[0].partitions()[0].data() {
+            Ok(ref data) => assert_eq!(8, data.highwatermark_offset()),
+            Err(ref e) => panic!("Unexpected error: {:?}", e),
+        }
+
+        // ~ an error of the request as a whole
+        match Response::from_vec(response(35), Some(&req), true) {
+            Err(Error(ErrorKind::Kafka(KafkaCode::UnsupportedVersion), _)) => {}
+            r => panic!("Expected UnsupportedVersion, but got: {:?}", r),
         }
     }
 
@@ -730,7 +2344,7 @@ mod tests {
         use super::into_messages;
 
         fn bench_decode_new_fetch_response(b: &mut Bencher, data: Vec<u8>, validate_crc: bool) {
-            let mut reqs = FetchRequest::new(0, "foo", -1, -1);
+            let mut reqs = FetchRequest::new(0, "foo", -1, -1, 0);
             reqs.add("my-topic", 0, 0, -1);
             b.bytes = data.len() as u64;
             b.iter(|| {
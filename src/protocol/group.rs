@@ -0,0 +1,694 @@
+use std::io::{Read, Write};
+
+use codecs::{self, decode_string_field, AsStrings, ToByte, FromByte};
+use error::{Error, Result};
+
+use super::{HeaderRequest, HeaderResponse};
+use super::{API_KEY_JOIN_GROUP, API_KEY_HEARTBEAT, API_KEY_LEAVE_GROUP, API_KEY_SYNC_GROUP,
+            API_KEY_DESCRIBE_GROUPS, API_KEY_LIST_GROUPS, API_VERSION};
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct JoinGroupRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub group: &'b str,
+    pub session_timeout: i32,
+    pub member_id: &'b str,
+    pub protocol_type: &'b str,
+    pub group_protocols: Vec<GroupProtocol<'b>>,
+}
+
+#[derive(Debug)]
+pub struct GroupProtocol<'a> {
+    pub name: &'a str,
+    pub metadata: Vec<u8>,
+}
+
+impl<'a, 'b> JoinGroupRequest<'a, 'b> {
+    pub fn new(
+        group: &'b str,
+        session_timeout: i32,
+        member_id: &'b str,
+        protocol_type: &'b str,
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> JoinGroupRequest<'a, 'b> {
+        JoinGroupRequest {
+            header: HeaderRequest::new(API_KEY_JOIN_GROUP, API_VERSION, correlation_id, client_id),
+            group: group,
+            session_timeout: session_timeout,
+            member_id: member_id,
+            protocol_type: protocol_type,
+            group_protocols: vec![],
+        }
+    }
+
+    pub fn add(&mut self, name: &'b str, metadata: Vec<u8>) {
+        self.group_protocols.push(GroupProtocol {
+            name: name,
+            metadata: metadata,
+        });
+    }
+}
+
+impl<'a, 'b> ToByte for JoinGroupRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.group.encode(buffer),
+            self.session_timeout.encode(buffer),
+            self.member_id.encode(buffer),
+            self.protocol_type.encode(buffer),
+            codecs::encode_as_array(buffer, &self.group_protocols, |buffer, p| {
+                try_multi!(p.name.encode(buffer), p.metadata[..].encode(buffer))
+            })
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+pub struct JoinGroupResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub generation_id: i32,
+    pub group_protocol: String,
+    pub leader_id: String,
+    pub member_id: String,
+    pub members: Vec<JoinGroupMember>,
+}
+
+#[derive(Debug, Default)]
+pub struct JoinGroupMember {
+    pub member_id: String,
+    pub metadata: Vec<u8>,
+}
+
+impl JoinGroupResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for JoinGroupResponse {
+    type R = JoinGroupResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.generation_id.decode(buffer),
+            self.group_protocol.decode(buffer),
+            self.leader_id.decode(buffer),
+            self.member_id.decode(buffer),
+            self.members.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for JoinGroupMember {
+    type R = JoinGroupMember;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.member_id.decode(buffer), self.metadata.decode(buffer))
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct SyncGroupRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub group: &'b str,
+    pub generation_id: i32,
+    pub member_id: &'b str,
+    pub group_assignment: Vec<SyncGroupAssignment<'b>>,
+}
+
+#[derive(Debug)]
+pub struct SyncGroupAssignment<'a> {
+    pub member_id: &'a str,
+    pub assignment: Vec<u8>,
+}
+
+impl<'a, 'b> SyncGroupRequest<'a, 'b> {
+    pub fn new(
+        group: &'b str,
+        generation_id: i32,
+        member_id: &'b str,
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> SyncGroupRequest<'a, 'b> {
+        SyncGroupRequest {
+            header: HeaderRequest::new(API_KEY_SYNC_GROUP, API_VERSION, correlation_id, client_id),
+            group: group,
+            generation_id: generation_id,
+            member_id: member_id,
+            group_assignment: vec![],
+        }
+    }
+
+    pub fn add(&mut self, member_id: &'b str, assignment: Vec<u8>) {
+        self.group_assignment.push(SyncGroupAssignment {
+            member_id: member_id,
+            assignment: assignment,
+        });
+    }
+}
+
+impl<'a, 'b> ToByte for SyncGroupRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.group.encode(buffer),
+            self.generation_id.encode(buffer),
+            self.member_id.encode(buffer),
+            codecs::encode_as_array(buffer, &self.group_assignment, |buffer, a| {
+                try_multi!(a.member_id.encode(buffer), a.assignment[..].encode(buffer))
+            })
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+pub struct SyncGroupResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub member_assignment: Vec<u8>,
+}
+
+impl SyncGroupResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for SyncGroupResponse {
+    type R = SyncGroupResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.member_assignment.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct HeartbeatRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub group: &'b str,
+    pub generation_id: i32,
+    pub member_id: &'b str,
+}
+
+impl<'a, 'b> HeartbeatRequest<'a, 'b> {
+    pub fn new(
+        group: &'b str,
+        generation_id: i32,
+        member_id: &'b str,
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> HeartbeatRequest<'a, 'b> {
+        HeartbeatRequest {
+            header: HeaderRequest::new(API_KEY_HEARTBEAT, API_VERSION, correlation_id, client_id),
+            group: group,
+            generation_id: generation_id,
+            member_id: member_id,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for HeartbeatRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.group.encode(buffer),
+            self.generation_id.encode(buffer),
+            self.member_id.encode(buffer)
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HeartbeatResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+}
+
+impl HeartbeatResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for HeartbeatResponse {
+    type R = HeartbeatResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.error.decode(buffer))
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct LeaveGroupRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub group: &'b str,
+    pub member_id: &'b str,
+}
+
+impl<'a, 'b> LeaveGroupRequest<'a, 'b> {
+    pub fn new(
+        group: &'b str,
+        member_id: &'b str,
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> LeaveGroupRequest<'a, 'b> {
+        LeaveGroupRequest {
+            header: HeaderRequest::new(API_KEY_LEAVE_GROUP, API_VERSION, correlation_id, client_id),
+            group: group,
+            member_id: member_id,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for LeaveGroupRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            self.group.encode(buffer),
+            self.member_id.encode(buffer)
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LeaveGroupResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+}
+
+impl LeaveGroupResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for LeaveGroupResponse {
+    type R = LeaveGroupResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.error.decode(buffer))
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct DescribeGroupsRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub groups: Vec<&'b str>,
+}
+
+impl<'a, 'b> DescribeGroupsRequest<'a, 'b> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> DescribeGroupsRequest<'a, 'b> {
+        DescribeGroupsRequest {
+            header: HeaderRequest::new(
+                API_KEY_DESCRIBE_GROUPS,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            groups: vec![],
+        }
+    }
+
+    pub fn add(&mut self, group: &'b str) {
+        self.groups.push(group);
+    }
+}
+
+impl<'a, 'b> ToByte for DescribeGroupsRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(self.header.encode(buffer), AsStrings(&self.groups).encode(buffer))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DescribeGroupsResponse {
+    pub header: HeaderResponse,
+    pub groups: Vec<DescribedGroup>,
+}
+
+#[derive(Debug, Default)]
+pub struct DescribedGroup {
+    pub error: i16,
+    pub group: String,
+    pub state: String,
+    pub protocol_type: String,
+    pub protocol: String,
+    pub members: Vec<DescribedGroupMember>,
+}
+
+#[derive(Debug, Default)]
+pub struct DescribedGroupMember {
+    pub member_id: String,
+    pub client_id: String,
+    pub client_host: String,
+    pub metadata: Vec<u8>,
+    pub assignment: Vec<u8>,
+}
+
+impl DescribeGroupsResponse {
+    /// Fails with the first group level error reported in this
+    /// response.
+    pub fn to_result(self) -> Result<Self> {
+        for g in &self.groups {
+            if let Some(e) = Error::from_protocol(g.error) {
+                return Err(e);
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl FromByte for DescribeGroupsResponse {
+    type R = DescribeGroupsResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.groups.decode(buffer))
+    }
+}
+
+impl FromByte for DescribedGroup {
+    type R = DescribedGroup;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.error.decode(buffer),
+            decode_string_field(&mut self.group, buffer, "group id"),
+            self.state.decode(buffer),
+            self.protocol_type.decode(buffer),
+            self.protocol.decode(buffer),
+            self.members.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for DescribedGroupMember {
+    type R = DescribedGroupMember;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.member_id.decode(buffer),
+            self.client_id.decode(buffer),
+            self.client_host.decode(buffer),
+            self.metadata.decode(buffer),
+            self.assignment.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct ListGroupsRequest<'a> {
+    pub header: HeaderRequest<'a>,
+}
+
+impl<'a> ListGroupsRequest<'a> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> ListGroupsRequest<'a> {
+        ListGroupsRequest {
+            header: HeaderRequest::new(API_KEY_LIST_GROUPS, API_VERSION, correlation_id, client_id),
+        }
+    }
+}
+
+impl<'a> ToByte for ListGroupsRequest<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        self.header.encode(buffer)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ListGroupsResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub groups: Vec<ListedGroup>,
+}
+
+#[derive(Debug, Default)]
+pub struct ListedGroup {
+    pub group: String,
+    pub protocol_type: String,
+}
+
+impl ListGroupsResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for ListGroupsResponse {
+    type R = ListGroupsResponse;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.groups.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for ListedGroup {
+    type R = ListedGroup;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.group, buffer, "group id"),
+            self.protocol_type.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+/// The version of the embedded "consumer" protocol this library
+/// speaks when encoding group member metadata and assignments.
+pub const CONSUMER_PROTOCOL_VERSION: i16 = 0;
+
+/// The subscription of a group member as embedded in the join group
+/// protocol metadata of groups of the "consumer" protocol type.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConsumerGroupMemberMetadata {
+    pub version: i16,
+    pub topics: Vec<String>,
+    pub user_data: Vec<u8>,
+}
+
+impl ToByte for ConsumerGroupMemberMetadata {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.version.encode(buffer),
+            AsStrings(&self.topics).encode(buffer),
+            self.user_data[..].encode(buffer)
+        )
+    }
+}
+
+impl FromByte for ConsumerGroupMemberMetadata {
+    type R = ConsumerGroupMemberMetadata;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.version.decode(buffer),
+            self.topics.decode(buffer),
+            self.user_data.decode(buffer)
+        )
+    }
+}
+
+/// The partitions assigned to a group member as embedded in the sync
+/// group assignments of groups of the "consumer" protocol type.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConsumerGroupMemberAssignment {
+    pub version: i16,
+    pub topic_partitions: Vec<ConsumerGroupTopicAssignment>,
+    pub user_data: Vec<u8>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ConsumerGroupTopicAssignment {
+    pub topic: String,
+    pub partitions: Vec<i32>,
+}
+
+impl ToByte for ConsumerGroupMemberAssignment {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.version.encode(buffer),
+            codecs::encode_as_array(buffer, &self.topic_partitions, |buffer, tp| {
+                try_multi!(tp.topic.encode(buffer), tp.partitions.encode(buffer))
+            }),
+            self.user_data[..].encode(buffer)
+        )
+    }
+}
+
+impl FromByte for ConsumerGroupMemberAssignment {
+    type R = ConsumerGroupMemberAssignment;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.version.decode(buffer),
+            self.topic_partitions.decode(buffer),
+            self.user_data.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for ConsumerGroupTopicAssignment {
+    type R = ConsumerGroupTopicAssignment;
+
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{ToByte, FromByte};
+    use error::KafkaCode;
+    use super::{ConsumerGroupMemberMetadata, ConsumerGroupMemberAssignment,
+                ConsumerGroupTopicAssignment, CONSUMER_PROTOCOL_VERSION, DescribeGroupsResponse,
+                ListGroupsResponse};
+
+    #[test]
+    fn test_list_groups_response_decode() {
+        let mut buf = vec![];
+        // ~ correlation id, error code, two groups
+        7i32.encode(&mut buf).unwrap();
+        0i16.encode(&mut buf).unwrap();
+        2i32.encode(&mut buf).unwrap();
+        "a".encode(&mut buf).unwrap();
+        "consumer".encode(&mut buf).unwrap();
+        "b".encode(&mut buf).unwrap();
+        "connect".encode(&mut buf).unwrap();
+
+        let r = ListGroupsResponse::decode_new(&mut Cursor::new(&buf[..]))
+            .unwrap()
+            .to_result()
+            .unwrap();
+        let groups: Vec<_> = r.groups
+            .iter()
+            .map(|g| (&g.group[..], &g.protocol_type[..]))
+            .collect();
+        assert_eq!(vec![("a", "consumer"), ("b", "connect")], groups);
+    }
+
+    #[test]
+    fn test_describe_groups_response_decode() {
+        let mut buf = vec![];
+        // ~ correlation id
+        7i32.encode(&mut buf).unwrap();
+        // ~ one group with one member
+        1i32.encode(&mut buf).unwrap();
+        0i16.encode(&mut buf).unwrap();
+        "grp".encode(&mut buf).unwrap();
+        "Stable".encode(&mut buf).unwrap();
+        "consumer".encode(&mut buf).unwrap();
+        "range".encode(&mut buf).unwrap();
+        1i32.encode(&mut buf).unwrap();
+        "m-1".encode(&mut buf).unwrap();
+        "client".encode(&mut buf).unwrap();
+        "/127.0.0.1".encode(&mut buf).unwrap();
+        (&[1u8, 2][..]).encode(&mut buf).unwrap();
+        (&[3u8][..]).encode(&mut buf).unwrap();
+
+        let r = DescribeGroupsResponse::decode_new(&mut Cursor::new(&buf[..])).unwrap();
+        assert_eq!(7, r.header.correlation);
+        assert_eq!(1, r.groups.len());
+        let g = &r.groups[0];
+        assert_eq!(("grp", "Stable", "consumer", "range"),
+                   (&g.group[..], &g.state[..], &g.protocol_type[..], &g.protocol[..]));
+        assert_eq!(1, g.members.len());
+        let m = &g.members[0];
+        assert_eq!(("m-1", "client", "/127.0.0.1"),
+                   (&m.member_id[..], &m.client_id[..], &m.client_host[..]));
+        assert_eq!((&[1u8, 2][..], &[3u8][..]), (&m.metadata[..], &m.assignment[..]));
+        assert!(r.to_result().is_ok());
+
+        // ~ a group level error
+        buf[4 + 4 + 1] = KafkaCode::GroupAuthorizationFailed.to_i16() as u8;
+        let r = DescribeGroupsResponse::decode_new(&mut Cursor::new(&buf[..])).unwrap();
+        assert_eq!(
+            Some(KafkaCode::GroupAuthorizationFailed.to_i16()),
+            r.to_result().unwrap_err().kafka_code()
+        );
+    }
+
+    #[test]
+    fn test_member_metadata_codec() {
+        let orig = ConsumerGroupMemberMetadata {
+            version: CONSUMER_PROTOCOL_VERSION,
+            topics: vec!["foo".to_owned(), "bar".to_owned()],
+            user_data: vec![],
+        };
+        let mut buf = vec![];
+        orig.encode(&mut buf).unwrap();
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 2, 0, 3, b'f', b'o', b'o', 0, 3, b'b', b'a', b'r', 0, 0, 0, 0],
+            buf
+        );
+        let dec = ConsumerGroupMemberMetadata::decode_new(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(orig, dec);
+    }
+
+    #[test]
+    fn test_member_assignment_codec() {
+        let orig = ConsumerGroupMemberAssignment {
+            version: CONSUMER_PROTOCOL_VERSION,
+            topic_partitions: vec![
+                ConsumerGroupTopicAssignment {
+                    topic: "foo".to_owned(),
+                    partitions: vec![0, 2],
+                },
+            ],
+            user_data: vec![1, 2],
+        };
+        let mut buf = vec![];
+        orig.encode(&mut buf).unwrap();
+        let dec = ConsumerGroupMemberAssignment::decode_new(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(orig, dec);
+    }
+}
@@ -1,10 +1,18 @@
 use std::io::{Read, Write};
 
-use error::Result;
-use codecs::{AsStrings, ToByte, FromByte};
+use error::{Error, Result};
+use codecs::{decode_string_field, AsStrings, ToByte, FromByte};
 
-use super::{HeaderRequest, HeaderResponse};
+use super::{HeaderRequest, HeaderResponse, ResponseBuffer};
 use super::{API_KEY_METADATA, API_VERSION};
+use super::zreader::ZReader;
+
+/// The first version of the metadata API reporting the controller of
+/// the cluster.
+const API_VERSION_CONTROLLER: i16 = 1;
+
+/// The newest version of the metadata API supported by this library.
+pub const MAX_API_VERSION: i16 = 5;
 
 #[derive(Debug)]
 pub struct MetadataRequest<'a, T: 'a> {
@@ -19,11 +27,32 @@ impl<'a, T: AsRef<str>> MetadataRequest<'a, T> {
             topics: topics,
         }
     }
+
+    /// Sets the version of the metadata API to request in; see
+    /// `MAX_API_VERSION`.  Parse the response through a
+    /// `ResponseParser` of the same version.
+    pub fn with_api_version(mut self, api_version: i16) -> Self {
+        self.header.api_version = api_version;
+        self
+    }
 }
 
 impl<'a, T: AsRef<str> + 'a> ToByte for MetadataRequest<'a, T> {
     fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
-        try_multi!(self.header.encode(buffer), AsStrings(self.topics).encode(buffer))
+        try!(self.header.encode(buffer));
+        if self.header.api_version >= 1 && self.topics.is_empty() {
+            // ~ as of version 1 an empty array requests no topics at
+            // all; the null array requests all of them
+            try!((-1i32).encode(buffer));
+        } else {
+            try!(AsStrings(self.topics).encode(buffer));
+        }
+        if self.header.api_version >= 4 {
+            // ~ allow auto creating the requested topics as implied
+            // by the older versions
+            try!((1i8).encode(buffer));
+        }
+        Ok(())
     }
 }
 
@@ -59,6 +88,103 @@ pub struct PartitionMetadata {
     pub isr: Vec<i32>,
 }
 
+impl PartitionMetadata {
+    /// Decodes the partition level error code; `None` if there's no
+    /// error.
+    pub fn to_error(&self) -> Option<Error> {
+        Error::from_protocol(self.error)
+    }
+}
+
+/// Parses metadata responses of the given API version.  In contrast
+/// to `MetadataResponse::decode` - which understands exactly the
+/// version 0 - this handles the versions up to `MAX_API_VERSION`
+/// skipping the fields added by the newer versions.
+pub struct ResponseParser {
+    pub api_version: i16,
+}
+
+impl super::ResponseParser for ResponseParser {
+    type T = MetadataResponse;
+
+    fn parse(&self, response: ResponseBuffer) -> Result<Self::T> {
+        let v = self.api_version;
+        let mut r = ZReader::new(&response);
+        let correlation = try!(r.read_i32());
+        if v >= 3 {
+            let _throttle_time = try!(r.read_i32());
+        }
+        let n_brokers = try!(r.read_array_len());
+        let mut brokers = Vec::with_capacity(n_brokers);
+        for _ in 0..n_brokers {
+            let node_id = try!(r.read_i32());
+            let host = try!(r.read_str()).to_owned();
+            let port = try!(r.read_i32());
+            if v >= 1 {
+                let _rack = try!(r.read_str());
+            }
+            brokers.push(BrokerMetadata {
+                node_id: node_id,
+                host: host,
+                port: port,
+            });
+        }
+        if v >= 2 {
+            let _cluster_id = try!(r.read_str());
+        }
+        if v >= 1 {
+            let _controller_id = try!(r.read_i32());
+        }
+        let n_topics = try!(r.read_array_len());
+        let mut topics = Vec::with_capacity(n_topics);
+        for _ in 0..n_topics {
+            let error = try!(r.read_i16());
+            let topic = try!(r.read_str_field("topic name")).to_owned();
+            if v >= 1 {
+                let _is_internal = try!(r.read_i8());
+            }
+            let n_partitions = try!(r.read_array_len());
+            let mut partitions = Vec::with_capacity(n_partitions);
+            for _ in 0..n_partitions {
+                let error = try!(r.read_i16());
+                let id = try!(r.read_i32());
+                let leader = try!(r.read_i32());
+                let replicas = try!(read_i32s(&mut r));
+                let isr = try!(read_i32s(&mut r));
+                if v >= 5 {
+                    let _offline_replicas = try!(read_i32s(&mut r));
+                }
+                partitions.push(PartitionMetadata {
+                    error: error,
+                    id: id,
+                    leader: leader,
+                    replicas: replicas,
+                    isr: isr,
+                });
+            }
+            topics.push(TopicMetadata {
+                error: error,
+                topic: topic,
+                partitions: partitions,
+            });
+        }
+        Ok(MetadataResponse {
+            header: HeaderResponse { correlation: correlation },
+            brokers: brokers,
+            topics: topics,
+        })
+    }
+}
+
+fn read_i32s(r: &mut ZReader) -> Result<Vec<i32>> {
+    let n = try!(r.read_array_len());
+    let mut xs = Vec::with_capacity(n);
+    for _ in 0..n {
+        xs.push(try!(r.read_i32()));
+    }
+    Ok(xs)
+}
+
 impl FromByte for MetadataResponse {
     type R = MetadataResponse;
 
@@ -88,7 +214,7 @@ impl FromByte for TopicMetadata {
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
         try_multi!(
             self.error.decode(buffer),
-            self.topic.decode(buffer),
+            decode_string_field(&mut self.topic, buffer, "topic name"),
             self.partitions.decode(buffer)
         )
     }
@@ -108,3 +234,187 @@ impl FromByte for PartitionMetadata {
         )
     }
 }
+
+// --------------------------------------------------------------------
+
+/// Requests the brokers and the controller of the cluster (but no
+/// topics.)
+#[derive(Debug)]
+pub struct ControllerMetadataRequest<'a> {
+    pub header: HeaderRequest<'a>,
+}
+
+impl<'a> ControllerMetadataRequest<'a> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> ControllerMetadataRequest<'a> {
+        ControllerMetadataRequest {
+            header: HeaderRequest::new(
+                API_KEY_METADATA,
+                API_VERSION_CONTROLLER,
+                correlation_id,
+                client_id,
+            ),
+        }
+    }
+}
+
+impl<'a> ToByte for ControllerMetadataRequest<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        // ~ an empty (as opposed to a null) array requests no topics
+        try_multi!(self.header.encode(buffer), AsStrings::<&str>(&[]).encode(buffer))
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ControllerMetadataResponse {
+    pub header: HeaderResponse,
+    pub brokers: Vec<RackBrokerMetadata>,
+    pub controller_id: i32,
+}
+
+#[derive(Default, Debug)]
+pub struct RackBrokerMetadata {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: String,
+}
+
+impl ControllerMetadataResponse {
+    /// Retrieves the controller broker; `None` if no controller is
+    /// elected (i.e. the controller id is -1) or the controller is
+    /// not among the reported brokers.
+    pub fn controller(&self) -> Option<&RackBrokerMetadata> {
+        if self.controller_id < 0 {
+            return None;
+        }
+        self.brokers.iter().find(|b| b.node_id == self.controller_id)
+    }
+
+    /// Retrieves the "host:port" of the controller broker, if known.
+    pub fn controller_host(&self) -> Option<String> {
+        self.controller().map(|b| format!("{}:{}", b.host, b.port))
+    }
+}
+
+impl FromByte for ControllerMetadataResponse {
+    type R = ControllerMetadataResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        // ~ the (empty) topics array following the controller id is
+        // of no interest
+        try_multi!(
+            self.header.decode(buffer),
+            self.brokers.decode(buffer),
+            self.controller_id.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for RackBrokerMetadata {
+    type R = RackBrokerMetadata;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.node_id.decode(buffer),
+            self.host.decode(buffer),
+            self.port.decode(buffer),
+            self.rack.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{FromByte, ToByte};
+    use protocol::ResponseParser as _;
+    use super::{MetadataRequest, MetadataResponse, ResponseParser, MAX_API_VERSION};
+
+    // ~ renders a response of the given version describing one broker
+    // and one topic with one partition
+    fn render(api_version: i16) -> Vec<u8> {
+        let mut data = Vec::new();
+        (42i32).encode(&mut data).unwrap(); // ~ correlation id
+        if api_version >= 3 {
+            (0i32).encode(&mut data).unwrap(); // ~ throttle time
+        }
+        (1i32).encode(&mut data).unwrap();
+        (7i32).encode(&mut data).unwrap(); // ~ node id
+        "kafka-7".encode(&mut data).unwrap();
+        (9092i32).encode(&mut data).unwrap();
+        if api_version >= 1 {
+            "rack-1".encode(&mut data).unwrap();
+        }
+        if api_version >= 2 {
+            "cluster".encode(&mut data).unwrap();
+        }
+        if api_version >= 1 {
+            (7i32).encode(&mut data).unwrap(); // ~ controller id
+        }
+        (1i32).encode(&mut data).unwrap();
+        (0i16).encode(&mut data).unwrap(); // ~ error
+        "foo".encode(&mut data).unwrap();
+        if api_version >= 1 {
+            (0i8).encode(&mut data).unwrap(); // ~ is internal
+        }
+        (1i32).encode(&mut data).unwrap();
+        (5i16).encode(&mut data).unwrap(); // ~ error
+        (3i32).encode(&mut data).unwrap(); // ~ partition
+        (7i32).encode(&mut data).unwrap(); // ~ leader
+        for _ in 0..2 {
+            // ~ replicas and isr
+            (1i32).encode(&mut data).unwrap();
+            (7i32).encode(&mut data).unwrap();
+        }
+        if api_version >= 5 {
+            (0i32).encode(&mut data).unwrap(); // ~ offline replicas
+        }
+        data
+    }
+
+    fn assert_response(resp: &MetadataResponse) {
+        assert_eq!(42, resp.header.correlation);
+        assert_eq!(1, resp.brokers.len());
+        let b = &resp.brokers[0];
+        assert_eq!((7, "kafka-7", 9092), (b.node_id, &b.host[..], b.port));
+        assert_eq!(1, resp.topics.len());
+        assert_eq!("foo", resp.topics[0].topic);
+        assert_eq!(1, resp.topics[0].partitions.len());
+        let p = &resp.topics[0].partitions[0];
+        assert_eq!((5, 3, 7), (p.error, p.id, p.leader));
+        assert_eq!((&[7][..], &[7][..]), (&p.replicas[..], &p.isr[..]));
+    }
+
+    #[test]
+    fn test_response_parser() {
+        for api_version in 0..MAX_API_VERSION + 1 {
+            let p = ResponseParser { api_version: api_version };
+            assert_response(&p.parse(render(api_version).into()).unwrap());
+        }
+        // ~ the version 0 is understood by the plain decoder, too
+        let resp = MetadataResponse::decode_new(&mut Cursor::new(render(0))).unwrap();
+        assert_response(&resp);
+    }
+
+    #[test]
+    fn test_request_topics() {
+        let encode = |api_version: i16, topics: &[&str]| {
+            let mut buf = Vec::new();
+            MetadataRequest::new(0, "test", topics)
+                .with_api_version(api_version)
+                .encode(&mut buf)
+                .unwrap();
+            // ~ skip the header
+            buf.split_off(2 + 2 + 4 + (2 + 4))
+        };
+        // ~ all topics
+        assert_eq!(vec![0, 0, 0, 0], encode(0, &[]));
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff], encode(1, &[]));
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff, 1], encode(4, &[]));
+        // ~ a particular topic
+        assert_eq!(vec![0, 0, 0, 1, 0, 3, b'f', b'o', b'o'], encode(1, &["foo"]));
+    }
+}
@@ -1,5 +1,7 @@
 use std::io::{Read, Write};
 use std::mem;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use codecs::{ToByte, FromByte};
@@ -18,6 +20,11 @@ pub mod produce;
 pub mod offset;
 pub mod metadata;
 pub mod consumer;
+pub mod group;
+pub mod api_versions;
+pub mod admin;
+pub mod sasl;
+pub mod producer_id;
 
 mod zreader;
 pub mod fetch;
@@ -26,22 +33,51 @@ pub mod fetch;
 // submodules
 pub use self::fetch::FetchRequest;
 pub use self::produce::{ProduceRequest, ProduceResponse};
-pub use self::offset::{OffsetRequest, OffsetResponse};
-pub use self::metadata::{MetadataRequest, MetadataResponse};
+pub use self::offset::{OffsetRequest, OffsetResponse, TimeOffsetResponse};
+pub use self::metadata::{MetadataRequest, MetadataResponse, ControllerMetadataRequest,
+                         ControllerMetadataResponse};
 pub use self::consumer::{GroupCoordinatorRequest, GroupCoordinatorResponse, OffsetFetchVersion,
                          OffsetFetchRequest, OffsetFetchResponse, OffsetCommitVersion,
                          OffsetCommitRequest, OffsetCommitResponse};
+pub use self::group::{JoinGroupRequest, JoinGroupResponse, SyncGroupRequest, SyncGroupResponse,
+                      HeartbeatRequest, HeartbeatResponse, LeaveGroupRequest,
+                      LeaveGroupResponse, DescribeGroupsRequest, DescribeGroupsResponse,
+                      ListGroupsRequest, ListGroupsResponse};
+pub use self::api_versions::{ApiVersionsRequest, ApiVersionsResponse};
+pub use self::admin::{CreateTopicsRequest, CreateTopicsResponse, DeleteTopicsRequest,
+                      DeleteTopicsResponse, CreatePartitionsRequest, CreatePartitionsResponse,
+                      DeleteRecordsRequest, DeleteRecordsResponse, DescribeConfigsRequest,
+                      DescribeConfigsResponse, AlterConfigsRequest, AlterConfigsResponse};
+pub use self::producer_id::{InitProducerIdRequest, InitProducerIdResponse};
+pub use self::sasl::{SaslHandshakeRequest, SaslHandshakeResponse, SaslAuthenticateRequest,
+                     SaslAuthenticateResponse};
 
 // --------------------------------------------------------------------
 
-const API_KEY_PRODUCE: i16 = 0;
-const API_KEY_FETCH: i16 = 1;
+pub const API_KEY_PRODUCE: i16 = 0;
+pub const API_KEY_FETCH: i16 = 1;
 const API_KEY_OFFSET: i16 = 2;
-const API_KEY_METADATA: i16 = 3;
+pub const API_KEY_METADATA: i16 = 3;
 // 4-7 reserved for non-public kafka api services
 const API_KEY_OFFSET_COMMIT: i16 = 8;
 const API_KEY_OFFSET_FETCH: i16 = 9;
 const API_KEY_GROUP_COORDINATOR: i16 = 10;
+const API_KEY_JOIN_GROUP: i16 = 11;
+const API_KEY_HEARTBEAT: i16 = 12;
+const API_KEY_LEAVE_GROUP: i16 = 13;
+const API_KEY_SYNC_GROUP: i16 = 14;
+const API_KEY_DESCRIBE_GROUPS: i16 = 15;
+const API_KEY_LIST_GROUPS: i16 = 16;
+pub const API_KEY_SASL_HANDSHAKE: i16 = 17;
+const API_KEY_API_VERSIONS: i16 = 18;
+const API_KEY_CREATE_TOPICS: i16 = 19;
+const API_KEY_DELETE_TOPICS: i16 = 20;
+const API_KEY_DELETE_RECORDS: i16 = 21;
+const API_KEY_INIT_PRODUCER_ID: i16 = 22;
+const API_KEY_DESCRIBE_CONFIGS: i16 = 32;
+const API_KEY_ALTER_CONFIGS: i16 = 33;
+const API_KEY_SASL_AUTHENTICATE: i16 = 36;
+const API_KEY_CREATE_PARTITIONS: i16 = 37;
 
 // the default version of Kafka API we are requesting
 const API_VERSION: i16 = 0;
@@ -52,17 +88,69 @@ const API_VERSION: i16 = 0;
 /// particular response structure.
 pub trait ResponseParser {
     type T;
-    fn parse(&self, response: Vec<u8>) -> Result<Self::T>;
+    fn parse(&self, response: ResponseBuffer) -> Result<Self::T>;
+}
+
+/// The raw data of a response read into a buffer lent out by a broker
+/// connection.  Once dropped, the buffer returns to the connection to
+/// read subsequent responses into; parsed responses referring to the
+/// raw data keep the buffer for as long as they live.
+#[derive(Debug)]
+pub struct ResponseBuffer {
+    data: Vec<u8>,
+    // ~ the slot of the connection the buffer returns to; `None` if
+    // the buffer is not to be reused
+    home: Option<Arc<Mutex<Vec<u8>>>>,
+}
+
+impl ResponseBuffer {
+    pub fn new(data: Vec<u8>, home: Arc<Mutex<Vec<u8>>>) -> ResponseBuffer {
+        ResponseBuffer {
+            data: data,
+            home: Some(home),
+        }
+    }
+}
+
+impl From<Vec<u8>> for ResponseBuffer {
+    fn from(data: Vec<u8>) -> ResponseBuffer {
+        ResponseBuffer {
+            data: data,
+            home: None,
+        }
+    }
+}
+
+impl Deref for ResponseBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for ResponseBuffer {
+    fn drop(&mut self) {
+        if let Some(ref home) = self.home {
+            // ~ a poisoned slot merely costs the reuse of the buffer
+            if let Ok(mut slot) = home.lock() {
+                *slot = mem::replace(&mut self.data, Vec::new());
+            }
+        }
+    }
 }
 
 // --------------------------------------------------------------------
 
 impl KafkaCode {
+    /// Decodes the error code as transmitted by Kafka.  Returns `None`
+    /// for `0` (no error); non-zero codes unknown to this library are
+    /// mapped to `KafkaCode::Unknown` and should be treated as such.
     fn from_protocol(n: i16) -> Option<KafkaCode> {
         if n == 0 {
             return None;
         }
-        if n >= KafkaCode::OffsetOutOfRange as i16 && n <= KafkaCode::UnsupportedVersion as i16 {
+        if n >= KafkaCode::OffsetOutOfRange as i16 && n <= KafkaCode::InvalidProducerEpoch as i16 {
             return Some(unsafe { mem::transmute(n as i8) });
         }
         Some(KafkaCode::Unknown)
@@ -87,6 +175,12 @@ fn test_kafka_code_from_protocol() {
     assert_kafka_code!(KafkaCode::OffsetOutOfRange, KafkaCode::OffsetOutOfRange as i16);
     assert_kafka_code!(KafkaCode::IllegalGeneration, KafkaCode::IllegalGeneration as i16);
     assert_kafka_code!(KafkaCode::UnsupportedVersion, KafkaCode::UnsupportedVersion as i16);
+    assert_kafka_code!(KafkaCode::TopicAlreadyExists, KafkaCode::TopicAlreadyExists as i16);
+    assert_kafka_code!(KafkaCode::NotController, KafkaCode::NotController as i16);
+    assert_kafka_code!(
+        KafkaCode::DuplicateSequenceNumber,
+        KafkaCode::DuplicateSequenceNumber as i16
+    );
     assert_kafka_code!(KafkaCode::Unknown, KafkaCode::Unknown as i16);
     // ~ test some un mapped non-zero codes; should all map to "unknown"
     assert_kafka_code!(KafkaCode::Unknown, i16::MAX);
@@ -95,10 +189,75 @@ fn test_kafka_code_from_protocol() {
     assert_kafka_code!(KafkaCode::Unknown, 100);
 }
 
+#[test]
+fn test_kafka_code_from_protocol_all_mapped() {
+    for n in 1..32 {
+        match KafkaCode::from_protocol(n) {
+            None | Some(KafkaCode::Unknown) => panic!("Kafka code {} not decoded", n),
+            Some(_) => {}
+        }
+    }
+    assert_eq!(Some(KafkaCode::NetworkException), KafkaCode::from_protocol(13));
+}
+
+#[test]
+fn test_kafka_code_to_i16() {
+    assert_eq!(1, KafkaCode::OffsetOutOfRange.to_i16());
+    assert_eq!(-1, KafkaCode::Unknown.to_i16());
+    // ~ round trip all the mapped codes
+    let max = KafkaCode::InvalidProducerEpoch.to_i16();
+    for n in KafkaCode::OffsetOutOfRange.to_i16()..(max + 1) {
+        assert_eq!(Some(n), KafkaCode::from_protocol(n).map(|c| c.to_i16()));
+    }
+}
+
 // a (sub-) module private method for error
 impl Error {
+    /// Decodes the error code as transmitted by Kafka.  Returns `None`
+    /// for `0` (no error); non-zero codes unknown to this library are
+    /// preserved as `ErrorKind::UnknownKafkaCode`.
     fn from_protocol(n: i16) -> Option<Error> {
-        KafkaCode::from_protocol(n).map(|err| ErrorKind::Kafka(err).into())
+        match KafkaCode::from_protocol(n) {
+            None => None,
+            Some(KafkaCode::Unknown) if n != KafkaCode::Unknown as i16 => {
+                Some(ErrorKind::UnknownKafkaCode(n).into())
+            }
+            Some(err) => Some(ErrorKind::Kafka(err).into()),
+        }
+    }
+}
+
+/// Decodes the error code as transmitted by Kafka; see
+/// `Error::from_protocol`.
+pub fn kafka_error(n: i16) -> Option<Error> {
+    Error::from_protocol(n)
+}
+
+/// Determines the `KafkaCode` of an error decoded from the code
+/// transmitted by Kafka; codes unknown to this library map to
+/// `KafkaCode::Unknown`.  The raw code remains available through
+/// `Error::kafka_code`.
+pub fn kafka_code(e: &Error) -> KafkaCode {
+    match *e.kind() {
+        ErrorKind::Kafka(code) => code,
+        _ => KafkaCode::Unknown,
+    }
+}
+
+#[test]
+fn test_error_from_protocol() {
+    assert!(Error::from_protocol(0).is_none());
+    match Error::from_protocol(KafkaCode::NetworkException as i16) {
+        Some(Error(ErrorKind::Kafka(KafkaCode::NetworkException), _)) => {}
+        e => panic!("Expected NetworkException, but got: {:?}", e),
+    }
+    match Error::from_protocol(-1) {
+        Some(Error(ErrorKind::Kafka(KafkaCode::Unknown), _)) => {}
+        e => panic!("Expected Unknown, but got: {:?}", e),
+    }
+    match Error::from_protocol(100) {
+        Some(Error(ErrorKind::UnknownKafkaCode(100), _)) => {}
+        e => panic!("Expected UnknownKafkaCode(100), but got: {:?}", e),
     }
 }
 
@@ -178,6 +337,32 @@ pub fn to_millis_i32(d: Duration) -> Result<i32> {
     }
 }
 
+/// Safely converts a Duration into the number of milliseconds as a
+/// i64 as required e.g. for retention times in the kafka protocol.
+pub fn to_millis_i64(d: Duration) -> Result<i64> {
+    use std::i64;
+    let m = d.as_secs().saturating_mul(1_000).saturating_add(
+        (d.subsec_nanos() / 1_000_000) as
+            u64,
+    );
+    if m > i64::MAX as u64 {
+        bail!(ErrorKind::InvalidDuration)
+    } else {
+        Ok(m as i64)
+    }
+}
+
+#[test]
+fn test_to_millis_i64() {
+    use std::u64;
+
+    assert_eq!(540_123, to_millis_i64(Duration::new(540, 123_456_789)).unwrap());
+    assert_eq!(
+        Some(ErrorKind::InvalidDuration),
+        to_millis_i64(Duration::from_millis(u64::MAX)).err().map(|e| e.0)
+    );
+}
+
 #[test]
 fn test_to_millis_i32() {
     use std::{i32, u32, u64};
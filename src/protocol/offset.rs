@@ -1,12 +1,16 @@
 use std::io::{Read, Write};
 
-use std;
-use codecs::{ToByte, FromByte};
-use error::{Result, KafkaCode};
+use codecs::{decode_string_field, encode_as_array, ToByte, FromByte};
+use error::{Error, Result};
 use utils::PartitionOffset;
 use super::{HeaderRequest, HeaderResponse};
 use super::{API_KEY_OFFSET, API_VERSION};
 
+/// The version of the offset (aka list offsets) API looking up the
+/// offset of the first message with a timestamp at or after a given
+/// time.
+const API_VERSION_BY_TIME: i16 = 1;
+
 
 #[derive(Debug)]
 pub struct OffsetRequest<'a> {
@@ -37,6 +41,21 @@ impl<'a> OffsetRequest<'a> {
         }
     }
 
+    /// Creates a request for the version `1` of the offset API.  The
+    /// response needs to be decoded as `TimeOffsetResponse`.
+    pub fn new_by_time(correlation_id: i32, client_id: &'a str) -> OffsetRequest<'a> {
+        OffsetRequest {
+            header: HeaderRequest::new(
+                API_KEY_OFFSET,
+                API_VERSION_BY_TIME,
+                correlation_id,
+                client_id,
+            ),
+            replica: -1,
+            topic_partitions: vec![],
+        }
+    }
+
     pub fn add(&mut self, topic: &'a str, partition: i32, time: i64) {
         for tp in &mut self.topic_partitions {
             if tp.topic == topic {
@@ -77,26 +96,23 @@ impl PartitionOffsetRequest {
 
 impl<'a> ToByte for OffsetRequest<'a> {
     fn encode<T: Write>(&self, buffer: &mut T) -> Result<()> {
+        let by_time = self.header.api_version >= API_VERSION_BY_TIME;
         try_multi!(
             self.header.encode(buffer),
             self.replica.encode(buffer),
-            self.topic_partitions.encode(buffer)
-        )
-    }
-}
-
-impl<'a> ToByte for TopicPartitionOffsetRequest<'a> {
-    fn encode<T: Write>(&self, buffer: &mut T) -> Result<()> {
-        try_multi!(self.topic.encode(buffer), self.partitions.encode(buffer))
-    }
-}
-
-impl ToByte for PartitionOffsetRequest {
-    fn encode<T: Write>(&self, buffer: &mut T) -> Result<()> {
-        try_multi!(
-            self.partition.encode(buffer),
-            self.time.encode(buffer),
-            self.max_offsets.encode(buffer)
+            encode_as_array(buffer, &self.topic_partitions, |buffer, tp| {
+                try!(tp.topic.encode(buffer));
+                encode_as_array(buffer, &tp.partitions, |buffer, p| {
+                    try!(p.partition.encode(buffer));
+                    try!(p.time.encode(buffer));
+                    // ~ version 1 delivers a single offset per
+                    // partition and does not allow asking for more
+                    if !by_time {
+                        try!(p.max_offsets.encode(buffer));
+                    }
+                    Ok(())
+                })
+            })
         )
     }
 }
@@ -123,9 +139,9 @@ pub struct PartitionOffsetResponse {
 }
 
 impl PartitionOffsetResponse {
-    pub fn into_offset(&self) -> std::result::Result<PartitionOffset, KafkaCode> {
-        match KafkaCode::from_protocol(self.error) {
-            Some(code) => Err(code),
+    pub fn into_offset(&self) -> Result<PartitionOffset> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
             None => {
                 let offset = match self.offset.first() {
                     Some(offs) => *offs,
@@ -155,7 +171,10 @@ impl FromByte for TopicPartitionOffsetResponse {
 
     #[allow(unused_must_use)]
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
-        try_multi!(self.topic.decode(buffer), self.partitions.decode(buffer))
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
     }
 }
 
@@ -171,3 +190,160 @@ impl FromByte for PartitionOffsetResponse {
         )
     }
 }
+
+// --------------------------------------------------------------------
+
+/// The response to an `OffsetRequest` created through
+/// `OffsetRequest::new_by_time`.
+#[derive(Default, Debug)]
+pub struct TimeOffsetResponse {
+    pub header: HeaderResponse,
+    pub topic_partitions: Vec<TopicPartitionTimeOffsetResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct TopicPartitionTimeOffsetResponse {
+    pub topic: String,
+    pub partitions: Vec<PartitionTimeOffsetResponse>,
+}
+
+#[derive(Default, Debug)]
+pub struct PartitionTimeOffsetResponse {
+    pub partition: i32,
+    pub error: i16,
+    pub timestamp: i64,
+    pub offset: i64,
+}
+
+impl PartitionTimeOffsetResponse {
+    pub fn into_offset(&self) -> Result<PartitionOffset> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(PartitionOffset {
+                partition: self.partition,
+                offset: self.offset,
+            }),
+        }
+    }
+}
+
+impl FromByte for TimeOffsetResponse {
+    type R = TimeOffsetResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(self.header.decode(buffer), self.topic_partitions.decode(buffer))
+    }
+}
+
+impl FromByte for TopicPartitionTimeOffsetResponse {
+    type R = TopicPartitionTimeOffsetResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
+    }
+}
+
+impl FromByte for PartitionTimeOffsetResponse {
+    type R = PartitionTimeOffsetResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.partition.decode(buffer),
+            self.error.decode(buffer),
+            self.timestamp.decode(buffer),
+            self.offset.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{ToByte, FromByte};
+    use error::{Error, ErrorKind, KafkaCode};
+    use utils::PartitionOffset;
+    use super::{OffsetRequest, PartitionOffsetResponse, TimeOffsetResponse};
+
+    #[test]
+    fn test_encode_offset_request() {
+        let mut req = OffsetRequest::new(1, "x");
+        req.add("t", 0, -2);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 2, 0, 0, 0, 0, 0, 1, 0, 1, b'x', // header
+            255, 255, 255, 255, // replica
+            0, 0, 0, 1, 0, 1, b't', // topic
+            0, 0, 0, 1, 0, 0, 0, 0, // partition
+            255, 255, 255, 255, 255, 255, 255, 254, // time
+            0, 0, 0, 1, // max offsets
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_encode_offset_request_by_time() {
+        let mut req = OffsetRequest::new_by_time(1, "x");
+        req.add("t", 0, 1_000);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0, 2, 0, 1, 0, 0, 0, 1, 0, 1, b'x', // header
+            255, 255, 255, 255, // replica
+            0, 0, 0, 1, 0, 1, b't', // topic
+            0, 0, 0, 1, 0, 0, 0, 0, // partition
+            0, 0, 0, 0, 0, 0, 3, 232, // time
+        ];
+        assert_eq!(expected, &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_time_offset_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 1, // correlation
+            0, 0, 0, 1, 0, 1, b't', // topic
+            0, 0, 0, 2, // partitions
+            0, 0, 0, 0, 0, 0, // partition 0; no error
+            0, 0, 0, 0, 0, 0, 3, 233, // timestamp
+            0, 0, 0, 0, 0, 0, 0, 42, // offset
+            0, 0, 0, 1, 0, 3, // partition 1; unknown topic or partition
+            255, 255, 255, 255, 255, 255, 255, 255,
+            255, 255, 255, 255, 255, 255, 255, 255,
+        ];
+        let resp = TimeOffsetResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        let tp = &resp.topic_partitions[0];
+        assert_eq!("t", tp.topic);
+        assert_eq!(1_001, tp.partitions[0].timestamp);
+        assert_eq!(
+            PartitionOffset {
+                partition: 0,
+                offset: 42,
+            },
+            tp.partitions[0].into_offset().unwrap()
+        );
+        match tp.partitions[1].into_offset() {
+            Err(Error(ErrorKind::Kafka(KafkaCode::UnknownTopicOrPartition), _)) => {}
+            r => panic!("Expected UnknownTopicOrPartition, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_into_offset_preserves_unknown_codes() {
+        let p = PartitionOffsetResponse {
+            partition: 0,
+            error: 100,
+            offset: vec![],
+        };
+        match p.into_offset() {
+            Err(Error(ErrorKind::UnknownKafkaCode(100), _)) => {}
+            r => panic!("Expected UnknownKafkaCode(100), but got: {:?}", r),
+        }
+    }
+}
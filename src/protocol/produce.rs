@@ -1,21 +1,68 @@
 use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use codecs::{ToByte, FromByte};
+use codecs::{crc32c, decode_string_field, write_varint, ToByte, FromByte};
 use compression::Compression;
 #[cfg(feature = "gzip")]
 use compression::gzip;
 #[cfg(feature = "snappy")]
 use compression::snappy;
+#[cfg(feature = "lz4")]
+use compression::lz4;
+#[cfg(feature = "zstd")]
+use compression::zstd;
 
-use error::{KafkaCode, Result};
+use error::{Error, Result};
+#[cfg(feature = "zstd")]
+use error::ErrorKind;
 
 use producer::{ProduceConfirm, ProducePartitionConfirm};
-use super::{HeaderRequest, HeaderResponse};
+use super::{HeaderRequest, HeaderResponse, ResponseBuffer, kafka_code};
 use super::{API_KEY_PRODUCE, API_VERSION};
 use super::to_crc;
+use super::zreader::ZReader;
 
-/// The magic byte (a.k.a version) we use for sent messages.
-const MESSAGE_MAGIC_BYTE: i8 = 0;
+/// The version of the produce API required to send messages in the
+/// message format v1 (i.e. with the magic byte 1.)
+const API_VERSION_MESSAGE_V1: i16 = 2;
+
+/// The version of the produce API required to send record batches
+/// (i.e. messages in the format v2.)
+const API_VERSION_MESSAGE_V2: i16 = 3;
+
+/// The newest version of the produce API supported by this library.
+/// The versions 4 to 7 render requests just like the version 3; their
+/// responses differ as of version 5 only.
+pub const MAX_API_VERSION: i16 = 7;
+
+/// Determines the newest message format which can be sent through
+/// the given version of the produce API.
+pub fn max_message_version(api_version: i16) -> i8 {
+    match api_version {
+        v if v >= API_VERSION_MESSAGE_V2 => 2,
+        v if v >= API_VERSION_MESSAGE_V1 => 1,
+        _ => 0,
+    }
+}
+
+/// The first version of the produce API accepting zstd compressed
+/// messages.
+#[cfg(feature = "zstd")]
+pub const ZSTD_MIN_API_VERSION: i16 = 7;
+
+/// Determines the version of the produce API to send record batches
+/// compressed with the given codec through; zstd compressed batches
+/// require a newer version than all other codecs.
+#[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+fn record_batch_api_version(compression: Compression) -> i16 {
+    #[cfg(feature = "zstd")]
+    {
+        if let Compression::ZSTD = compression {
+            return ZSTD_MIN_API_VERSION;
+        }
+    }
+    API_VERSION_MESSAGE_V2
+}
 
 #[derive(Debug)]
 pub struct ProduceRequest<'a, 'b> {
@@ -24,6 +71,13 @@ pub struct ProduceRequest<'a, 'b> {
     pub timeout: i32,
     pub topic_partitions: Vec<TopicPartitionProduceRequest<'b>>,
     pub compression: Compression,
+    pub compression_level: u32,
+    // ~ the size in bytes a message set must exceed to get compressed
+    pub compression_threshold: usize,
+    // ~ the magic byte of the sent messages
+    pub message_version: i8,
+    // ~ the timestamp of messages not explicitly carrying one
+    pub timestamp: i64,
 }
 
 #[derive(Debug)]
@@ -31,18 +85,28 @@ pub struct TopicPartitionProduceRequest<'a> {
     pub topic: &'a str,
     pub partitions: Vec<PartitionProduceRequest<'a>>,
     pub compression: Compression,
+    pub compression_level: u32,
+    pub compression_threshold: usize,
+    pub message_version: i8,
 }
 
 #[derive(Debug)]
 pub struct PartitionProduceRequest<'a> {
     pub partition: i32,
     pub messages: Vec<MessageProduceRequest<'a>>,
+    // ~ the idempotent producer identification of the record batch;
+    // -1 if the producer is not idempotent
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
 }
 
 #[derive(Debug)]
 pub struct MessageProduceRequest<'a> {
     key: Option<&'a [u8]>,
     value: Option<&'a [u8]>,
+    timestamp: i64,
+    headers: &'a [(String, Vec<u8>)],
 }
 
 impl<'a, 'b> ProduceRequest<'a, 'b> {
@@ -52,48 +116,114 @@ impl<'a, 'b> ProduceRequest<'a, 'b> {
         correlation_id: i32,
         client_id: &'a str,
         compression: Compression,
+        compression_level: u32,
+        message_version: i8,
     ) -> ProduceRequest<'a, 'b> {
+        let api_version = match message_version {
+            v if v >= 2 => record_batch_api_version(compression),
+            1 => API_VERSION_MESSAGE_V1,
+            _ => API_VERSION,
+        };
         ProduceRequest {
-            header: HeaderRequest::new(API_KEY_PRODUCE, API_VERSION, correlation_id, client_id),
+            header: HeaderRequest::new(API_KEY_PRODUCE, api_version, correlation_id, client_id),
             required_acks: required_acks,
             timeout: timeout,
             topic_partitions: vec![],
             compression: compression,
+            compression_level: compression_level,
+            compression_threshold: 0,
+            message_version: message_version,
+            timestamp: now_millis(),
         }
     }
 
+    /// Sets the size in bytes the uncompressed message set of a
+    /// partition must exceed to get compressed; smaller message sets
+    /// are sent uncompressed.  Applies to the messages added
+    /// subsequently.
+    pub fn with_compression_threshold(mut self, threshold: usize) -> ProduceRequest<'a, 'b> {
+        self.compression_threshold = threshold;
+        self
+    }
+
+    /// Adds a message to this request.  Messages without an explicit
+    /// `timestamp` are assigned the time of this request's creation.
+    /// Timestamps are transmitted only as part of the message formats
+    /// v1 and v2, `headers` only as part of the latter.
     pub fn add(
         &mut self,
         topic: &'b str,
         partition: i32,
         key: Option<&'b [u8]>,
         value: Option<&'b [u8]>,
+        timestamp: Option<i64>,
+        headers: &'b [(String, Vec<u8>)],
     ) {
+        let timestamp = timestamp.unwrap_or(self.timestamp);
         for tp in &mut self.topic_partitions {
             if tp.topic == topic {
-                tp.add(partition, key, value);
+                tp.add(partition, key, value, timestamp, headers);
                 return;
             }
         }
-        let mut tp = TopicPartitionProduceRequest::new(topic, self.compression);
-        tp.add(partition, key, value);
+        let mut tp = TopicPartitionProduceRequest::new(
+            topic,
+            self.compression,
+            self.compression_level,
+            self.message_version,
+        );
+        tp.compression_threshold = self.compression_threshold;
+        tp.add(partition, key, value, timestamp, headers);
         self.topic_partitions.push(tp);
     }
+
+    /// Marks the record batches of this request as sent by the
+    /// identified idempotent producer; `base_sequence` determines
+    /// the sequence number of the first message of the given topic
+    /// partition.  To be called once all messages have been added.
+    /// Takes effect only with the message format v2.
+    pub fn set_producer<F>(&mut self, producer_id: i64, producer_epoch: i16, mut base_sequence: F)
+    where
+        F: FnMut(&str, i32) -> i32,
+    {
+        for tp in &mut self.topic_partitions {
+            for pp in &mut tp.partitions {
+                pp.producer_id = producer_id;
+                pp.producer_epoch = producer_epoch;
+                pp.base_sequence = base_sequence(tp.topic, pp.partition);
+            }
+        }
+    }
 }
 
 impl<'a> TopicPartitionProduceRequest<'a> {
-    pub fn new(topic: &'a str, compression: Compression) -> TopicPartitionProduceRequest<'a> {
+    pub fn new(
+        topic: &'a str,
+        compression: Compression,
+        compression_level: u32,
+        message_version: i8,
+    ) -> TopicPartitionProduceRequest<'a> {
         TopicPartitionProduceRequest {
             topic: topic,
             partitions: vec![],
             compression: compression,
+            compression_level: compression_level,
+            compression_threshold: 0,
+            message_version: message_version,
         }
     }
 
-    pub fn add(&mut self, partition: i32, key: Option<&'a [u8]>, value: Option<&'a [u8]>) {
+    pub fn add(
+        &mut self,
+        partition: i32,
+        key: Option<&'a [u8]>,
+        value: Option<&'a [u8]>,
+        timestamp: i64,
+        headers: &'a [(String, Vec<u8>)],
+    ) {
         for pp in &mut self.partitions {
             if pp.partition == partition {
-                pp.add(key, value);
+                pp.add(key, value, timestamp, headers);
                 return;
             }
         }
@@ -101,6 +231,8 @@ impl<'a> TopicPartitionProduceRequest<'a> {
             partition,
             key,
             value,
+            timestamp,
+            headers,
         ));
     }
 }
@@ -110,24 +242,57 @@ impl<'a> PartitionProduceRequest<'a> {
         partition: i32,
         key: Option<&'b [u8]>,
         value: Option<&'b [u8]>,
+        timestamp: i64,
+        headers: &'b [(String, Vec<u8>)],
     ) -> PartitionProduceRequest<'b> {
         let mut r = PartitionProduceRequest {
             partition: partition,
             messages: Vec::new(),
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
         };
-        r.add(key, value);
+        r.add(key, value, timestamp, headers);
         r
     }
 
-    pub fn add(&mut self, key: Option<&'a [u8]>, value: Option<&'a [u8]>) {
-        self.messages.push(MessageProduceRequest::new(key, value));
+    pub fn add(
+        &mut self,
+        key: Option<&'a [u8]>,
+        value: Option<&'a [u8]>,
+        timestamp: i64,
+        headers: &'a [(String, Vec<u8>)],
+    ) {
+        self.messages.push(MessageProduceRequest::new(key, value, timestamp, headers));
+    }
+}
+
+/// Determines the current time in milliseconds since the unix epoch.
+fn now_millis() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64 * 1_000 + (d.subsec_nanos() / 1_000_000) as i64,
+        Err(_) => 0,
     }
 }
 
 impl<'a, 'b> ToByte for ProduceRequest<'a, 'b> {
     fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        // ~ reject zstd early rather than having the broker reject
+        // the request
+        #[cfg(feature = "zstd")]
+        {
+            if let Compression::ZSTD = self.compression {
+                if self.header.api_version < ZSTD_MIN_API_VERSION {
+                    bail!(ErrorKind::UnsupportedCompression);
+                }
+            }
+        }
+        try!(self.header.encode(buffer));
+        if self.header.api_version >= API_VERSION_MESSAGE_V2 {
+            // ~ the transactional id; null
+            try!((-1i16).encode(buffer));
+        }
         try_multi!(
-            self.header.encode(buffer),
             self.required_acks.encode(buffer),
             self.timeout.encode(buffer),
             self.topic_partitions.encode(buffer)
@@ -141,7 +306,13 @@ impl<'a> ToByte for TopicPartitionProduceRequest<'a> {
         try!(self.topic.encode(buffer));
         try!((self.partitions.len() as i32).encode(buffer));
         for e in &self.partitions {
-            try!(e._encode(buffer, self.compression))
+            try!(e._encode(
+                buffer,
+                self.compression,
+                self.compression_level,
+                self.compression_threshold,
+                self.message_version,
+            ))
         }
         Ok(())
     }
@@ -152,65 +323,211 @@ impl<'a> PartitionProduceRequest<'a> {
     //
     // MessetSet => [Offset MessageSize Message]
     // MessageSets are not preceded by an int32 like other array elements in the protocol.
-    fn _encode<W: Write>(&self, out: &mut W, compression: Compression) -> Result<()> {
+    fn _encode<W: Write>(
+        &self,
+        out: &mut W,
+        compression: Compression,
+        compression_level: u32,
+        compression_threshold: usize,
+        magic: i8,
+    ) -> Result<()> {
         try!(self.partition.encode(out));
+        if magic >= 2 {
+            return self._encode_record_batch(
+                out,
+                compression,
+                compression_level,
+                compression_threshold,
+            );
+        }
 
         // ~ render the whole MessageSet first to a temporary buffer
+        let mut buf = try!(self.render_messages(magic, false));
+        let compression = effective_compression(compression, buf.len(), compression_threshold);
+        // ~ in the message format v1 the messages to be compressed
+        // carry offsets relative to their wrapper message
+        if magic >= 1 && compression as i8 != Compression::NONE as i8 {
+            buf = try!(self.render_messages(magic, true));
+        }
+        if let Some(cdata) = try!(compress(&buf, compression, compression_level, magic)) {
+            try!(self.render_compressed(&mut buf, &cdata, compression, magic));
+        }
+        buf.encode(out)
+    }
+
+    // ~ renders the messages of this partition as a message set
+    // (without the leading size) of the given format; either with
+    // zero or relative offsets
+    fn render_messages(&self, magic: i8, relative_offsets: bool) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
-        for msg in &self.messages {
-            try!(msg._encode_to_buf(&mut buf, MESSAGE_MAGIC_BYTE, 0));
+        for (i, msg) in self.messages.iter().enumerate() {
+            let offset = if relative_offsets { i as i64 } else { 0 };
+            try!(msg._encode_to_buf(&mut buf, offset, magic, 0));
         }
-        match compression {
-            Compression::NONE => {
-                // ~ nothing to do
-            }
-            #[cfg(feature = "gzip")]
-            Compression::GZIP => {
-                let cdata = try!(gzip::compress(&buf));
-                try!(render_compressed(&mut buf, &cdata, compression));
-            }
-            #[cfg(feature = "snappy")]
-            Compression::SNAPPY => {
-                let cdata = try!(snappy::compress(&buf));
-                try!(render_compressed(&mut buf, &cdata, compression));
-            }
+        Ok(buf)
+    }
+
+    // render: RecordBatchSize RecordBatch
+    //
+    // RecordBatch => BaseOffset BatchLength PartitionLeaderEpoch Magic Crc
+    //                Attributes LastOffsetDelta FirstTimestamp MaxTimestamp
+    //                ProducerId ProducerEpoch BaseSequence [Record]
+    // BaseOffset => int64 (zero; assigned by the broker)
+    // BatchLength => int32
+    // PartitionLeaderEpoch => int32
+    // Magic => int8 (two)
+    // Crc => int32 (crc32c of the data following the crc)
+    // Attributes => int16
+    // LastOffsetDelta => int32
+    // FirstTimestamp, MaxTimestamp, ProducerId => int64
+    // ProducerEpoch => int16
+    // BaseSequence => int32
+    //
+    // note: the records are compressed as a whole (if at all)
+    fn _encode_record_batch<W: Write>(
+        &self,
+        out: &mut W,
+        compression: Compression,
+        compression_level: u32,
+        compression_threshold: usize,
+    ) -> Result<()> {
+        let first_timestamp = self.messages.first().map(|m| m.timestamp).unwrap_or(0);
+        let max_timestamp = self.messages.iter().map(|m| m.timestamp).max().unwrap_or(0);
+        let mut records = Vec::new();
+        for (i, msg) in self.messages.iter().enumerate() {
+            try!(msg._encode_record(&mut records, i as i32, first_timestamp));
         }
+        let compression = effective_compression(compression, records.len(), compression_threshold);
+        if let Some(cdata) = try!(compress(&records, compression, compression_level, 2)) {
+            records = cdata;
+        }
+
+        let mut buf = Vec::with_capacity(records.len() + 61);
+        try!((0i64).encode(&mut buf));
+        let size_pos = buf.len();
+        try!((0i32).encode(&mut buf)); // reserve space for the size to be computed later
+        try!((-1i32).encode(&mut buf));
+        try!((2i8).encode(&mut buf));
+        let crc_pos = buf.len();
+        try!((0i32).encode(&mut buf)); // reserve space for the crc to be computed later
+        try!((compression as i16).encode(&mut buf));
+        try!((self.messages.len() as i32 - 1).encode(&mut buf));
+        try!(first_timestamp.encode(&mut buf));
+        try!(max_timestamp.encode(&mut buf));
+        // ~ the producer is never transactional; if it isn't
+        // idempotent either, these are all -1
+        try!(self.producer_id.encode(&mut buf));
+        try!(self.producer_epoch.encode(&mut buf));
+        try!(self.base_sequence.encode(&mut buf));
+        try!((self.messages.len() as i32).encode(&mut buf));
+        buf.extend_from_slice(&records);
+
+        // compute the crc and store it back in the reserved space
+        let crc = crc32c(&buf[(crc_pos + 4)..]) as i32;
+        try!(crc.encode(&mut &mut buf[crc_pos..crc_pos + 4]));
+
+        // compute the size and store it back in the reserved space
+        let size = (buf.len() - size_pos - 4) as i32;
+        try!(size.encode(&mut &mut buf[size_pos..size_pos + 4]));
+
         buf.encode(out)
     }
+
+    // ~ A helper method to render `cdata` into `out` as a compressed message.
+    // ~ `out` is first cleared and then populated with the rendered message.
+    fn render_compressed(
+        &self,
+        out: &mut Vec<u8>,
+        cdata: &[u8],
+        compression: Compression,
+        magic: i8,
+    ) -> Result<()> {
+        out.clear();
+        // ~ in the message format v1 the wrapper carries the offset of
+        // its last inner message and the latest of their timestamps
+        let (offset, timestamp) = if magic >= 1 {
+            (
+                self.messages.len() as i64 - 1,
+                self.messages.iter().map(|m| m.timestamp).max().unwrap_or(0),
+            )
+        } else {
+            (0, 0)
+        };
+        let cmsg = MessageProduceRequest::new(None, Some(cdata), timestamp, &[]);
+        cmsg._encode_to_buf(out, offset, magic, compression as i8)
+    }
+}
+
+/// Determines the compression to apply to a message set of the
+/// given (uncompressed) size; `Compression::NONE` unless the size
+/// exceeds the given threshold.
+fn effective_compression(compression: Compression, size: usize, threshold: usize) -> Compression {
+    if size > threshold {
+        compression
+    } else {
+        Compression::NONE
+    }
 }
 
-// ~ A helper method to render `cdata` into `out` as a compressed message.
-// ~ `out` is first cleared and then populated with the rendered message.
-#[cfg(any(feature = "snappy", feature = "gzip"))]
-fn render_compressed(out: &mut Vec<u8>, cdata: &[u8], compression: Compression) -> Result<()> {
-    out.clear();
-    let cmsg = MessageProduceRequest::new(None, Some(cdata));
-    cmsg._encode_to_buf(out, MESSAGE_MAGIC_BYTE, compression as i8)
+/// Compresses `data` using the given codec as part of messages of the
+/// specified format; `None` if no compression is requested.
+#[cfg_attr(not(all(feature = "gzip", feature = "lz4")), allow(unused_variables))]
+fn compress(
+    data: &[u8],
+    compression: Compression,
+    compression_level: u32,
+    magic: i8,
+) -> Result<Option<Vec<u8>>> {
+    match compression {
+        Compression::NONE => Ok(None),
+        #[cfg(feature = "gzip")]
+        Compression::GZIP => gzip::compress(data, compression_level).map(Some),
+        #[cfg(feature = "snappy")]
+        Compression::SNAPPY => snappy::compress(data).map(Some),
+        #[cfg(feature = "lz4")]
+        Compression::LZ4 => lz4::compress(data, magic == 0).map(Some),
+        #[cfg(feature = "zstd")]
+        Compression::ZSTD => zstd::compress(data).map(Some),
+    }
 }
 
 impl<'a> MessageProduceRequest<'a> {
-    fn new<'b>(key: Option<&'b [u8]>, value: Option<&'b [u8]>) -> MessageProduceRequest<'b> {
+    fn new<'b>(
+        key: Option<&'b [u8]>,
+        value: Option<&'b [u8]>,
+        timestamp: i64,
+        headers: &'b [(String, Vec<u8>)],
+    ) -> MessageProduceRequest<'b> {
         MessageProduceRequest {
             key: key,
             value: value,
+            timestamp: timestamp,
+            headers: headers,
         }
     }
 
     // render a single message as: Offset MessageSize Message
     //
-    // Offset => int64 (always encoded as zero here)
+    // Offset => int64 (zero except for messages inside v1 wrappers)
     // MessageSize => int32
-    // Message => Crc MagicByte Attributes Key Value
+    // Message => Crc MagicByte Attributes [Timestamp] Key Value
     // Crc => int32
     // MagicByte => int8
     // Attributes => int8
+    // Timestamp => int64 (only if MagicByte >= 1)
     // Key => bytes
     // Value => bytes
     //
     // note: the rendered data corresponds to a single MessageSet in the kafka protocol
-    fn _encode_to_buf(&self, buffer: &mut Vec<u8>, magic: i8, attributes: i8) -> Result<()> {
+    fn _encode_to_buf(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset: i64,
+        magic: i8,
+        attributes: i8,
+    ) -> Result<()> {
 
-        try!((0i64).encode(buffer)); // offset in the response request can be anything
+        try!(offset.encode(buffer)); // offset in the response request can be anything
 
         let size_pos = buffer.len();
         let mut size: i32 = 0;
@@ -221,6 +538,9 @@ impl<'a> MessageProduceRequest<'a> {
         try!(crc.encode(buffer)); // reserve space for the crc to be computed later
         try!(magic.encode(buffer));
         try!(attributes.encode(buffer));
+        if magic >= 1 {
+            try!(self.timestamp.encode(buffer));
+        }
         try!(self.key.encode(buffer));
         try!(self.value.encode(buffer));
 
@@ -234,6 +554,50 @@ impl<'a> MessageProduceRequest<'a> {
 
         Ok(())
     }
+
+    // render a single record of a record batch as: Length Record
+    //
+    // Length => varint
+    // Record => Attributes TimestampDelta OffsetDelta Key Value [Header]
+    // Attributes => int8 (unused)
+    // TimestampDelta => varlong (relative to the batch's first timestamp)
+    // OffsetDelta => varint
+    // Key => varint length (-1 for null) followed by the data
+    // Value => varint length (-1 for null) followed by the data
+    // Header => HeaderKey HeaderValue (both rendered like Key)
+    fn _encode_record(
+        &self,
+        buffer: &mut Vec<u8>,
+        offset_delta: i32,
+        first_timestamp: i64,
+    ) -> Result<()> {
+        let mut rec = Vec::new();
+        try!((0i8).encode(&mut rec));
+        try!(write_varint(&mut rec, self.timestamp - first_timestamp));
+        try!(write_varint(&mut rec, offset_delta as i64));
+        try!(encode_varint_bytes(&mut rec, self.key));
+        try!(encode_varint_bytes(&mut rec, self.value));
+        try!(write_varint(&mut rec, self.headers.len() as i64));
+        for (key, value) in self.headers {
+            try!(encode_varint_bytes(&mut rec, Some(key.as_bytes())));
+            try!(encode_varint_bytes(&mut rec, Some(value)));
+        }
+        try!(write_varint(buffer, rec.len() as i64));
+        buffer.extend_from_slice(&rec);
+        Ok(())
+    }
+}
+
+/// Renders `data` prefixed by its varint encoded length as used by
+/// the record batch format; `None` renders as the length `-1`.
+fn encode_varint_bytes<W: Write>(buffer: &mut W, data: Option<&[u8]>) -> Result<()> {
+    match data {
+        None => write_varint(buffer, -1),
+        Some(data) => {
+            try!(write_varint(buffer, data.len() as i64));
+            buffer.write_all(data).or_else(|e| Err(From::from(e)))
+        }
+    }
 }
 
 impl<'a> ToByte for Option<&'a [u8]> {
@@ -251,6 +615,10 @@ impl<'a> ToByte for Option<&'a [u8]> {
 pub struct ProduceResponse {
     pub header: HeaderResponse,
     pub topic_partitions: Vec<TopicPartitionProduceResponse>,
+    // ~ the duration in milliseconds the request was throttled due
+    // to a quota violation; available as of version 1 (zero
+    // otherwise)
+    pub throttle_time: i32,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -264,6 +632,13 @@ pub struct PartitionProduceResponse {
     pub partition: i32,
     pub error: i16,
     pub offset: i64,
+    // ~ the time at which the broker appended the messages; available
+    // as of version 2 for topics configured with the log append time
+    // as their message timestamp type (-1 otherwise)
+    pub log_append_time: i64,
+    // ~ the earliest offset of the partition; available as of
+    // version 5 (-1 otherwise)
+    pub log_start_offset: i64,
 }
 
 impl ProduceResponse {
@@ -293,9 +668,14 @@ impl PartitionProduceResponse {
     pub fn get_response(&self) -> ProducePartitionConfirm {
         ProducePartitionConfirm {
             partition: self.partition,
-            offset: match KafkaCode::from_protocol(self.error) {
+            offset: match Error::from_protocol(self.error) {
                 None => Ok(self.offset),
-                Some(code) => Err(code),
+                Some(e) => Err(kafka_code(&e)),
+            },
+            error_code: self.error,
+            log_append_time: match self.log_append_time {
+                -1 => None,
+                t => Some(t),
             },
         }
     }
@@ -315,7 +695,70 @@ impl FromByte for TopicPartitionProduceResponse {
 
     #[allow(unused_must_use)]
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
-        try_multi!(self.topic.decode(buffer), self.partitions.decode(buffer))
+        try_multi!(
+            decode_string_field(&mut self.topic, buffer, "topic name"),
+            self.partitions.decode(buffer)
+        )
+    }
+}
+
+/// Parses produce responses of the given API version.  In contrast to
+/// `ProduceResponse::decode` - which understands exactly the version
+/// 0 - this handles the additional fields of the versions 1 to 7.
+pub struct ResponseParser {
+    pub api_version: i16,
+}
+
+impl super::ResponseParser for ResponseParser {
+    type T = ProduceResponse;
+
+    fn parse(&self, response: ResponseBuffer) -> Result<Self::T> {
+        let mut r = ZReader::new(&response);
+        let correlation = try!(r.read_i32());
+        let n_topics = try!(r.read_array_len());
+        let mut topic_partitions = Vec::with_capacity(n_topics);
+        for _ in 0..n_topics {
+            let topic = try!(r.read_str_field("topic name")).to_owned();
+            let n_partitions = try!(r.read_array_len());
+            let mut partitions = Vec::with_capacity(n_partitions);
+            for _ in 0..n_partitions {
+                let partition = try!(r.read_i32());
+                let error = try!(r.read_i16());
+                let offset = try!(r.read_i64());
+                let log_append_time = if self.api_version >= 2 {
+                    try!(r.read_i64())
+                } else {
+                    -1
+                };
+                let log_start_offset = if self.api_version >= 5 {
+                    try!(r.read_i64())
+                } else {
+                    -1
+                };
+                partitions.push(PartitionProduceResponse {
+                    partition: partition,
+                    error: error,
+                    offset: offset,
+                    log_append_time: log_append_time,
+                    log_start_offset: log_start_offset,
+                });
+            }
+            topic_partitions.push(TopicPartitionProduceResponse {
+                topic: topic,
+                partitions: partitions,
+            });
+        }
+        // ~ versions 1 and up end with the throttle time
+        let throttle_time = if self.api_version >= 1 {
+            try!(r.read_i32())
+        } else {
+            0
+        };
+        Ok(ProduceResponse {
+            header: HeaderResponse { correlation: correlation },
+            topic_partitions: topic_partitions,
+            throttle_time: throttle_time,
+        })
     }
 }
 
@@ -324,6 +767,8 @@ impl FromByte for PartitionProduceResponse {
 
     #[allow(unused_must_use)]
     fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        self.log_append_time = -1;
+        self.log_start_offset = -1;
         try_multi!(
             self.partition.decode(buffer),
             self.error.decode(buffer),
@@ -331,3 +776,252 @@ impl FromByte for PartitionProduceResponse {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use codecs::ToByte;
+    use compression::Compression;
+    #[cfg(feature = "zstd")]
+    use error::{Error, ErrorKind};
+    use protocol::zreader::ZReader;
+    use super::{ProduceRequest, PartitionProduceRequest};
+
+    #[test]
+    fn test_message_null_key_encoding() {
+        let mut buf = Vec::new();
+        PartitionProduceRequest::new(0, None, Some(b"v"), -1, &[])
+            ._encode(&mut buf, Compression::NONE, 0, 0, 0)
+            .unwrap();
+        // ~ partition, message set size, offset, message size, crc,
+        // magic byte, attributes
+        let mut r = ZReader::new(&buf[4 + 4 + 8 + 4 + 4 + 1 + 1..]);
+        // ~ a null key is rendered with a length of -1
+        assert_eq!(-1, r.read_i32().unwrap());
+        assert_eq!(b"v", r.read_bytes().unwrap());
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_compression_threshold() {
+        // ~ renders a single message of the given format with gzip
+        // and the given threshold; returns the attributes of the
+        // rendered message (set)
+        let attributes = |magic: i8, threshold: usize| {
+            let mut buf = Vec::new();
+            PartitionProduceRequest::new(0, None, Some(b"0123456789"), -1, &[])
+                ._encode(&mut buf, Compression::GZIP, 6, threshold, magic)
+                .unwrap();
+            // ~ partition, message set size, (base) offset, message size
+            // (batch length)
+            let mut r = ZReader::new(&buf[4 + 4 + 8 + 4..]);
+            if magic >= 2 {
+                // ~ leader epoch, magic, crc
+                r.read_i32().unwrap();
+                r.read_i8().unwrap();
+                r.read_i32().unwrap();
+                r.read_i16().unwrap() as i8
+            } else {
+                // ~ crc, magic
+                r.read_i32().unwrap();
+                r.read_i8().unwrap();
+                r.read_i8().unwrap()
+            }
+        };
+        for magic in 0..3 {
+            // ~ the message set is far smaller than the threshold
+            assert_eq!(0, attributes(magic, 1_000));
+            // ~ the message set exceeds the threshold
+            assert_eq!(Compression::GZIP as i8, attributes(magic, 0));
+        }
+    }
+
+    #[test]
+    fn test_record_batch_encoding() {
+        use codecs::crc32c;
+
+        let mut req = ProduceRequest::new(1, 1000, 1, "test", Compression::NONE, 0, 2);
+        req.add("foo", 0, None, Some(b"a"), Some(2_000), &[]);
+        req.add("foo", 0, None, Some(b"b"), Some(1_000), &[]);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+
+        let mut r = ZReader::new(&buf);
+        r.read_i16().unwrap(); // ~ api key
+        assert_eq!(3, r.read_i16().unwrap());
+        r.read_i32().unwrap(); // ~ correlation id
+        assert_eq!("test", r.read_str().unwrap());
+        // ~ a null transactional id
+        assert_eq!(-1, r.read_i16().unwrap());
+        assert_eq!(1, r.read_i16().unwrap());
+        assert_eq!(1000, r.read_i32().unwrap());
+        assert_eq!(1, r.read_array_len().unwrap());
+        assert_eq!("foo", r.read_str().unwrap());
+        assert_eq!(1, r.read_array_len().unwrap());
+        assert_eq!(0, r.read_i32().unwrap());
+        let mut batch = ZReader::new(r.read_bytes().unwrap());
+        assert!(r.is_empty());
+
+        assert_eq!(0, batch.read_i64().unwrap()); // ~ base offset
+        let len = batch.read_i32().unwrap();
+        assert_eq!(len as usize, batch.rest().len());
+        assert_eq!(-1, batch.read_i32().unwrap()); // ~ partition leader epoch
+        assert_eq!(2, batch.read_i8().unwrap()); // ~ magic
+        let crc = batch.read_i32().unwrap();
+        assert_eq!(crc32c(batch.rest()) as i32, crc);
+        assert_eq!(0, batch.read_i16().unwrap()); // ~ attributes
+        assert_eq!(1, batch.read_i32().unwrap()); // ~ last offset delta
+        assert_eq!(2_000, batch.read_i64().unwrap()); // ~ first timestamp
+        assert_eq!(2_000, batch.read_i64().unwrap()); // ~ max timestamp
+        assert_eq!(-1, batch.read_i64().unwrap()); // ~ producer id
+        assert_eq!(-1, batch.read_i16().unwrap()); // ~ producer epoch
+        assert_eq!(-1, batch.read_i32().unwrap()); // ~ base sequence
+        assert_eq!(2, batch.read_i32().unwrap());
+
+        // ~ the second record's timestamp lies before the first one's
+        assert_eq!(7, batch.read_varint().unwrap());
+        assert_eq!(0, batch.read_i8().unwrap());
+        assert_eq!(0, batch.read_varlong().unwrap());
+        assert_eq!(0, batch.read_varint().unwrap());
+        assert_eq!(-1, batch.read_varint().unwrap());
+        assert_eq!(b"a", batch.read_varint_bytes().unwrap());
+        assert_eq!(0, batch.read_varint().unwrap());
+        assert_eq!(8, batch.read_varint().unwrap());
+        assert_eq!(0, batch.read_i8().unwrap());
+        assert_eq!(-1_000, batch.read_varlong().unwrap());
+        assert_eq!(1, batch.read_varint().unwrap());
+    }
+
+    #[test]
+    fn test_record_batch_producer_encoding() {
+        let mut req = ProduceRequest::new(-1, 1000, 1, "test", Compression::NONE, 0, 2);
+        req.add("foo", 0, None, Some(b"a"), Some(1_000), &[]);
+        req.add("foo", 1, None, Some(b"b"), Some(1_000), &[]);
+        req.set_producer(77, 3, |topic, partition| {
+            assert_eq!("foo", topic);
+            10 + partition
+        });
+        for (partition, pp) in req.topic_partitions[0].partitions.iter().enumerate() {
+            let mut buf = Vec::new();
+            pp._encode(&mut buf, Compression::NONE, 0, 0, 2).unwrap();
+            // ~ partition, batch size, and the batch up to the
+            // producer id
+            let mut batch = ZReader::new(&buf[4 + 4 + 8 + 4 + 4 + 1 + 4 + 2 + 4 + 8 + 8..]);
+            assert_eq!(77, batch.read_i64().unwrap()); // ~ producer id
+            assert_eq!(3, batch.read_i16().unwrap()); // ~ producer epoch
+            assert_eq!(10 + partition as i32, batch.read_i32().unwrap()); // ~ base sequence
+        }
+    }
+
+    #[test]
+    fn test_add_groups_by_topic_partition() {
+        let mut req = ProduceRequest::new(1, 1000, 1, "test-client", Compression::NONE, 0, 0);
+        let msgs: &[(&str, i32, &str)] = &[
+            ("foo", 0, "a"),
+            ("bar", 0, "b"),
+            ("foo", 1, "c"),
+            ("foo", 0, "d"),
+            ("bar", 0, "e"),
+        ];
+        for &(topic, partition, value) in msgs {
+            req.add(topic, partition, None, Some(value.as_bytes()), None, &[]);
+        }
+        let layout: Vec<(&str, i32, Vec<&[u8]>)> = req.topic_partitions
+            .iter()
+            .flat_map(|tp| {
+                tp.partitions.iter().map(move |p| {
+                    (tp.topic, p.partition, p.messages.iter().map(|m| m.value.unwrap()).collect())
+                })
+            })
+            .collect();
+        // ~ one entry per topic partition preserving the order of the
+        // added messages
+        assert_eq!(
+            vec![
+                ("foo", 0, vec![&b"a"[..], &b"d"[..]]),
+                ("foo", 1, vec![&b"c"[..]]),
+                ("bar", 0, vec![&b"b"[..], &b"e"[..]]),
+            ],
+            layout
+        );
+    }
+
+    #[test]
+    fn test_response_parser() {
+        use codecs::ToByte;
+        use protocol::ResponseParser as _;
+        use super::ResponseParser;
+
+        // ~ renders a response of the given version
+        let render = |api_version: i16| {
+            let mut data = Vec::new();
+            (42i32).encode(&mut data).unwrap(); // ~ correlation id
+            (1i32).encode(&mut data).unwrap();
+            "foo".encode(&mut data).unwrap();
+            (1i32).encode(&mut data).unwrap();
+            (3i32).encode(&mut data).unwrap(); // ~ partition
+            (0i16).encode(&mut data).unwrap(); // ~ error
+            (1_000i64).encode(&mut data).unwrap(); // ~ offset
+            if api_version >= 2 {
+                (1_500i64).encode(&mut data).unwrap(); // ~ log append time
+            }
+            if api_version >= 5 {
+                (900i64).encode(&mut data).unwrap(); // ~ log start offset
+            }
+            if api_version >= 1 {
+                (250i32).encode(&mut data).unwrap(); // ~ throttle time
+            }
+            data
+        };
+        for api_version in 0..8 {
+            let p = ResponseParser { api_version: api_version };
+            let resp = p.parse(render(api_version).into()).unwrap();
+            assert_eq!(42, resp.header.correlation);
+            assert_eq!(
+                if api_version >= 5 { 900 } else { -1 },
+                resp.topic_partitions[0].partitions[0].log_start_offset
+            );
+            assert_eq!(if api_version >= 1 { 250 } else { 0 }, resp.throttle_time);
+            let confirms = resp.get_response();
+            assert_eq!(1, confirms.len());
+            assert_eq!("foo", confirms[0].topic);
+            assert_eq!(3, confirms[0].partition_confirms[0].partition);
+            assert_eq!(Ok(1_000), confirms[0].partition_confirms[0].offset);
+            assert_eq!(
+                if api_version >= 2 { Some(1_500) } else { None },
+                confirms[0].partition_confirms[0].log_append_time
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_unsupported_api_version() {
+        let mut req = ProduceRequest::new(1, 1000, 1, "test-client", Compression::ZSTD, 0, 0);
+        req.add("foo", 0, None, Some("bar".as_bytes()), None, &[]);
+        let mut buf = Vec::new();
+        match req.encode(&mut buf) {
+            Err(Error(ErrorKind::UnsupportedCompression, _)) => {}
+            r => panic!("Expected UnsupportedCompression, but got: {:?}", r),
+        }
+        // ~ nothing rendered for the broker to reject
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_zstd_api_version() {
+        let mut req = ProduceRequest::new(1, 1000, 1, "test-client", Compression::ZSTD, 0, 2);
+        assert_eq!(7, req.header.api_version);
+        req.add("foo", 0, None, Some("bar".as_bytes()), None, &[]);
+        let mut buf = Vec::new();
+        req.encode(&mut buf).unwrap();
+        let mut r = ZReader::new(&buf);
+        assert_eq!(0, r.read_i16().unwrap()); // ~ api key
+        assert_eq!(7, r.read_i16().unwrap()); // ~ api version
+
+        // ~ other codecs stick to the version 3
+        let req = ProduceRequest::new(1, 1000, 1, "test-client", Compression::NONE, 0, 2);
+        assert_eq!(3, req.header.api_version);
+    }
+}
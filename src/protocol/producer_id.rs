@@ -0,0 +1,109 @@
+use std::io::{Read, Write};
+
+use codecs::{ToByte, FromByte};
+use error::{Error, Result};
+
+use super::{HeaderRequest, HeaderResponse};
+use super::{API_KEY_INIT_PRODUCER_ID, API_VERSION};
+
+/// Requests a producer id and epoch for an idempotent (but not
+/// transactional) producer.  Supported as of Kafka 0.11.
+#[derive(Debug)]
+pub struct InitProducerIdRequest<'a> {
+    pub header: HeaderRequest<'a>,
+    pub transaction_timeout: i32,
+}
+
+impl<'a> InitProducerIdRequest<'a> {
+    pub fn new(correlation_id: i32, client_id: &'a str) -> InitProducerIdRequest<'a> {
+        InitProducerIdRequest {
+            header: HeaderRequest::new(
+                API_KEY_INIT_PRODUCER_ID,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            // ~ not relevant without a transactional id
+            transaction_timeout: i32::max_value(),
+        }
+    }
+}
+
+impl<'a> ToByte for InitProducerIdRequest<'a> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(
+            self.header.encode(buffer),
+            // ~ the transactional id; null
+            (-1i16).encode(buffer),
+            self.transaction_timeout.encode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Default, Debug)]
+pub struct InitProducerIdResponse {
+    pub header: HeaderResponse,
+    pub throttle_time: i32,
+    pub error: i16,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+impl InitProducerIdResponse {
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for InitProducerIdResponse {
+    type R = InitProducerIdResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.throttle_time.decode(buffer),
+            self.error.decode(buffer),
+            self.producer_id.decode(buffer),
+            self.producer_epoch.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{ToByte, FromByte};
+    use super::{InitProducerIdRequest, InitProducerIdResponse};
+
+    #[test]
+    fn test_encode_init_producer_id_request() {
+        let mut buf = Vec::new();
+        InitProducerIdRequest::new(5, "c").encode(&mut buf).unwrap();
+        assert_eq!(
+            &[0, 22, 0, 0, 0, 0, 0, 5, 0, 1, b'c', 255, 255, 127, 255, 255, 255][..],
+            &buf[..]
+        );
+    }
+
+    #[test]
+    fn test_decode_init_producer_id_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 5, // correlation
+            0, 0, 0, 0, // throttle time
+            0, 0, // error
+            0, 0, 0, 0, 0, 0, 3, 232, // producer id
+            0, 2, // producer epoch
+        ];
+        let resp = InitProducerIdResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        let resp = resp.to_result().unwrap();
+        assert_eq!(1000, resp.producer_id);
+        assert_eq!(2, resp.producer_epoch);
+    }
+}
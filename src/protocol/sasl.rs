@@ -0,0 +1,206 @@
+use std::io::{Read, Write};
+
+use codecs::{ToByte, FromByte};
+use error::{Error, ErrorKind, KafkaCode, Result};
+
+use super::{HeaderRequest, HeaderResponse};
+use super::{API_KEY_SASL_HANDSHAKE, API_KEY_SASL_AUTHENTICATE, API_VERSION};
+
+/// The version of the sasl handshake request announcing that the
+/// authentication bytes will be exchanged through
+/// `SaslAuthenticateRequest`s (rather than as raw, size-prefixed
+/// data.)
+pub const SASL_HANDSHAKE_AUTHENTICATE_VERSION: i16 = 1;
+
+#[derive(Debug)]
+pub struct SaslHandshakeRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub mechanism: &'b str,
+}
+
+impl<'a, 'b> SaslHandshakeRequest<'a, 'b> {
+    pub fn new(
+        version: i16,
+        mechanism: &'b str,
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> SaslHandshakeRequest<'a, 'b> {
+        SaslHandshakeRequest {
+            header: HeaderRequest::new(API_KEY_SASL_HANDSHAKE, version, correlation_id, client_id),
+            mechanism: mechanism,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for SaslHandshakeRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(self.header.encode(buffer), self.mechanism.encode(buffer))
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct SaslHandshakeResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub enabled_mechanisms: Vec<String>,
+}
+
+impl SaslHandshakeResponse {
+    /// Fails with `ErrorKind::AuthenticationFailed` if the broker
+    /// does not support the requested mechanism.
+    pub fn to_result(self) -> Result<Self> {
+        if self.error == KafkaCode::UnsupportedSaslMechanism as i16 {
+            bail!(ErrorKind::AuthenticationFailed(format!(
+                "mechanism not enabled by the broker; enabled mechanisms: {:?}",
+                self.enabled_mechanisms
+            )));
+        }
+        match Error::from_protocol(self.error) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+impl FromByte for SaslHandshakeResponse {
+    type R = SaslHandshakeResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.enabled_mechanisms.decode(buffer)
+        )
+    }
+}
+
+// --------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct SaslAuthenticateRequest<'a, 'b> {
+    pub header: HeaderRequest<'a>,
+    pub auth_bytes: &'b [u8],
+}
+
+impl<'a, 'b> SaslAuthenticateRequest<'a, 'b> {
+    pub fn new(
+        auth_bytes: &'b [u8],
+        correlation_id: i32,
+        client_id: &'a str,
+    ) -> SaslAuthenticateRequest<'a, 'b> {
+        SaslAuthenticateRequest {
+            header: HeaderRequest::new(
+                API_KEY_SASL_AUTHENTICATE,
+                API_VERSION,
+                correlation_id,
+                client_id,
+            ),
+            auth_bytes: auth_bytes,
+        }
+    }
+}
+
+impl<'a, 'b> ToByte for SaslAuthenticateRequest<'a, 'b> {
+    fn encode<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        try_multi!(self.header.encode(buffer), self.auth_bytes.encode(buffer))
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct SaslAuthenticateResponse {
+    pub header: HeaderResponse,
+    pub error: i16,
+    pub error_message: String,
+    pub auth_bytes: Vec<u8>,
+}
+
+impl SaslAuthenticateResponse {
+    /// Fails with `ErrorKind::AuthenticationFailed` if the broker
+    /// rejected the authentication.
+    pub fn to_result(self) -> Result<Self> {
+        match Error::from_protocol(self.error) {
+            None => Ok(self),
+            Some(_) if !self.error_message.is_empty() => {
+                bail!(ErrorKind::AuthenticationFailed(self.error_message))
+            }
+            Some(e) => bail!(ErrorKind::AuthenticationFailed(e.to_string())),
+        }
+    }
+}
+
+impl FromByte for SaslAuthenticateResponse {
+    type R = SaslAuthenticateResponse;
+
+    #[allow(unused_must_use)]
+    fn decode<T: Read>(&mut self, buffer: &mut T) -> Result<()> {
+        try_multi!(
+            self.header.decode(buffer),
+            self.error.decode(buffer),
+            self.error_message.decode(buffer),
+            self.auth_bytes.decode(buffer)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use codecs::{ToByte, FromByte};
+    use error::{Error, ErrorKind};
+    use super::{SaslHandshakeRequest, SaslHandshakeResponse, SaslAuthenticateRequest,
+                SaslAuthenticateResponse};
+
+    #[test]
+    fn test_encode_sasl_requests() {
+        let mut buf = Vec::new();
+        SaslHandshakeRequest::new(1, "PLAIN", 3, "c").encode(&mut buf).unwrap();
+        assert_eq!(
+            &[0, 17, 0, 1, 0, 0, 0, 3, 0, 1, b'c', 0, 5, b'P', b'L', b'A', b'I', b'N'][..],
+            &buf[..]
+        );
+
+        let mut buf = Vec::new();
+        SaslAuthenticateRequest::new(b"\0u\0p", 4, "c").encode(&mut buf).unwrap();
+        assert_eq!(
+            &[0, 36, 0, 0, 0, 0, 0, 4, 0, 1, b'c', 0, 0, 0, 4, 0, b'u', 0, b'p'][..],
+            &buf[..]
+        );
+    }
+
+    #[test]
+    fn test_decode_sasl_handshake_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 3, // correlation
+            0, 33, // error: unsupported sasl mechanism
+            0, 0, 0, 1, // array length
+            0, 5, b'S', b'C', b'R', b'A', b'M',
+        ];
+        let resp = SaslHandshakeResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(vec!["SCRAM".to_owned()], resp.enabled_mechanisms);
+        match resp.to_result() {
+            Err(Error(ErrorKind::AuthenticationFailed(_), _)) => {}
+            r => panic!("Expected AuthenticationFailed, but got: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_decode_sasl_authenticate_response() {
+        static DATA: &'static [u8] = &[
+            0, 0, 0, 4, // correlation
+            0, 58, // error: sasl authentication failed
+            0, 3, b'b', b'a', b'd', // error message
+            0, 0, 0, 0, // auth bytes
+        ];
+        let resp = SaslAuthenticateResponse::decode_new(&mut Cursor::new(DATA)).unwrap();
+        assert_eq!(
+            Some(ErrorKind::AuthenticationFailed("bad".to_owned())),
+            resp.to_result().err().map(|e| e.0)
+        );
+
+        static OK: &'static [u8] = &[0, 0, 0, 4, 0, 0, 255, 255, 0, 0, 0, 0];
+        let resp = SaslAuthenticateResponse::decode_new(&mut Cursor::new(OK)).unwrap();
+        assert!(resp.to_result().is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 use std::str;
 
 use byteorder::{BigEndian, ByteOrder};
+use codecs::{name_field, read_varint_i32, read_varint_i64};
 use error::{ErrorKind, Result};
 
 static EMPTY_STR: &'static str = "";
@@ -69,13 +70,16 @@ impl<'a> ZReader<'a> {
             Ok(EMPTY_STR)
         } else {
             // alternatively: str::from_utf8_unchecked(..)
-            match str::from_utf8(try!(self.read(len as usize))) {
-                Ok(s) => Ok(s),
-                Err(_) => bail!(ErrorKind::StringDecodeError),
-            }
+            Ok(try!(str::from_utf8(try!(self.read(len as usize)))))
         }
     }
 
+    /// Reads a string just like `read_str` but reports invalid utf8
+    /// as `ErrorKind::FieldDecodeError` naming the given field.
+    pub fn read_str_field<'b>(&'b mut self, field: &'static str) -> Result<&'a str> {
+        self.read_str().map_err(|e| name_field(e, field))
+    }
+
     /// Reads 'bytes' as defined by the Kafka Protocol. The 'null'
     /// bytes are delivered as an empty slice.
     pub fn read_bytes<'b>(&'b mut self) -> Result<&'a [u8]> {
@@ -87,12 +91,53 @@ impl<'a> ZReader<'a> {
         }
     }
 
+    /// Reads a zigzag encoded, variable length 32 bit integer as used
+    /// by the record batch format. Upon failure the reader will _not_
+    /// advance.
+    pub fn read_varint(&mut self) -> Result<i32> {
+        let mut data = self.data;
+        let v = try!(read_varint_i32(&mut data));
+        self.data = data;
+        Ok(v)
+    }
+
+    /// Reads a zigzag encoded, variable length 64 bit integer as used
+    /// by the record batch format. Upon failure the reader will _not_
+    /// advance.
+    pub fn read_varlong(&mut self) -> Result<i64> {
+        let mut data = self.data;
+        let v = try!(read_varint_i64(&mut data));
+        self.data = data;
+        Ok(v)
+    }
+
+    /// Reads 'bytes' prefixed by their varint encoded length as used
+    /// by the record batch format. The 'null' bytes are delivered as
+    /// an empty slice.
+    pub fn read_varint_bytes<'b>(&'b mut self) -> Result<&'a [u8]> {
+        let len = try!(self.read_varint());
+        if len <= 0 {
+            Ok(&self.data[0..0])
+        } else {
+            self.read(len as usize)
+        }
+    }
+
     /// Reads the size of an array as defined by the Kafka
     /// Protocol. The size of 'null' array will be returned as the
-    /// size an array of an empty array.
+    /// size an array of an empty array.  Since every array element
+    /// occupies at least one byte, a size exceeding the number of
+    /// the remaining bytes is rejected as `ErrorKind::CodecError`;
+    /// this prevents excessive pre-allocations for corrupted input.
     pub fn read_array_len(&mut self) -> Result<usize> {
         let len = try!(self.read_i32());
-        Ok(if len < 0 { 0 } else { len as usize })
+        if len < 0 {
+            return Ok(0);
+        }
+        if len as usize > self.data.len() {
+            bail!(ErrorKind::CodecError);
+        }
+        Ok(len as usize)
     }
 }
 
@@ -214,6 +259,75 @@ fn test_read_str() {
     assert!(r.read_str().is_err());
 }
 
+#[test]
+fn test_read_str_invalid_utf8() {
+    use error::Error;
+
+    let data = &[0u8, 3, b'h', 0xff, b'o'];
+    let mut r = ZReader::new(data);
+    match r.read_str() {
+        Err(Error(ErrorKind::StringDecodeError(1), _)) => {}
+        e => panic!("Expected StringDecodeError(1), but got: {:?}", e),
+    }
+    let mut r = ZReader::new(data);
+    match r.read_str_field("topic name") {
+        Err(Error(ErrorKind::FieldDecodeError("topic name", 1), _)) => {}
+        e => panic!("Expected FieldDecodeError(\"topic name\", 1), but got: {:?}", e),
+    }
+}
+
+#[test]
+fn test_read_varint() {
+    let data = &[0x00, 0x01, 0x02, 0x7f, 0x80, 0x01, 0xfe, 0xff, 0xff, 0xff, 0x0f,
+                 0xff, 0xff, 0xff, 0xff, 0x0f];
+    let mut r = ZReader::new(data);
+    assert_eq!(0, r.read_varint().unwrap());
+    assert_eq!(-1, r.read_varint().unwrap());
+    assert_eq!(1, r.read_varint().unwrap());
+    assert_eq!(-64, r.read_varint().unwrap());
+    assert_eq!(64, r.read_varint().unwrap());
+    assert_eq!(i32::MAX, r.read_varint().unwrap());
+    assert_eq!(i32::MIN, r.read_varint().unwrap());
+    assert!(r.is_empty());
+
+    // ~ incomplete input
+    let mut r = ZReader::new(&[0x80, 0x80]);
+    match r.read_varint() {
+        Err(::error::Error(ErrorKind::UnexpectedEOF, _)) => {}
+        e => panic!("Expected UnexpectedEOF, but got: {:?}", e),
+    }
+    assert_eq!(2, r.rest().len());
+
+    // ~ too long for a 32 bit integer
+    let mut r = ZReader::new(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]);
+    match r.read_varint() {
+        Err(::error::Error(ErrorKind::CodecError, _)) => {}
+        e => panic!("Expected CodecError, but got: {:?}", e),
+    }
+}
+
+#[test]
+fn test_read_varlong() {
+    let data = &[0x01, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+                 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+    let mut r = ZReader::new(data);
+    assert_eq!(-1, r.read_varlong().unwrap());
+    assert_eq!(i64::MAX, r.read_varlong().unwrap());
+    assert_eq!(i64::MIN, r.read_varlong().unwrap());
+    assert!(r.is_empty());
+}
+
+#[test]
+fn test_read_varint_bytes() {
+    let data = &[0x06, b'f', b'o', b'o', 0x01, 0x00, 0x04, b'x'];
+    let mut r = ZReader::new(data);
+    assert_eq!(b"foo", r.read_varint_bytes().unwrap());
+    // ~ null and empty bytes
+    assert_eq!(b"", r.read_varint_bytes().unwrap());
+    assert_eq!(b"", r.read_varint_bytes().unwrap());
+    assert!(r.read_varint_bytes().is_err());
+}
+
 /// Verify we can advance the reader while holding on to a previously
 /// returned slice/string.
 #[test]
@@ -227,3 +341,20 @@ fn test_mutability_lifetimes() {
     assert_eq!("hi", x);
     assert_eq!("ho", y);
 }
+
+#[test]
+fn test_read_array_len() {
+    use error::Error;
+
+    let data = &[0, 0, 0, 2, 0, 0, 255, 255, 255, 255, 0x7f, 0xff, 0xff, 0xff];
+    let mut r = ZReader::new(data);
+    assert_eq!(2, r.read_array_len().unwrap());
+    r.read(2).unwrap();
+    // ~ null array
+    assert_eq!(0, r.read_array_len().unwrap());
+    // ~ a length exceeding the available data
+    match r.read_array_len() {
+        Err(Error(ErrorKind::CodecError, _)) => {}
+        r => panic!("Expected CodecError, but got: {:?}", r),
+    }
+}
@@ -0,0 +1,229 @@
+//! Retrying operations failing with transient errors.
+//!
+//! Kafka reports a number of conditions - e.g. a partition leader
+//! being elected or a request timing out - which are expected to
+//! resolve themselves after a short while.  `with_retries` is the
+//! recommended wrapper around calls like `Producer::send` or
+//! `Consumer::poll` to transparently re-attempt them in such cases,
+//! backing off exponentially between the attempts.  Only errors
+//! classified as retriable by `Error::is_retriable` are retried; all
+//! other errors are passed to the caller immediately.
+//!
+//! Beware that re-attempting `Producer::send` this way bypasses the
+//! guard of `Producer::allow_duplicates_on_retry`: a record whose
+//! request timed out may have been appended by the broker nonetheless
+//! and is then duplicated.  Unless duplicates are acceptable, prefer
+//! `Producer::set_retries` together with `Producer::set_idempotent`
+//! which re-sends records without duplicating them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use kafka::producer::{Producer, Record};
+//! use kafka::retry::{with_retries, RetryPolicy};
+//!
+//! // ~ duplicates are acceptable for this producer; see above
+//! let mut producer = Producer::from_hosts(vec!["localhost:9092".to_owned()])
+//!     .create()
+//!     .unwrap();
+//! let policy = RetryPolicy::new(5, Duration::from_millis(100), Duration::from_secs(5));
+//! with_retries(&policy, || producer.send(&Record::from_value("my-topic", "hello")))
+//!     .unwrap();
+//! ```
+
+use std::cmp;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use error::Result;
+
+/// The default value for `RetryPolicy::max_attempts`.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// The default value for `RetryPolicy::base_delay`.
+pub const DEFAULT_BASE_DELAY_MILLIS: u64 = 100;
+
+/// The default value for `RetryPolicy::max_delay`.
+pub const DEFAULT_MAX_DELAY_MILLIS: u64 = 10_000;
+
+/// Describes how often and how patiently `with_retries` re-attempts a
+/// failing operation.
+#[derive(Debug, Copy, Clone)]
+pub struct RetryPolicy {
+    /// The max. number of times to attempt the operation, including
+    /// the initial attempt.  Zero is treated as one.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry.  The delay doubles with each
+    /// further retry.
+    pub base_delay: Duration,
+
+    /// The upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a new policy with all attributes specified.
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            base_delay: base_delay,
+            max_delay: max_delay,
+        }
+    }
+
+    // ~ the delay before the given retry (zero based) prior to
+    // applying any jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        // ~ prevent overflowing the multiplier; the delay is capped
+        // by `max_delay` anyway
+        let factor = 1u32 << cmp::min(retry, 16);
+        match self.base_delay.checked_mul(factor) {
+            Some(d) => cmp::min(d, self.max_delay),
+            None => self.max_delay,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(
+            DEFAULT_MAX_ATTEMPTS,
+            Duration::from_millis(DEFAULT_BASE_DELAY_MILLIS),
+            Duration::from_millis(DEFAULT_MAX_DELAY_MILLIS),
+        )
+    }
+}
+
+/// Invokes `op` until it succeeds, fails with an error not being
+/// retriable (see `Error::is_retriable`), or `policy.max_attempts` is
+/// exhausted.  Returns the result of the last invocation.
+///
+/// Between two attempts the calling thread sleeps for an
+/// exponentially growing, randomly jittered delay bound by
+/// `policy.max_delay`.
+pub fn with_retries<T, F>(policy: &RetryPolicy, op: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    __with_retries(policy, op, thread::sleep)
+}
+
+fn __with_retries<T, F, S>(policy: &RetryPolicy, mut op: F, mut sleep: S) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+    S: FnMut(Duration),
+{
+    let mut retry = 0;
+    loop {
+        match op() {
+            Err(ref e) if e.is_retriable() && retry + 1 < policy.max_attempts => {
+                let delay = jitter(policy.backoff(retry));
+                debug!("retrying in {:?} after: {}", delay, e);
+                sleep(delay);
+                retry += 1;
+            }
+            r => return r,
+        }
+    }
+}
+
+// ~ randomizes the given delay within its upper half to prevent
+// multiple clients from retrying in lockstep
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    let half_nanos = half.as_secs() * 1_000_000_000 + half.subsec_nanos() as u64;
+    if half_nanos == 0 {
+        return delay;
+    }
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    // ~ scramble the bits of the clock (xorshift)
+    let mut x = seed ^ 0x9e37_79b9_7f4a_7c15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    half + Duration::from_nanos(x % (half_nanos + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use error::{ErrorKind, KafkaCode};
+    use super::{RetryPolicy, __with_retries};
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts, Duration::from_millis(100), Duration::from_millis(350))
+    }
+
+    #[test]
+    fn test_backoff() {
+        let p = policy(10);
+        let delays: Vec<_> = (0..5).map(|i| p.backoff(i)).collect();
+        let expected: Vec<_> = [100, 200, 350, 350, 350]
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect();
+        assert_eq!(expected, delays);
+        assert_eq!(Duration::from_millis(350), p.backoff(u32::max_value()));
+    }
+
+    #[test]
+    fn test_retries_retriable_errors() {
+        let mut attempts = 0;
+        let mut sleeps = Vec::new();
+        let r = __with_retries(
+            &policy(5),
+            || {
+                attempts += 1;
+                if attempts < 3 {
+                    bail!(ErrorKind::Kafka(KafkaCode::LeaderNotAvailable));
+                }
+                Ok(attempts)
+            },
+            |d| sleeps.push(d),
+        );
+        assert_eq!(3, r.unwrap());
+        assert_eq!(2, sleeps.len());
+        // ~ jittered within the upper half of the backoff
+        assert!(sleeps[0] >= Duration::from_millis(50) && sleeps[0] <= Duration::from_millis(100));
+        assert!(sleeps[1] >= Duration::from_millis(100) && sleeps[1] <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let mut attempts = 0;
+        let r: ::error::Result<()> = __with_retries(
+            &policy(3),
+            || {
+                attempts += 1;
+                bail!(ErrorKind::Kafka(KafkaCode::RequestTimedOut))
+            },
+            |_| {},
+        );
+        assert_eq!(
+            Some(KafkaCode::RequestTimedOut.to_i16()),
+            r.unwrap_err().kafka_code()
+        );
+        assert_eq!(3, attempts);
+    }
+
+    #[test]
+    fn test_does_not_retry_permanent_errors() {
+        let mut attempts = 0;
+        let r: ::error::Result<()> = __with_retries(
+            &policy(3),
+            || {
+                attempts += 1;
+                bail!(ErrorKind::Kafka(KafkaCode::MessageSizeTooLarge))
+            },
+            |_| panic!("must not sleep"),
+        );
+        assert!(r.is_err());
+        assert_eq!(1, attempts);
+    }
+}
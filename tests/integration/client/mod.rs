@@ -1,6 +1,6 @@
 /// Still to test:
 ///
-/// * compression
+/// * gzip and snappy compression
 /// * secure connections
 
 use super::*;
@@ -8,8 +8,11 @@ use std::collections::HashSet;
 use std::collections::HashMap;
 use std::time::Duration;
 use kafka::client::{KafkaClient, CommitOffset, PartitionOffset, FetchPartition, ProduceMessage,
-                    RequiredAcks, FetchOffset};
+                    RequiredAcks, FetchOffset, TopicSpec};
+use kafka::error::KafkaCode;
 use kafka::client::fetch::Response;
+#[cfg(feature = "lz4")]
+use kafka::client::Compression;
 
 fn flatten_fetched_messages(resps: &Vec<Response>) -> Vec<(&str, i32, &[u8])> {
     let mut messages = Vec::new();
@@ -65,6 +68,24 @@ fn test_kafka_client_load_metadata() {
     assert_eq!(correct_topic_partitions, topic_partitions);
 }
 
+#[test]
+fn test_kafka_client_list_topics() {
+    let hosts = vec![LOCAL_KAFKA_BOOTSTRAP_HOST.to_owned()];
+    let mut client = KafkaClient::new(hosts);
+
+    let topics: Vec<String> = client
+        .list_topics()
+        .unwrap()
+        .into_iter()
+        // don't count the consumer offsets internal topic
+        .filter(|name| name != KAFKA_CONSUMER_OFFSETS_TOPIC_NAME)
+        .collect();
+    assert_eq!(vec![TEST_TOPIC_NAME.to_owned(), TEST_TOPIC_NAME_2.to_owned()], topics);
+
+    // ~ listing the topics must not load the client's metadata
+    assert_eq!(0, client.topics().len());
+}
+
 /// Tests:
 ///
 /// * KafkaClient::produce_messages
@@ -258,3 +279,260 @@ fn test_commit_offsets() {
         assert_eq!(correct_partition_offsets, partition_offsets);
     }
 }
+
+/// Tests:
+///
+/// * KafkaClient::join_group
+/// * KafkaClient::sync_group
+/// * ConsumerGroup::join
+#[test]
+fn test_consumer_group_join() {
+    use kafka::consumer::ConsumerGroup;
+
+    let group = format!("{}-join", TEST_GROUP_NAME);
+    let mut cg = ConsumerGroup::new(
+        new_ready_kafka_client(),
+        group,
+        vec![TEST_TOPIC_NAME.to_owned()],
+    );
+
+    // ~ being the only member, we are the leader and get assigned
+    // all of the topic's partitions
+    let assignment = cg.join().unwrap().to_vec();
+    let correct_assignment: Vec<(String, i32)> = TEST_TOPIC_PARTITIONS
+        .iter()
+        .map(|&p| (TEST_TOPIC_NAME.to_owned(), p))
+        .collect();
+    assert_eq!(correct_assignment, assignment);
+    assert!(!cg.member_id().is_empty());
+    assert!(cg.generation_id() >= 0);
+}
+
+/// Tests producing through a `SharedClient` from two threads
+/// concurrently.
+#[test]
+fn test_shared_client_concurrent_produce() {
+    use std::thread;
+    use kafka::client::SharedClient;
+
+    let client = SharedClient::new(new_ready_kafka_client());
+    let threads: Vec<_> = (0..2)
+        .map(|p| {
+            let client = client.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    let msg = ProduceMessage::new(TEST_TOPIC_NAME, p, None, Some(b"shared"));
+                    let confirms = client
+                        .produce_messages(RequiredAcks::One, Duration::from_millis(1000), &[msg])
+                        .unwrap();
+                    assert!(confirms[0].partition_confirms[0].offset.is_ok());
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+/// Tests:
+///
+/// * KafkaClient::describe_groups
+/// * KafkaClient::list_groups
+#[test]
+fn test_describe_groups() {
+    let group = format!("{}-describe", TEST_GROUP_NAME);
+    let mut client = new_ready_kafka_client();
+    let m = client
+        .join_group(&group, "", Duration::from_secs(30), &[TEST_TOPIC_NAME])
+        .unwrap();
+    let assignment = kafka::client::GroupAssignment {
+        member_id: m.member_id.clone(),
+        partitions: vec![(TEST_TOPIC_NAME.to_owned(), 0)],
+    };
+    client
+        .sync_group(&group, m.generation_id, &m.member_id, &[assignment])
+        .unwrap();
+
+    let ds = client.describe_groups(&[&group]).unwrap();
+    assert_eq!(1, ds.len());
+    assert_eq!(group, ds[0].group);
+    assert_eq!("Stable", ds[0].state);
+    assert_eq!("consumer", ds[0].protocol_type);
+    assert_eq!(1, ds[0].members.len());
+    let member = &ds[0].members[0];
+    assert_eq!(m.member_id, member.member_id);
+    assert_eq!(vec![TEST_TOPIC_NAME.to_owned()], member.topics);
+    assert_eq!(vec![(TEST_TOPIC_NAME.to_owned(), 0)], member.assignment);
+
+    // ~ the group is listed while it has members
+    let groups = client.list_groups().unwrap();
+    assert!(groups.contains(&(group.clone(), "consumer".to_owned())));
+
+    client.leave_group(&group, &m.member_id).unwrap();
+}
+
+/// Tests:
+///
+/// * KafkaClient::heartbeat
+/// * ConsumerGroup::heartbeat
+#[test]
+fn test_consumer_group_heartbeat() {
+    use kafka::consumer::{ConsumerGroup, HeartbeatStatus};
+
+    let group = format!("{}-heartbeat", TEST_GROUP_NAME);
+    let mut cg = ConsumerGroup::new(
+        new_ready_kafka_client(),
+        group,
+        vec![TEST_TOPIC_NAME.to_owned()],
+    );
+
+    // ~ not having joined yet, we're told to join
+    assert_eq!(HeartbeatStatus::Rejoin, cg.heartbeat().unwrap());
+
+    cg.join().unwrap();
+    assert_eq!(HeartbeatStatus::Alive, cg.heartbeat().unwrap());
+}
+
+/// Tests:
+///
+/// * KafkaClient::leave_group
+/// * ConsumerGroup::close
+#[test]
+fn test_consumer_group_close() {
+    use kafka::consumer::{ConsumerGroup, HeartbeatStatus};
+
+    let group = format!("{}-close", TEST_GROUP_NAME);
+    let mut cg = ConsumerGroup::new(
+        new_ready_kafka_client(),
+        group,
+        vec![TEST_TOPIC_NAME.to_owned()],
+    );
+    cg.join().unwrap();
+
+    cg.close().unwrap();
+    assert!(cg.member_id().is_empty());
+    assert!(cg.assignment().is_empty());
+    assert_eq!(HeartbeatStatus::Rejoin, cg.heartbeat().unwrap());
+
+    // ~ closing again is a no-op
+    cg.close().unwrap();
+}
+
+/// Tests producing an LZ4 compressed message set and fetching it back.
+#[cfg(feature = "lz4")]
+#[test]
+fn test_produce_fetch_messages_lz4() {
+    let mut client = new_ready_kafka_client();
+    client.set_compression(Compression::LZ4);
+
+    let req = vec![
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, None, Some("lz4-a".as_bytes())),
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, None, Some("lz4-b".as_bytes())),
+    ];
+    let resp = client
+        .produce_messages(RequiredAcks::All, Duration::from_millis(1000), req)
+        .unwrap();
+    let offset = resp[0].partition_confirms[0].offset.unwrap();
+
+    let fetch_resps = client
+        .fetch_messages(vec![FetchPartition::new(TEST_TOPIC_NAME, 0, offset)])
+        .unwrap();
+    let messages = flatten_fetched_messages(&fetch_resps);
+    assert_eq!(
+        vec![
+            (TEST_TOPIC_NAME, 0, "lz4-a".as_bytes()),
+            (TEST_TOPIC_NAME, 0, "lz4-b".as_bytes()),
+        ],
+        messages
+    );
+}
+
+/// Tests producing messages in the message format v1 and fetching
+/// them back along with their timestamps.
+#[test]
+fn test_produce_fetch_messages_v1() {
+    let mut client = new_ready_kafka_client();
+    client.set_message_version(1).unwrap();
+
+    let req = vec![
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, None, Some("v1-a".as_bytes()))
+            .with_timestamp(1_500_000_000_000),
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, None, Some("v1-b".as_bytes())),
+    ];
+    let resp = client
+        .produce_messages(RequiredAcks::All, Duration::from_millis(1000), req)
+        .unwrap();
+    let offset = resp[0].partition_confirms[0].offset.unwrap();
+
+    let fetch_resps = client
+        .fetch_messages(vec![FetchPartition::new(TEST_TOPIC_NAME, 0, offset)])
+        .unwrap();
+    let data = fetch_resps[0].topics()[0].partitions()[0].data().as_ref().unwrap();
+    let messages: Vec<_> = data.messages().iter().take(2).collect();
+    assert_eq!(2, messages.len());
+    assert_eq!((offset, "v1-a".as_bytes()), (messages[0].offset(), messages[0].value));
+    assert_eq!(1_500_000_000_000, messages[0].timestamp());
+    assert_eq!((offset + 1, "v1-b".as_bytes()), (messages[1].offset(), messages[1].value));
+    assert!(messages[1].timestamp() > 1_500_000_000_000);
+}
+
+/// Tests that messages in the format v2 keep their timestamps and
+/// headers on their way through kafka.
+#[test]
+fn test_produce_fetch_messages_v2() {
+    let mut client = new_ready_kafka_client();
+    client.set_message_version(2).unwrap();
+
+    let headers = vec![("trace-id".to_owned(), b"abc".to_vec()), ("empty".to_owned(), vec![])];
+    let req = vec![
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, Some("k".as_bytes()), Some("v2-a".as_bytes()))
+            .with_timestamp(1_500_000_000_000)
+            .with_headers(&headers),
+        ProduceMessage::new(TEST_TOPIC_NAME, 0, None, Some("v2-b".as_bytes())),
+    ];
+    let resp = client
+        .produce_messages(RequiredAcks::All, Duration::from_millis(1000), req)
+        .unwrap();
+    let offset = resp[0].partition_confirms[0].offset.unwrap();
+
+    let fetch_resps = client
+        .fetch_messages(vec![FetchPartition::new(TEST_TOPIC_NAME, 0, offset)])
+        .unwrap();
+    let data = fetch_resps[0].topics()[0].partitions()[0].data().as_ref().unwrap();
+    let messages: Vec<_> = data.messages().iter().take(2).collect();
+    assert_eq!(2, messages.len());
+    assert_eq!((offset, "v2-a".as_bytes()), (messages[0].offset(), messages[0].value));
+    assert_eq!("k".as_bytes(), messages[0].key);
+    assert_eq!(1_500_000_000_000, messages[0].timestamp());
+    assert_eq!(
+        &[("trace-id", &b"abc"[..]), ("empty", &b""[..])],
+        messages[0].headers()
+    );
+    assert_eq!((offset + 1, "v2-b".as_bytes()), (messages[1].offset(), messages[1].value));
+    assert!(messages[1].headers().is_empty());
+}
+
+/// Tests:
+///
+/// * KafkaClient::create_topics
+/// * KafkaClient::delete_topics
+#[test]
+fn test_topics_create_delete() {
+    let mut client = new_ready_kafka_client();
+    let topic = "kafka-rust-test-admin";
+
+    let rs = client.create_topics(&[TopicSpec::new(topic, 1, 1)]).unwrap();
+    assert_eq!(1, rs.len());
+    assert_eq!(topic, rs[0].topic);
+    assert_eq!(Ok(()), rs[0].result);
+
+    // ~ creating the topic again is reported per topic
+    let rs = client.create_topics(&[TopicSpec::new(topic, 1, 1)]).unwrap();
+    assert_eq!(Err(KafkaCode::TopicAlreadyExists), rs[0].result);
+
+    let rs = client.delete_topics(&[topic], Duration::from_secs(10)).unwrap();
+    assert_eq!(1, rs.len());
+    assert_eq!(topic, rs[0].topic);
+    assert_eq!(Ok(()), rs[0].result);
+}
@@ -30,6 +30,32 @@ fn test_consumer_poll() {
     assert_eq!(correct_message_contents, message_content, "incorrect message contents");
 }
 
+/// Tests that the consumer's position advances past polled messages
+#[test]
+fn test_consumer_current_offsets() {
+    // poll once to set a position in the topic
+    let mut consumer = test_consumer();
+    consumer.poll().unwrap();
+
+    let mut producer = test_producer();
+    producer
+        .send(&Record::from_value(TEST_TOPIC_NAME, "test_consumer_current_offsets".as_bytes()))
+        .unwrap();
+
+    let messages = consumer.poll().unwrap();
+    let message_set = messages.iter().next().unwrap();
+    let last_offset = message_set.messages().last().unwrap().offset;
+
+    let offsets = consumer.current_offsets();
+    let position = offsets
+        .get(TEST_TOPIC_NAME)
+        .unwrap()
+        .iter()
+        .find(|po| po.partition == message_set.partition())
+        .unwrap();
+    assert_eq!(last_offset + 1, position.offset);
+}
+
 /// Test Consumer::commit_messageset
 #[test]
 fn test_consumer_commit_messageset() {
@@ -160,3 +186,81 @@ fn test_consumer_non_existent_topic() {
     let correct_error_code = error::KafkaCode::UnknownTopicOrPartition;
     assert_eq!(correct_error_code, error_code, "should have errored on non-existent topic");
 }
+
+/// Tests Consumer::seek back to a previously delivered message and
+/// past the end of a partition
+#[test]
+fn test_consumer_seek() {
+    let mut consumer = test_consumer();
+    consumer.poll().unwrap();
+
+    let mut producer = test_producer();
+    let record = Record::from_value(TEST_TOPIC_NAME, "test_consumer_seek".as_bytes());
+    producer.send(&record.with_partition(0)).unwrap();
+
+    let offset = {
+        let messages = consumer.poll().unwrap();
+        let message_set = messages.iter().next().unwrap();
+        message_set.messages()[0].offset
+    };
+
+    // ~ seeking back delivers the message again
+    consumer.seek(TEST_TOPIC_NAME, 0, FetchOffset::Exact(offset)).unwrap();
+    {
+        let messages = consumer.poll().unwrap();
+        let message_set = messages.iter().next().unwrap();
+        assert_eq!(offset, message_set.messages()[0].offset);
+        assert_eq!("test_consumer_seek".as_bytes(), message_set.messages()[0].value);
+    }
+
+    // ~ seeking past the end delivers nothing but doesn't fail
+    consumer.seek(TEST_TOPIC_NAME, 0, FetchOffset::Exact(offset + 1_000)).unwrap();
+    assert!(consumer.poll().unwrap().is_empty());
+
+    // ~ seeking to the latest offset positions right after the message
+    consumer.seek(TEST_TOPIC_NAME, 0, FetchOffset::Latest).unwrap();
+    let position = consumer.current_offsets()[TEST_TOPIC_NAME]
+        .iter()
+        .find(|po| po.partition == 0)
+        .unwrap()
+        .offset;
+    assert_eq!(offset + 1, position);
+
+    // ~ seeking a partition not being consumed fails
+    match consumer.seek(TEST_TOPIC_NAME_2, 0, FetchOffset::Earliest) {
+        Err(error::Error(error::ErrorKind::Kafka(code), _)) => {
+            assert_eq!(error::KafkaCode::UnknownTopicOrPartition, code)
+        }
+        r => panic!("Expected UnknownTopicOrPartition, but got: {:?}", r),
+    }
+}
+
+/// Tests iterating lazily over the consumed messages
+#[test]
+fn test_consumer_iter() {
+    let mut consumer = test_consumer();
+    consumer.poll().unwrap();
+
+    let mut producer = test_producer();
+    let values = ["test_consumer_iter_1", "test_consumer_iter_2", "test_consumer_iter_3"];
+    for value in &values {
+        let record = Record::from_value(TEST_TOPIC_NAME, value.as_bytes());
+        producer.send(&record.with_partition(0)).unwrap();
+    }
+
+    let msgs: Vec<_> = consumer.iter().map(|m| m.unwrap()).collect();
+    let delivered: Vec<_> = msgs.iter().map(|m| &m.value[..]).collect();
+    let expected: Vec<_> = values.iter().map(|v| v.as_bytes()).collect();
+    assert_eq!(expected, delivered);
+    assert!(msgs.iter().all(|m| m.topic == TEST_TOPIC_NAME && m.partition == 0));
+
+    // ~ the delivered messages have been marked as consumed
+    let last = msgs.last().unwrap();
+    assert_eq!(
+        Some(last.offset),
+        consumer.last_consumed_message(TEST_TOPIC_NAME, 0)
+    );
+    // ~ and there's nothing more to deliver
+    assert_eq!(0, consumer.iter().count());
+}
+
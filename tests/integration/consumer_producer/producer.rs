@@ -1,6 +1,9 @@
 use super::*;
+use std::time::Duration;
+
 use kafka::error;
-use kafka::producer::Record;
+use kafka::client::FetchPartition;
+use kafka::producer::{Record, RequiredAcks};
 
 /// Tests that basic message sending results in a successful call.
 #[test]
@@ -11,6 +14,44 @@ fn test_producer_send() {
         .unwrap();
 }
 
+/// Sending without requiring acknowledgements must not await a
+/// response from the brokers.
+#[test]
+fn test_producer_send_no_acks() {
+    let mut producer = test_producer();
+    producer.set_required_acks(RequiredAcks::None);
+    assert_eq!(RequiredAcks::None, producer.required_acks());
+
+    producer
+        .send(&Record::from_value(TEST_TOPIC_NAME, "foo".as_bytes()))
+        .unwrap();
+    let confirms = producer
+        .send_all(&[Record::from_value(TEST_TOPIC_NAME, "bar".as_bytes())])
+        .unwrap();
+    assert!(confirms.is_empty());
+
+    // ~ switching back yields confirmations again
+    producer.set_required_acks(RequiredAcks::All);
+    let confirms = producer
+        .send_all(&[Record::from_value(TEST_TOPIC_NAME, "baz".as_bytes())])
+        .unwrap();
+    assert_eq!(1, confirms.len());
+}
+
+/// Tests changing the ack timeout of an existing producer.
+#[test]
+fn test_producer_set_ack_timeout() {
+    let mut producer = test_producer();
+    producer.set_ack_timeout(Duration::from_millis(2500)).unwrap();
+    assert_eq!(Duration::from_millis(2500), producer.ack_timeout());
+    assert!(producer.set_ack_timeout(Duration::from_secs(u64::max_value())).is_err());
+    assert_eq!(Duration::from_millis(2500), producer.ack_timeout());
+
+    producer
+        .send(&Record::from_value(TEST_TOPIC_NAME, "foo".as_bytes()))
+        .unwrap();
+}
+
 /// Sending to a non-existent topic should fail.
 #[test]
 fn test_producer_send_non_existent_topic() {
@@ -66,3 +107,46 @@ fn test_producer_send_all_non_existent_topic() {
     let correct_error_code = error::KafkaCode::UnknownTopicOrPartition;
     assert_eq!(correct_error_code, error_code, "should have errored on non-existent topic");
 }
+
+/// Tests that records sent through one `send_all` call are appended
+/// to their partitions in order and at consecutive offsets.
+#[test]
+fn test_producer_send_all_preserves_order() {
+    let mut producer = test_producer();
+    let values: Vec<String> = (0..10).map(|i| format!("msg-{}", i)).collect();
+    let records: Vec<_> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            Record::from_value(TEST_TOPIC_NAME, v.as_bytes())
+                .with_partition(TEST_TOPIC_PARTITIONS[i % TEST_TOPIC_PARTITIONS.len()])
+        })
+        .collect();
+    let confirms = producer.send_all(&records).unwrap();
+    assert_eq!(1, confirms.len());
+    assert_eq!(TEST_TOPIC_PARTITIONS.len(), confirms[0].partition_confirms.len());
+
+    let mut client = new_ready_kafka_client();
+    for pc in &confirms[0].partition_confirms {
+        let offset = pc.offset.unwrap();
+        let resps = client
+            .fetch_messages(vec![FetchPartition::new(TEST_TOPIC_NAME, pc.partition, offset)])
+            .unwrap();
+        let expected: Vec<(i64, &[u8])> = records
+            .iter()
+            .filter(|r| r.partition == pc.partition)
+            .enumerate()
+            .map(|(i, r)| (offset + i as i64, r.value))
+            .collect();
+        let fetched: Vec<(i64, &[u8])> = resps[0].topics()[0].partitions()[0]
+            .data()
+            .as_ref()
+            .unwrap()
+            .messages()
+            .iter()
+            .map(|m| (m.offset, m.value))
+            .take(expected.len())
+            .collect();
+        assert_eq!(expected, fetched);
+    }
+}